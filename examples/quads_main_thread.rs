@@ -91,7 +91,7 @@ fn main() {
     // This is very CPU and data intensive (2GB/s at 4K60) so it's recommended to:
     //
     // 1) Compress the raw frame data to disk:
-    let compressor = renderer::Compressor::new("recorded_frames", None, 0, true);
+    let compressor = renderer::Compressor::new("recorded_frames", renderer::CompressorOptions { stats_sink: renderer::StatsSink::Print, ..Default::default() });
     renderer.start_recording(&[&pipeline], Some(clear_color), 500., Box::new(move |video_frame| {
         compressor.compress_to_disk(video_frame);
     }));
@@ -116,7 +116,17 @@ fn main() {
     }
 
     // Alternatively, you could skip compression/decompression and write PNGs directly.
-    // This is slower but might be fine for your use case. Bring your own concurrecncy.
+    // This is slower but might be fine for your use case. PngEncoderPool spreads the
+    // encoding across threads, e.g. in the process function passed to start_recording:
+    //
+    //   let naming_function = Arc::new(|f: &renderer::VideoFrame| format!("frame_{:06}.png", f.frame_number));
+    //   let png_pool = renderer::PngEncoderPool::to_files(naming_function, 4, 8, renderer::PngOptions { tag_srgb: true, ..Default::default() });
+    //
+    //   renderer.start_recording(&[&pipeline], None, 500., Box::new(move |video_frame| png_pool.encode(video_frame)));
+
+    // Set the window's viewport to a square, surrounded by black borders.
+    // This is recalculated automatically when the window is resized.
+    let viewport = renderer.viewport(1., 1.); // e.g. (16., 9.)
 
     // Set the start position of each quad and its velocity in the x, y directions.
     let mut x1 = (0.3, 0.015);
@@ -143,9 +153,6 @@ fn main() {
                     // Update the quad positions that _do_ change per render.
                     renderer.set_instanced(&pipeline, I_OFFSET, &[x1.0, y1.0, x2.0, y2.0]);
 
-                    // Set the window's viewport to a square, surrounded by black borders.
-                    let viewport = renderer.viewport(1., 1.); // e.g. (16., 9.)
-
                     // Render two instances, each comprised of four vertices.
                     renderer.render(&pipeline, Some(clear_color), Some(&viewport), (2, 4));
                     renderer.finish_frame();
@@ -0,0 +1,126 @@
+use renderer::{Renderer, Skeleton};
+use std::sync::Arc;
+use std::f32::consts;
+use winit::{event, event_loop, window};
+
+const A_POSITION: usize = 0;
+const A_JOINTS: usize = 1;
+const A_WEIGHTS: usize = 2;
+
+const ROWS: usize = 9;
+
+fn main() {
+    // Compile the vertex and fragment shaders for this example to SPIR-V.
+    renderer::Compiler::compile_shaders("examples/skinning");
+
+    let event_loop = event_loop::EventLoop::new().unwrap();
+    let window = Arc::new(window::WindowBuilder::new().build(&event_loop).unwrap());
+    let renderer = Renderer::new(window.clone());
+
+    let vert = include_bytes!("./skinning/skinned.vert.spirv");
+    let frag = include_bytes!("./skinning/skinned.frag.spirv");
+
+    // An arm with two bones: the upper arm starts at the shoulder (y=-0.6) and
+    // the forearm starts at the elbow (y=0). The forearm's parent is the upper arm.
+    let parents = vec![None, Some(0)];
+    let inverse_bind = vec![translate(0., 0.6), translate(0., 0.)];
+
+    let mut skeleton = Skeleton::new(&renderer, A_JOINTS, A_WEIGHTS, parents, inverse_bind);
+    let a_position = renderer.attribute(A_POSITION, 2);
+
+    // The bone matrices are bound at set 0, binding 0 like any instanced data.
+    let program = renderer.program(vert, frag, vec![
+        a_position,                                         // attribute 0
+        skeleton.joints.clone(),                            // attribute 1
+        skeleton.weights.clone(),                           // attribute 2
+    ], vec![
+        skeleton.bones.clone(),                             // set 0, binding 0
+    ], vec![], vec![]);
+
+    let blend_mode = Renderer::pre_multiplied_blend();
+    let primitive = Renderer::triangle_strip_primitive();
+    let target = Renderer::screen_target();
+
+    let pipeline = renderer.pipeline(program, blend_mode, primitive, 1, vec![target]);
+    let clear_color = Renderer::clear_color(0., 0., 0., 1.);
+    let viewport = renderer.viewport(1., 1.);
+
+    // A strip of quads from the shoulder to the hand. Vertices near the elbow
+    // are blended between the two bones so that the arm bends smoothly.
+    let (positions, joints, weights) = arm_mesh();
+
+    renderer.set_attribute(&pipeline, A_POSITION, &positions);
+    skeleton.set_mesh(&renderer, &joints, &weights);
+
+    let mut time = 0_f32;
+
+    event_loop.run(move |event, window_target| {
+        match event {
+            event::Event::AboutToWait => {
+                window.request_redraw();
+            },
+            event::Event::WindowEvent { event, .. } => match event {
+                event::WindowEvent::RedrawRequested => {
+                    time += 1. / 60.;
+
+                    // Each local transform is relative to the bone's parent.
+                    let shoulder = multiply(&translate(0., -0.6), &rotate(time.sin() * 0.3));
+                    let elbow = multiply(&translate(0., 0.6), &rotate((time * 2.).sin() * consts::FRAC_PI_3));
+
+                    skeleton.set_pose(&[shoulder, elbow]);
+                    skeleton.upload(&renderer);
+
+                    renderer.render(&pipeline, Some(clear_color), Some(&viewport), (1, ROWS as u32 * 2));
+                    renderer.finish_frame();
+                },
+                event::WindowEvent::Resized(size) => {
+                    renderer.resize_swap_chain(&size);
+                },
+                event::WindowEvent::CloseRequested => {
+                    window_target.exit();
+                },
+                _ => {},
+            },
+            _ => {},
+        }
+    }).unwrap();
+}
+
+fn arm_mesh() -> (Vec<f32>, Vec<u32>, Vec<f32>) {
+    let (mut positions, mut joints, mut weights) = (vec![], vec![], vec![]);
+
+    for row in 0..ROWS {
+        let y = -0.6 + 1.2 * row as f32 / (ROWS - 1) as f32;
+        let forearm = ((y + 0.2) / 0.4).clamp(0., 1.);
+
+        for x in [-0.08, 0.08] {
+            positions.extend([x, y]);
+            joints.extend([0, 1, 0, 0]);
+            weights.extend([1. - forearm, forearm, 0., 0.]);
+        }
+    }
+
+    (positions, joints, weights)
+}
+
+// Column-major 4x4 matrices, like GLSL.
+fn translate(x: f32, y: f32) -> [f32; 16] {
+    [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., x, y, 0., 1.]
+}
+
+fn rotate(angle: f32) -> [f32; 16] {
+    let (sin, cos) = angle.sin_cos();
+    [cos, sin, 0., 0., -sin, cos, 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.]
+}
+
+fn multiply(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let mut out = [0.; 16];
+
+    for column in 0..4 {
+        for row in 0..4 {
+            out[column * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,11 @@
+#version 310 es
+
+precision highp float;
+
+layout(location=0) in vec4 v_weights;
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+  f_color = vec4(v_weights.x, 0.5, v_weights.y, 1.0);
+}
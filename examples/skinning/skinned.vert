@@ -0,0 +1,21 @@
+#version 310 es
+
+precision highp float;
+
+layout(set=0, binding=0) readonly buffer _0 { mat4 i_bones[]; };
+
+layout(location=0) in vec2 a_position;
+layout(location=1) in uvec4 a_joints;
+layout(location=2) in vec4 a_weights;
+
+layout(location=0) out vec4 v_weights;
+
+void main() {
+  mat4 skin = a_weights.x * i_bones[a_joints.x]
+            + a_weights.y * i_bones[a_joints.y]
+            + a_weights.z * i_bones[a_joints.z]
+            + a_weights.w * i_bones[a_joints.w];
+
+  v_weights = a_weights;
+  gl_Position = skin * vec4(a_position, 0.0, 1.0);
+}
@@ -0,0 +1,105 @@
+use std::{sync::Arc, time};
+use winit::{dpi, event, event_loop, window};
+
+// Owns the event loop, the window and the renderer so that small tools don't need
+// the bootstrap code from the examples. The setup function is called once with the
+// renderer to create pipelines, etc. and returns the function that renders each
+// frame. The window's swap chain is resized automatically and the frame is
+// finished after the frame function returns. Check frame.renderer.is_hidden() to
+// skip work while the window is minimized or occluded.
+//
+//   renderer::App::run(renderer::AppConfig::default(), |renderer| {
+//       let pipeline = renderer.pipeline(...);
+//       move |frame| frame.renderer.render(&pipeline, None, None, (1, 3))
+//   });
+
+pub struct App;
+
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub title: String,
+    pub size: (u32, u32),
+    pub resizable: bool,
+    pub decorations: bool, // false for a window without a title bar, e.g. for clean screen captures.
+    pub icon: Option<(Vec<u8>, u32, u32)>, // (rgba, width, height)
+    pub continuous: bool, // Redraw every frame rather than only after window events.
+    pub renderer_options: crate::RendererOptions,
+}
+
+pub struct FrameContext<'a> {
+    pub renderer: &'a crate::Renderer<'static>,
+    pub window: &'a window::Window,
+    pub events: &'a [event::WindowEvent], // Since the previous frame, e.g. for input.
+    pub frame_number: u64,
+    pub elapsed_time: f64, // In seconds since the app started.
+    pub delta_time: f64,   // In seconds since the previous frame.
+    pub exit: bool,        // Set to close the window after this frame.
+}
+
+impl App {
+    pub fn run<F: FnMut(&mut FrameContext)>(config: AppConfig, setup_function: impl FnOnce(&crate::Renderer<'static>) -> F) {
+        let event_loop = event_loop::EventLoop::new().unwrap();
+        let window = Arc::new(build_window(&config, &event_loop));
+        let renderer = crate::Renderer::new_with_options(window.clone(), config.renderer_options.clone());
+
+        let mut frame_function = setup_function(&renderer);
+
+        let started_at = time::Instant::now();
+        let mut previous_time = 0.;
+        let mut frame_number = 0;
+        let mut events = vec![];
+
+        event_loop.run(move |event, window_target| {
+            let event = match event {
+                event::Event::AboutToWait => { if config.continuous { window.request_redraw(); } return; },
+                event::Event::WindowEvent { event, .. } => event,
+                _ => return,
+            };
+
+            match event {
+                event::WindowEvent::RedrawRequested => {
+                    let elapsed_time = started_at.elapsed().as_secs_f64();
+                    frame_number += 1;
+
+                    let mut frame = FrameContext { renderer: &renderer, window: &window, events: &events, frame_number, elapsed_time, delta_time: elapsed_time - previous_time, exit: false };
+
+                    frame_function(&mut frame);
+                    renderer.finish_frame();
+
+                    if frame.exit { window_target.exit(); }
+
+                    previous_time = elapsed_time;
+                    events.clear();
+                },
+                event::WindowEvent::CloseRequested => {
+                    window_target.exit();
+                },
+                event => {
+                    if let event::WindowEvent::Resized(size) = &event { renderer.resize_swap_chain(size); }
+                    if let event::WindowEvent::Occluded(occluded) = &event { renderer.set_occluded(*occluded); }
+                    if !config.continuous { window.request_redraw(); }
+
+                    events.push(event);
+                },
+            }
+        }).unwrap();
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self { title: "renderer".to_string(), size: (800, 600), resizable: true, decorations: true, icon: None, continuous: true, renderer_options: crate::RendererOptions::default() }
+    }
+}
+
+fn build_window(config: &AppConfig, event_loop: &event_loop::EventLoop<()>) -> window::Window {
+    let icon = config.icon.clone().map(|(rgba, width, height)| window::Icon::from_rgba(rgba, width, height).expect("The icon's rgba must be width * height * 4 bytes."));
+
+    window::WindowBuilder::new()
+        .with_title(&config.title)
+        .with_inner_size(dpi::PhysicalSize::new(config.size.0, config.size.1))
+        .with_resizable(config.resizable)
+        .with_decorations(config.decorations)
+        .with_window_icon(icon)
+        .build(event_loop).unwrap()
+}
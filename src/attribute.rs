@@ -4,24 +4,58 @@ pub struct Attribute {
     pub inner: wgpu::VertexAttribute,
     pub location: usize,
     pub size: u32,
+    pub pulled: bool,
 }
 
 impl Attribute {
     pub fn new(device: &wgpu::Device, location: usize, size: u32) -> Self {
+        Self::new_with_format(device, location, size, false)
+    }
+
+    // Integer attributes are declared as uint/uvec in GLSL, e.g. for joint indices.
+    pub fn new_u32(device: &wgpu::Device, location: usize, size: u32) -> Self {
+        Self::new_with_format(device, location, size, true)
+    }
+
+    fn new_with_format(device: &wgpu::Device, location: usize, size: u32, integer: bool) -> Self {
         let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
         let buffer = crate::Buffer::new(device, usage);
-        let inner = wgpu_attribute(location as u32, size);
+        let inner = wgpu_attribute(location as u32, size, integer);
+
+        Self { buffer, inner, location, size, pulled: false }
+    }
+
+    // Pulled attributes are bound as read-only storage buffers for the vertex
+    // shader to fetch from itself (vertex pulling) rather than as vertex buffers,
+    // e.g. to share vertices between primitives or for data that doesn't fit a
+    // vertex format. Any size is allowed. They're bound after everything else in
+    // the order of the program's attributes (see Program::pulled_index_tuple) and
+    // declared in GLSL as `readonly buffer Name { float values[]; };` to be read
+    // at values[gl_VertexIndex * size + i]. Use uint[] for u32 data.
+    pub fn new_pulled(device: &wgpu::Device, location: usize, size: u32) -> Self {
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let buffer = crate::Buffer::new(device, usage);
+        let inner = wgpu::VertexAttribute { offset: 0, shader_location: location as u32, format: wgpu::VertexFormat::Float32 }; // Unused.
+
+        Self { buffer, inner, location, size, pulled: true }
+    }
 
-        Self { buffer, inner, location, size }
+    pub fn binding<'a>(&self, buffer: &'a wgpu::Buffer, id: u32) -> (wgpu::BindGroupEntry<'a>, wgpu::BindGroupLayoutEntry) {
+        let storage = crate::Instanced { buffer: self.buffer.clone(), access: crate::StorageAccess::ReadOnly, visibility: crate::Visibility::VertexShader };
+        storage.binding(buffer, id)
     }
 }
 
-fn wgpu_attribute(shader_location: u32, size: u32) -> wgpu::VertexAttribute {
-    let format = match size {
-        1 => wgpu::VertexFormat::Float32,
-        2 => wgpu::VertexFormat::Float32x2,
-        3 => wgpu::VertexFormat::Float32x3,
-        4 => wgpu::VertexFormat::Float32x4,
+fn wgpu_attribute(shader_location: u32, size: u32, integer: bool) -> wgpu::VertexAttribute {
+    let format = match (size, integer) {
+        (1, false) => wgpu::VertexFormat::Float32,
+        (2, false) => wgpu::VertexFormat::Float32x2,
+        (3, false) => wgpu::VertexFormat::Float32x3,
+        (4, false) => wgpu::VertexFormat::Float32x4,
+        (1, true) => wgpu::VertexFormat::Uint32,
+        (2, true) => wgpu::VertexFormat::Uint32x2,
+        (3, true) => wgpu::VertexFormat::Uint32x3,
+        (4, true) => wgpu::VertexFormat::Uint32x4,
         _ => panic!("Unsupported attribute size"),
     };
 
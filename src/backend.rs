@@ -0,0 +1,97 @@
+use std::{env, fmt};
+
+// Forces the renderer onto particular backends, e.g. to check whether a bug only
+// happens with Vulkan. The RENDERER_BACKEND environment variable takes precedence
+// so that users can switch backends without recompiling. It is a comma separated
+// list of vulkan, metal, dx12 and gl, e.g. RENDERER_BACKEND=vulkan,gl.
+
+#[derive(Clone, Debug, Default)]
+pub struct RendererOptions {
+    pub backends: Option<wgpu::Backends>, // None for all of them.
+}
+
+// A report of the adapters that were considered and what the surface supports
+// on each of them, e.g. to ask for in platform-specific bug reports. Adapters
+// that can't present to the surface (or headless renderers) have no formats or
+// present modes.
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    pub selected: wgpu::AdapterInfo,
+    pub backend_variable: Option<String>,
+    pub adapters: Vec<AdapterDiagnostics>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdapterDiagnostics {
+    pub info: wgpu::AdapterInfo,
+    pub formats: Vec<wgpu::TextureFormat>,
+    pub present_modes: Vec<wgpu::PresentMode>,
+    pub alpha_modes: Vec<wgpu::CompositeAlphaMode>,
+}
+
+pub const BACKEND_VARIABLE: &str = "RENDERER_BACKEND";
+
+impl RendererOptions {
+    pub fn backends(&self) -> wgpu::Backends {
+        let from_variable = env::var(BACKEND_VARIABLE).ok().and_then(|s| parse_backends(&s));
+        from_variable.or(self.backends).unwrap_or(wgpu::Backends::all())
+    }
+}
+
+// Returns None if the list is empty. Unknown names are ignored with a warning so
+// that a typo in the variable doesn't stop an app from starting.
+pub fn parse_backends(list: &str) -> Option<wgpu::Backends> {
+    let names = list.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+
+    let backends = names.fold(wgpu::Backends::empty(), |backends, name| backends | match name.as_str() {
+        "vulkan" | "vk" => wgpu::Backends::VULKAN,
+        "metal" => wgpu::Backends::METAL,
+        "dx12" | "d3d12" => wgpu::Backends::DX12,
+        "gl" | "gles" | "opengl" => wgpu::Backends::GL,
+        _ => {
+            eprintln!("Warning: Ignoring unknown backend '{}' in {}. Please choose from vulkan, metal, dx12 and gl.", name, BACKEND_VARIABLE);
+            wgpu::Backends::empty()
+        },
+    });
+
+    if backends.is_empty() { None } else { Some(backends) }
+}
+
+impl Diagnostics {
+    pub fn new(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>, selected: &wgpu::Adapter) -> Self {
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all()).iter().map(|adapter| {
+            let capabilities = surface.map(|s| s.get_capabilities(adapter)).unwrap_or_default();
+
+            AdapterDiagnostics {
+                info: adapter.get_info(),
+                formats: capabilities.formats,
+                present_modes: capabilities.present_modes,
+                alpha_modes: capabilities.alpha_modes,
+            }
+        }).collect();
+
+        Self { selected: selected.get_info(), backend_variable: env::var(BACKEND_VARIABLE).ok(), adapters }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Selected: {}", describe(&self.selected))?;
+        writeln!(f, "{}: {}", BACKEND_VARIABLE, self.backend_variable.as_deref().unwrap_or("(not set)"))?;
+        writeln!(f, "Adapters considered: {}", self.adapters.len())?;
+
+        for adapter in &self.adapters {
+            writeln!(f)?;
+            writeln!(f, "  {}", describe(&adapter.info))?;
+            writeln!(f, "    Formats: {:?}", adapter.formats)?;
+            writeln!(f, "    Present modes: {:?}", adapter.present_modes)?;
+            writeln!(f, "    Alpha modes: {:?}", adapter.alpha_modes)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn describe(info: &wgpu::AdapterInfo) -> String {
+    format!("{} ({:?}, {:?}, driver: {} {})", info.name, info.backend, info.device_type, info.driver, info.driver_info)
+}
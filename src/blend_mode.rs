@@ -13,6 +13,12 @@ impl BlendMode {
         Self { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha }
     }
 
+    // Blends the source over the destination by the pipeline's blend constant,
+    // e.g. to fade a layer in or out without changing its uniforms.
+    pub fn constant() -> Self {
+        Self { src_factor: wgpu::BlendFactor::Constant, dst_factor: wgpu::BlendFactor::OneMinusConstant }
+    }
+
     pub fn state(&self, target_format: crate::Format) -> wgpu::ColorTargetState {
         let blend_component = blend_component(self.src_factor, self.dst_factor);
 
@@ -1,4 +1,4 @@
-use std::{cell, mem, ops, rc};
+use std::{cell, mem, rc};
 
 #[derive(Clone)]
 pub struct Buffer {
@@ -6,11 +6,27 @@ pub struct Buffer {
 }
 
 pub struct InnerB {
-    pub buffer: wgpu::Buffer,
+    pub buffer: rc::Rc<wgpu::Buffer>,
     pub usage: wgpu::BufferUsages,
     pub size: usize,
     pub generation: u32,
     pub previous: u64,
+    pub len: usize,
+    pub underused: Option<(u64, usize)>, // (frame_index when it became underused, peak len since)
+}
+
+// Buffers that have been below the utilization for the number of frames are
+// recreated at a smaller size (see Renderer::set_shrink_policy).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShrinkPolicy {
+    pub frames: u64,
+    pub utilization: f32,
+}
+
+impl Default for ShrinkPolicy {
+    fn default() -> Self {
+        Self { frames: 300, utilization: 0.25 }
+    }
 }
 
 const INITIAL_SIZE: usize = mem::size_of::<f32>() * 16; // Enough for a mat4 uniform.
@@ -19,43 +35,118 @@ const HEADROOM: usize = mem::size_of::<f32>() * 256;
 impl Buffer {
     pub fn new(device: &wgpu::Device, usage: wgpu::BufferUsages) -> Self {
         let buffer = create_buffer(device, usage);
-        let inner = InnerB { buffer, usage, size: INITIAL_SIZE, generation: 0, previous: u64::MAX };
+        let inner = InnerB { buffer: rc::Rc::new(buffer), usage, size: INITIAL_SIZE, generation: 0, previous: u64::MAX, len: 0, underused: None };
 
         Self { inner: rc::Rc::new(cell::RefCell::new(inner)) }
     }
 
-    pub fn set_data(&self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[f32], flushes: u64) {
+    // The shrink policy is paired with the renderer's frame index because it
+    // counts frames rather than flushes, which can happen several times a frame.
+    pub fn set_data(&self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[f32], flushes: u64, shrink_policy: Option<(&ShrinkPolicy, u64)>) {
         let mut inner = self.inner.borrow_mut();
 
         if flushes == inner.previous { panic!("Wasteful call to buffer.set_data(). The previous data would be overridden."); }
         inner.previous = flushes;
 
         let bytes = bytemuck::cast_slice(data);
+        span!("buffer_upload", bytes = bytes.len());
 
-        if bytes.len() > inner.size {
-            let (buffer, size) = create_buffer_with_headroom(device, inner.usage, bytes);
+        inner.len = bytes.len();
+        let shrink_to = shrink_policy.and_then(|(p, frame_index)| inner.track_utilization(p, frame_index));
 
-            inner.buffer = buffer;
+        if bytes.len() > inner.size || shrink_to.is_some() {
+            let (buffer, size) = create_buffer_with_headroom(device, inner.usage, bytes, shrink_to.unwrap_or(0));
+
+            inner.buffer = rc::Rc::new(buffer);
             inner.size = size;
             inner.generation += 1;
+            inner.underused = None;
         } else {
             queue.write_buffer(&inner.buffer, 0, bytes);
         }
     }
 
+    // Buffers can only be copied from by compact if they have COPY_SRC, which is
+    // only added while a shrink policy is set. Empty buffers are recreated with it
+    // straight away and the rest get it the next time they're resized.
+    pub fn allow_compaction(&self, device: &wgpu::Device) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.usage.contains(wgpu::BufferUsages::COPY_SRC) { return; }
+
+        inner.usage |= wgpu::BufferUsages::COPY_SRC;
+
+        if inner.len == 0 {
+            inner.buffer = rc::Rc::new(create_buffer(device, inner.usage));
+            inner.size = INITIAL_SIZE;
+            inner.generation += 1;
+        }
+    }
+
+    // Recreates the buffer with just enough headroom for the data that was last
+    // set and copies that data across. Returns false if it wouldn't be smaller or
+    // the buffer can't be copied from (see allow_compaction).
+    pub fn compact(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let mut inner = self.inner.borrow_mut();
+
+        let size = size_with_headroom(inner.len);
+        if inner.len == 0 || size >= inner.size { return false; }
+        if !inner.buffer.usage().contains(wgpu::BufferUsages::COPY_SRC) { return false; }
+
+        let descriptor = wgpu::BufferDescriptor { label: None, size: size as u64, usage: inner.usage, mapped_at_creation: false };
+        let buffer = device.create_buffer(&descriptor);
+
+        encoder.copy_buffer_to_buffer(&inner.buffer, 0, &buffer, 0, inner.len as u64);
+
+        inner.buffer = rc::Rc::new(buffer);
+        inner.size = size;
+        inner.generation += 1;
+        inner.underused = None;
+
+        true
+    }
+
+    // The buffer is replaced when it grows so hold onto the Rc while it is used.
+    pub fn buffer(&self) -> rc::Rc<wgpu::Buffer> {
+        rc::Rc::clone(&self.inner.borrow().buffer)
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.borrow().size
+    }
+
     pub fn generation(&self) -> u32 {
         self.inner.borrow().generation
     }
 }
 
+impl InnerB {
+    // Returns the len to shrink to once the buffer has been underused for long
+    // enough. This is the peak len over that time so that the buffer doesn't
+    // shrink and then immediately grow again.
+    fn track_utilization(&mut self, policy: &ShrinkPolicy, frame_index: u64) -> Option<usize> {
+        if (self.len as f32) >= self.size as f32 * policy.utilization || self.size <= HEADROOM {
+            self.underused = None;
+            return None;
+        }
+
+        let (since, peak_len) = self.underused.get_or_insert((frame_index, 0));
+        *peak_len = (*peak_len).max(self.len);
+
+        let shrink_to = *peak_len;
+        let shrinks = frame_index - *since >= policy.frames && size_with_headroom(shrink_to) < self.size;
+
+        if shrinks { Some(shrink_to) } else { None }
+    }
+}
+
 fn create_buffer(device: &wgpu::Device, usage: wgpu::BufferUsages) -> wgpu::Buffer {
     let descriptor = wgpu::BufferDescriptor { label: None, size: INITIAL_SIZE as u64, usage, mapped_at_creation: false };
 
     device.create_buffer(&descriptor)
 }
 
-fn create_buffer_with_headroom(device: &wgpu::Device, usage: wgpu::BufferUsages, bytes: &[u8]) -> (wgpu::Buffer, usize) {
-    let buffer_size = (bytes.len() + HEADROOM).next_power_of_two();
+fn create_buffer_with_headroom(device: &wgpu::Device, usage: wgpu::BufferUsages, bytes: &[u8], min_len: usize) -> (wgpu::Buffer, usize) {
+    let buffer_size = size_with_headroom(bytes.len().max(min_len));
 
     let descriptor = wgpu::BufferDescriptor { label: None, size: buffer_size as u64, usage, mapped_at_creation: true };
     let buffer = device.create_buffer(&descriptor);
@@ -66,10 +157,6 @@ fn create_buffer_with_headroom(device: &wgpu::Device, usage: wgpu::BufferUsages,
     (buffer, buffer_size)
 }
 
-impl ops::Deref for Buffer {
-    type Target = wgpu::Buffer;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.inner.try_borrow_unguarded().unwrap().buffer }
-    }
+fn size_with_headroom(len: usize) -> usize {
+    (len + HEADROOM).next_power_of_two()
 }
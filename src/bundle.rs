@@ -0,0 +1,117 @@
+use std::{cell, rc};
+
+// Bakes the draw calls for a static scene into a wgpu::RenderBundle once so that
+// rendering it each frame costs almost nothing on the CPU. Bind groups are
+// captured at bake time so the bundle must be baked again if any of its buffers
+// or textures are resized or its pipelines change, e.g. a texture is swapped.
+// Bundled pipelines can't be recorded or use MSAA or depth.
+
+pub struct Bundle {
+    pub render_bundle: wgpu::RenderBundle,
+    pub formats: Vec<wgpu::TextureFormat>,
+    pub blend_constant: Option<crate::ClearColor>, // Set on the render pass because bundles can't set it.
+    pub index_buffers: Vec<Option<wgpu::Buffer>>, // One per draw so draws of the same pipeline can have different counts.
+    pub sources: Vec<BundleSource>,
+}
+
+// What each draw was baked with so that changes can be detected.
+pub struct BundleSource {
+    pub program: crate::Program,
+    pub textures: crate::Textures,
+    pub generations: Vec<u32>,
+    pub revision: rc::Rc<cell::Cell<u32>>,
+    pub seen_revision: u32,
+}
+
+pub struct Draw<'a> {
+    pub pipeline: &'a crate::Pipeline,
+    pub count: (u32, u32),
+    pub instance_offset: u32,
+}
+
+impl Bundle {
+    pub fn new(renderer: &crate::Renderer, draws: &[Draw]) -> Self {
+        if draws.is_empty() { panic!("A bundle must contain at least one draw."); }
+
+        let window_size = (renderer.window_size().width, renderer.window_size().height);
+        let formats = target_formats(draws[0].pipeline);
+        let blend_constant = draws.iter().find_map(|d| d.pipeline.inner.borrow().blend_constant);
+
+        for draw in draws {
+            let pipeline = draw.pipeline;
+
+            if target_formats(pipeline) != formats { panic!("The pipelines in a bundle must have targets with the same formats."); }
+            if pipeline.inner.borrow().msaa_samples != 1 { panic!("The pipelines in a bundle can't use MSAA."); }
+            if !pipeline.inner.borrow().recordings.is_empty() { panic!("The pipelines in a bundle can't be recorded."); }
+            if pipeline.inner.borrow().depth.is_some() { panic!("The pipelines in a bundle can't use a depth buffer."); }
+            if pipeline.inner.borrow().multiview.is_some() { panic!("The pipelines in a bundle can't use multiview."); }
+            if pipeline.inner.borrow().transparent_oit { panic!("The pipelines in a bundle can't use order-independent transparency."); }
+
+            let constant = pipeline.inner.borrow().blend_constant.map(|c| c.inner);
+            if constant.is_some() && constant != blend_constant.map(|c| c.inner) { panic!("The pipelines in a bundle must have the same blend constant because it is set once for the render pass."); }
+
+            pipeline.recreate_on_buffer_or_texture_resize(&renderer.device, window_size, &pipeline.targets);
+        }
+
+        let index_buffers = draws.iter().map(|draw| {
+            let indices = draw.pipeline.inner.borrow().primitive.indices(draw.count.1)?;
+            Some((crate::pipeline::create_index_buffer(&renderer.device, &indices), indices.len() as u32))
+        }).collect::<Vec<_>>();
+
+        // Hold onto the pipeline states and buffers until the bundle is finished.
+        let states = draws.iter().map(|d| d.pipeline.inner.borrow()).collect::<Vec<_>>();
+        let buffers = draws.iter().map(|d| d.pipeline.program.vertex_attributes().map(|a| a.buffer.buffer()).collect::<Vec<_>>()).collect::<Vec<_>>();
+
+        let color_formats = formats.iter().map(|f| Some(*f)).collect::<Vec<_>>();
+        let descriptor = wgpu::RenderBundleEncoderDescriptor { label: None, color_formats: &color_formats, depth_stencil: None, sample_count: 1, multiview: None };
+        let mut encoder = renderer.device.create_render_bundle_encoder(&descriptor);
+
+        for (((draw, state), buffers), indices) in draws.iter().zip(&states).zip(&buffers).zip(&index_buffers) {
+            let (instance_count, vertices_per_instance) = draw.count;
+            let instances = draw.instance_offset..draw.instance_offset + instance_count;
+
+            encoder.set_pipeline(&state.pipeline);
+
+            for (i, bind_group) in state.bind_groups.iter().enumerate() {
+                encoder.set_bind_group(i as u32, bind_group, &[]);
+            }
+
+            for (slot, buffer) in buffers.iter().enumerate() {
+                encoder.set_vertex_buffer(slot as u32, buffer.slice(..));
+            }
+
+            if let Some((index_buffer, index_count)) = indices {
+                encoder.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                encoder.draw_indexed(0..*index_count, 0, instances);
+            } else {
+                encoder.draw(0..vertices_per_instance, instances);
+            }
+        }
+
+        let render_bundle = encoder.finish(&wgpu::RenderBundleDescriptor { label: None });
+
+        let sources = draws.iter().zip(&states).map(|(draw, state)| {
+            let program = draw.pipeline.program.clone();
+            let generations = program.latest_generations(&state.textures).collect();
+            let revision = rc::Rc::clone(&draw.pipeline.revision);
+            let seen_revision = revision.get();
+
+            BundleSource { program, textures: state.textures.clone(), generations, revision, seen_revision }
+        }).collect();
+
+        let index_buffers = index_buffers.into_iter().map(|i| i.map(|(buffer, _)| buffer)).collect();
+
+        Self { render_bundle, formats, blend_constant, index_buffers, sources }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.sources.iter().any(|source| {
+            let resized = !source.program.latest_generations(&source.textures).eq(source.generations.iter().copied());
+            resized || source.revision.get() != source.seen_revision
+        })
+    }
+}
+
+fn target_formats(pipeline: &crate::Pipeline) -> Vec<wgpu::TextureFormat> {
+    pipeline.targets.iter().map(|t| t.format().texture_format()).collect()
+}
@@ -0,0 +1,45 @@
+// Keeps positions in f64 on the CPU and uploads them as f32 offsets from the
+// camera. The subtraction happens in f64 so things near the camera keep their
+// precision no matter how far the camera is from the origin. Instance data is
+// interleaved with the position in the first components of each instance.
+
+#[derive(Clone, Debug, Default)]
+pub struct CameraRelative {
+    pub camera_position: Vec<f64>, // 2 or 3 components
+    pub offsets: Vec<f32>,
+}
+
+impl CameraRelative {
+    pub fn new(camera_position: &[f64]) -> Self {
+        Self { camera_position: camera_position.to_vec(), offsets: vec![] }
+    }
+
+    pub fn set_camera_position(&mut self, camera_position: &[f64]) {
+        assert_eq!(camera_position.len(), self.camera_position.len(), "The camera position must have the same number of components.");
+
+        self.camera_position.copy_from_slice(camera_position);
+    }
+
+    // Converts data with stride f64s per instance and returns the f32s to upload.
+    // The remaining components of each instance are converted as they are.
+
+    pub fn offsets(&mut self, data: &[f64], stride: usize) -> &[f32] {
+        let dimensions = self.camera_position.len();
+
+        assert!(stride >= dimensions, "The stride must be at least {} to fit the position.", dimensions);
+        assert_eq!(data.len() % stride, 0, "The data length must be a multiple of the stride.");
+
+        self.offsets.clear();
+        self.offsets.reserve(data.len());
+
+        for instance in data.chunks(stride) {
+            for (component, camera) in instance[..dimensions].iter().zip(&self.camera_position) {
+                self.offsets.push((component - camera) as f32);
+            }
+
+            self.offsets.extend(instance[dimensions..].iter().map(|&c| c as f32));
+        }
+
+        &self.offsets
+    }
+}
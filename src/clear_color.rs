@@ -1,6 +1,10 @@
+// Depth and stencil are cleared by the render that uses this if they're set,
+// otherwise the depth buffer is cleared the first time it's used each frame.
 #[derive(Clone, Copy, Debug)]
 pub struct ClearColor {
     pub inner: wgpu::Color,
+    pub depth: Option<f32>,
+    pub stencil: Option<u32>,
 }
 
 impl ClearColor {
@@ -12,6 +16,18 @@ impl ClearColor {
             a: alpha as f64,
         };
 
-        Self { inner }
+        Self { inner, depth: None, stencil: None }
+    }
+
+    pub fn with_depth(self, depth: f32) -> Self {
+        Self { depth: Some(depth), ..self }
+    }
+
+    pub fn with_stencil(self, stencil: u32) -> Self {
+        Self { stencil: Some(stencil), ..self }
+    }
+
+    pub fn without_depth_stencil(self) -> Self {
+        Self { depth: None, stencil: None, ..self }
     }
 }
@@ -0,0 +1,68 @@
+// Interpolates a ClearColor between keyframes over time, e.g. for loading screens
+// or background transitions. Keyframes are (time in seconds, color) and must be
+// in order of time. The first and last colors are held before and after the
+// keyframes unless the animation loops, in which case time wraps at the last
+// keyframe. Depth and stencil are taken from the keyframe being eased from.
+//
+//   let animation = renderer::ClearColorAnimation::new(vec![(0., black), (2., blue)], renderer::Easing::EaseInOut, false);
+//   renderer.render(&pipeline, Some(animation.current(&renderer)), None, (1, 3));
+
+#[derive(Clone, Debug)]
+pub struct ClearColorAnimation {
+    pub keyframes: Vec<(f32, crate::ClearColor)>,
+    pub easing: Easing,
+    pub looping: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl ClearColorAnimation {
+    pub fn new(keyframes: Vec<(f32, crate::ClearColor)>, easing: Easing, looping: bool) -> Self {
+        if keyframes.is_empty() { panic!("The animation needs at least one keyframe."); }
+        if keyframes.windows(2).any(|w| w[0].0 > w[1].0) { panic!("The keyframes must be in order of time."); }
+
+        Self { keyframes, easing, looping }
+    }
+
+    // Uses the time since the renderer was created, like the builtin uniform.
+    pub fn current(&self, renderer: &crate::Renderer) -> crate::ClearColor {
+        self.at(renderer.elapsed_time())
+    }
+
+    pub fn at(&self, time: f32) -> crate::ClearColor {
+        let (first, last) = (self.keyframes[0], self.keyframes[self.keyframes.len() - 1]);
+        let duration = last.0 - first.0;
+
+        let time = if self.looping && duration > 0. { first.0 + (time - first.0).rem_euclid(duration) } else { time };
+
+        if time <= first.0 { return first.1; }
+        if time >= last.0 { return last.1; }
+
+        let i = self.keyframes.iter().rposition(|(t, _)| *t <= time).unwrap();
+        let ((from_time, from), (to_time, to)) = (self.keyframes[i], self.keyframes[i + 1]);
+
+        let t = self.easing.apply(((time - from_time) / (to_time - from_time)) as f64);
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+
+        let inner = wgpu::Color { r: lerp(from.inner.r, to.inner.r), g: lerp(from.inner.g, to.inner.g), b: lerp(from.inner.b, to.inner.b), a: lerp(from.inner.a, to.inner.a) };
+        crate::ClearColor { inner, ..from }
+    }
+}
+
+impl Easing {
+    pub fn apply(&self, t: f64) -> f64 {
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => 1. - (1. - t) * (1. - t),
+            Self::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Colors are stored in linear space and are converted when they're passed to the
+// GPU. The renderer's 8-bit targets (including the screen) store values without
+// conversion and are displayed as sRGB so ClearColor and the built-in pipelines
+// receive sRGB-encoded values. Float targets usually hold linear values so use
+// to_linear for those instead. Mixing the two up causes washed-out or dark colors.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32,
+}
+
+impl Color {
+    pub fn new(red: f32, green: f32, blue: f32, alpha: f32) -> Self {
+        Self { red, green, blue, alpha }
+    }
+
+    // Alpha is always linear.
+    pub fn from_srgb(red: f32, green: f32, blue: f32, alpha: f32) -> Self {
+        Self::new(srgb_to_linear(red), srgb_to_linear(green), srgb_to_linear(blue), alpha)
+    }
+
+    pub fn from_srgb_u8(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        let [r, g, b, a] = [red, green, blue, alpha].map(|c| c as f32 / 255.);
+        Self::from_srgb(r, g, b, a)
+    }
+
+    // For literals. Use try_from_srgb_hex for user input.
+    pub fn from_srgb_hex(hex: &str) -> Self {
+        Self::try_from_srgb_hex(hex).unwrap_or_else(|| panic!("Invalid hex color: {}", hex))
+    }
+
+    // Accepts #rgb, #rgba, #rrggbb and #rrggbbaa like CSS. The # is optional.
+    pub fn try_from_srgb_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) { return None; }
+        let parse = |s: &str| u8::from_str_radix(s, 16).unwrap();
+
+        let channels = match digits.len() {
+            3 | 4 => digits.chars().map(|c| parse(&c.to_string()) * 17).collect::<Vec<_>>(),
+            6 | 8 => (0..digits.len()).step_by(2).map(|i| parse(&digits[i..i + 2])).collect(),
+            _ => return None,
+        };
+
+        Some(Self::from_srgb_u8(channels[0], channels[1], channels[2], *channels.get(3).unwrap_or(&255)))
+    }
+
+    pub fn to_linear(&self) -> [f32; 4] {
+        [self.red, self.green, self.blue, self.alpha]
+    }
+
+    pub fn to_srgb(&self) -> [f32; 4] {
+        [linear_to_srgb(self.red), linear_to_srgb(self.green), linear_to_srgb(self.blue), self.alpha]
+    }
+
+    // Multiplies the color channels by alpha for Renderer::pre_multiplied_blend.
+    pub fn pre_multiplied(&self) -> Self {
+        Self::new(self.red * self.alpha, self.green * self.alpha, self.blue * self.alpha, self.alpha)
+    }
+
+    pub fn with_alpha(&self, alpha: f32) -> Self {
+        Self { alpha, ..*self }
+    }
+}
+
+impl From<Color> for crate::ClearColor {
+    fn from(color: Color) -> Self {
+        let [r, g, b, a] = color.to_srgb();
+        Self::new(r, g, b, a)
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1. / 2.4) - 0.055 }
+}
@@ -15,6 +15,8 @@ impl Compiler {
                 Self::compile_shader(name, ShaderKind::Vertex);
             } else if name.ends_with(".frag") {
                 Self::compile_shader(name, ShaderKind::Fragment);
+            } else if name.ends_with(".comp") {
+                Self::compile_shader(name, ShaderKind::Compute);
             }
         }
     }
@@ -1,5 +1,5 @@
-use std::{mem, thread, time, io::{Write, BufWriter}, cell::RefCell, sync::atomic::Ordering};
-use std::{path::Path, fs};
+use std::{mem, thread, time, io::{self, Write, BufWriter}, cell::RefCell, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}};
+use std::{collections::BTreeMap, path::Path, fs};
 use chrono::{DateTime, SecondsFormat, Utc};
 use crossbeam_channel::{Sender, Receiver};
 use lzzzz::lz4f;
@@ -7,42 +7,148 @@ use lzzzz::lz4f;
 pub struct Compressor {
     pub timestamp: String,
     pub threads: Vec<thread::JoinHandle<()>>,
+    pub bytes_per_thread: Vec<Arc<AtomicUsize>>,
+    pub bytes_to_sink: Arc<AtomicUsize>,
     pub sender: Option<Sender<crate::VideoFrame>>,
     pub stats: Option<RefCell<Stats>>,
 }
 
+// Where each thread's .sz and .szi files are written (see create_part). The
+// DirectorySink writes them to disk and the MemorySink keeps them in memory but
+// a sink could also send them over the network or upload them to object storage.
+// Writers are dropped when their part is rotated or the Compressor finishes. An
+// error creating the first parts is returned from Compressor::new_with_sink but
+// an error creating a rotated part panics the thread that was writing it.
+pub trait CompressorSink: Send + Sync {
+    fn create(&self, filename: &str) -> io::Result<Box<dyn Write + Send>>;
+    fn description(&self) -> String; // Shown in the stats.
+}
+
+pub struct DirectorySink {
+    pub directory: String,
+}
+
+// Files are keyed by filename and appended to as their buffers are flushed. They
+// are complete once Compressor::finish has returned.
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    pub files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+struct MemoryFile {
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    filename: String,
+}
+
+// Sinks needn't be seekable so the position in the part is counted for the index
+// and the bytes written to the sink are counted for the stats.
+struct CountingWriter {
+    writer: Box<dyn Write + Send>,
+    position: u64,
+    bytes_to_sink: Arc<AtomicUsize>,
+}
+
+// The defaults don't limit the queue, compress as fast as possible, use a
+// thread per core, don't report stats and never rotate files.
+#[derive(Default)]
+pub struct CompressorOptions {
+    pub max_frames_queued: Option<usize>,
+    pub lz4_compression_level: u8,
+    pub stats_sink: StatsSink,
+    pub num_threads: Option<usize>,
+    pub thread_hints: ThreadHints,
+    pub rotation: Rotation,
+}
+
+// Stats are reported every 60 frames. Print clears the terminal and prints them
+// whereas Callback passes them to a function, e.g. to show them in a GUI.
+#[derive(Default)]
+pub enum StatsSink {
+    #[default]
+    None,
+    Print,
+    Callback(Box<dyn FnMut(&CompressorStats) + Send>),
+}
+
+// Hints for the compression worker threads so they don't starve the render
+// thread. Threads are pinned to core_ids round robin (if any are given) and
+// low_priority raises the threads' nice value (currently Linux only).
+#[derive(Clone, Debug, Default)]
+pub struct ThreadHints {
+    pub low_priority: bool,
+    pub core_ids: Vec<usize>,
+}
+
+// Closes each thread's .sz file and starts a new part when it reaches a size
+// or age so that long captures can be uploaded or transferred piecemeal. Parts
+// are named {timestamp}--{thread}--{part}.sz and the Decompressor reads them in
+// order. Files are only rotated between LZ4 frames (every PACKETS_PER_SEGMENT).
+#[derive(Clone, Debug, Default)]
+pub struct Rotation {
+    pub rotate_every_bytes: Option<u64>,
+    pub rotate_every_seconds: Option<f32>,
+}
+
 impl Compressor {
-    pub fn new(directory: &str, max_frames_queued: Option<usize>, lz4_compression_level: u8, print_stats: bool) -> Self {
+    pub fn new(directory: &str, options: CompressorOptions) -> Self {
+        Self::create(Arc::new(DirectorySink::new(directory)), options, None).unwrap()
+    }
+
+    pub fn new_with_sink(sink: impl CompressorSink + 'static, options: CompressorOptions) -> io::Result<Self> {
+        Self::create(Arc::new(sink), options, None)
+    }
+
+    // Encrypts the compressed frames with the key. See crate::Cipher.
+    #[cfg(feature="frame_encryption")]
+    pub fn new_with_encryption(directory: &str, options: CompressorOptions, encryption_key: [u8; 32]) -> Self {
+        Self::create(Arc::new(DirectorySink::new(directory)), options, Some(encryption_key)).unwrap()
+    }
+
+    #[cfg(feature="frame_encryption")]
+    pub fn new_with_sink_and_encryption(sink: impl CompressorSink + 'static, options: CompressorOptions, encryption_key: [u8; 32]) -> io::Result<Self> {
+        Self::create(Arc::new(sink), options, Some(encryption_key))
+    }
+
+    fn create(sink: Arc<dyn CompressorSink>, options: CompressorOptions, encryption_key: Option<[u8; 32]>) -> io::Result<Self> {
+        let CompressorOptions { max_frames_queued, lz4_compression_level, stats_sink, num_threads, thread_hints, rotation } = options;
+
         let is_valid_level = lz4_compression_level as i32 <= lz4f::CLEVEL_MAX;
         assert!(is_valid_level, "Please choose a compression level in the range 0..={}", lz4f::CLEVEL_MAX);
 
-        fs::create_dir_all(directory).unwrap();
-
         let timestamp = generate_timestamp();
         let (sender, receiver) = create_channel(max_frames_queued);
 
-        let threads = (0..num_cpus::get()).map(|i| {
-            spawn_thread(&receiver, &directory, &timestamp, i, lz4_compression_level)
-        }).collect();
+        let num_threads = num_threads.unwrap_or_else(num_cpus::get).max(1);
+        let bytes_per_thread = (0..num_threads).map(|_| Arc::new(AtomicUsize::new(0))).collect::<Vec<_>>();
+        let bytes_to_sink = Arc::new(AtomicUsize::new(0));
 
-        let mut stats = None;
-        if print_stats {
-            stats = Some(RefCell::new(Stats::new(directory, lz4_compression_level, max_frames_queued)));
-        }
+        let threads = (0..num_threads).map(|i| {
+            let core_id = if thread_hints.core_ids.is_empty() { None } else { Some(thread_hints.core_ids[i % thread_hints.core_ids.len()]) };
+            spawn_thread(&receiver, &sink, &timestamp, i, lz4_compression_level, core_id, thread_hints.low_priority, &bytes_per_thread[i], &bytes_to_sink, &rotation, encryption_key)
+        }).collect::<io::Result<_>>()?;
 
-        Compressor { timestamp, threads, sender: Some(sender), stats }
+        let stats = match stats_sink {
+            StatsSink::None => None,
+            stats_sink => Some(RefCell::new(Stats::new(&sink.description(), lz4_compression_level, max_frames_queued, stats_sink))),
+        };
+
+        Ok(Compressor { timestamp, threads, bytes_per_thread, bytes_to_sink, sender: Some(sender), stats })
     }
 
     pub fn compress_to_disk(&self, video_frame: crate::VideoFrame) {
         let sender = self.sender.as_ref().unwrap();
 
         if let Some(stats) = self.stats.as_ref() {
-            stats.borrow_mut().update(&video_frame, &self.timestamp, self.threads.len(), sender.len());
+            stats.borrow_mut().update(&video_frame, &self.bytes_per_thread, &self.bytes_to_sink, sender.len());
         }
 
         sender.send(video_frame).unwrap();
     }
 
+    pub fn latest_stats(&self) -> Option<CompressorStats> {
+        self.stats.as_ref().and_then(|s| s.borrow().latest.clone())
+    }
+
     pub fn finish(&mut self) {
         if self.sender.is_none() { return; }
 
@@ -68,7 +174,7 @@ impl Drop for Compressor {
     }
 }
 
-fn generate_timestamp() -> String {
+pub(crate) fn generate_timestamp() -> String {
     Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).replace(":", "_")
 }
 
@@ -85,57 +191,272 @@ fn create_channel(max_frames_queued: Option<usize>) -> (Sender<crate::VideoFrame
     }
 }
 
-fn spawn_thread(receiver: &Receiver<crate::VideoFrame>, directory: &str, timestamp: &str, i: usize, lz4_compression_level: u8) -> thread::JoinHandle<()> {
+fn spawn_thread(receiver: &Receiver<crate::VideoFrame>, sink: &Arc<dyn CompressorSink>, timestamp: &str, i: usize, lz4_compression_level: u8, core_id: Option<usize>, low_priority: bool, bytes_written: &Arc<AtomicUsize>, bytes_to_sink: &Arc<AtomicUsize>, rotation: &Rotation, encryption_key: Option<[u8; 32]>) -> io::Result<thread::JoinHandle<()>> {
     let receiver = receiver.clone();
+    let bytes_written = Arc::clone(bytes_written);
+    let (sink, bytes_to_sink) = (Arc::clone(sink), Arc::clone(bytes_to_sink));
+    let (timestamp, rotation) = (timestamp.to_string(), rotation.clone());
+
+    // Index entries are timed from the session's timestamp by each frame's elapsed_time.
+    let session_start: DateTime<Utc> = DateTime::parse_from_rfc3339(&timestamp.replace("_", ":")).unwrap().into();
 
     let compress_config = compression_config(lz4_compression_level);
     let encode_config = encoding_config();
 
-    let filename = format!("{}--{}.sz", timestamp, i);
-    let path = Path::new(directory).join(filename).into_os_string().into_string().unwrap();
+    let mut part = 0;
+    let (mut file_writer, mut index_writer) = create_part(&*sink, &timestamp, i, part, &bytes_to_sink)?;
+
+    #[cfg(feature="frame_encryption")]
+    let (cipher, mut segment) = (encryption_key.map(|k| crate::Cipher::new(&k)), vec![]);
 
-    let file = fs::File::create(path).unwrap();
-    let mut writer = lz4f::WriteCompressor::new(BufWriter::new(file), compress_config).unwrap();
+    #[cfg(not(feature="frame_encryption"))]
+    let _ = encryption_key;
+
+    Ok(thread::spawn(move || {
+        if let Some(id) = core_id { core_affinity::set_for_current(core_affinity::CoreId { id }); }
+        if low_priority { lower_thread_priority(); }
 
-    thread::spawn(move || {
         // When a video_frame is received from the channel, write it to the
         // compressor in packets of bytes that have this layout:
         //
         // [ packet_len | video_frame_len | video_frame | image_data ]
         //     (u64)           (u64)          (bincode)        (raw)
+        //
+        // Every PACKETS_PER_SEGMENT packets, a new LZ4 frame is started so that
+        // the Decompressor can seek to it. Each LZ4 frame is written to an index
+        // file (.szi) in entries that have this layout:
+        //
+        // [ first_frame_number | byte_offset | decompressed_offset | recorded_at ]
+        //         (u64)             (u64)             (u64)          (i64 millis)
 
-        loop {
-            let mut packet_len: u64 = (U64_LEN + U64_LEN) as u64;
+        let mut decompressed_offset = 0;
+        let mut part_started_at = time::Instant::now();
 
+        loop {
             let video_frame = match receiver.recv() { Ok(f) => f, _ => break };
-            let video_frame_bytes = bincode::encode_to_vec(&video_frame, encode_config).unwrap();
-            let video_frame_len = video_frame_bytes.len() as u64;
-            packet_len += video_frame_len;
 
-            let mut write_image_data_bytes = None;
+            // Rotate before starting a segment so that the last part is never empty.
+            let part_len = file_writer.position;
+            let too_big = rotation.rotate_every_bytes.map(|b| part_len >= b).unwrap_or(false);
+            let too_long = rotation.rotate_every_seconds.map(|s| part_started_at.elapsed().as_secs_f32() >= s).unwrap_or(false);
+
+            if part_len > FILE_HEADER_LEN && (too_big || too_long) {
+                #[cfg(feature="frame_encryption")]
+                if let Some(cipher) = &cipher { write_end_record(cipher, &mut file_writer, &timestamp, i, part); }
+
+                file_writer.flush().unwrap();
 
-            if let Some(image_data) = &video_frame.image_data {
-                let image_data_bytes = image_data.buffer().slice(..).get_mapped_range();
-                packet_len += image_data_bytes.len() as u64;
+                part += 1;
+                (file_writer, index_writer) = create_part(&*sink, &timestamp, i, part, &bytes_to_sink).expect("The sink failed to create the next part.");
+
+                decompressed_offset = 0;
+                part_started_at = time::Instant::now();
+            }
 
-                write_image_data_bytes = Some(move |w: &mut Writer| {
-                    w.write_all(&image_data_bytes).unwrap()
-                });
+            let byte_offset = file_writer.position;
+            let recorded_at = session_start + chrono::Duration::milliseconds((video_frame.elapsed_time * 1000.) as i64);
+            write_index_entry(&mut index_writer, video_frame.frame_number, byte_offset, decompressed_offset, recorded_at);
+
+            // Encrypted segments are compressed into memory and then written as a record.
+            #[cfg(feature="frame_encryption")]
+            if let Some(cipher) = &cipher {
+                segment.clear();
+                decompressed_offset += compress_segment(&mut segment, video_frame, &receiver, compress_config, encode_config, i, &bytes_written);
+                cipher.write_record(&mut file_writer, &part_filename(&timestamp, i, part), byte_offset, &segment);
+                continue;
             }
 
-            writer.write_all(&packet_len.to_be_bytes()).unwrap();
-            writer.write_all(&video_frame_len.to_be_bytes()).unwrap();
-            writer.write_all(&video_frame_bytes).unwrap();
-            write_image_data_bytes.map(|closure| closure(&mut writer));
+            decompressed_offset += compress_segment(&mut file_writer, video_frame, &receiver, compress_config, encode_config, i, &bytes_written);
         }
-    })
+
+        #[cfg(feature="frame_encryption")]
+        if let Some(cipher) = &cipher { write_end_record(cipher, &mut file_writer, &timestamp, i, part); }
+    }))
+}
+
+// Marks the end of an encrypted part so that the Decompressor can tell if it was truncated.
+#[cfg(feature="frame_encryption")]
+fn write_end_record(cipher: &crate::Cipher, file_writer: &mut CountingWriter, timestamp: &str, thread: usize, part: usize) {
+    let offset = file_writer.position;
+    cipher.write_end(file_writer, &part_filename(timestamp, thread, part), offset);
+}
+
+// Compresses up to PACKETS_PER_SEGMENT frames into a single LZ4 frame. Returns
+// the number of uncompressed bytes that were written.
+fn compress_segment<W: Write>(writer: W, video_frame: crate::VideoFrame, receiver: &Receiver<crate::VideoFrame>, compress_config: lz4f::Preferences, encode_config: bincode::config::Configuration, thread: usize, bytes_written: &AtomicUsize) -> u64 {
+    let mut writer = lz4f::WriteCompressor::new(writer, compress_config).unwrap();
+    let mut len = compress_frame(&mut writer, video_frame, encode_config, thread, bytes_written);
+
+    for _ in 1..PACKETS_PER_SEGMENT {
+        let video_frame = match receiver.recv() { Ok(f) => f, _ => break };
+        len += compress_frame(&mut writer, video_frame, encode_config, thread, bytes_written);
+    }
+
+    len
+}
+
+// The first part is named {timestamp}--{thread}.sz like it was before rotation.
+fn create_part(sink: &dyn CompressorSink, timestamp: &str, thread: usize, part: usize, bytes_to_sink: &Arc<AtomicUsize>) -> io::Result<(CountingWriter, CountingWriter)> {
+    let filename = part_filename(timestamp, thread, part);
+    let index_filename = format!("{}i", filename);
+
+    let mut file_writer = CountingWriter::new(sink.create(&filename)?, bytes_to_sink);
+    write_file_header(&mut file_writer)?;
+
+    let index_writer = CountingWriter::new(sink.create(&index_filename)?, bytes_to_sink);
+
+    Ok((file_writer, index_writer))
+}
+
+fn part_filename(timestamp: &str, thread: usize, part: usize) -> String {
+    if part == 0 { format!("{}--{}.sz", timestamp, thread) } else { format!("{}--{}--{}.sz", timestamp, thread, part) }
+}
+
+// Returns the number of uncompressed bytes that were written.
+#[cfg_attr(not(feature="tracing"), allow(unused_variables))]
+fn compress_frame<W: Write>(writer: &mut W, video_frame: crate::VideoFrame, encode_config: bincode::config::Configuration, thread: usize, bytes_written: &AtomicUsize) -> u64 {
+    span!("compress_frame", thread, frame = video_frame.frame_number);
+
+    let video_frame_bytes = bincode::encode_to_vec(&video_frame, encode_config).unwrap();
+    let planes = video_frame.planes.iter().map(|p| &p.image_data);
+    let mapped_ranges = std::iter::once(&video_frame.image_data).chain(planes).flatten().map(|d| d.buffer().slice(..).get_mapped_range()).collect::<Vec<_>>();
+    let image_data_bytes = mapped_ranges.iter().map(|r| &r[..]).collect::<Vec<_>>();
+
+    let packet_len = write_packet(writer, &video_frame_bytes, &image_data_bytes).unwrap();
+    bytes_written.fetch_add(image_data_bytes.iter().map(|b| b.len()).sum(), Ordering::Relaxed);
+
+    U64_LEN as u64 + packet_len
+}
+
+fn write_index_entry<W: Write>(writer: &mut W, first_frame_number: usize, byte_offset: u64, decompressed_offset: u64, recorded_at: DateTime<Utc>) {
+    writer.write_all(&(first_frame_number as u64).to_be_bytes()).unwrap();
+    writer.write_all(&byte_offset.to_be_bytes()).unwrap();
+    writer.write_all(&decompressed_offset.to_be_bytes()).unwrap();
+    writer.write_all(&recorded_at.timestamp_millis().to_be_bytes()).unwrap();
+
+    // Flush so the index is usable even if the process doesn't exit cleanly.
+    writer.flush().unwrap();
+}
+
+impl DirectorySink {
+    pub fn new(directory: &str) -> Self {
+        fs::create_dir_all(directory).unwrap();
+        Self { directory: directory.to_string() }
+    }
+}
+
+impl CompressorSink for DirectorySink {
+    fn create(&self, filename: &str) -> io::Result<Box<dyn Write + Send>> {
+        let path = Path::new(&self.directory).join(filename);
+        Ok(Box::new(BufWriter::new(fs::File::create(path)?)))
+    }
+
+    fn description(&self) -> String {
+        self.directory.clone()
+    }
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Removes the files written so far, e.g. to upload them after finishing.
+    pub fn take(&self) -> BTreeMap<String, Vec<u8>> {
+        mem::take(&mut *self.files.lock().unwrap())
+    }
+}
+
+impl CompressorSink for MemorySink {
+    fn create(&self, filename: &str) -> io::Result<Box<dyn Write + Send>> {
+        self.files.lock().unwrap().insert(filename.to_string(), vec![]);
+        Ok(Box::new(BufWriter::new(MemoryFile { files: Arc::clone(&self.files), filename: filename.to_string() })))
+    }
+
+    fn description(&self) -> String {
+        "(in memory)".to_string()
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.files.lock().unwrap().entry(self.filename.clone()).or_default().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CountingWriter {
+    fn new(writer: Box<dyn Write + Send>, bytes_to_sink: &Arc<AtomicUsize>) -> Self {
+        Self { writer, position: 0, bytes_to_sink: Arc::clone(bytes_to_sink) }
+    }
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.writer.write(buf)?;
+
+        self.position += len as u64;
+        self.bytes_to_sink.fetch_add(len, Ordering::Relaxed);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(target_os="linux")]
+fn lower_thread_priority() {
+    // On Linux, the nice value is per-thread rather than per-process.
+    unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10); }
+}
+
+#[cfg(not(target_os="linux"))]
+fn lower_thread_priority() {}
+
+// Each .sz file starts with a header so that the Decompressor knows which
+// version of the packet format it was written with. It isn't compressed or
+// encrypted. Files written before the header was added are version 0.
+//
+// [ magic | version ]
+//   (8)     (u64)
+//
+// Bump FILE_VERSION whenever VideoFrame's fields change and add a migration from
+// the previous version to decompressor::decode_video_frame.
+
+pub(crate) fn write_file_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&FILE_MAGIC)?;
+    writer.write_all(&FILE_VERSION.to_be_bytes())
+}
+
+// The image_data is followed by each plane's data, if there is any.
+pub(crate) fn write_packet<W: Write>(writer: &mut W, video_frame_bytes: &[u8], image_data_bytes: &[&[u8]]) -> io::Result<u64> {
+    let video_frame_len = video_frame_bytes.len() as u64;
+    let image_data_len = image_data_bytes.iter().map(|b| b.len()).sum::<usize>() as u64;
+    let packet_len = (U64_LEN + U64_LEN) as u64 + video_frame_len + image_data_len;
+
+    writer.write_all(&packet_len.to_be_bytes())?;
+    writer.write_all(&video_frame_len.to_be_bytes())?;
+    writer.write_all(video_frame_bytes)?;
+
+    for bytes in image_data_bytes { writer.write_all(bytes)?; }
+
+    Ok(packet_len)
 }
 
 const U64_LEN: usize = mem::size_of::<u64>();
+pub(crate) const PACKETS_PER_SEGMENT: usize = 30;
 
-type Writer = lz4f::WriteCompressor::<BufWriter<fs::File>>;
+pub(crate) const FILE_MAGIC: [u8; 8] = *b"RENDERSZ";
+pub(crate) const FILE_VERSION: u64 = 3;
+pub(crate) const FILE_HEADER_LEN: u64 = (FILE_MAGIC.len() + U64_LEN) as u64;
 
-fn compression_config(lz4_compression_level: u8) -> lz4f::Preferences {
+pub(crate) fn compression_config(lz4_compression_level: u8) -> lz4f::Preferences {
     lz4f::PreferencesBuilder::new()
         .compression_level(lz4_compression_level as i32)
         .favor_dec_speed(lz4f::FavorDecSpeed::Disabled)
@@ -143,7 +464,7 @@ fn compression_config(lz4_compression_level: u8) -> lz4f::Preferences {
         .build()
 }
 
-fn encoding_config() -> bincode::config::Configuration {
+pub(crate) fn encoding_config() -> bincode::config::Configuration {
     bincode::config::standard()
 }
 
@@ -155,23 +476,50 @@ pub struct Stats {
     pub started_at: Option<DateTime<Utc>>,
     pub frames_captured: usize,
     pub frames_dropped: usize,
+    pub frames_skipped: usize,
     pub has_resized: bool,
     pub prev_width: usize,
     pub prev_height: usize,
     pub raw_video_size: usize,
+    pub sink: Option<StatsSink>,
+    pub latest: Option<CompressorStats>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressorStats {
+    pub directory: String, // Or the sink's description.
+    pub started_at: DateTime<Utc>,
+    pub elapsed: time::Duration,
+    pub frames_captured: usize,
+    pub frames_dropped: usize,
+    pub frames_skipped: usize,
+    pub average_frame_rate: f32,
+    pub average_frame_size_in_bytes: f32,
+    pub current_resolution: (usize, usize),
+    pub has_resized: bool,
+    pub raw_video_size_in_bytes: usize,
+    pub size_on_disk_in_bytes: usize, // Or written to the sink.
+    pub compression_ratio: f32,
+    pub average_write_speed_in_bytes_per_second: f32,
+    pub queue_size: usize,
+    pub max_frames_queued: Option<usize>,
+    pub bytes_per_second_per_thread: Vec<f32>,
+    pub lz4_compression_level: u8,
+    pub gpu_buffer_size_in_bytes: usize,
 }
 
 impl Stats {
-    fn new(directory: &str, lz4_compression_level: u8, max_frames_queued: Option<usize>) -> Self {
+    fn new(directory: &str, lz4_compression_level: u8, max_frames_queued: Option<usize>, sink: StatsSink) -> Self {
         Self {
             directory: directory.to_string(),
             lz4_compression_level,
             max_frames_queued,
+            sink: Some(sink),
             ..Self::default()
         }
     }
 
-    fn update(&mut self, video_frame: &crate::VideoFrame, filename_timestamp: &str, num_threads: usize, queue_size: usize) {
+    fn update(&mut self, video_frame: &crate::VideoFrame, bytes_per_thread: &[Arc<AtomicUsize>], bytes_to_sink: &AtomicUsize, queue_size: usize) {
         match &video_frame.status {
             crate::FrameStatus::Captured => {
                 self.frames_captured += 1;
@@ -190,58 +538,95 @@ impl Stats {
             crate::FrameStatus::Dropped => {
                 self.frames_dropped += 1;
             },
+            crate::FrameStatus::Skipped => {
+                self.frames_skipped += 1;
+            },
             _ => unreachable!(),
         }
 
         if video_frame.frame_number % 60 != 0 { return; }
 
-        let started_at = *self.started_at.as_ref().unwrap();
-        let elapsed = (Utc::now() - started_at).to_std().unwrap().as_secs();
+        let started_at = match self.started_at { Some(t) => t, _ => return };
+        let elapsed = (Utc::now() - started_at).to_std().unwrap();
+        let elapsed_secs = elapsed.as_secs() as f32;
+
+        let size_on_disk = bytes_to_sink.load(Ordering::Relaxed);
+
+        let stats = CompressorStats {
+            directory: self.directory.clone(),
+            started_at,
+            elapsed,
+            frames_captured: self.frames_captured,
+            frames_dropped: self.frames_dropped,
+            frames_skipped: self.frames_skipped,
+            average_frame_rate: video_frame.frame_number as f32 / elapsed_secs,
+            average_frame_size_in_bytes: self.raw_video_size as f32 / self.frames_captured as f32,
+            current_resolution: (video_frame.width, video_frame.height),
+            has_resized: self.has_resized,
+            raw_video_size_in_bytes: self.raw_video_size,
+            size_on_disk_in_bytes: size_on_disk,
+            compression_ratio: self.raw_video_size as f32 / size_on_disk as f32,
+            average_write_speed_in_bytes_per_second: size_on_disk as f32 / elapsed_secs,
+            queue_size,
+            max_frames_queued: self.max_frames_queued,
+            bytes_per_second_per_thread: bytes_per_thread.iter().map(|b| b.load(Ordering::Relaxed) as f32 / elapsed_secs).collect(),
+            lz4_compression_level: self.lz4_compression_level,
+            gpu_buffer_size_in_bytes: video_frame.buffer_size_in_bytes.load(Ordering::Relaxed),
+        };
+
+        match self.sink.as_mut() {
+            Some(StatsSink::Print) => stats.print(),
+            Some(StatsSink::Callback(f)) => f(&stats),
+            _ => {},
+        }
+
+        self.latest = Some(stats);
+    }
+}
+
+impl CompressorStats {
+    pub fn print(&self) {
+        let elapsed = self.elapsed.as_secs();
 
         print!("{esc}c", esc = 27 as char); // Clear terminal.
 
         println!("Capturing frames to disk...");
         println!("Directory: {}", self.directory);
         println!();
-        println!("Started at: {}", started_at.to_rfc3339_opts(SecondsFormat::Millis, true));
+        println!("Started at: {}", self.started_at.to_rfc3339_opts(SecondsFormat::Millis, true));
         println!("Duration: {:02}:{:02}:{:02}", elapsed / 3600, (elapsed % 3600) / 60, elapsed % 60);
         println!();
         println!("Frames captured: {}", self.frames_captured);
         println!("Frames dropped: {}", self.frames_dropped);
+        println!("Frames skipped: {}", self.frames_skipped);
         println!();
-        println!("Average frame rate: {:.1} Hz", video_frame.frame_number as f32 / elapsed as f32);
-        println!("Average frame size: {:.2} MB", self.raw_video_size as f32 / self.frames_captured as f32 / 1000. / 1000.);
+        println!("Average frame rate: {:.1} Hz", self.average_frame_rate);
+        println!("Average frame size: {:.2} MB", self.average_frame_size_in_bytes / 1000. / 1000.);
         println!();
-        println!("Current resolution: {}x{}", video_frame.width, video_frame.height);
+        println!("Current resolution: {}x{}", self.current_resolution.0, self.current_resolution.1);
         println!("Viewport has resized: {}", if self.has_resized { "Yes" } else { "No" });
         println!();
-        println!("Raw video size: {:.1} GB", self.raw_video_size as f32 / 1000. / 1000. / 1000.);
-
-        let mut size_on_disk = 0;
-        for result in fs::read_dir(&self.directory).unwrap() {
-            let dir_entry = match result { Ok(d) => d, _ => continue };
-            let filename = match dir_entry.file_name().into_string() { Ok(s) => s, _ => continue };
-            if !filename.contains(filename_timestamp) { continue; }
-
-            let num_bytes = dir_entry.metadata().unwrap().len();
-            size_on_disk += num_bytes;
-        }
-
-        println!("Compressed size on disk: {:.1} GB", size_on_disk as f32 / 1000. / 1000. / 1000.);
+        println!("Raw video size: {:.1} GB", self.raw_video_size_in_bytes as f32 / 1000. / 1000. / 1000.);
+        println!("Compressed size on disk: {:.1} GB", self.size_on_disk_in_bytes as f32 / 1000. / 1000. / 1000.);
         println!();
-        println!("Compression ratio: {:.1}x", self.raw_video_size as f32 / size_on_disk as f32);
-        println!("Average write speed to disk: {:.1} MB/s", size_on_disk as f32 / elapsed as f32 / 1000. / 1000.);
+        println!("Compression ratio: {:.1}x", self.compression_ratio);
+        println!("Average write speed to disk: {:.1} MB/s", self.average_write_speed_in_bytes_per_second / 1000. / 1000.);
         println!();
 
         if let Some(queue_limit) = self.max_frames_queued.as_ref() {
-            println!("Compression queue size: {} (limit={})", queue_size, queue_limit);
+            println!("Compression queue size: {} (limit={})", self.queue_size, queue_limit);
         } else {
-            println!("Compression queue size: {} (no limit)", queue_size);
+            println!("Compression queue size: {} (no limit)", self.queue_size);
+        }
+
+        println!("Compression worker threads: {}", self.bytes_per_second_per_thread.len());
+
+        for (i, bytes_per_second) in self.bytes_per_second_per_thread.iter().enumerate() {
+            println!("  Thread {}: {:.1} MB/s", i, bytes_per_second / 1000. / 1000.);
         }
 
-        println!("Compression worker threads: {}", num_threads);
         println!();
         println!("LZ4 compression level: {}", self.lz4_compression_level);
-        println!("GPU memory buffer size: {:.1} MB", video_frame.buffer_size_in_bytes.load(Ordering::Relaxed) as f32 / 1000. / 1000.);
+        println!("GPU memory buffer size: {:.1} MB", self.gpu_buffer_size_in_bytes as f32 / 1000. / 1000.);
     }
 }
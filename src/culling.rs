@@ -0,0 +1,85 @@
+// Skips instances that are off screen before they're uploaded so that large
+// scenes don't pay to upload or draw them. Instance data is laid out like for
+// CameraRelative, with the position in the first components of each instance.
+// The visible instances are packed together so that their count can be passed
+// straight to render. Instances are tested as bounding circles/spheres.
+
+#[derive(Clone, Debug, Default)]
+pub struct Culling {
+    pub planes: Vec<[f32; 4]>, // (normal, distance) with unit normals pointing inwards.
+    pub dimensions: usize,     // 2 or 3 position components.
+    pub visible: Vec<f32>,
+}
+
+impl Culling {
+    // Culls 3D instances against the clip volume of a column-major view-projection
+    // matrix, i.e. whatever the vertex shader projects to the screen with it.
+    pub fn frustum(view_projection: &[f32; 16]) -> Self {
+        Self { planes: frustum_planes(view_projection), dimensions: 3, visible: vec![] }
+    }
+
+    // Culls 2D instances against a rectangle in the same space as their positions,
+    // e.g. the part of the world that the camera shows in the viewport.
+    pub fn rect(min: (f32, f32), max: (f32, f32)) -> Self {
+        Self { planes: rect_planes(min, max), dimensions: 2, visible: vec![] }
+    }
+
+    pub fn set_frustum(&mut self, view_projection: &[f32; 16]) {
+        self.planes = frustum_planes(view_projection);
+        self.dimensions = 3;
+    }
+
+    pub fn set_rect(&mut self, min: (f32, f32), max: (f32, f32)) {
+        self.planes = rect_planes(min, max);
+        self.dimensions = 2;
+    }
+
+    // Returns the data of the visible instances and how many there are. Each
+    // instance's bounding radius (or half its size) is the component at
+    // radius_index. If there isn't one, instances are culled as points.
+
+    pub fn cull(&mut self, data: &[f32], stride: usize, radius_index: Option<usize>) -> (&[f32], u32) {
+        let dimensions = self.dimensions;
+
+        assert!(stride >= dimensions, "The stride must be at least {} to fit the position.", dimensions);
+        assert_eq!(data.len() % stride, 0, "The data length must be a multiple of the stride.");
+        if let Some(i) = radius_index { assert!(i < stride, "The radius index must be less than the stride."); }
+
+        self.visible.clear();
+        let mut count = 0;
+
+        for instance in data.chunks(stride) {
+            let radius = radius_index.map(|i| instance[i].abs()).unwrap_or(0.);
+            let inside = self.planes.iter().all(|plane| {
+                let distance = instance[..dimensions].iter().zip(plane).map(|(p, n)| p * n).sum::<f32>() + plane[3];
+                distance >= -radius
+            });
+
+            if inside {
+                self.visible.extend_from_slice(instance);
+                count += 1;
+            }
+        }
+
+        (&self.visible, count)
+    }
+}
+
+// Gribb and Hartmann's method. The near plane is at z = 0 because that's where
+// wgpu's clip volume starts, unlike OpenGL's which starts at z = -w.
+fn frustum_planes(m: &[f32; 16]) -> Vec<[f32; 4]> {
+    let row = |i: usize| [m[i], m[4 + i], m[8 + i], m[12 + i]];
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+    let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+    let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+    [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), r2, sub(r3, r2)].into_iter().map(|p| {
+        let length = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt().max(f32::EPSILON);
+        [p[0] / length, p[1] / length, p[2] / length, p[3] / length]
+    }).collect()
+}
+
+fn rect_planes(min: (f32, f32), max: (f32, f32)) -> Vec<[f32; 4]> {
+    vec![[1., 0., 0., -min.0], [-1., 0., 0., max.0], [0., 1., 0., -min.1], [0., -1., 0., max.1]]
+}
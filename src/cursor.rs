@@ -0,0 +1,95 @@
+// Draws a cursor sprite at the mouse position as a final overlay so it looks the
+// same on every platform and can be included in recordings (pass its pipeline to
+// start_recording). The app feeds it the mouse position in physical pixels, e.g.
+// from winit's CursorMoved event. The hotspot is the texel that points, e.g. the
+// tip of an arrow. While the platform's hardware cursor is in use (e.g. outside
+// of a game's UI), set hardware_cursor to true to hide the software cursor.
+
+pub struct Cursor {
+    pub pipeline: crate::Pipeline,
+    pub texture_size: (f32, f32),
+    pub hotspot: (f32, f32),
+    pub scale: f32,
+    pub position: Option<(f32, f32)>, // None when the mouse is outside the window.
+    pub hardware_cursor: bool,
+}
+
+impl Cursor {
+    // The texture must have a sampler. Use nearest filtering for crisp pixel art.
+    pub fn new(renderer: &crate::Renderer, texture: &crate::Texture, hotspot: (f32, f32), blend_mode: crate::BlendMode, targets: Vec<crate::Target>) -> Self {
+        if texture.sampler.is_none() { panic!("The texture for a cursor must have a sampler."); }
+        if targets.len() != 1 { panic!("The cursor must render to a single target."); }
+
+        let (width, height, _) = texture.size();
+
+        let attributes = vec![renderer.attribute(crate::Material::A_POSITION, 2), renderer.attribute(crate::Material::A_TEX_COORD, 2)];
+        let textures = vec![(texture.clone(), crate::Visibility::FragmentShader)];
+
+        let program = renderer.program_wgsl(crate::Material::Textured.vertex_shader(), &fragment_shader(), attributes, vec![], vec![], textures);
+        let pipeline = renderer.pipeline(program, blend_mode, crate::Primitive::TriangleStrip, 1, targets);
+
+        renderer.set_attribute(&pipeline, crate::Material::A_TEX_COORD, &[0., 0., 0., 1., 1., 0., 1., 1.]);
+
+        Self { pipeline, texture_size: (width as f32, height as f32), hotspot, scale: 1., position: None, hardware_cursor: false }
+    }
+
+    pub fn set_position(&mut self, position: Option<(f32, f32)>) {
+        self.position = position;
+    }
+
+    // Scales the sprite, e.g. by the window's scale factor.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn set_hardware_cursor(&mut self, hardware_cursor: bool) {
+        self.hardware_cursor = hardware_cursor;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.position.is_some() && !self.hardware_cursor
+    }
+
+    // Call this after everything else has rendered to the targets. It doesn't use
+    // a viewport because the mouse position is relative to the whole window.
+    pub fn render(&self, renderer: &crate::Renderer) {
+        let (x, y) = match self.position { Some(p) if !self.hardware_cursor => p, _ => return };
+        let window_size = renderer.window_size();
+
+        let (width, height) = (self.texture_size.0 * self.scale, self.texture_size.1 * self.scale);
+        let (left, top) = (x - self.hotspot.0 * self.scale, y - self.hotspot.1 * self.scale);
+
+        let xs = [left, left + width].map(|x| x / window_size.width as f32 * 2. - 1.);
+        let ys = [top, top + height].map(|y| 1. - y / window_size.height as f32 * 2.);
+
+        renderer.set_attribute(&self.pipeline, crate::Material::A_POSITION, &[xs[0], ys[0], xs[0], ys[1], xs[1], ys[0], xs[1], ys[1]]);
+        renderer.render(&self.pipeline, None, None, (1, 4));
+    }
+}
+
+// Like the textured material but it writes the color to every output that a
+// pipeline can have so that each recording (and its planes) gets the cursor
+// too. Outputs without a color target are ignored.
+fn fragment_shader() -> String {
+    let locations = 0..MAX_COLOR_TARGETS;
+
+    let fields = locations.clone().map(|i| format!("    @location({}) output_{}: vec4<f32>,", i, i)).collect::<Vec<_>>().join("\n");
+    let values = locations.map(|_| "color").collect::<Vec<_>>().join(", ");
+
+    format!("
+@group(0) @binding(0) var t_texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+struct FragmentOutput {{
+{}
+}}
+
+@fragment
+fn main(@location(0) tex_coord: vec2<f32>) -> FragmentOutput {{
+    let color = textureSample(t_texture, texture_sampler, tex_coord);
+    return FragmentOutput({});
+}}
+", fields, values)
+}
+
+const MAX_COLOR_TARGETS: usize = 8; // wgpu's default max_color_attachments.
@@ -1,76 +1,258 @@
-use std::{mem, fs, path::Path, thread, cmp, ops, io::{Read, BufReader}};
+use std::{mem, fs, path::{Path, PathBuf}, thread, cmp, ops, cell::RefCell, io::{self, Read, BufRead, BufReader, Seek, SeekFrom}};
 use std::collections::{BinaryHeap, BTreeMap};
-use std::sync::{Arc, atomic::AtomicUsize};
+use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
 use chrono::{DateTime, Utc};
 use crossbeam_channel::Receiver;
 use lzzzz::lz4f::BufReadDecompressor;
 
 pub struct Decompressor {
-    pub directory: String,
+    pub source: Arc<dyn DecompressorSource>,
     pub remove_files_after_decompression: bool,
+    pub progress_function: RefCell<Option<ProgressFunction>>,
+    pub cancel_token: CancelToken,
+    pub memory_map_read_ahead: Option<usize>,
+    pub encryption_key: Option<[u8; 32]>,
+}
+
+// Where the .sz and .szi files written by the Compressor are read from. The
+// DirectorySource reads them from disk and the MemorySource reads the files kept
+// by a MemorySink, but a source could also read them from a zip archive or the
+// network. Sources that can't seek can read and discard bytes up to byte_offset.
+pub trait DecompressorSource: Send + Sync {
+    fn filenames(&self) -> Vec<String>; // Without empty files.
+    fn open(&self, filename: &str, byte_offset: u64) -> Option<Box<dyn BufRead + Send>>;
+
+    // Called for each file if remove_files_after_decompression is set.
+    fn remove(&self, _filename: &str) {}
+
+    // Sources backed by files return their path so that they can be memory-mapped.
+    fn path(&self, _filename: &str) -> Option<PathBuf> { None }
+}
+
+pub struct DirectorySource {
+    pub directory: String,
 }
 
+pub struct MemorySource {
+    pub files: BTreeMap<String, Arc<[u8]>>,
+}
+
+// The total is estimated from the index files so it might be slightly off. It is
+// never less than frames_processed and is 0 for recordings without an index.
+#[derive(Clone, Copy, Debug)]
+pub struct DecompressProgress {
+    pub frames_processed: usize,
+    pub estimated_total_frames: usize,
+}
+
+// Cancels decompression between frames. It can be cloned and sent to another
+// thread, e.g. so that a GUI's cancel button can stop an export.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(pub Arc<AtomicBool>);
+
+// Delta encoded frames are sent without a T because they can only be
+// reconstructed in order so the per_thread_function is called on the main thread.
 struct Worker<T> {
     pub thread: thread::JoinHandle<()>,
-    pub receiver: Receiver<(crate::VideoFrame, T)>,
+    pub receiver: Receiver<(crate::VideoFrame, Option<T>)>,
+}
+
+// An entry in the index files written by the Compressor. There is one for each
+// LZ4 frame in a .sz file, which contains frames from first_frame_number until
+// the next entry for the same file.
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub filename: String,
+    pub first_frame_number: usize,
+    pub byte_offset: u64,
+    pub decompressed_offset: u64,
+    pub recorded_at: DateTime<Utc>, // The session timestamp plus the elapsed_time of the first frame.
 }
 
 pub type PerThreadFunction<T> = Arc<dyn Fn(&crate::VideoFrame, DateTime<Utc>) -> T + Send + Sync>;
 pub type InOrderFunction<T> = Box<dyn FnMut(crate::VideoFrame, Result<T, &'static str>, &DateTime<Utc>)>;
+pub type ProgressFunction = Box<dyn FnMut(DecompressProgress)>;
 
 impl Decompressor {
     pub fn new(directory: &str, remove_files_after_decompression: bool) -> Self {
-        Self { directory: directory.to_string(), remove_files_after_decompression }
+        Self::new_with_source(DirectorySource::new(directory), remove_files_after_decompression)
+    }
+
+    pub fn new_with_source(source: impl DecompressorSource + 'static, remove_files_after_decompression: bool) -> Self {
+        Self { source: Arc::new(source), remove_files_after_decompression, progress_function: RefCell::new(None), cancel_token: CancelToken::default(), memory_map_read_ahead: None, encryption_key: None }
+    }
+
+    // Memory-maps the .sz files instead of reading them through a BufReader so
+    // that large files aren't copied into an extra buffer. The OS is asked to
+    // page in read_ahead_in_bytes ahead of the decompressor. Sources without paths
+    // are read as normal. The files must not be truncated while they're mapped.
+    #[cfg(feature="memory_map")]
+    pub fn set_memory_mapped(&mut self, read_ahead_in_bytes: usize) {
+        self.memory_map_read_ahead = Some(read_ahead_in_bytes.max(1));
+    }
+
+    // The key must match the one given to Compressor::new_with_encryption (or new_with_sink_and_encryption).
+    #[cfg(feature="frame_encryption")]
+    pub fn set_encryption_key(&mut self, encryption_key: [u8; 32]) {
+        self.encryption_key = Some(encryption_key);
+    }
+
+    // The function is called after each frame is passed to the in_order_function.
+    pub fn set_progress_function(&self, progress_function: ProgressFunction) {
+        *self.progress_function.borrow_mut() = Some(progress_function);
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
     }
 
     pub fn can_run(directory: &str) -> bool {
-        !scan_directory_for_timestamps(directory).is_empty()
+        !scan_source_for_timestamps(&DirectorySource::new(directory)).is_empty()
     }
 
-    pub fn decompress_from_disk<T: Send + 'static>(&self, per_thread_function: PerThreadFunction<T>, mut in_order_function: InOrderFunction<T>) {
-        let mut ordered_timestamps = scan_directory_for_timestamps(&self.directory);
+    pub fn sessions(&self) -> Vec<DateTime<Utc>> {
+        scan_source_for_timestamps(&*self.source).into_keys().collect()
+    }
+
+    // Returns a single frame from a recording session without decompressing it
+    // from the start, e.g. to show a preview when browsing recordings. The index
+    // files written by the Compressor are used to seek to the right LZ4 frame.
+    // Recordings without index files are read from the start of each file.
+
+    pub fn thumbnail(&self, session: &DateTime<Utc>, frame_number: usize) -> Option<crate::VideoFrame> {
+        let filenames = scan_source_for_timestamps(&*self.source).remove(session)?;
+        let mut video_frame_bytes = vec![];
+
+        check_file_versions(&*self.source, &filenames);
 
-        for (timestamp, filenames) in ordered_timestamps.iter_mut() {
-            filenames.sort();
+        for filename in filenames {
+            let index = read_index(&*self.source, &filename);
 
-            let workers = filenames.iter().map(|filename| {
-                spawn_worker(&self.directory, &filename, &per_thread_function, timestamp)
+            // Skip files that start after the frame. The index is empty for older recordings.
+            let byte_offset = match seek_offset(&index, frame_number) { Some(o) => o, _ => continue };
+
+            let (mut reader, version) = match open_reader(&*self.source, &filename, byte_offset, self.read_options()) { Some(r) => r, _ => continue };
+
+            while let Some(Ok(mut video_frame)) = read_packet(&mut reader, &mut video_frame_bytes, version) {
+                if video_frame.frame_number > frame_number { break; }
+                if video_frame.frame_number != frame_number { continue; }
+
+                // Keyframes aren't delta encoded so this only recurses once.
+                if let crate::DeltaEncoding::Delta { keyframe } = video_frame.delta_encoding {
+                    let mut keyframe = self.thumbnail(session, keyframe).and_then(|f| Some((keyframe, f.image_data.as_ref()?.bytes().to_vec())));
+                    reconstruct_delta_frame(&mut video_frame, &mut keyframe, &|_| None).ok()?;
+                }
+
+                return Some(video_frame);
+            }
+        }
+
+        None
+    }
+
+    // Returns the index entries for all of the session's files ordered by frame.
+    pub fn frame_index(&self, session: &DateTime<Utc>) -> Vec<IndexEntry> {
+        let filenames = scan_source_for_timestamps(&*self.source).remove(session).unwrap_or_default();
+
+        let mut entries = filenames.iter().flat_map(|f| read_index(&*self.source, f)).collect::<Vec<_>>();
+        entries.sort_by_key(|e| e.first_frame_number);
+
+        entries
+    }
+
+    // Returns the range of frames recorded between two times. The index only has
+    // an entry per LZ4 frame so the range is widened to the nearest entries.
+
+    pub fn frames_between(&self, session: &DateTime<Utc>, from: DateTime<Utc>, to: DateTime<Utc>) -> ops::Range<usize> {
+        let entries = self.frame_index(session);
+
+        let start = entries.iter().rev().find(|e| e.recorded_at <= from).map(|e| e.first_frame_number).unwrap_or(1);
+        let end = entries.iter().find(|e| e.recorded_at > to).map(|e| e.first_frame_number).unwrap_or(usize::MAX);
+
+        start..end
+    }
+
+    // Returns false if decompression was cancelled, in which case no files are removed.
+    pub fn decompress_from_disk<T: Send + 'static>(&self, per_thread_function: PerThreadFunction<T>, mut in_order_function: InOrderFunction<T>) -> bool {
+        let ordered_timestamps = scan_source_for_timestamps(&*self.source);
+        ordered_timestamps.values().for_each(|f| check_file_versions(&*self.source, f));
+
+        let estimated_total_frames = ordered_timestamps.values().map(|f| estimate_frames(&*self.source, f)).sum();
+        let mut advance = self.progress_tracker(estimated_total_frames);
+
+        for (timestamp, filenames) in ordered_timestamps.iter() {
+            let workers = group_parts(filenames).into_iter().map(|parts| {
+                let parts = parts.into_iter().map(|filename| (filename, 0)).collect();
+                spawn_worker(&self.source, parts, &per_thread_function, timestamp, 1..usize::MAX, self.read_options())
             }).collect();
 
-            order_frames_from_worker_threads(workers, &mut in_order_function, timestamp);
+            let completed = order_frames_from_worker_threads(workers, &per_thread_function, &mut in_order_function, timestamp, 1, &mut advance, &|_| None);
+            if !completed { return false; }
         }
 
         // Wait until the very end before removing files in case a panic happens mid-way through.
         if self.remove_files_after_decompression {
-            for (_timestamp, filenames) in ordered_timestamps {
+            for (timestamp, filenames) in ordered_timestamps {
                 for filename in filenames {
-                    let _ = fs::remove_file(path(&self.directory, &filename));
+                    self.source.remove(&filename);
+                    self.source.remove(&format!("{}i", filename)); // The index file.
                 }
+
+                // Input events recorded by an InputRecorder in the same directory.
+                self.source.remove(&crate::input_recorder::sidecar_filename(&timestamp));
             }
         }
+
+        true
     }
-}
 
-fn path(directory: &str, filename: &str) -> String {
-    Path::new(directory).join(filename).into_os_string().into_string().unwrap()
-}
+    // Decompresses the session's frames in the range, e.g. to export a sub-clip,
+    // using the index files to skip straight to the LZ4 frames that contain them.
+    // Files are never removed when decompressing a range.
 
-fn scan_directory_for_timestamps(directory: &str) -> BTreeMap<DateTime<Utc>, Vec<String>> {
-    let mut map = BTreeMap::new();
+    pub fn decompress_range<T: Send + 'static>(&self, session: &DateTime<Utc>, frames: ops::Range<usize>, per_thread_function: PerThreadFunction<T>, mut in_order_function: InOrderFunction<T>) -> bool {
+        let filenames = match scan_source_for_timestamps(&*self.source).remove(session) { Some(f) => f, _ => return true };
+        check_file_versions(&*self.source, &filenames);
 
-    let listing = match fs::read_dir(directory) { Ok(d) => d, _ => return map };
+        let estimated_end = (estimate_frames(&*self.source, &filenames) + 1).min(frames.end);
+        let mut advance = self.progress_tracker(estimated_end.saturating_sub(frames.start));
 
-    for result in listing {
-        let dir_entry = match result { Ok(d) => d, _ => continue };
+        let workers = group_parts(&filenames).into_iter().map(|parts| {
+            let parts = seek_parts(&*self.source, &parts, frames.start);
+            spawn_worker(&self.source, parts, &per_thread_function, session, frames.clone(), self.read_options())
+        }).collect();
 
-        let result = dir_entry.metadata();
-        let metadata = match result { Ok(m) => m, _ => continue };
+        // Delta encoded frames at the start of the range might need an earlier keyframe.
+        let fetch_keyframe = |frame_number| self.thumbnail(session, frame_number);
 
-        if metadata.len() == 0 { continue; }
+        order_frames_from_worker_threads(workers, &per_thread_function, &mut in_order_function, session, frames.start.max(1), &mut advance, &fetch_keyframe)
+    }
 
-        let result = dir_entry.file_name().into_string();
-        let filename = match result { Ok(f) => f, _ => continue };
+    fn read_options(&self) -> ReadOptions {
+        ReadOptions { memory_map_read_ahead: self.memory_map_read_ahead, encryption_key: self.encryption_key }
+    }
 
+    // Returns a function to call after each frame that reports progress and
+    // returns false if decompression has been cancelled.
+    fn progress_tracker(&self, estimated_total_frames: usize) -> impl FnMut() -> bool + '_ {
+        let mut frames_processed = 0;
+
+        move || {
+            frames_processed += 1;
+
+            if let Some(progress_function) = self.progress_function.borrow_mut().as_mut() {
+                progress_function(DecompressProgress { frames_processed, estimated_total_frames: estimated_total_frames.max(frames_processed) });
+            }
+
+            !self.cancel_token.is_cancelled()
+        }
+    }
+}
+
+fn scan_source_for_timestamps(source: &dyn DecompressorSource) -> BTreeMap<DateTime<Utc>, Vec<String>> {
+    let mut map = BTreeMap::new();
+
+    for filename in source.filenames() {
         let result = recover_timestamp_from_filename(&filename);
         let timestamp = match result { Ok(t) => t, _ => continue };
 
@@ -93,9 +275,21 @@ fn recover_timestamp_from_filename(filename: &str) -> Result<DateTime<Utc>, ()>
     Ok(timestamp.into())
 }
 
-fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_function: &mut InOrderFunction<T>, timestamp: &DateTime<Utc>) {
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Returns false if advance returned false, i.e. decompression was cancelled.
+fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, per_thread_function: &PerThreadFunction<T>, in_order_function: &mut InOrderFunction<T>, timestamp: &DateTime<Utc>, first_frame: usize, advance: &mut dyn FnMut() -> bool, fetch_keyframe: &dyn Fn(usize) -> Option<crate::VideoFrame>) -> bool {
     let mut min_heap = BinaryHeap::new();
-    let mut expected_frame = 1;
+    let mut expected_frame = first_frame;
+    let mut keyframe = None;
 
     loop {
         // Ask each worker for their next stream frame. If the stream frame doesn't
@@ -110,7 +304,10 @@ fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_fun
         //
         // Therefore, keep consuming until a frame with real image data is received so
         // that we mimic the thread balancing pattern from the compression side.
-        let drained = workers.extract_if(|worker| {
+        //
+        // Workers that have run out of frames are partitioned out so that their
+        // threads can be joined. The rest keep their order for the next iteration.
+        let (drained, remaining): (Vec<_>, Vec<_>) = mem::take(&mut workers).into_iter().partition(|worker| {
             loop {
                 if let Ok((video_frame, t)) = worker.receiver.recv() {
                     let has_image_data = video_frame.image_data.is_some();
@@ -124,6 +321,8 @@ fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_fun
             }
         });
 
+        workers = remaining;
+
         // Panic in the main thread if a worker thread didn't terminate properly.
         for worker in drained { worker.thread.join().unwrap(); }
 
@@ -135,15 +334,24 @@ fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_fun
         loop {
             let min_frame = match min_heap.pop() {
                 Some(cmp_reverse_wrapper) => cmp_reverse_wrapper,
-                _ => if workers.is_empty() { return } else { break },
+                _ => if workers.is_empty() { return true } else { break },
             };
 
             if min_frame.0.frame_number == expected_frame {
-                let (video_frame, t) = min_frame.0.0;
-                in_order_function(video_frame, Ok(t), timestamp);
+                let (mut video_frame, t) = min_frame.0.0;
+                let reconstructed = reconstruct_delta_frame(&mut video_frame, &mut keyframe, fetch_keyframe);
+
+                let result = match t {
+                    Some(t) => Ok(t),
+                    None => reconstructed.map(|_| per_thread_function(&video_frame, *timestamp)),
+                };
+
+                in_order_function(video_frame, result, timestamp);
 
                 expected_frame += 1;
                 advanced_by_at_least_one_frame = true;
+
+                if !advance() { return stop_workers(workers); }
             } else {
                 min_heap.push(min_frame); // Put the frame back.
                 break;
@@ -169,6 +377,8 @@ fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_fun
                     status: crate::FrameStatus::Missing,
                     image_data: None,
                     frame_number: expected_frame,
+                    planes: vec![],
+                    scale_factor: 1.,
                     buffer_size_in_bytes: Arc::new(AtomicUsize::new(0)),
                     ..Default::default()
                 },
@@ -178,12 +388,59 @@ fn order_frames_from_worker_threads<T>(mut workers: Vec<Worker<T>>, in_order_fun
 
             expected_frame += 1;
 
+            if !advance() { return stop_workers(workers); }
+
             if expected_frame == next_available_frame { break; }
         }
     }
 }
 
-fn spawn_worker<T: Send + 'static>(directory: &str, filename: &str, per_thread_function: &PerThreadFunction<T>, timestamp: &DateTime<Utc>) -> Worker<T> {
+// Keyframes are kept until the next one so the frames after them can be XORed
+// with them again. Returns an error and removes the image_data if the keyframe
+// is missing, e.g. because its .sz file was deleted.
+fn reconstruct_delta_frame(video_frame: &mut crate::VideoFrame, keyframe: &mut Option<(usize, Vec<u8>)>, fetch_keyframe: &dyn Fn(usize) -> Option<crate::VideoFrame>) -> Result<(), &'static str> {
+    let keyframe_number = match video_frame.delta_encoding {
+        crate::DeltaEncoding::None => return Ok(()),
+        crate::DeltaEncoding::Keyframe => {
+            if let Some(image_data) = &video_frame.image_data { *keyframe = Some((video_frame.frame_number, image_data.bytes().to_vec())); }
+            return Ok(());
+        },
+        crate::DeltaEncoding::Delta { keyframe } => keyframe,
+    };
+
+    if keyframe.as_ref().map(|(n, _)| *n) != Some(keyframe_number) {
+        *keyframe = fetch_keyframe(keyframe_number).and_then(|f| Some((keyframe_number, f.image_data.as_ref()?.bytes().to_vec())));
+    }
+
+    match (keyframe.as_ref(), &mut video_frame.image_data) {
+        (Some((_, keyframe_bytes)), Some(crate::ImageData::Bytes(bytes))) => {
+            // The row padding is XORed too because the frames have the same layout.
+            for (byte, keyframe_byte) in bytes.iter_mut().zip(keyframe_bytes) { *byte ^= keyframe_byte; }
+
+            video_frame.delta_encoding = crate::DeltaEncoding::None;
+            Ok(())
+        },
+        _ => {
+            video_frame.status = crate::FrameStatus::Missing;
+            video_frame.image_data = None;
+            Err("The frame's keyframe was missing from the compressed files.")
+        },
+    }
+}
+
+// Dropping the receivers makes the workers' next send fail so that they return.
+fn stop_workers<T>(workers: Vec<Worker<T>>) -> bool {
+    for Worker { thread, receiver } in workers {
+        drop(receiver);
+        let _ = thread.join();
+    }
+
+    false
+}
+
+// Each worker reads the parts written by one of the Compressor's threads in
+// order, starting from the byte offset given for each part.
+fn spawn_worker<T: Send + 'static>(source: &Arc<dyn DecompressorSource>, parts: Vec<(String, u64)>, per_thread_function: &PerThreadFunction<T>, timestamp: &DateTime<Utc>, frames: ops::Range<usize>, read_options: ReadOptions) -> Worker<T> {
     // Usually the slow part of the code will be the actual processing rather
     // than decompressing and decoding stream frames. Therefore, bound the
     // channel size to 0 to keep memory usage down. This forces worker threads
@@ -192,62 +449,365 @@ fn spawn_worker<T: Send + 'static>(directory: &str, filename: &str, per_thread_f
 
     let per_thread_function = Arc::clone(per_thread_function);
     let timestamp = timestamp.clone();
-    let decode_config = decoding_config();
+    let source = Arc::clone(source);
 
-    let file = fs::File::open(path(directory, filename)).unwrap();
-    let mut reader = BufReadDecompressor::new(BufReader::new(file)).unwrap();
-
-    let mut packet_len_bytes = [0; U64_LEN];
-    let mut video_frame_len_bytes = [0; U64_LEN];
     let mut video_frame_bytes = vec![];
 
     let thread = thread::spawn(move || {
-        // Read decompressed bytes from the file. Decode each packet to a
-        // VideoFrame and send it to the channel. The packets have this layout:
-        //
-        // [ packet_len | video_frame_len | video_frame | image_data ]
-        //     (u64)           (u64)          (bincode)        (raw)
-        //
-        // If the reader ends cleanly at the end of a packet then return.
-        // Otherwise, send a VideoFrame to the channel with FrameStatus::Corrupt.
+        for (filename, byte_offset) in parts {
+            let (mut reader, version) = match open_reader(&*source, &filename, byte_offset, read_options) { Some(r) => r, _ => continue };
+
+            // Read decompressed bytes from the file. Decode each packet to a
+            // VideoFrame and send it to the channel.
+            //
+            // The file is a sequence of LZ4 frames (so that it can be seeked) so
+            // start decompressing the next one when the current one is finished.
+            //
+            // If the reader ends cleanly at the end of a packet then move on to
+            // the next part. Otherwise, return.
 
-        loop {
-            // Read and decode packet_len.
-            match reader.read_exact(&mut packet_len_bytes) { Ok(_) => {}, _ => return }
-            let packet_len = u64::from_be_bytes(packet_len_bytes) as usize;
-
-            // Read and decode video_frame_len.
-            match reader.read_exact(&mut video_frame_len_bytes) { Ok(_) => {}, _ => break }
-            let video_frame_len = u64::from_be_bytes(video_frame_len_bytes) as usize;
-
-            // Read video_frame.
-            video_frame_bytes.resize(video_frame_len, 0);
-            match reader.read_exact(&mut video_frame_bytes) { Ok(_) => {}, _ => break }
-
-            // Decode video_frame.
-            let result = bincode::decode_from_slice(&video_frame_bytes, decode_config);
-            let mut video_frame: crate::VideoFrame = match result { Ok((f, _)) => f, _ => break }; // TODO: advance to next packet instead of breaking
-
-            if video_frame.image_data.is_some() {
-                // Read image_data.
-                let remainder_len = packet_len - U64_LEN - U64_LEN - video_frame_len;
-                let mut image_data_bytes = vec![0; remainder_len];
-                match reader.read_exact(&mut image_data_bytes) { Ok(_) => {}, _ => break } // TODO: advance to next packet instead of breaking
-
-                // Decode image_data.
-                video_frame.image_data = Some(crate::ImageData::Bytes(image_data_bytes));
+            loop {
+                let video_frame = match read_packet(&mut reader, &mut video_frame_bytes, version) {
+                    Some(Ok(f)) => f,
+                    Some(Err(_)) => return, // TODO: corrupt frame
+                    None => match new_reader(reader.into_inner()) { Some(r) => { reader = r; continue }, _ => break },
+                };
+
+                // Frame numbers only increase within a thread's parts so stop after the range.
+                if video_frame.frame_number < frames.start { continue; }
+                if video_frame.frame_number >= frames.end { return; }
+
+                let is_delta = matches!(video_frame.delta_encoding, crate::DeltaEncoding::Delta { .. });
+                let t = if is_delta { None } else { Some(per_thread_function(&video_frame, timestamp)) };
+
+                // Time spent here is the main thread being slower than the workers.
+                // The send fails if the main thread cancelled decompression.
+                { span!("wait_for_main_thread"); if sender.send((video_frame, t)).is_err() { return; } }
             }
-
-            let t = per_thread_function(&video_frame, timestamp);
-            sender.send((video_frame, t)).unwrap();
         }
-
-        // TODO: corrupt frame
     });
 
     Worker { thread, receiver }
 }
 
+type Reader = BufReadDecompressor<'static, Source>;
+
+enum Source {
+    Stream(Box<dyn BufRead + Send>),
+    #[cfg(feature="memory_map")] MemoryMap(MemoryMapReader),
+    #[cfg(feature="frame_encryption")] Decrypted(Box<crate::DecryptingReader<Source>>),
+}
+
+#[derive(Clone, Copy)]
+struct ReadOptions {
+    memory_map_read_ahead: Option<usize>,
+    encryption_key: Option<[u8; 32]>,
+}
+
+// Returns the reader and the file's version. The byte offset skips the header.
+fn open_reader(source: &dyn DecompressorSource, filename: &str, byte_offset: u64, options: ReadOptions) -> Option<(Reader, u64)> {
+    let (version, header_len) = read_file_header(source, filename)?;
+    let byte_offset = byte_offset.max(header_len);
+    let source = open_source(source, filename, byte_offset, options.memory_map_read_ahead)?;
+
+    #[cfg(feature="frame_encryption")]
+    if let Some(key) = &options.encryption_key {
+        let reader = crate::DecryptingReader::new(source, crate::Cipher::new(key), filename, byte_offset);
+        return Some((new_reader(Source::Decrypted(Box::new(reader)))?, version));
+    }
+
+    #[cfg(not(feature="frame_encryption"))]
+    let _ = options.encryption_key;
+
+    Some((new_reader(source)?, version))
+}
+
+// Returns the file's version and the length of its header. Files written before
+// the header was added start with an LZ4 frame (or a record if encrypted) so
+// they are version 0 and have no header. See compressor::write_file_header.
+fn read_file_header(source: &dyn DecompressorSource, filename: &str) -> Option<(u64, u64)> {
+    let (magic, header_len) = (crate::compressor::FILE_MAGIC, crate::compressor::FILE_HEADER_LEN);
+
+    let mut file = source.open(filename, 0)?;
+    let mut header = [0; crate::compressor::FILE_HEADER_LEN as usize];
+
+    if file.read_exact(&mut header).is_err() || header[..magic.len()] != magic { return Some((0, 0)); }
+    let version = u64::from_be_bytes(header[magic.len()..].try_into().unwrap());
+
+    Some((version, header_len))
+}
+
+// Fails loudly rather than decoding VideoFrames from a newer version as garbage.
+fn check_file_versions(source: &dyn DecompressorSource, filenames: &[String]) {
+    for filename in filenames {
+        let version = match read_file_header(source, filename) { Some((v, _)) => v, _ => continue };
+        if version <= crate::compressor::FILE_VERSION { continue; }
+
+        panic!("{} was recorded with file version {} but this version of the renderer can only read versions up to {}. Please upgrade it to decompress the file.", filename, version, crate::compressor::FILE_VERSION);
+    }
+}
+
+fn open_source(source: &dyn DecompressorSource, filename: &str, byte_offset: u64, memory_map_read_ahead: Option<usize>) -> Option<Source> {
+    #[cfg(feature="memory_map")]
+    if let (Some(read_ahead), Some(path)) = (memory_map_read_ahead, source.path(filename)) {
+        let file = fs::File::open(path).ok()?;
+        return Some(Source::MemoryMap(MemoryMapReader::new(&file, byte_offset, read_ahead)?));
+    }
+
+    #[cfg(not(feature="memory_map"))]
+    let _ = memory_map_read_ahead;
+
+    Some(Source::Stream(source.open(filename, byte_offset)?))
+}
+
+// Returns None if there are no more LZ4 frames in the file. Panics if the file
+// can't be read rather than treating it as the end of the file.
+fn new_reader(mut buf_reader: Source) -> Option<Reader> {
+    match buf_reader.fill_buf() {
+        Ok(bytes) => if bytes.is_empty() { return None; },
+        Err(error) => panic!("Unable to read the compressed file: {}", error),
+    }
+
+    BufReadDecompressor::new(buf_reader).ok()
+}
+
+// Reads and decodes a packet with this layout:
+//
+// [ packet_len | video_frame_len | video_frame | image_data | planes ]
+//     (u64)           (u64)          (bincode)        (raw)     (raw)
+//
+// Returns None if the reader ends cleanly at the end of a packet.
+
+fn read_packet<R: Read>(reader: &mut R, video_frame_bytes: &mut Vec<u8>, version: u64) -> Option<Result<crate::VideoFrame, ()>> {
+    let mut packet_len_bytes = [0; U64_LEN];
+    let mut video_frame_len_bytes = [0; U64_LEN];
+
+    // Read and decode packet_len.
+    match reader.read_exact(&mut packet_len_bytes) { Ok(_) => {}, _ => return None }
+    let packet_len = u64::from_be_bytes(packet_len_bytes) as usize;
+    span!("decompress_frame", bytes = packet_len);
+
+    // Read and decode video_frame_len.
+    match reader.read_exact(&mut video_frame_len_bytes) { Ok(_) => {}, _ => return Some(Err(())) }
+    let video_frame_len = u64::from_be_bytes(video_frame_len_bytes) as usize;
+
+    // Read video_frame.
+    video_frame_bytes.resize(video_frame_len, 0);
+    match reader.read_exact(video_frame_bytes) { Ok(_) => {}, _ => return Some(Err(())) }
+
+    // Decode video_frame.
+    let mut video_frame = match decode_video_frame(video_frame_bytes, version) { Ok(f) => f, _ => return Some(Err(())) }; // TODO: advance to next packet instead of breaking
+
+    if video_frame.image_data.is_some() {
+        // Read image_data.
+        let planes_len = video_frame.planes.iter().filter(|p| p.image_data.is_some()).map(|p| p.padded_bytes_per_row * video_frame.height).sum::<usize>();
+        let remainder_len = packet_len - U64_LEN - U64_LEN - video_frame_len;
+        let mut image_data_bytes = vec![0; remainder_len.saturating_sub(planes_len)];
+        match reader.read_exact(&mut image_data_bytes) { Ok(_) => {}, _ => return Some(Err(())) } // TODO: advance to next packet instead of breaking
+
+        // Decode image_data.
+        video_frame.image_data = Some(crate::ImageData::Bytes(image_data_bytes));
+
+        for plane in video_frame.planes.iter_mut().filter(|p| p.image_data.is_some()) {
+            let mut plane_bytes = vec![0; plane.padded_bytes_per_row * video_frame.height];
+            match reader.read_exact(&mut plane_bytes) { Ok(_) => {}, _ => return Some(Err(())) }
+
+            plane.image_data = Some(crate::ImageData::Bytes(plane_bytes));
+        }
+    }
+
+    Some(Ok(video_frame))
+}
+
+// Decodes a VideoFrame that was written with the version of the file format,
+// migrating it from older versions by filling in the fields they didn't have.
+// Older versions didn't store capture times so frames are assumed to be 60 FPS.
+
+fn decode_video_frame(video_frame_bytes: &[u8], version: u64) -> Result<crate::VideoFrame, ()> {
+    let elapsed_time = |frame_number: usize| frame_number.saturating_sub(1) as f64 / crate::DEFAULT_FRAME_RATE as f64;
+
+    match version {
+        0 => {
+            let (f, _): (VideoFrameV0, _) = bincode::decode_from_slice(video_frame_bytes, decoding_config()).map_err(|_| ())?;
+
+            Ok(crate::VideoFrame {
+                status: f.status,
+                image_data: f.image_data,
+                width: f.width,
+                height: f.height,
+                scale_factor: 1.,
+                region: None,
+                format: f.format,
+                unpadded_bytes_per_row: f.unpadded_bytes_per_row,
+                padded_bytes_per_row: f.padded_bytes_per_row,
+                frame_number: f.frame_number,
+                delta_encoding: crate::DeltaEncoding::None,
+                elapsed_time: elapsed_time(f.frame_number),
+                frame_size_in_bytes: f.frame_size_in_bytes,
+                buffer_size_in_bytes: f.buffer_size_in_bytes,
+                planes: vec![],
+            })
+        },
+        1 => {
+            let (f, _): (VideoFrameV1, _) = bincode::decode_from_slice(video_frame_bytes, decoding_config()).map_err(|_| ())?;
+
+            Ok(crate::VideoFrame {
+                status: f.status,
+                image_data: f.image_data,
+                width: f.width,
+                height: f.height,
+                scale_factor: f.scale_factor,
+                region: None,
+                format: f.format,
+                unpadded_bytes_per_row: f.unpadded_bytes_per_row,
+                padded_bytes_per_row: f.padded_bytes_per_row,
+                frame_number: f.frame_number,
+                delta_encoding: f.delta_encoding,
+                elapsed_time: elapsed_time(f.frame_number),
+                frame_size_in_bytes: f.frame_size_in_bytes,
+                buffer_size_in_bytes: f.buffer_size_in_bytes,
+                planes: f.planes,
+            })
+        },
+        2 => {
+            let (f, _): (VideoFrameV2, _) = bincode::decode_from_slice(video_frame_bytes, decoding_config()).map_err(|_| ())?;
+
+            Ok(crate::VideoFrame {
+                status: f.status,
+                image_data: f.image_data,
+                width: f.width,
+                height: f.height,
+                scale_factor: f.scale_factor,
+                region: None,
+                format: f.format,
+                unpadded_bytes_per_row: f.unpadded_bytes_per_row,
+                padded_bytes_per_row: f.padded_bytes_per_row,
+                frame_number: f.frame_number,
+                delta_encoding: f.delta_encoding,
+                elapsed_time: f.elapsed_time,
+                frame_size_in_bytes: f.frame_size_in_bytes,
+                buffer_size_in_bytes: f.buffer_size_in_bytes,
+                planes: f.planes,
+            })
+        },
+        crate::compressor::FILE_VERSION => bincode::decode_from_slice(video_frame_bytes, decoding_config()).map(|(f, _)| f).map_err(|_| ()),
+        _ => Err(()),
+    }
+}
+
+// The VideoFrame fields before planes, adaptive quality and delta encoding were added.
+#[derive(bincode::Decode)]
+struct VideoFrameV0 {
+    status: crate::FrameStatus,
+    image_data: Option<crate::ImageData>,
+    width: usize,
+    height: usize,
+    format: crate::Format,
+    unpadded_bytes_per_row: usize,
+    padded_bytes_per_row: usize,
+    frame_number: usize,
+    frame_size_in_bytes: usize,
+    buffer_size_in_bytes: Arc<AtomicUsize>,
+}
+
+// The VideoFrame fields before elapsed_time was added.
+#[derive(bincode::Decode)]
+struct VideoFrameV1 {
+    status: crate::FrameStatus,
+    image_data: Option<crate::ImageData>,
+    width: usize,
+    height: usize,
+    scale_factor: f32,
+    format: crate::Format,
+    unpadded_bytes_per_row: usize,
+    padded_bytes_per_row: usize,
+    frame_number: usize,
+    delta_encoding: crate::DeltaEncoding,
+    frame_size_in_bytes: usize,
+    buffer_size_in_bytes: Arc<AtomicUsize>,
+    planes: Vec<crate::Plane>,
+}
+
+// The VideoFrame fields before region was added.
+#[derive(bincode::Decode)]
+struct VideoFrameV2 {
+    status: crate::FrameStatus,
+    image_data: Option<crate::ImageData>,
+    width: usize,
+    height: usize,
+    scale_factor: f32,
+    format: crate::Format,
+    unpadded_bytes_per_row: usize,
+    padded_bytes_per_row: usize,
+    frame_number: usize,
+    delta_encoding: crate::DeltaEncoding,
+    elapsed_time: f64,
+    frame_size_in_bytes: usize,
+    buffer_size_in_bytes: Arc<AtomicUsize>,
+    planes: Vec<crate::Plane>,
+}
+
+// Reads the index file (.szi) that the Compressor wrote alongside the .sz file.
+fn read_index(source: &dyn DecompressorSource, filename: &str) -> Vec<IndexEntry> {
+    let mut bytes = vec![];
+    match source.open(&format!("{}i", filename), 0).map(|mut r| r.read_to_end(&mut bytes)) { Some(Ok(_)) => {}, _ => return vec![] }
+
+    let field = |entry: &[u8], i: usize| u64::from_be_bytes(entry[i * U64_LEN..(i + 1) * U64_LEN].try_into().unwrap());
+
+    bytes.chunks_exact(U64_LEN * 4).map(|entry| IndexEntry {
+        filename: filename.to_string(),
+        first_frame_number: field(entry, 0) as usize,
+        byte_offset: field(entry, 1),
+        decompressed_offset: field(entry, 2),
+        recorded_at: DateTime::from_timestamp_millis(field(entry, 3) as i64).unwrap_or_default(),
+    }).collect()
+}
+
+// Groups a session's files into the parts written by each of the Compressor's
+// threads, in the order they were written. See compressor::Rotation.
+fn group_parts(filenames: &[String]) -> Vec<Vec<String>> {
+    let mut threads = BTreeMap::new();
+
+    for filename in filenames {
+        let mut fields = filename.trim_end_matches(".sz").split("--").skip(1);
+
+        let thread = fields.next().unwrap_or("").to_string();
+        let part = fields.next().and_then(|p| p.parse::<usize>().ok()).unwrap_or(0);
+
+        threads.entry(thread).or_insert_with(|| vec![]).push((part, filename.clone()));
+    }
+
+    threads.into_values().map(|mut parts| {
+        parts.sort();
+        parts.into_iter().map(|(_, filename)| filename).collect()
+    }).collect()
+}
+
+// Returns the parts to read and the byte offsets to start from so that reading
+// begins at the last LZ4 frame that starts at or before the frame number.
+fn seek_parts(source: &dyn DecompressorSource, parts: &[String], frame_number: usize) -> Vec<(String, u64)> {
+    let offsets = parts.iter().map(|f| seek_offset(&read_index(source, f), frame_number)).collect::<Vec<_>>();
+    let first = offsets.iter().rposition(|o| o.is_some()).unwrap_or(0);
+
+    parts.iter().zip(offsets).skip(first).map(|(filename, offset)| (filename.clone(), offset.unwrap_or(0))).collect()
+}
+
+// Each index entry starts a segment of up to PACKETS_PER_SEGMENT frames. Assume
+// the last segment in each file is half full.
+fn estimate_frames(source: &dyn DecompressorSource, filenames: &[String]) -> usize {
+    let segment = crate::compressor::PACKETS_PER_SEGMENT;
+
+    filenames.iter().map(|f| (read_index(source, f).len() * segment).saturating_sub(segment / 2)).sum()
+}
+
+// Returns the byte offset of the last LZ4 frame that starts at or before the
+// frame, or None if the file starts after it. Files without an index start at 0.
+fn seek_offset(index: &[IndexEntry], frame_number: usize) -> Option<u64> {
+    if index.is_empty() { return Some(0); }
+
+    index.iter().rev().find(|e| e.first_frame_number <= frame_number).map(|e| e.byte_offset)
+}
+
 const U64_LEN: usize = mem::size_of::<u64>();
 
 fn decoding_config() -> bincode::config::Configuration {
@@ -283,3 +843,149 @@ impl<T> cmp::PartialEq for OrderableFrame<T> {
 }
 
 impl<T> cmp::Eq for OrderableFrame<T> {}
+
+impl DirectorySource {
+    pub fn new(directory: &str) -> Self {
+        Self { directory: directory.to_string() }
+    }
+}
+
+impl DecompressorSource for DirectorySource {
+    fn filenames(&self) -> Vec<String> {
+        let listing = match fs::read_dir(&self.directory) { Ok(d) => d, _ => return vec![] };
+
+        listing.filter_map(|result| {
+            let dir_entry = result.ok()?;
+            if dir_entry.metadata().ok()?.len() == 0 { return None; }
+
+            dir_entry.file_name().into_string().ok()
+        }).collect()
+    }
+
+    fn open(&self, filename: &str, byte_offset: u64) -> Option<Box<dyn BufRead + Send>> {
+        let mut file = fs::File::open(Path::new(&self.directory).join(filename)).ok()?;
+        file.seek(SeekFrom::Start(byte_offset)).ok()?;
+
+        Some(Box::new(BufReader::new(file)))
+    }
+
+    fn remove(&self, filename: &str) {
+        let _ = fs::remove_file(Path::new(&self.directory).join(filename));
+    }
+
+    fn path(&self, filename: &str) -> Option<PathBuf> {
+        Some(Path::new(&self.directory).join(filename))
+    }
+}
+
+// Files are shared with the worker threads rather than copied, e.g. those taken
+// from a MemorySink after the Compressor has finished.
+impl MemorySource {
+    pub fn new(files: BTreeMap<String, Vec<u8>>) -> Self {
+        Self { files: files.into_iter().map(|(filename, bytes)| (filename, bytes.into())).collect() }
+    }
+}
+
+impl DecompressorSource for MemorySource {
+    fn filenames(&self) -> Vec<String> {
+        self.files.iter().filter(|(_, bytes)| !bytes.is_empty()).map(|(filename, _)| filename.clone()).collect()
+    }
+
+    fn open(&self, filename: &str, byte_offset: u64) -> Option<Box<dyn BufRead + Send>> {
+        let mut cursor = io::Cursor::new(Arc::clone(self.files.get(filename)?));
+        cursor.set_position(byte_offset);
+
+        Some(Box::new(cursor))
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Stream(r) => r.read(buf),
+            #[cfg(feature="memory_map")] Self::MemoryMap(r) => r.read(buf),
+            #[cfg(feature="frame_encryption")] Self::Decrypted(r) => r.read(buf),
+        }
+    }
+}
+
+impl BufRead for Source {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            Self::Stream(r) => r.fill_buf(),
+            #[cfg(feature="memory_map")] Self::MemoryMap(r) => r.fill_buf(),
+            #[cfg(feature="frame_encryption")] Self::Decrypted(r) => r.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self {
+            Self::Stream(r) => r.consume(amount),
+            #[cfg(feature="memory_map")] Self::MemoryMap(r) => r.consume(amount),
+            #[cfg(feature="frame_encryption")] Self::Decrypted(r) => r.consume(amount),
+        }
+    }
+}
+
+// Hands out windows of the memory-mapped file to the LZ4 decompressor. Each
+// time the position crosses into a new window, the OS is advised to page in the
+// one after it so that reads from fast drives overlap with decompression.
+#[cfg(feature="memory_map")]
+struct MemoryMapReader {
+    mmap: memmap2::Mmap,
+    position: usize,
+    read_ahead: usize,
+    advised_up_to: usize,
+}
+
+#[cfg(feature="memory_map")]
+impl MemoryMapReader {
+    fn new(file: &fs::File, byte_offset: u64, read_ahead: usize) -> Option<Self> {
+        // SAFETY: The map is only sound while nothing else modifies the file. If
+        // another process truncates it concurrently, reading past the new end
+        // raises SIGBUS (and writes change bytes we've already validated). The
+        // Decompressor only reads finished captures so it's up to the caller not
+        // to memory map files that are still being written or cleaned up.
+        let mmap = unsafe { memmap2::Mmap::map(file) }.ok()?;
+
+        #[cfg(unix)] let _ = mmap.advise(memmap2::Advice::Sequential);
+
+        let position = (byte_offset as usize).min(mmap.len());
+        Some(Self { mmap, position, read_ahead, advised_up_to: position })
+    }
+
+    fn advise_read_ahead(&mut self) {
+        let end = (self.position + self.read_ahead * 2).min(self.mmap.len());
+        if self.advised_up_to >= self.position + self.read_ahead || self.advised_up_to >= end { return; }
+
+        #[cfg(unix)] let _ = self.mmap.advise_range(memmap2::Advice::WillNeed, self.advised_up_to, end - self.advised_up_to);
+        self.advised_up_to = end;
+    }
+}
+
+#[cfg(feature="memory_map")]
+impl Read for MemoryMapReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+#[cfg(feature="memory_map")]
+impl BufRead for MemoryMapReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.advise_read_ahead();
+
+        let end = (self.position + self.read_ahead).min(self.mmap.len());
+        Ok(&self.mmap[self.position..end])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.mmap.len());
+    }
+}
@@ -0,0 +1,125 @@
+// XORs each recorded frame with the most recent keyframe on the GPU before it is
+// read back. Pixels that haven't changed since the keyframe become zeros, which
+// LZ4 compresses to almost nothing, so mostly static scenes (e.g. UIs and desktop
+// captures) take up far less space in the Compressor's files and ReplayBuffer.
+// The Decompressor XORs them with the keyframe again to reconstruct the frames.
+
+pub struct DeltaEncoder {
+    pub keyframe_interval: usize,
+    pub keyframe: Option<(usize, (u32, u32, u32), (u32, u32, u32, u32))>, // (frame_number, texture size, frame region)
+    pub keyframe_texture: crate::Texture,
+    pub delta_texture: crate::Texture,
+    pub layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl DeltaEncoder {
+    pub fn new(device: &wgpu::Device, keyframe_interval: usize) -> Self {
+        let keyframe_texture = create_target(device);
+        let delta_texture = create_target(device);
+
+        let layout = create_bind_group_layout(device);
+        let pipeline = create_pipeline(device, &layout);
+
+        Self { keyframe_interval: keyframe_interval.max(1), keyframe: None, keyframe_texture, delta_texture, layout, pipeline }
+    }
+
+    // Starts a new keyframe every keyframe_interval frames or when the size or
+    // recorded region changes.
+    pub fn next_encoding(&mut self, frame_number: usize, texture_size: (u32, u32, u32), frame_region: (u32, u32, u32, u32)) -> crate::DeltaEncoding {
+        match self.keyframe {
+            Some((keyframe, t, r)) if t == texture_size && r == frame_region && frame_number - keyframe < self.keyframe_interval => crate::DeltaEncoding::Delta { keyframe },
+            _ => { self.keyframe = Some((frame_number, texture_size, frame_region)); crate::DeltaEncoding::Keyframe },
+        }
+    }
+
+    pub fn set_keyframe(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, source: &crate::Texture) {
+        self.keyframe_texture.resize(device, source.size());
+
+        let source_texture = source.texture();
+        let keyframe_texture = self.keyframe_texture.texture();
+
+        let source_copy = crate::Texture::image_copy_texture(&source_texture, (0, 0, 0));
+        let keyframe_copy = crate::Texture::image_copy_texture(&keyframe_texture, (0, 0, 0));
+
+        encoder.copy_texture_to_texture(source_copy, keyframe_copy, source.extent());
+    }
+
+    // Renders the source XOR the keyframe into the delta_texture.
+    pub fn encode(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, source: &crate::Texture) {
+        self.delta_texture.resize(device, source.size());
+
+        let (source_view, keyframe_view) = (source.view(), self.keyframe_texture.view());
+        let entries = [&source_view, &keyframe_view].iter().enumerate().map(|(i, view)| wgpu::BindGroupEntry { binding: i as u32, resource: wgpu::BindingResource::TextureView(view) }).collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &self.layout, entries: &entries });
+
+        let view = self.delta_texture.view();
+        let ops = wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store };
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment { view: &view, resolve_target: None, ops })];
+        let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None };
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_target(device: &wgpu::Device) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest;
+    let format = crate::Format::RgbaU8;
+    let renderable = true;
+    let copyable = true;
+    let with_sampler = false;
+
+    crate::Texture::new(device, (1, 1, 1), filter_mode, format, 1, renderable, copyable, with_sampler)
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let entries = (0..2).map(|binding| {
+        let ty = wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false };
+        wgpu::BindGroupLayoutEntry { binding, visibility: wgpu::ShaderStages::FRAGMENT, ty, count: None }
+    }).collect::<Vec<_>>();
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries })
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(DELTA_SHADER.into()) });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[layout], push_constant_ranges: &[] });
+    let target = wgpu::ColorTargetState { format: crate::Format::RgbaU8.texture_format(), blend: None, write_mask: wgpu::ColorWrites::ALL };
+
+    let descriptor = wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &[Some(target)] }),
+        multiview: None,
+    };
+
+    device.create_render_pipeline(&descriptor)
+}
+
+// A full-screen triangle that XORs the 8-bit channels of each texel.
+const DELTA_SHADER: &str = "
+@group(0) @binding(0) var current: texture_2d<f32>;
+@group(0) @binding(1) var keyframe: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    let a = vec4<u32>(round(textureLoad(current, coords, 0) * 255.0));
+    let b = vec4<u32>(round(textureLoad(keyframe, coords, 0) * 255.0));
+
+    return vec4<f32>(a ^ b) / 255.0;
+}
+";
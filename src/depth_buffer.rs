@@ -0,0 +1,138 @@
+use std::{cell, rc};
+
+// A depth buffer can be shared by several pipelines so that they test against
+// each other's geometry. It is resized to match the targets it is rendered with
+// and cleared to 1.0 (and stencil to 0) the first time it is used each frame
+// unless the ClearColor of that render says otherwise. Its msaa samples must
+// match those of the pipelines that use it.
+#[derive(Clone)]
+pub struct DepthBuffer {
+    pub inner: rc::Rc<cell::RefCell<InnerD>>,
+    pub msaa_samples: u32,
+    pub with_stencil: bool,
+}
+
+pub struct InnerD {
+    pub texture: rc::Rc<wgpu::Texture>,
+    pub view: rc::Rc<wgpu::TextureView>,
+    pub size: (u32, u32),
+    pub cleared_at: u64, // The renderer's frame_index when it was last cleared.
+}
+
+// How a pipeline uses a depth buffer. Fragments pass if their depth compares
+// with the depth buffer according to test. Write updates the depth buffer.
+#[derive(Clone)]
+pub struct Depth {
+    pub buffer: DepthBuffer,
+    pub test: DepthTest,
+    pub write: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthTest {
+    Less,
+    LessEqual,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Always,
+}
+
+impl DepthBuffer {
+    pub fn new(device: &wgpu::Device, size: (u32, u32), msaa_samples: u32, with_stencil: bool) -> Self {
+        let texture = create_depth_texture(device, size, msaa_samples, format(with_stencil));
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let inner = InnerD { texture: rc::Rc::new(texture), view: rc::Rc::new(view), size, cleared_at: u64::MAX };
+
+        Self { inner: rc::Rc::new(cell::RefCell::new(inner)), msaa_samples, with_stencil }
+    }
+
+    pub fn resize(&self, device: &wgpu::Device, new_size: (u32, u32)) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.size == new_size || new_size.0 == 0 || new_size.1 == 0 { return; }
+
+        let texture = create_depth_texture(device, new_size, self.msaa_samples, self.format());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        inner.texture = rc::Rc::new(texture);
+        inner.view = rc::Rc::new(view);
+        inner.size = new_size;
+        inner.cleared_at = u64::MAX;
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        format(self.with_stencil)
+    }
+
+    // The view is replaced on resize so hold onto the Rc while it is used.
+    pub fn view(&self) -> rc::Rc<wgpu::TextureView> {
+        rc::Rc::clone(&self.inner.borrow().view)
+    }
+
+    // Returns true if this is the first use of the frame so the pass should clear it.
+    pub fn clear_if_first_use(&self, frame_index: u64) -> bool {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.cleared_at == frame_index { return false; }
+        inner.cleared_at = frame_index;
+
+        true
+    }
+}
+
+impl Depth {
+    pub fn new(buffer: &DepthBuffer, test: DepthTest, write: bool) -> Self {
+        Self { buffer: buffer.clone(), test, write }
+    }
+
+    pub fn state(&self) -> wgpu::DepthStencilState {
+        depth_stencil_state(self.buffer.format(), self.test, self.write)
+    }
+}
+
+impl DepthTest {
+    pub fn compare_function(&self) -> wgpu::CompareFunction {
+        match self {
+            Self::Less => wgpu::CompareFunction::Less,
+            Self::LessEqual => wgpu::CompareFunction::LessEqual,
+            Self::Equal => wgpu::CompareFunction::Equal,
+            Self::Greater => wgpu::CompareFunction::Greater,
+            Self::GreaterEqual => wgpu::CompareFunction::GreaterEqual,
+            Self::Always => wgpu::CompareFunction::Always,
+        }
+    }
+}
+
+pub fn depth_stencil_state(format: wgpu::TextureFormat, test: DepthTest, write: bool) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format,
+        depth_write_enabled: write,
+        depth_compare: test.compare_function(),
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+fn format(with_stencil: bool) -> wgpu::TextureFormat {
+    if with_stencil { STENCIL_FORMAT } else { DEPTH_FORMAT }
+}
+
+fn create_depth_texture(device: &wgpu::Device, (width, height): (u32, u32), msaa_samples: u32, format: wgpu::TextureFormat) -> wgpu::Texture {
+    let descriptor = wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: msaa_samples,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    };
+
+    device.create_texture(&descriptor)
+}
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
@@ -0,0 +1,99 @@
+// Renders a texture at half its size by averaging each 2x2 block of texels. The
+// video recorder uses this for adaptive quality so that it can keep recording at
+// a lower resolution rather than dropping frames when its buffers are full.
+
+pub struct Downscaler {
+    pub texture: crate::Texture,
+    pub layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl Downscaler {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = create_target(device, (1, 1));
+        let layout = create_bind_group_layout(device);
+        let pipeline = create_pipeline(device, &layout);
+
+        Self { texture, layout, pipeline }
+    }
+
+    // The texture is resized to half the size of the source, rounded down.
+    pub fn downscale(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, source: &crate::Texture) {
+        let (width, height, _) = source.size();
+        self.texture.resize(device, ((width / 2).max(1), (height / 2).max(1), 1));
+
+        let source_view = source.view();
+        let entries = [wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&source_view) }];
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &self.layout, entries: &entries });
+
+        let view = self.texture.view();
+        let ops = wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store };
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment { view: &view, resolve_target: None, ops })];
+        let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None };
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_target(device: &wgpu::Device, (width, height): (u32, u32)) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest;
+    let format = crate::Format::RgbaU8;
+    let renderable = true;
+    let copyable = true;
+    let with_sampler = false;
+
+    crate::Texture::new(device, (width, height, 1), filter_mode, format, 1, renderable, copyable, with_sampler)
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let ty = wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false };
+    let entries = [wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::FRAGMENT, ty, count: None }];
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries })
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(DOWNSCALE_SHADER.into()) });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[layout], push_constant_ranges: &[] });
+    let target = wgpu::ColorTargetState { format: crate::Format::RgbaU8.texture_format(), blend: None, write_mask: wgpu::ColorWrites::ALL };
+
+    let descriptor = wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &[Some(target)] }),
+        multiview: None,
+    };
+
+    device.create_render_pipeline(&descriptor)
+}
+
+// A full-screen triangle that averages the 2x2 block of source texels under each pixel.
+const DOWNSCALE_SHADER: &str = "
+@group(0) @binding(0) var source: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let max_coords = vec2<i32>(textureDimensions(source)) - 1;
+    let coords = vec2<i32>(position.xy) * 2;
+
+    let a = textureLoad(source, min(coords, max_coords), 0);
+    let b = textureLoad(source, min(coords + vec2<i32>(1, 0), max_coords), 0);
+    let c = textureLoad(source, min(coords + vec2<i32>(0, 1), max_coords), 0);
+    let d = textureLoad(source, min(coords + vec2<i32>(1, 1), max_coords), 0);
+
+    return (a + b + c + d) * 0.25;
+}
+";
@@ -0,0 +1,141 @@
+use std::{io, mem, io::{Read, BufRead, Write}};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce, Key, aead::{Aead, AeadCore, KeyInit, OsRng, Payload}};
+
+// Encrypts each LZ4 frame in a .sz file with XChaCha20-Poly1305 so that captures
+// of sensitive content can't be read or tampered with without the key. Index
+// files aren't encrypted so frame numbers, byte offsets and times are visible.
+// Each LZ4 frame is written as a record that has this layout:
+//
+// [ record_len | nonce | ciphertext ]
+//     (u64)      (24)    (kind + LZ4 frame + 16 byte tag)
+//
+// The filename and the record's byte offset in the file are authenticated too
+// (as associated data) so that records can't be reordered, dropped or moved
+// between files. The offset is used rather than a counter because it's known
+// after seeking. The last record is an empty END record so that truncated files
+// are detected. Files can't be renamed because their names are authenticated.
+
+pub struct Cipher {
+    pub inner: XChaCha20Poly1305,
+}
+
+// Presents the decrypted records of a .sz file as a stream of LZ4 frames. Panics
+// if a record fails to authenticate or the file ends before its END record.
+pub struct DecryptingReader<R> {
+    pub inner: R,
+    pub cipher: Cipher,
+    pub filename: String,
+    pub offset: u64, // The byte offset of the next record in the file.
+    pub finished: bool,
+    pub plaintext: Vec<u8>,
+    pub position: usize,
+}
+
+impl Cipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self { inner: XChaCha20Poly1305::new(Key::from_slice(key)) }
+    }
+
+    // The offset is where the record starts in the file, i.e. its position.
+    pub fn write_record<W: Write>(&self, writer: &mut W, filename: &str, offset: u64, lz4_frame: &[u8]) {
+        self.write(writer, filename, offset, DATA, lz4_frame);
+    }
+
+    pub fn write_end<W: Write>(&self, writer: &mut W, filename: &str, offset: u64) {
+        self.write(writer, filename, offset, END, &[]);
+    }
+
+    fn write<W: Write>(&self, writer: &mut W, filename: &str, offset: u64, kind: u8, bytes: &[u8]) {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = [&[kind][..], bytes].concat();
+        let aad = associated_data(filename, offset);
+
+        let ciphertext = self.inner.encrypt(&nonce, Payload { msg: &plaintext, aad: &aad }).unwrap();
+        let record_len = (NONCE_LEN + ciphertext.len()) as u64;
+
+        writer.write_all(&record_len.to_be_bytes()).unwrap();
+        writer.write_all(nonce.as_slice()).unwrap();
+        writer.write_all(&ciphertext).unwrap();
+    }
+
+    // Returns the LZ4 frame and the length of the record or None for the END
+    // record. Returns an error if the reader ends before the END record.
+    pub fn read_record<R: Read>(&self, reader: &mut R, filename: &str, offset: u64) -> io::Result<(Option<Vec<u8>>, u64)> {
+        let mut len_bytes = [0; U64_LEN];
+        reader.read_exact(&mut len_bytes).map_err(|_| truncated())?;
+
+        let record_len = u64::from_be_bytes(len_bytes) as usize;
+        if record_len < NONCE_LEN { return Err(io::ErrorKind::InvalidData.into()); }
+
+        let mut record = vec![0; record_len];
+        reader.read_exact(&mut record).map_err(|_| truncated())?;
+
+        let nonce = XNonce::from_slice(&record[..NONCE_LEN]);
+        let aad = associated_data(filename, offset);
+
+        let mut plaintext = match self.inner.decrypt(nonce, Payload { msg: &record[NONCE_LEN..], aad: &aad }) {
+            Ok(p) => p,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "A record could not be decrypted. Is the key correct? The file may have been tampered with or renamed.")),
+        };
+
+        let kind = if plaintext.is_empty() { END } else { plaintext.remove(0) };
+        let lz4_frame = if kind == DATA { Some(plaintext) } else { None };
+
+        Ok((lz4_frame, (U64_LEN + record_len) as u64))
+    }
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(inner: R, cipher: Cipher, filename: &str, offset: u64) -> Self {
+        Self { inner, cipher, filename: filename.to_string(), offset, finished: false, plaintext: vec![], position: 0 }
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for DecryptingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.plaintext.len() && !self.finished {
+            // Panic rather than return an error so that corrupt or truncated files
+            // can't be mistaken for files that ended cleanly.
+            let (lz4_frame, record_len) = match self.cipher.read_record(&mut self.inner, &self.filename, self.offset) {
+                Ok(r) => r,
+                Err(error) => panic!("Unable to decrypt {} at byte {}: {}", self.filename, self.offset, error),
+            };
+
+            self.offset += record_len;
+            self.finished = lz4_frame.is_none();
+            self.plaintext = lz4_frame.unwrap_or_default();
+            self.position = 0;
+        }
+
+        Ok(&self.plaintext[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.plaintext.len());
+    }
+}
+
+fn associated_data(filename: &str, offset: u64) -> Vec<u8> {
+    [filename.as_bytes(), &offset.to_be_bytes()].concat()
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "The file ends before its END record. It may have been truncated.")
+}
+
+const U64_LEN: usize = mem::size_of::<u64>();
+const NONCE_LEN: usize = 24;
+const DATA: u8 = 0;
+const END: u8 = 1;
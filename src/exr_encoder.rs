@@ -0,0 +1,26 @@
+pub struct ExrEncoder;
+
+impl ExrEncoder {
+    // Writes the f32 data from renderer.read_texture_f32 to an OpenEXR file so
+    // that HDR values outside of 0..1 are kept. RU8 data is written as grayscale.
+
+    pub fn write(path: &str, size: (u32, u32), format: crate::Format, data: &[f32]) -> Result<(), &'static str> {
+        let channels = format.channels() as usize;
+        let (width, height) = (size.0 as usize, size.1 as usize);
+
+        if data.len() != width * height * channels {
+            return Err("The data could not be written because it doesn't match the size and format.");
+        }
+
+        let result = exr::prelude::write_rgba_file(path, width, height, |x, y| {
+            let i = (y * width + x) * channels;
+
+            match channels {
+                1 => (data[i], data[i], data[i], 1.),
+                _ => (data[i], data[i + 1], data[i + 2], data[i + 3]),
+            }
+        });
+
+        result.map_err(|_| "The EXR file could not be written.")
+    }
+}
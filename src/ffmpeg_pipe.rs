@@ -1,5 +1,5 @@
 use std::process::{Command, Child, Stdio};
-use std::{io::Write, path::Path};
+use std::{fs, io::Write, mem, path::Path};
 use std::thread;
 use chrono::{DateTime, Utc, SecondsFormat};
 
@@ -8,10 +8,64 @@ pub struct FfmpegPipe {
     pub output_directory: Option<String>,
     pub output_filename: Option<String>,
     pub ffmpeg_args: Vec<String>,
+    pub subtitles: Vec<Annotation>,
+    pub chapters: Vec<Annotation>,
+    pub interpolation: FrameInterpolation,
+    pub resolution_change: ResolutionChange,
+    pub playback_speed: Option<f32>,
+    pub frame_rate: usize,
+    pub color_metadata: bool,
 
     pub child: Option<Child>,
     pub timestamp: Option<DateTime<Utc>>,
     pub prev_bytes: Option<Vec<u8>>,
+    pub sidecar_paths: Vec<String>,
+    pub size: Option<(usize, usize)>, // The resolution of the file being written.
+    pub part: usize,
+    pub first_frame: usize,
+    pub start_time: f64, // The elapsed_time of the file's first frame.
+    pub frames_written: usize,
+    pub gap: usize, // Frames to fill in before the next captured frame.
+    pub frame_times: Vec<(usize, usize)>, // (frame_number, output_frame) of each frame written to a retimed file.
+    pub output_path: Option<String>,
+}
+
+// Frame numbers are inclusive and start from 1 like VideoFrame::frame_number.
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    pub start_frame: usize,
+    pub end_frame: usize,
+    pub text: String,
+}
+
+// Dropped, skipped and missing frames are filled in to keep a steady frame rate,
+// either by duplicating the previous frame or by blending it with the next one.
+// Only the frames that were filled in are blended. Frames are duplicated at the
+// end of a file and if the neighbours can't be blended, e.g. they're 16-bit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameInterpolation {
+    Duplicate,
+    Blend,
+}
+
+// What to do when a frame's resolution differs from the first frame of the file,
+// e.g. because the window was resized while recording. Split (the default) starts
+// a new file, e.g. recorded-1.mp4, at each change. Scale stretches frames to the first
+// resolution and Pad scales them to fit inside it and adds black bars.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResolutionChange {
+    Split,
+    Scale,
+    Pad,
+}
+
+// Presets choose codec arguments so that callers don't need to know ffmpeg's
+// flags. Use FfmpegPreset::detect() to pick a hardware encoder when available.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FfmpegPreset {
+    Nvenc,
+    VideoToolbox,
+    X264Fast,
 }
 
 // If audio_directory is provided, looks for an audio file with the same name as
@@ -24,7 +78,59 @@ impl FfmpegPipe {
         let output_filename = output_filename.map(|s| s.to_string());
         let ffmpeg_args = ffmpeg_args.iter().map(|s| s.to_string()).collect();
 
-        Self { audio_directory, output_directory, output_filename, ffmpeg_args, child: None, timestamp: None, prev_bytes: None }
+        Self { audio_directory, output_directory, output_filename, ffmpeg_args, subtitles: vec![], chapters: vec![], interpolation: FrameInterpolation::Duplicate, resolution_change: ResolutionChange::Split, playback_speed: None, frame_rate: crate::DEFAULT_FRAME_RATE, color_metadata: false, child: None, timestamp: None, prev_bytes: None, sidecar_paths: vec![], size: None, part: 0, first_frame: 1, start_time: 0., frames_written: 0, gap: 0, frame_times: vec![], output_path: None }
+    }
+
+    pub fn with_preset(audio_directory: Option<&str>, output_directory: Option<&str>, output_filename: Option<&str>, preset: FfmpegPreset) -> Self {
+        Self::new(audio_directory, output_directory, output_filename, preset.args())
+    }
+
+    // Subtitles and chapters are written to sidecar files when ffmpeg is spawned
+    // and muxed into the output so they must be added before the first write.
+
+    pub fn add_subtitle(&mut self, start_frame: usize, end_frame: usize, text: &str) {
+        self.subtitles.push(Annotation { start_frame, end_frame, text: text.to_string() });
+    }
+
+    pub fn add_chapter(&mut self, start_frame: usize, end_frame: usize, title: &str) {
+        self.chapters.push(Annotation { start_frame, end_frame, text: title.to_string() });
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: FrameInterpolation) {
+        self.interpolation = interpolation;
+    }
+
+    // Scale and Pad add a -vf filter to every file, even if its resolution never
+    // changes, so they can't be combined with one in ffmpeg_args. Subtitles,
+    // chapters and audio are split across the files with Split.
+    pub fn set_resolution_change(&mut self, resolution_change: ResolutionChange) {
+        self.resolution_change = resolution_change;
+    }
+
+    // Retimes the output using the time each frame was captured, e.g. 0.25 for
+    // slow motion of a high frame rate capture or 30 for a timelapse. Frames are
+    // repeated or skipped to keep the output at a steady frame rate so Blend
+    // interpolation can be used to smooth slow motion. None writes each frame once.
+    // The audio's tempo is changed to match. Subtitles and chapters are muxed in
+    // once each file has been written so that they start when their frames do.
+    pub fn set_playback_speed(&mut self, playback_speed: Option<f32>) {
+        if let Some(speed) = playback_speed { assert!(speed > 0. && speed.is_finite(), "The playback speed must be greater than 0."); }
+        self.playback_speed = playback_speed;
+    }
+
+    // The rate that frames are written at. Annotations and retimed frames are
+    // converted to times using it.
+    pub fn set_frame_rate(&mut self, frame_rate: usize) {
+        assert!(frame_rate > 0, "The frame rate must be greater than 0.");
+        self.frame_rate = frame_rate;
+    }
+
+    // Converts frames to BT.709 YUV and tags the output with its color space so
+    // that players don't guess (many assume BT.601, which shifts the colors). The
+    // transfer is tagged as sRGB because that's what the frames were shown with.
+    // This adds a -vf filter so it can't be combined with one in ffmpeg_args.
+    pub fn set_color_metadata(&mut self, color_metadata: bool) {
+        self.color_metadata = color_metadata;
     }
 
     pub fn available() -> bool {
@@ -35,23 +141,70 @@ impl FfmpegPipe {
         if png_bytes.is_empty() && self.prev_bytes.is_none() { return; }
 
         if self.child.is_none() || self.timestamp_has_changed(timestamp) {
-            self.re_spawn_process(timestamp);
+            self.wait_for_process();
+            self.part = 0;
+            self.first_frame = 1;
+            self.re_spawn_process(video_frame, timestamp);
+        } else if self.resolution_has_changed(video_frame, &png_bytes) {
+            self.wait_for_process();
+            self.part += 1;
+            self.first_frame = video_frame.frame_number;
+            self.re_spawn_process(video_frame, timestamp);
         }
 
-        let child = self.child.as_mut().unwrap();
-        let stdin = child.stdin.as_mut().unwrap();
-
         let duplicate_frame = png_bytes.is_empty();
 
         if duplicate_frame {
-            eprintln!("Warning: Frame {} is {}. Duplicating previous frame to maintain a steady frame rate.", video_frame.frame_number, video_frame.status);
+            let action = if self.interpolation == FrameInterpolation::Duplicate { "Duplicating previous frame" } else { "Interpolating it" };
+            eprintln!("Warning: Frame {} is {}. {} to maintain a steady frame rate.", video_frame.frame_number, video_frame.status, action);
+        }
+
+        if let Some(playback_speed) = self.playback_speed {
+            // The next frame fills the gap with the previous frame.
+            if duplicate_frame { return; }
+
+            let output_time = (video_frame.elapsed_time - self.start_time).max(0.) / playback_speed as f64;
+            let output_frame = (output_time * self.frame_rate as f64).round() as usize;
+
+            // Skip frames that are due before the next output frame, e.g. for timelapses.
+            if output_frame < self.frames_written { return; }
 
-            let duplicate = self.prev_bytes.as_ref().unwrap();
-            stdin.write_all(duplicate).unwrap();
+            // Fill in frames until this one is due, e.g. for slow motion.
+            self.gap += output_frame - self.frames_written;
+            self.frames_written = output_frame;
+            self.frame_times.push((video_frame.frame_number, output_frame));
+        }
+
+        if duplicate_frame {
+            self.gap += 1;
         } else {
+            self.fill_gap(Some(&png_bytes));
+
+            let stdin = self.child.as_mut().unwrap().stdin.as_mut().unwrap();
             stdin.write_all(&png_bytes).unwrap();
+
             self.prev_bytes = Some(png_bytes);
         }
+
+        self.frames_written += 1;
+    }
+
+    // Writes the frames between the previous captured frame and the next one. The
+    // end of a file has no next frame so it's filled with duplicates.
+    fn fill_gap(&mut self, next_bytes: Option<&[u8]>) {
+        let gap = mem::take(&mut self.gap);
+
+        let (child, prev_bytes) = match (self.child.as_mut(), self.prev_bytes.as_ref()) { (Some(c), Some(p)) => (c, p), _ => return };
+        let stdin = child.stdin.as_mut().unwrap();
+
+        let blended = match (self.interpolation, next_bytes) {
+            (FrameInterpolation::Blend, Some(next_bytes)) if gap > 0 => blend_pngs(prev_bytes, next_bytes, gap),
+            _ => None,
+        };
+
+        for i in 0..gap {
+            stdin.write_all(blended.as_ref().map_or(prev_bytes, |b| &b[i])).unwrap();
+        }
     }
 
     fn timestamp_has_changed(&self, timestamp: Option<&DateTime<Utc>>) -> bool {
@@ -64,18 +217,34 @@ impl FfmpegPipe {
         true
     }
 
-    fn re_spawn_process(&mut self, timestamp: Option<&DateTime<Utc>>) {
+    // Dropped and missing frames are duplicates so they don't change the resolution.
+    fn resolution_has_changed(&self, video_frame: &crate::VideoFrame, png_bytes: &[u8]) -> bool {
+        if self.resolution_change != ResolutionChange::Split || png_bytes.is_empty() { return false; }
+
+        let size = video_frame.full_size();
+        if self.size == Some(size) { return false; }
+
+        eprintln!("Warning: Frame {} changed resolution to {}x{}. Starting a new file.", video_frame.frame_number, size.0, size.1);
+        true
+    }
+
+    fn re_spawn_process(&mut self, video_frame: &crate::VideoFrame, timestamp: Option<&DateTime<Utc>>) {
+        self.wait_for_process();
+
         self.timestamp = timestamp.cloned();
+        self.size = Some(video_frame.full_size());
+        self.start_time = video_frame.elapsed_time;
+        self.frames_written = 0;
 
         let mut command = Command::new("ffmpeg");
 
         command.arg("-hide_banner").arg("-loglevel").arg("error").arg("-stats");
         command.arg("-f").arg("image2pipe");
 
-        // TODO: Make this better. Ideally, we'd store elapsed_time on each
-        // video frame since the start of the recording timestamp.
+        // TODO: Make this better. Video frames store their elapsed_time since
+        // the start of the recording but it's only used for playback_speed.
         //
-        // We'd then use a Rust crate to do the encoding (e.g. rav1e) and
+        // Ideally, we'd use a Rust crate to do the encoding (e.g. rav1e) and
         // pass the explicit frame times through (variable frame rate - VRF).
         //
         // The elapsed_time should be as close as possible to when the frame is
@@ -83,26 +252,126 @@ impl FfmpegPipe {
         //
         // Doing this should make it easier to synchronize video with audio from
         // my AudioMixer crate which uses a similar pattern.
-        command.arg("-framerate").arg("60");
+        command.arg("-framerate").arg(self.frame_rate.to_string());
 
         command.arg("-y").arg("-i").arg("-");
 
         let (output_filename, output_path) = self.output_filename_and_path();
+        let mut input_index = 0;
 
-        if let Some(wav_filename) = self.look_for_wav_file(&output_filename) {
+        // Files after the first start part way through the recording's audio.
+        let wav_input = self.look_for_wav_file(&output_filename).map(|wav_filename| {
+            if self.start_time > 0. { command.arg("-ss").arg(format!("{:.3}", self.start_time)); }
             command.arg("-i").arg(wav_filename);
+            input_index += 1; input_index
+        });
+
+        // Retimed files add them once they've been written (see mux_annotations).
+        let (subtitles_input, chapters_input) = match self.playback_speed {
+            None => self.annotation_inputs(&mut command, &output_path, input_index),
+            Some(_) => (None, None),
+        };
+
+        // Streams must be mapped explicitly once there are inputs other than audio.
+        if subtitles_input.is_some() || chapters_input.is_some() {
+            command.arg("-map").arg("0:v");
+
+            if let Some(i) = wav_input { command.arg("-map").arg(format!("{}:a", i)); }
+            map_annotations(&mut command, subtitles_input, chapters_input);
+        }
+
+        let filters = [self.resolution_filter(), self.color_filter()].into_iter().flatten().collect::<Vec<_>>();
+
+        if !filters.is_empty() {
+            command.arg("-vf").arg(filters.join(","));
+        }
+
+        if let Some(filter) = wav_input.and(self.audio_filter()) {
+            command.arg("-af").arg(filter);
+        }
+
+        if self.color_metadata {
+            command.arg("-colorspace").arg("bt709").arg("-color_primaries").arg("bt709");
+            command.arg("-color_trc").arg("iec61966-2-1").arg("-color_range").arg("tv");
         }
 
         for arg in &self.ffmpeg_args {
             command.arg(arg);
         }
 
-        command.arg(output_path);
+        command.arg(&output_path);
         command.stdin(Stdio::piped()).stdout(Stdio::piped());
 
         self.child = Some(command.spawn().unwrap());
+        self.output_path = Some(output_path);
+        self.frame_times.clear();
+    }
+
+    fn annotation_inputs(&mut self, command: &mut Command, output_path: &str, mut input_index: usize) -> (Option<usize>, Option<usize>) {
+        let subtitles_input = self.write_subtitles_file(output_path).map(|srt_path| {
+            command.arg("-i").arg(srt_path);
+            input_index += 1; input_index
+        });
+
+        let chapters_input = self.write_chapters_file(output_path).map(|metadata_path| {
+            command.arg("-i").arg(metadata_path);
+            input_index += 1; input_index
+        });
+
+        (subtitles_input, chapters_input)
+    }
+
+    // Retimed files only know when each frame is shown once they've been written
+    // so their subtitles and chapters are muxed in afterwards without re-encoding.
+    fn mux_annotations(&mut self, output_path: &str) {
+        let path = Path::new(output_path);
+        let muxing_path = path.with_file_name(format!("muxing-{}", path.file_name().unwrap().to_string_lossy()));
+
+        let mut command = Command::new("ffmpeg");
+        command.arg("-hide_banner").arg("-loglevel").arg("error").arg("-y").arg("-i").arg(output_path);
+
+        let (subtitles_input, chapters_input) = self.annotation_inputs(&mut command, output_path, 0);
+        if subtitles_input.is_none() && chapters_input.is_none() { return; }
+
+        command.arg("-map").arg("0").arg("-c").arg("copy");
+        map_annotations(&mut command, subtitles_input, chapters_input);
+        command.arg(&muxing_path);
+
+        let exit_status = command.status().unwrap();
+        if !exit_status.success() { panic!("ffmpeg exited with {} while adding subtitles and chapters", exit_status); }
+
+        fs::rename(muxing_path, output_path).unwrap();
+    }
+
+    // atempo changes the tempo by 0.5 to 2 times so it's chained for other speeds.
+    fn audio_filter(&self) -> Option<String> {
+        let mut speed = self.playback_speed? as f64;
+        let mut filters = vec![];
+
+        while speed > 2. { filters.push("atempo=2".to_string()); speed /= 2.; }
+        while speed < 0.5 { filters.push("atempo=0.5".to_string()); speed /= 0.5; }
+
+        filters.push(format!("atempo={}", speed));
+        Some(filters.join(","))
+    }
+
+    // ffmpeg reconfigures its filters when the size of the input changes so the
+    // output stays at the resolution the process was started with.
+    fn resolution_filter(&self) -> Option<String> {
+        let (width, height) = self.size?;
+
+        match self.resolution_change {
+            ResolutionChange::Split => None,
+            ResolutionChange::Scale => Some(format!("scale={}:{}", width, height)),
+            ResolutionChange::Pad => Some(format!("scale={0}:{1}:force_original_aspect_ratio=decrease,pad={0}:{1}:(ow-iw)/2:(oh-ih)/2", width, height)),
+        }
+    }
+
+    fn color_filter(&self) -> Option<String> {
+        if self.color_metadata { Some("scale=out_color_matrix=bt709:out_range=tv".to_string()) } else { None }
     }
 
+    // The filename isn't numbered so that every file finds the recording's audio.
     fn output_filename_and_path(&self) -> (String, String) {
         let directory = self.output_directory.clone().unwrap_or_else(|| ".".to_string());
 
@@ -113,11 +382,107 @@ impl FfmpegPipe {
             format!("{}.mp4", formatted)
         });
 
-        let path = Path::new(&directory).join(&filename).into_os_string().into_string().unwrap();
+        // Files after a resolution change are numbered, e.g. recorded-1.mp4.
+        let numbered = match filename.rsplit_once('.') {
+            Some((stem, extension)) if self.part > 0 => format!("{}-{}.{}", stem, self.part, extension),
+            None if self.part > 0 => format!("{}-{}", filename, self.part),
+            _ => filename.clone(),
+        };
+
+        let path = Path::new(&directory).join(&numbered).into_os_string().into_string().unwrap();
 
         (filename, path)
     }
 
+    fn write_subtitles_file(&mut self, output_path: &str) -> Option<String> {
+        if self.subtitles.is_empty() { return None; }
+
+        let mut srt = String::new();
+
+        for (i, subtitle) in self.annotations_in_file(&self.subtitles).enumerate() {
+            let start = srt_time(self.frame_start_millis(subtitle.start_frame));
+            let end = srt_time(self.frame_start_millis(subtitle.end_frame + 1));
+
+            srt.push_str(&format!("{}\n{} --> {}\n{}\n\n", i + 1, start, end, subtitle.text));
+        }
+
+        let path = format!("{}.srt", output_path);
+        fs::write(&path, srt).unwrap();
+
+        self.sidecar_paths.push(path.clone());
+        Some(path)
+    }
+
+    fn write_chapters_file(&mut self, output_path: &str) -> Option<String> {
+        if self.chapters.is_empty() { return None; }
+
+        let mut metadata = ";FFMETADATA1\n".to_string();
+
+        for chapter in self.annotations_in_file(&self.chapters) {
+            let start = self.frame_start_millis(chapter.start_frame);
+            let end = self.frame_start_millis(chapter.end_frame + 1);
+            let title = chapter.text.replace("\\", "\\\\").replace("=", "\\=").replace(";", "\\;").replace("#", "\\#").replace("\n", "\\\n");
+
+            metadata.push_str(&format!("\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n", start, end, title));
+        }
+
+        let path = format!("{}.ffmetadata", output_path);
+        fs::write(&path, metadata).unwrap();
+
+        self.sidecar_paths.push(path.clone());
+        Some(path)
+    }
+
+    // Renumbers the annotations relative to the first frame of the file.
+    fn annotations_in_file<'a>(&self, annotations: &'a [Annotation]) -> impl Iterator<Item=Annotation> + 'a {
+        let offset = self.first_frame - 1;
+
+        annotations.iter().filter(move |a| a.end_frame > offset).map(move |a| {
+            Annotation { start_frame: a.start_frame.max(offset + 1) - offset, end_frame: a.end_frame - offset, text: a.text.clone() }
+        })
+    }
+
+    // Retimed files look up where the frame was written (or the next frame that
+    // was if it was skipped) because frames aren't written once each.
+    fn frame_start_millis(&self, frame_number: usize) -> usize {
+        let output_frame = match self.playback_speed {
+            None => frame_number.saturating_sub(1),
+            Some(_) => {
+                let frame_number = frame_number + self.first_frame - 1; // Undo annotations_in_file.
+                self.frame_times.iter().find(|(n, _)| *n >= frame_number).map_or(self.frames_written, |(_, f)| *f)
+            },
+        };
+
+        output_frame * 1000 / self.frame_rate
+    }
+
+    // Waits for ffmpeg to finish writing the current file.
+    fn wait_for_process(&mut self) {
+        if !thread::panicking() { self.fill_gap(None); }
+
+        let mut child = match self.child.take() { Some(p) => p, _ => return };
+        let result = child.wait();
+
+        let output_path = self.output_path.take();
+        let succeeded = result.as_ref().map_or(false, |s| s.success());
+
+        if self.playback_speed.is_some() && succeeded && !thread::panicking() {
+            if let Some(output_path) = output_path { self.mux_annotations(&output_path); }
+        }
+
+        for path in self.sidecar_paths.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+
+        // Don't panic while panicking if stdin already closed (broken pipe).
+        if thread::panicking() { return; }
+
+        let exit_status = result.unwrap();
+        if !exit_status.success() {
+            panic!("ffmpeg exited with {}", exit_status);
+        }
+    }
+
     fn look_for_wav_file(&self, output_filename: &str) -> Option<String> {
         if let Some(directory) = self.audio_directory.as_ref() {
             let mut path_buf = Path::new(directory).join(output_filename).to_path_buf();
@@ -137,17 +502,93 @@ impl FfmpegPipe {
     }
 }
 
-impl Drop for FfmpegPipe {
-    fn drop(&mut self) {
-        let mut child = match self.child.take() { Some(p) => p, _ => return };
-        let result = child.wait();
+fn can_encode(args: &[&str]) -> bool {
+    let mut command = Command::new("ffmpeg");
 
-        // Don't panic while panicking if stdin already closed (broken pipe).
-        if thread::panicking() { return; }
+    command.args(["-hide_banner", "-loglevel", "error", "-f", "lavfi", "-i", "color=size=256x256:rate=1"]);
+    command.args(["-frames:v", "1"]).args(args).args(["-f", "null", "-"]);
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
 
-        let exit_status = result.unwrap();
-        if !exit_status.success() {
-            panic!("ffmpeg exited with {}", exit_status);
+    command.status().map(|s| s.success()).unwrap_or(false)
+}
+
+// Returns count images that fade from a to b or None if they can't be blended,
+// e.g. because the resolution changed in between.
+fn blend_pngs(a: &[u8], b: &[u8], count: usize) -> Option<Vec<Vec<u8>>> {
+    let (a_info, a_pixels) = decode_png(a)?;
+    let (b_info, b_pixels) = decode_png(b)?;
+
+    let layout = |i: &png::OutputInfo| (i.width, i.height, i.color_type, i.bit_depth);
+    if layout(&a_info) != layout(&b_info) || a_info.bit_depth != png::BitDepth::Eight { return None; }
+
+    let blended = (1..=count).map(|i| {
+        let t = i as f32 / (count + 1) as f32;
+        let pixels = a_pixels.iter().zip(&b_pixels).map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * t).round() as u8).collect::<Vec<_>>();
+
+        encode_png(&a_info, &pixels)
+    });
+
+    Some(blended.collect())
+}
+
+// Palettes are expanded so that the channels can be blended.
+fn decode_png(bytes: &[u8]) -> Option<(png::OutputInfo, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let mut reader = decoder.read_info().ok()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+
+    let info = reader.next_frame(&mut pixels).ok()?;
+    pixels.truncate(info.buffer_size());
+
+    Some((info, pixels))
+}
+
+fn encode_png(info: &png::OutputInfo, pixels: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut png = png::Encoder::new(&mut bytes, info.width, info.height);
+
+    png.set_color(info.color_type);
+    png.set_depth(info.bit_depth);
+    png.write_header().unwrap().write_image_data(pixels).unwrap();
+
+    bytes
+}
+
+fn map_annotations(command: &mut Command, subtitles_input: Option<usize>, chapters_input: Option<usize>) {
+    if let Some(i) = subtitles_input { command.arg("-map").arg(format!("{}:s", i)).arg("-c:s").arg("mov_text"); }
+    if let Some(i) = chapters_input { command.arg("-map_chapters").arg(i.to_string()); }
+}
+
+fn srt_time(millis: usize) -> String {
+    format!("{:02}:{:02}:{:02},{:03}", millis / 3_600_000, (millis % 3_600_000) / 60_000, (millis % 60_000) / 1000, millis % 1000)
+}
+
+impl FfmpegPreset {
+    // Encoders can be compiled into ffmpeg but unusable, e.g. h264_nvenc without
+    // an NVIDIA GPU or driver, so try encoding a single frame with each of them.
+    pub fn detect() -> Self {
+        if cfg!(target_os="macos") && can_encode(Self::VideoToolbox.args()) {
+            Self::VideoToolbox
+        } else if can_encode(Self::Nvenc.args()) {
+            Self::Nvenc
+        } else {
+            Self::X264Fast
+        }
+    }
+
+    pub fn args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Nvenc => &["-c:v", "h264_nvenc", "-preset", "p4", "-cq", "19", "-pix_fmt", "yuv420p", "-movflags", "+faststart"],
+            Self::VideoToolbox => &["-c:v", "h264_videotoolbox", "-b:v", "20M", "-pix_fmt", "yuv420p", "-movflags", "+faststart"],
+            Self::X264Fast => &["-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-pix_fmt", "yuv420p", "-movflags", "+faststart"],
         }
     }
 }
+
+impl Drop for FfmpegPipe {
+    fn drop(&mut self) {
+        self.wait_for_process();
+    }
+}
@@ -40,6 +40,84 @@ impl Format {
     pub fn bytes_per_texel(&self) -> u32 {
         self.channels() * self.bytes_per_channel()
     }
+
+    // Converts texels read back from the GPU to f32s. BgraU8 is reordered to RGBA
+    // and unsigned formats are normalized to 0..1 like they are in shaders.
+
+    pub fn to_f32(&self, bytes: &[u8]) -> Vec<f32> {
+        match self {
+            Self::RU8 | Self::RgbaU8 => bytes.iter().map(|&b| b as f32 / 255.).collect(),
+            Self::BgraU8 => bytes.chunks(4).flat_map(|c| [c[2], c[1], c[0], c[3]]).map(|b| b as f32 / 255.).collect(),
+            Self::RgbaF16 => bytes.chunks(2).map(|c| f16_to_f32(u16::from_le_bytes([c[0], c[1]]))).collect(),
+            Self::RgbaF32 => bytes.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect(),
+        }
+    }
+
+    // The inverse of to_f32. Unsigned formats are clamped to 0..1.
+
+    pub fn from_f32(&self, floats: &[f32]) -> Vec<u8> {
+        let to_u8 = |v: f32| (v.clamp(0., 1.) * 255. + 0.5) as u8;
+
+        match self {
+            Self::RU8 | Self::RgbaU8 => floats.iter().map(|&v| to_u8(v)).collect(),
+            Self::BgraU8 => floats.chunks(4).flat_map(|c| [c[2], c[1], c[0], c[3]]).map(to_u8).collect(),
+            Self::RgbaF16 => floats.iter().flat_map(|&v| f32_to_f16(v).to_le_bytes()).collect(),
+            Self::RgbaF32 => floats.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    // Converts texels to RgbaU8 for image encoders. Floats are clamped to 0..1 and
+    // RU8 is expanded to grey. If linear_to_srgb is set, the sRGB transfer function
+    // is applied to the color channels but not to alpha.
+
+    pub fn to_rgba_u8(&self, bytes: &[u8], linear_to_srgb: bool) -> Vec<u8> {
+        if let (Self::RgbaU8, false) = (self, linear_to_srgb) { return bytes.to_vec(); }
+
+        let floats = self.to_f32(bytes);
+        let channels = self.channels() as usize;
+
+        floats.chunks(channels).flat_map(|c| {
+            let rgba = if channels == 1 { [c[0], c[0], c[0], 1.] } else { [c[0], c[1], c[2], c[3]] };
+
+            let mut texel = [0; 4];
+            for (i, value) in rgba.iter().enumerate() {
+                let value = if linear_to_srgb && i < 3 { crate::linear_to_srgb(*value) } else { *value };
+                texel[i] = (value.clamp(0., 1.) * 255. + 0.5) as u8;
+            }
+            texel
+        }).collect()
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1. } else { -1. };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 => if mantissa == 0. { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1. + mantissa / 1024.) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Rounds towards zero and flushes values that are too small to zero.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if value.is_nan() { return sign | 0x7e00; }
+    if exponent >= 31 { return sign | 0x7c00; }
+    if exponent < -10 { return sign; }
+
+    if exponent <= 0 {
+        let subnormal = (mantissa | 0x80_0000) >> (14 - exponent);
+        return sign | subnormal as u16;
+    }
+
+    sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
 }
 
 impl Default for Format {
@@ -0,0 +1,27 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+// Signals once the GPU has finished the work that was submitted before it was
+// created, e.g. to know when it's safe to destroy or re-upload resources that
+// earlier frames used. It's signalled when the device is polled, which happens at
+// the end of each frame, so is_done can be checked every frame without stalling.
+// It can be checked from other threads, e.g. when it came from a RenderThread.
+
+#[derive(Clone)]
+pub struct FrameFence {
+    pub done: Arc<AtomicBool>,
+}
+
+impl FrameFence {
+    pub fn new(queue: &wgpu::Queue) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let done_clone = Arc::clone(&done);
+
+        queue.on_submitted_work_done(move || done_clone.store(true, Ordering::Release));
+
+        Self { done }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+}
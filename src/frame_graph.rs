@@ -0,0 +1,59 @@
+use std::{fmt::Write, rc};
+
+// Describes a frame as a Graphviz DOT graph to help debug multi-pass setups.
+// Pipelines are numbered in the order given (which should be the order they're
+// rendered in) and edges show which textures they sample and render into.
+// Render it with e.g. `dot -Tpng frame.dot -o frame.png`.
+
+pub struct FrameGraph;
+
+impl FrameGraph {
+    pub fn dot(pipelines: &[(&str, &crate::Pipeline)], grab_textures: &[crate::Texture]) -> String {
+        let mut textures: Vec<crate::Texture> = vec![];
+        let mut dot = String::from("digraph frame {\n  rankdir=LR;\n  screen [shape=doubleoctagon];\n");
+
+        let mut texture_node = |dot: &mut String, texture: &crate::Texture| {
+            if let Some(i) = textures.iter().position(|t| rc::Rc::ptr_eq(&t.inner, &texture.inner)) {
+                return format!("texture_{}", i);
+            }
+
+            let (width, height, layers) = texture.size();
+            let is_grab = grab_textures.iter().any(|t| rc::Rc::ptr_eq(&t.inner, &texture.inner));
+            let kind = if is_grab { "grab texture" } else { "texture" };
+
+            let id = format!("texture_{}", textures.len());
+            writeln!(dot, "  {} [shape=note, label=\"{} {}\\n{}x{}x{} {:?}\"];", id, kind, textures.len(), width, height, layers, texture.format).unwrap();
+
+            textures.push(texture.clone());
+            id
+        };
+
+        for (i, (name, pipeline)) in pipelines.iter().enumerate() {
+            let state = pipeline.inner.borrow();
+            let id = format!("pipeline_{}", i);
+
+            writeln!(dot, "  {} [shape=box, style=bold, label=\"{}. {}\\n{:?} {:?}\\nmsaa={}\"];", id, i + 1, name, state.blend_mode, state.primitive, state.msaa_samples).unwrap();
+
+            for (j, (texture, _)) in state.textures.iter().enumerate() {
+                let texture_id = texture_node(&mut dot, texture);
+                writeln!(dot, "  {} -> {} [style=dashed, label=\"samples {}\"];", texture_id, id, j).unwrap();
+            }
+
+            for target in &pipeline.targets {
+                let target_id = match target {
+                    crate::Target::Screen => "screen".to_string(),
+                    crate::Target::Texture(t) => texture_node(&mut dot, t),
+                };
+
+                writeln!(dot, "  {} -> {};", id, target_id).unwrap();
+            }
+
+            for (recording_id, _, _) in crate::attached_recordings(&state) {
+                writeln!(dot, "  recording_{0} [shape=cylinder, label=\"recording {0}\"];\n  {1} -> recording_{0};", recording_id.0, id).unwrap();
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
@@ -0,0 +1,85 @@
+use futures::executor;
+use std::sync::atomic;
+
+// Runs a pipeline setup for a number of frames on a headless renderer and reports
+// what happened, e.g. to turn the examples into regression tests or to test the
+// pipelines of an app. Each frame (and the setup) runs in a wgpu error scope so
+// that validation errors are collected instead of panicking. The setup function
+// creates pipelines, etc. and returns the function that renders each frame.
+//
+//   let report = renderer::Harness::run((64, 64), 3, |renderer| {
+//       let pipeline = renderer.pipeline(...);
+//       move |renderer, _frame_number| renderer.render(&pipeline, None, None, (1, 3))
+//   });
+//
+//   if let Some(report) = report { report.assert_ok(Some(3), Some(3)); }
+//
+// Harness::run returns None if there's no GPU adapter, e.g. on a CI machine.
+
+pub struct Harness;
+
+#[derive(Clone, Debug, Default)]
+pub struct HarnessReport {
+    pub frames: u64,
+    pub flushes: u64,  // Including those made by the setup function.
+    pub presents: u64, // Frames that rendered to the screen.
+    pub validation_errors: Vec<(u64, String)>, // (frame_number, error) where 0 is the setup.
+}
+
+impl Harness {
+    pub fn run<F: FnMut(&crate::Renderer<'static>, u64)>(size: (u32, u32), frames: u64, setup_function: impl FnOnce(&crate::Renderer<'static>) -> F) -> Option<HarnessReport> {
+        let renderer = crate::Renderer::try_new_headless(size, crate::RendererOptions::default())?;
+        Some(Self::run_with_renderer(&renderer, frames, setup_function))
+    }
+
+    // Runs on an existing renderer, e.g. one with a window or particular backends.
+    pub fn run_with_renderer<'a, F: FnMut(&crate::Renderer<'a>, u64)>(renderer: &crate::Renderer<'a>, frames: u64, setup_function: impl FnOnce(&crate::Renderer<'a>) -> F) -> HarnessReport {
+        let flushes_before = renderer.flushes.load(atomic::Ordering::Relaxed);
+        let presents_before = renderer.presents.load(atomic::Ordering::Relaxed);
+
+        let mut report = HarnessReport { frames, ..HarnessReport::default() };
+
+        renderer.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let mut frame_function = setup_function(renderer);
+        pop_error_scope(renderer, 0, &mut report);
+
+        for frame_number in 1..=frames {
+            renderer.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+            frame_function(renderer, frame_number);
+            renderer.finish_frame();
+
+            pop_error_scope(renderer, frame_number, &mut report);
+        }
+
+        report.flushes = renderer.flushes.load(atomic::Ordering::Relaxed) - flushes_before;
+        report.presents = renderer.presents.load(atomic::Ordering::Relaxed) - presents_before;
+
+        report
+    }
+}
+
+impl HarnessReport {
+    // Panics if there were validation errors or the counts don't match. Counts
+    // that are None aren't checked.
+    pub fn assert_ok(&self, expected_flushes: Option<u64>, expected_presents: Option<u64>) {
+        if let Some((frame_number, error)) = self.validation_errors.first() {
+            panic!("{} validation error(s) occurred. The first was in frame {}: {}", self.validation_errors.len(), frame_number, error);
+        }
+
+        if let Some(flushes) = expected_flushes {
+            assert_eq!(self.flushes, flushes, "The renderer flushed {} times but {} were expected.", self.flushes, flushes);
+        }
+
+        if let Some(presents) = expected_presents {
+            assert_eq!(self.presents, presents, "The renderer presented {} times but {} were expected.", self.presents, presents);
+        }
+    }
+}
+
+// Each error scope only captures its first error.
+fn pop_error_scope(renderer: &crate::Renderer, frame_number: u64, report: &mut HarnessReport) {
+    if let Some(error) = executor::block_on(renderer.device.pop_error_scope()) {
+        report.validation_errors.push((frame_number, error.to_string()));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fs, thread, path::Path, io::BufWriter};
+use crossbeam_channel::{Sender, Receiver};
+
+// Writes numbered image files (e.g. frame_000001.png) because many VFX tools
+// ingest image sequences rather than videos. Files are numbered from the frame
+// number so frames can be encoded in parallel and the timing is preserved.
+// Frames without image data (dropped or missing) are skipped.
+
+pub struct ImageSequenceWriter {
+    pub directory: String,
+    pub image_format: ImageFormat,
+    pub zero_padding: usize,
+    pub start_index: usize,
+    pub threads: Vec<thread::JoinHandle<()>>,
+    pub sender: Option<Sender<crate::VideoFrame>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ImageFormat {
+    Png,
+    #[cfg(feature="frame_to_jpeg")] Jpeg { quality: u8 },
+}
+
+impl ImageSequenceWriter {
+    pub fn new(directory: &str, image_format: ImageFormat, zero_padding: usize, start_index: usize, num_threads: usize) -> Self {
+        fs::create_dir_all(directory).unwrap();
+
+        // Bound the queue so that the caller is slowed down rather than filling up memory.
+        let (sender, receiver) = crossbeam_channel::bounded(num_threads * 2);
+
+        let mut writer = Self { directory: directory.to_string(), image_format, zero_padding, start_index, threads: vec![], sender: Some(sender) };
+        writer.threads = (0..num_threads.max(1)).map(|_| spawn_thread(&receiver, &writer)).collect();
+
+        writer
+    }
+
+    pub fn write(&self, video_frame: crate::VideoFrame) {
+        if video_frame.image_data.is_none() {
+            eprintln!("Warning: Frame {} is {}. Skipping it in the image sequence.", video_frame.frame_number, video_frame.status);
+            return;
+        }
+
+        self.sender.as_ref().unwrap().send(video_frame).unwrap();
+    }
+
+    pub fn path(&self, frame_number: usize) -> String {
+        image_path(&self.directory, self.image_format, self.zero_padding, self.start_index + frame_number - 1)
+    }
+
+    pub fn finish(&mut self) {
+        if self.sender.is_none() { return; }
+
+        // Disconnect the channel so that the worker threads break.
+        let sender = self.sender.take().unwrap();
+        drop(sender);
+
+        // Wait for the worker threads to exit.
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ImageSequenceWriter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            #[cfg(feature="frame_to_jpeg")] Self::Jpeg { .. } => "jpg",
+        }
+    }
+}
+
+fn image_path(directory: &str, image_format: ImageFormat, zero_padding: usize, index: usize) -> String {
+    let filename = format!("frame_{:0width$}.{}", index, image_format.extension(), width = zero_padding);
+
+    Path::new(directory).join(filename).into_os_string().into_string().unwrap()
+}
+
+fn spawn_thread(receiver: &Receiver<crate::VideoFrame>, writer: &ImageSequenceWriter) -> thread::JoinHandle<()> {
+    let receiver = receiver.clone();
+    let directory = writer.directory.clone();
+    let (image_format, zero_padding, start_index) = (writer.image_format, writer.zero_padding, writer.start_index);
+
+    thread::spawn(move || {
+        loop {
+            let video_frame = match receiver.recv() { Ok(f) => f, _ => break };
+            let path = image_path(&directory, image_format, zero_padding, start_index + video_frame.frame_number - 1);
+
+            match image_format {
+                ImageFormat::Png => {
+                    let file = fs::File::create(path).unwrap();
+                    crate::PngEncoder::encode(&video_frame, BufWriter::new(file)).unwrap();
+                },
+                #[cfg(feature="frame_to_jpeg")] ImageFormat::Jpeg { quality } => {
+                    encode_jpeg(&video_frame, &path, quality);
+                },
+            }
+        }
+    })
+}
+
+#[cfg(feature="frame_to_jpeg")]
+fn encode_jpeg(video_frame: &crate::VideoFrame, path: &str, quality: u8) {
+    let mut unpadded_bytes = Vec::with_capacity(video_frame.width * video_frame.height * 4);
+
+    video_frame.image_data.as_ref().unwrap().bytes_fn(|bytes| {
+        for row in bytes.chunks(video_frame.padded_bytes_per_row) {
+            unpadded_bytes.extend(video_frame.format.to_rgba_u8(&row[..video_frame.unpadded_bytes_per_row], false));
+        }
+    });
+
+    let (width, height) = video_frame.full_size();
+    let rgba = video_frame.upscale_rgba(unpadded_bytes);
+
+    let encoder = jpeg_encoder::Encoder::new_file(path, quality).unwrap();
+    encoder.encode(&rgba, width as u16, height as u16, jpeg_encoder::ColorType::Rgba).unwrap();
+}
@@ -0,0 +1,163 @@
+use std::{fs, mem, time, path::Path, io::{Write, BufWriter}};
+use chrono::{DateTime, SecondsFormat, Utc};
+use winit::event::{WindowEvent, ElementState, MouseButton, MouseScrollDelta};
+use winit::keyboard::PhysicalKey;
+
+// Records input events alongside a Compressor's files so that sessions can be
+// replayed deterministically or exported with keypress overlays. Events are
+// written to a sidecar file (.szk) named after the Compressor's timestamp and
+// are tagged with the frame number they affect. Call finish_frame each time the
+// renderer finishes a frame so that frame numbers line up with the recording.
+
+pub struct InputRecorder {
+    pub writer: BufWriter<fs::File>,
+    pub started_at: time::Instant,
+    pub frame_number: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, bincode::Encode, bincode::Decode)]
+pub struct InputEvent {
+    pub frame_number: usize,
+    pub elapsed_micros: u64,
+    pub kind: InputKind,
+}
+
+// Keys are the names of winit's physical KeyCodes, e.g. "KeyA" or "Space".
+#[derive(Clone, Debug, PartialEq, bincode::Encode, bincode::Decode)]
+pub enum InputKind {
+    KeyPressed(String),
+    KeyReleased(String),
+    CursorMoved(f64, f64),
+    MousePressed(u32),
+    MouseReleased(u32),
+    MouseWheel(f32, f32),
+    Focused(bool),
+    Resized(u32, u32),
+}
+
+impl InputRecorder {
+    // The timestamp should be the Compressor's so that the Decompressor's sessions
+    // can be matched up with their input events.
+    pub fn new(directory: &str, timestamp: &str) -> Self {
+        fs::create_dir_all(directory).unwrap();
+
+        let file = fs::File::create(path(directory, timestamp)).unwrap();
+
+        Self { writer: BufWriter::new(file), started_at: time::Instant::now(), frame_number: 1 }
+    }
+
+    // Returns true if the event was recorded. Other events (e.g. redraws) are ignored.
+    pub fn record(&mut self, event: &WindowEvent) -> bool {
+        let kind = match InputKind::from_window_event(event) { Some(k) => k, _ => return false };
+        let elapsed_micros = self.started_at.elapsed().as_micros() as u64;
+
+        let input_event = InputEvent { frame_number: self.frame_number, elapsed_micros, kind };
+        let bytes = bincode::encode_to_vec(&input_event, crate::compressor::encoding_config()).unwrap();
+
+        self.writer.write_all(&(bytes.len() as u64).to_be_bytes()).unwrap();
+        self.writer.write_all(&bytes).unwrap();
+
+        true
+    }
+
+    pub fn finish_frame(&mut self) {
+        self.frame_number += 1;
+    }
+
+    pub fn flush(&mut self) {
+        self.writer.flush().unwrap();
+    }
+
+    // Reads the input events for one of the Decompressor's sessions in the order
+    // they were recorded. Returns an empty Vec if there's no .szk file for it.
+    pub fn read(directory: &str, session: &DateTime<Utc>) -> Vec<InputEvent> {
+        let path = Path::new(directory).join(sidecar_filename(session));
+        let bytes = match fs::read(path) { Ok(b) => b, _ => return vec![] };
+
+        let mut events = vec![];
+        let mut offset = 0;
+
+        // Stop at a truncated event in case the process didn't exit cleanly.
+        while offset + U64_LEN <= bytes.len() {
+            let len = u64::from_be_bytes(bytes[offset..offset + U64_LEN].try_into().unwrap()) as usize;
+            offset += U64_LEN;
+
+            if offset + len > bytes.len() { break; }
+
+            let result = bincode::decode_from_slice(&bytes[offset..offset + len], crate::compressor::encoding_config());
+            let input_event = match result { Ok((e, _)) => e, _ => break };
+
+            events.push(input_event);
+            offset += len;
+        }
+
+        events
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+impl InputKind {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        let kind = match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.repeat { return None; }
+
+                let key = match event.physical_key { PhysicalKey::Code(c) => format!("{:?}", c), _ => return None };
+
+                match event.state {
+                    ElementState::Pressed => Self::KeyPressed(key),
+                    ElementState::Released => Self::KeyReleased(key),
+                }
+            },
+            WindowEvent::CursorMoved { position, .. } => Self::CursorMoved(position.x, position.y),
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = mouse_button_number(*button);
+
+                match state {
+                    ElementState::Pressed => Self::MousePressed(button),
+                    ElementState::Released => Self::MouseReleased(button),
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => Self::MouseWheel(*x, *y),
+                MouseScrollDelta::PixelDelta(p) => Self::MouseWheel(p.x as f32, p.y as f32),
+            },
+            WindowEvent::Focused(focused) => Self::Focused(*focused),
+            WindowEvent::Resized(size) => Self::Resized(size.width, size.height),
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
+// 0=left, 1=right, 2=middle, 3=back, 4=forward, otherwise 5 + winit's Other(n)
+// so that other buttons don't collide with the named ones.
+fn mouse_button_number(button: MouseButton) -> u32 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Back => 3,
+        MouseButton::Forward => 4,
+        MouseButton::Other(n) => OTHER_BUTTONS + n as u32,
+    }
+}
+
+fn path(directory: &str, timestamp: &str) -> String {
+    Path::new(directory).join(format!("{}.szk", timestamp)).into_os_string().into_string().unwrap()
+}
+
+// The Decompressor also uses this to remove the sidecar with the session's files.
+pub(crate) fn sidecar_filename(session: &DateTime<Utc>) -> String {
+    format!("{}.szk", session.to_rfc3339_opts(SecondsFormat::Millis, true).replace(":", "_"))
+}
+
+const OTHER_BUTTONS: u32 = 5;
+
+const U64_LEN: usize = mem::size_of::<u64>();
@@ -0,0 +1,67 @@
+// Reorders instance data by a key before it's uploaded, e.g. so that transparent
+// sprites are drawn back-to-front. Instance data is laid out like for Culling,
+// with stride f32s per instance. The order from the previous call is sorted
+// again rather than starting over. Instances rarely change places between frames
+// so it's almost sorted already, which the sort takes close to linear time for.
+
+#[derive(Clone, Debug)]
+pub struct InstanceSorter {
+    pub key: SortKey,
+    pub descending: bool,
+    pub keys: Vec<f32>,
+    pub order: Vec<u32>, // Indexes of the instances in sorted order.
+    pub sorted: Vec<f32>,
+}
+
+// Sorts by one component of each instance (e.g. its y coordinate for top-down
+// games) or by the distance of its position from a point (e.g. the camera).
+#[derive(Clone, Debug)]
+pub enum SortKey { Component(usize), Distance(Vec<f32>) }
+
+impl InstanceSorter {
+    // Sort by distance in descending order to draw back-to-front.
+    pub fn new(key: SortKey, descending: bool) -> Self {
+        Self { key, descending, keys: vec![], order: vec![], sorted: vec![] }
+    }
+
+    pub fn set_key(&mut self, key: SortKey) {
+        self.key = key;
+    }
+
+    pub fn sort(&mut self, data: &[f32], stride: usize) -> &[f32] {
+        let min_stride = match &self.key { SortKey::Component(i) => i + 1, SortKey::Distance(point) => point.len() };
+
+        assert!(stride >= min_stride, "The stride must be at least {} to fit the key.", min_stride);
+        assert_eq!(data.len() % stride, 0, "The data length must be a multiple of the stride.");
+
+        self.keys.clear();
+        self.keys.extend(data.chunks(stride).map(|instance| match &self.key {
+            SortKey::Component(i) => instance[*i],
+            SortKey::Distance(point) => point.iter().zip(instance).map(|(p, c)| (c - p) * (c - p)).sum(),
+        }));
+
+        // Start over if instances were added or removed since the last call.
+        if self.order.len() != self.keys.len() {
+            self.order.clear();
+            self.order.extend(0..self.keys.len() as u32);
+        }
+
+        let keys = &self.keys;
+        let descending = self.descending;
+
+        self.order.sort_by(|&a, &b| {
+            let ordering = keys[a as usize].total_cmp(&keys[b as usize]);
+            if descending { ordering.reverse() } else { ordering }
+        });
+
+        self.sorted.clear();
+        self.sorted.reserve(data.len());
+
+        for &index in &self.order {
+            let start = index as usize * stride;
+            self.sorted.extend_from_slice(&data[start..start + stride]);
+        }
+
+        &self.sorted
+    }
+}
@@ -1,33 +1,50 @@
 use std::num;
 
+// Instanced data is a read-only storage buffer for the vertex shader. Storage
+// buffers can also be read-write and visible to either shader so that shaders
+// can write results for later renders, e.g. for GPU simulations. These are bound
+// in the instances slots and declared in GLSL as `buffer Name { ... };` without
+// the readonly qualifier (or var<storage, read_write> in WGSL).
 #[derive(Clone)]
 pub struct Instanced {
     pub buffer: crate::Buffer,
+    pub access: StorageAccess,
+    pub visibility: crate::Visibility,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageAccess {
+    ReadOnly,
+    ReadWrite,
 }
 
 impl Instanced {
     pub fn new(device: &wgpu::Device) -> Self {
+        Self::new_storage_buffer(device, StorageAccess::ReadOnly, crate::Visibility::VertexShader)
+    }
+
+    pub fn new_storage_buffer(device: &wgpu::Device, access: StorageAccess, visibility: crate::Visibility) -> Self {
         let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
         let buffer = crate::Buffer::new(device, usage);
 
-        Self { buffer }
+        Self { buffer, access, visibility }
     }
 
-    pub fn binding(&self, id: u32) -> (wgpu::BindGroupEntry, wgpu::BindGroupLayoutEntry) {
-        let layout = instanced_binding_layout(id, &self.buffer);
-        let binding = instanced_binding(id, &self.buffer, self.buffer.inner.borrow().size);
+    pub fn binding<'a>(&self, buffer: &'a wgpu::Buffer, id: u32) -> (wgpu::BindGroupEntry<'a>, wgpu::BindGroupLayoutEntry) {
+        let layout = instanced_binding_layout(id, &self.buffer, self.access, &self.visibility);
+        let binding = instanced_binding(id, buffer, self.buffer.size());
 
         (binding, layout)
     }
 }
 
-fn instanced_binding_layout(id: u32, buffer: &crate::Buffer) -> wgpu::BindGroupLayoutEntry {
-    let size = num::NonZeroU64::new(buffer.inner.borrow().size as u64);
-    let storage = wgpu::BufferBindingType::Storage { read_only: true };
+fn instanced_binding_layout(id: u32, buffer: &crate::Buffer, access: StorageAccess, visibility: &crate::Visibility) -> wgpu::BindGroupLayoutEntry {
+    let size = num::NonZeroU64::new(buffer.size() as u64);
+    let storage = wgpu::BufferBindingType::Storage { read_only: access == StorageAccess::ReadOnly };
 
     let ty = wgpu::BindingType::Buffer { ty: storage, has_dynamic_offset: false, min_binding_size: size };
 
-    wgpu::BindGroupLayoutEntry { binding: id, visibility: wgpu::ShaderStages::VERTEX, ty, count: None }
+    wgpu::BindGroupLayoutEntry { binding: id, visibility: visibility.shader_stage(), ty, count: None }
 }
 
 fn instanced_binding(id: u32, buffer: &wgpu::Buffer, size: usize) -> wgpu::BindGroupEntry {
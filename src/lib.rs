@@ -1,48 +1,121 @@
-#![feature(extract_if)]
+#[macro_use] mod span;
 
 mod attribute;
+mod backend;
 mod blend_mode;
 mod buffer;
+mod bundle;
+mod camera_relative;
 mod clear_color;
+mod clear_color_animation;
+mod color;
+mod cursor;
+mod culling;
+mod delta_encoder;
+mod depth_buffer;
+mod downscaler;
 mod filter_mode;
 mod format;
+mod frame_fence;
+mod frame_graph;
+mod instance_sorter;
 mod instanced;
+mod material;
+mod memory_budget;
+mod nine_slice;
+mod overlay;
+mod particle_system;
 mod pipeline;
+mod pixel_reader;
 mod primitive;
 mod program;
+mod rasterization;
+mod readback_scheduler;
 mod renderer;
+mod resampler;
+mod retained_frame;
 mod render_pass;
+mod skeleton;
+mod stencil;
 mod target;
 mod texture;
+mod texture_array;
+mod texture_streamer;
+mod texture_upload;
+mod tiled_render;
+mod transparency;
 mod uniform;
+mod uniform_layout;
+mod validation;
 mod video_frame;
 mod video_recorder;
 mod viewport;
 mod visibility;
 
 pub use attribute::*;
+pub use backend::*;
 pub use blend_mode::*;
 pub use buffer::*;
+pub use bundle::*;
+pub use camera_relative::*;
 pub use clear_color::*;
+pub use clear_color_animation::*;
+pub use color::*;
+pub use cursor::*;
+pub use culling::*;
+pub(crate) use delta_encoder::*;
+pub use depth_buffer::*;
+pub(crate) use downscaler::*;
 pub use filter_mode::*;
 pub use format::*;
+pub use frame_fence::*;
+pub use frame_graph::*;
+pub use instance_sorter::*;
 pub use instanced::*;
+pub use material::*;
+pub use memory_budget::*;
+pub use nine_slice::*;
+pub use overlay::*;
+pub use particle_system::*;
 pub use pipeline::*;
+pub use pixel_reader::*;
 pub use primitive::*;
 pub use program::*;
+pub use rasterization::*;
+pub use readback_scheduler::*;
 pub use renderer::*;
+pub(crate) use resampler::*;
+pub use retained_frame::*;
 pub use render_pass::*;
+pub use skeleton::*;
+pub use stencil::*;
 pub use target::*;
 pub use texture::*;
+pub use texture_array::*;
+pub use texture_streamer::*;
+pub use texture_upload::*;
+pub use tiled_render::*;
+pub use transparency::*;
 pub use uniform::*;
+pub use uniform_layout::*;
+pub use validation::*;
 pub use video_frame::*;
 pub use video_recorder::*;
 pub use viewport::*;
 pub use visibility::*;
 
+#[cfg(feature="app")] mod app;
+#[cfg(feature="app")] pub use app::*;
+
+#[cfg(feature="harness")] mod harness;
+#[cfg(feature="harness")] pub use harness::*;
+
 #[cfg(feature="render_thread")] mod render_thread;
 #[cfg(feature="render_thread")] pub use render_thread::*;
 
+#[cfg(feature="remote_control")] mod remote_control;
+#[cfg(feature="remote_control")] pub use remote_control::*;
+
 #[cfg(feature="shader_compilation")] mod compiler;
 #[cfg(feature="shader_compilation")] pub use compiler::*;
 
@@ -52,8 +125,45 @@ pub use visibility::*;
 #[cfg(feature="frame_compression")] mod decompressor;
 #[cfg(feature="frame_compression")] pub use decompressor::*;
 
+#[cfg(feature="frame_compression")] mod player;
+#[cfg(feature="frame_compression")] pub use player::*;
+
+#[cfg(feature="frame_encryption")] mod encryption;
+#[cfg(feature="frame_encryption")] pub use encryption::*;
+
+#[cfg(feature="frame_compression")] mod input_recorder;
+#[cfg(feature="frame_compression")] pub use input_recorder::*;
+
+#[cfg(feature="frame_compression")] mod replay_buffer;
+#[cfg(feature="frame_compression")] pub use replay_buffer::*;
+
 #[cfg(feature="frame_to_png")] mod png_encoder;
 #[cfg(feature="frame_to_png")] pub use png_encoder::*;
 
+#[cfg(feature="frame_to_png")] mod png_encoder_pool;
+#[cfg(feature="frame_to_png")] pub use png_encoder_pool::*;
+
+#[cfg(feature="frame_to_png")] mod image_sequence_writer;
+#[cfg(feature="frame_to_png")] pub use image_sequence_writer::*;
+
+#[cfg(feature="frame_to_exr")] mod exr_encoder;
+#[cfg(feature="frame_to_exr")] pub use exr_encoder::*;
+
+#[cfg(feature="frame_watermark")] mod watermark;
+#[cfg(feature="frame_watermark")] pub use watermark::*;
+
 #[cfg(feature="pipe_to_ffmpeg")] mod ffmpeg_pipe;
 #[cfg(feature="pipe_to_ffmpeg")] pub use ffmpeg_pipe::*;
+
+#[cfg(feature="pipeline_statistics")] mod pipeline_statistics;
+#[cfg(feature="pipeline_statistics")] pub use pipeline_statistics::*;
+
+#[cfg(feature="ffmpeg_screen_capture")] mod screen_capture;
+#[cfg(feature="ffmpeg_screen_capture")] pub use screen_capture::*;
+
+#[cfg(feature="shapes")] mod shapes;
+#[cfg(feature="shapes")] pub use shapes::*;
+#[cfg(feature="shapes")] pub use lyon;
+
+#[cfg(feature="video_playback")] mod video_player;
+#[cfg(feature="video_playback")] pub use video_player::*;
@@ -0,0 +1,158 @@
+// A small library of built-in shaders for drawing basic primitives without the
+// shader_compilation feature or authoring GLSL. They're embedded as WGSL. Each
+// material's program has x, y positions in clip space at attribute 0 and:
+//
+//   SolidColor:     a uniform at set 0, binding 0 (see solid_color_data)
+//   LinearGradient: a uniform at set 0, binding 0 (see linear_gradient_data)
+//   RadialGradient: a uniform at set 0, binding 0 (see radial_gradient_data)
+//   Textured:       texture coordinates at attribute 1 and the texture at set 0,
+//                   binding 0 (its sampler is at binding 1)
+//
+// Gradients are clamped beyond their ends. Colors are sRGB-encoded (see Color) and
+// aren't pre-multiplied so use a blend mode that matches, or call pre_multiplied.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Material {
+    SolidColor,
+    LinearGradient,
+    RadialGradient,
+    Textured,
+}
+
+impl Material {
+    pub const A_POSITION: usize = 0;
+    pub const A_TEX_COORD: usize = 1;
+    pub const U_PAINT: (usize, usize) = (0, 0);
+    pub const T_TEXTURE: (usize, usize) = (0, 0);
+
+    pub fn vertex_shader(&self) -> &'static str {
+        match self {
+            Self::Textured => TEXTURED_VERTEX_SHADER,
+            _ => VERTEX_SHADER,
+        }
+    }
+
+    pub fn fragment_shader(&self) -> &'static str {
+        match self {
+            Self::SolidColor => SOLID_COLOR_SHADER,
+            Self::LinearGradient => LINEAR_GRADIENT_SHADER,
+            Self::RadialGradient => RADIAL_GRADIENT_SHADER,
+            Self::Textured => TEXTURED_FRAGMENT_SHADER,
+        }
+    }
+
+    // The texture must have a sampler. Other materials don't take a texture.
+    pub fn program(&self, renderer: &crate::Renderer, texture: Option<crate::Texture>) -> crate::Program {
+        let (vert, frag) = (self.vertex_shader(), self.fragment_shader());
+        let position = renderer.attribute(Self::A_POSITION, 2);
+
+        match (self, texture) {
+            (Self::Textured, Some(texture)) => {
+                if texture.sampler.is_none() { panic!("The texture for a textured material must have a sampler."); }
+
+                let tex_coord = renderer.attribute(Self::A_TEX_COORD, 2);
+                renderer.program_wgsl(vert, frag, vec![position, tex_coord], vec![], vec![], vec![(texture, crate::Visibility::FragmentShader)])
+            },
+            (Self::Textured, None) => panic!("A textured material needs a texture."),
+            (_, Some(_)) => panic!("Only the textured material takes a texture."),
+            (_, None) => {
+                renderer.program_wgsl(vert, frag, vec![position], vec![], vec![(renderer.uniform(), crate::Visibility::FragmentShader)], vec![])
+            },
+        }
+    }
+
+    pub fn solid_color_data(color: crate::Color) -> Vec<f32> {
+        color.to_srgb().to_vec()
+    }
+
+    pub fn linear_gradient_data(from: (f32, f32), from_color: crate::Color, to: (f32, f32), to_color: crate::Color) -> Vec<f32> {
+        [from_color.to_srgb(), to_color.to_srgb(), [from.0, from.1, to.0, to.1]].concat()
+    }
+
+    pub fn radial_gradient_data(center: (f32, f32), radius: f32, inner_color: crate::Color, outer_color: crate::Color) -> Vec<f32> {
+        [inner_color.to_srgb(), outer_color.to_srgb(), [center.0, center.1, radius, 0.]].concat()
+    }
+}
+
+// The position is passed through so gradients can be computed per fragment.
+const VERTEX_SHADER: &str = "
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) xy: vec2<f32>,
+}
+
+@vertex
+fn main(@location(0) position: vec2<f32>) -> VertexOutput {
+    return VertexOutput(vec4<f32>(position, 0.0, 1.0), position);
+}
+";
+
+const SOLID_COLOR_SHADER: &str = "
+@group(0) @binding(0) var<uniform> color: vec4<f32>;
+
+@fragment
+fn main(@location(0) xy: vec2<f32>) -> @location(0) vec4<f32> {
+    return color;
+}
+";
+
+const LINEAR_GRADIENT_SHADER: &str = "
+struct Gradient {
+    from_color: vec4<f32>,
+    to_color: vec4<f32>,
+    line: vec4<f32>, // from.xy, to.xy
+}
+
+@group(0) @binding(0) var<uniform> gradient: Gradient;
+
+@fragment
+fn main(@location(0) xy: vec2<f32>) -> @location(0) vec4<f32> {
+    let direction = gradient.line.zw - gradient.line.xy;
+    let length_squared = dot(direction, direction);
+
+    var t = 0.0;
+    if (length_squared > 0.0) { t = clamp(dot(xy - gradient.line.xy, direction) / length_squared, 0.0, 1.0); }
+
+    return mix(gradient.from_color, gradient.to_color, t);
+}
+";
+
+const RADIAL_GRADIENT_SHADER: &str = "
+struct Gradient {
+    inner_color: vec4<f32>,
+    outer_color: vec4<f32>,
+    circle: vec4<f32>, // center.xy, radius, unused
+}
+
+@group(0) @binding(0) var<uniform> gradient: Gradient;
+
+@fragment
+fn main(@location(0) xy: vec2<f32>) -> @location(0) vec4<f32> {
+    var t = 1.0;
+    if (gradient.circle.z > 0.0) { t = clamp(distance(xy, gradient.circle.xy) / gradient.circle.z, 0.0, 1.0); }
+
+    return mix(gradient.inner_color, gradient.outer_color, t);
+}
+";
+
+const TEXTURED_VERTEX_SHADER: &str = "
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+}
+
+@vertex
+fn main(@location(0) position: vec2<f32>, @location(1) tex_coord: vec2<f32>) -> VertexOutput {
+    return VertexOutput(vec4<f32>(position, 0.0, 1.0), tex_coord);
+}
+";
+
+const TEXTURED_FRAGMENT_SHADER: &str = "
+@group(0) @binding(0) var t_texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+@fragment
+fn main(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(t_texture, texture_sampler, tex_coord);
+}
+";
@@ -0,0 +1,143 @@
+use std::{cell, rc};
+use std::sync::{atomic, Arc, Mutex};
+
+// Tracks the GPU memory used by the textures and buffers that the renderer makes
+// and calls on_out_of_memory when they exceed the budget so that applications
+// can degrade (e.g. drop mip levels or shrink targets) instead of crashing. It
+// is also called if wgpu reports that it ran out of memory. The function is
+// called once each time the budget is exceeded, not on every frame after.
+
+pub struct MemoryBudget {
+    pub budget_in_bytes: u64,
+    pub on_out_of_memory: OutOfMemoryFunction,
+    pub exceeded: bool,
+    pub device_out_of_memory: Arc<atomic::AtomicBool>,
+}
+
+pub type OutOfMemoryFunction = Box<dyn FnMut(&crate::Renderer, &MemoryReport)>;
+
+// wgpu only has one uncaptured error handler per device and doesn't return the
+// previous one so the app's handler is kept here (see Renderer::on_uncaptured_error).
+pub type ErrorHandler = Arc<Mutex<Option<Box<dyn wgpu::UncapturedErrorHandler>>>>;
+
+pub struct MemoryReport {
+    pub budget_in_bytes: u64,
+    pub used_in_bytes: u64,
+    pub device_out_of_memory: bool,
+    pub top_consumers: Vec<MemoryConsumer>, // Largest first.
+}
+
+pub struct MemoryConsumer {
+    pub resource: Resource,
+    pub size_in_bytes: u64,
+}
+
+// Compare these with a texture or buffer's inner Rc to find out which it is.
+pub enum Resource {
+    Texture(rc::Rc<cell::RefCell<crate::InnerT>>),
+    Buffer(rc::Rc<cell::RefCell<crate::InnerB>>),
+}
+
+// The textures are stored alongside their bytes per texel (times msaa samples)
+// because their format isn't part of InnerT.
+#[derive(Default)]
+pub struct MemoryTracker {
+    pub textures: Vec<(rc::Weak<cell::RefCell<crate::InnerT>>, u64)>,
+    pub buffers: Vec<rc::Weak<cell::RefCell<crate::InnerB>>>,
+}
+
+impl MemoryBudget {
+    pub fn new(device: &wgpu::Device, budget_in_megabytes: f32, on_out_of_memory: OutOfMemoryFunction, error_handler: &ErrorHandler) -> Self {
+        let budget_in_bytes = (budget_in_megabytes as f64 * 1024. * 1024.) as u64;
+        let device_out_of_memory = Arc::new(atomic::AtomicBool::new(false));
+
+        // Other errors go to the app's handler, if it set one.
+        let (flag, error_handler) = (Arc::clone(&device_out_of_memory), Arc::clone(error_handler));
+        device.on_uncaptured_error(Box::new(move |error| match error {
+            wgpu::Error::OutOfMemory { .. } => flag.store(true, atomic::Ordering::Relaxed),
+            _ => forward_error(&error_handler, error),
+        }));
+
+        Self { budget_in_bytes, on_out_of_memory, exceeded: false, device_out_of_memory }
+    }
+
+    // Returns the report if on_out_of_memory should be called.
+    pub fn check(&mut self, tracker: &MemoryTracker) -> Option<MemoryReport> {
+        let device_out_of_memory = self.device_out_of_memory.swap(false, atomic::Ordering::Relaxed);
+        let used_in_bytes = tracker.used_in_bytes();
+
+        let was_exceeded = self.exceeded;
+        self.exceeded = used_in_bytes > self.budget_in_bytes;
+
+        if !device_out_of_memory && (!self.exceeded || was_exceeded) { return None; }
+
+        Some(MemoryReport { budget_in_bytes: self.budget_in_bytes, used_in_bytes, device_out_of_memory, top_consumers: tracker.top_consumers(TOP_CONSUMERS) })
+    }
+}
+
+// Calls the app's handler or panics like wgpu's default handler if there isn't one.
+pub fn forward_error(error_handler: &ErrorHandler, error: wgpu::Error) {
+    match &*error_handler.lock().unwrap() {
+        Some(handler) => handler(error),
+        None => panic!("wgpu error: {}", error),
+    }
+}
+
+impl MemoryTracker {
+    pub fn track_texture(&mut self, texture: &crate::Texture) {
+        let bytes_per_texel = texture.format.bytes_per_texel() as u64 * texture.msaa_samples as u64;
+        self.textures.push((rc::Rc::downgrade(&texture.inner), bytes_per_texel));
+    }
+
+    pub fn track_buffer(&mut self, buffer: &crate::Buffer) {
+        self.buffers.push(rc::Rc::downgrade(&buffer.inner));
+    }
+
+    // Forgets textures and buffers that have been dropped.
+    pub fn retain_live(&mut self) {
+        self.textures.retain(|(t, _)| t.strong_count() > 0);
+        self.buffers.retain(|b| b.strong_count() > 0);
+    }
+
+    pub fn used_in_bytes(&self) -> u64 {
+        self.consumers().map(|c| c.size_in_bytes).sum()
+    }
+
+    pub fn top_consumers(&self, count: usize) -> Vec<MemoryConsumer> {
+        let mut consumers = self.consumers().collect::<Vec<_>>();
+
+        consumers.sort_by_key(|c| std::cmp::Reverse(c.size_in_bytes));
+        consumers.truncate(count);
+
+        consumers
+    }
+
+    fn consumers(&self) -> impl Iterator<Item=MemoryConsumer> + '_ {
+        let textures = self.textures.iter().filter_map(|(t, bytes_per_texel)| t.upgrade().map(|inner| {
+            let (width, height, layers) = inner.borrow().size;
+            let size_in_bytes = width as u64 * height as u64 * layers as u64 * bytes_per_texel;
+
+            MemoryConsumer { resource: Resource::Texture(inner), size_in_bytes }
+        }));
+
+        let buffers = self.buffers.iter().filter_map(|b| b.upgrade().map(|inner| {
+            let size_in_bytes = inner.borrow().size as u64;
+
+            MemoryConsumer { resource: Resource::Buffer(inner), size_in_bytes }
+        }));
+
+        textures.chain(buffers)
+    }
+}
+
+impl MemoryConsumer {
+    pub fn is_texture(&self, texture: &crate::Texture) -> bool {
+        matches!(&self.resource, Resource::Texture(inner) if rc::Rc::ptr_eq(inner, &texture.inner))
+    }
+
+    pub fn is_buffer(&self, buffer: &crate::Buffer) -> bool {
+        matches!(&self.resource, Resource::Buffer(inner) if rc::Rc::ptr_eq(inner, &buffer.inner))
+    }
+}
+
+const TOP_CONSUMERS: usize = 10;
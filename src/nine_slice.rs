@@ -0,0 +1,149 @@
+use std::cell;
+
+// Draws scalable UI panels from a texture whose border (the insets, in texels)
+// should stay the same size while the middle stretches. Each panel is split into
+// nine quads which are rendered as instances of a built-in pipeline. Panels are
+// positioned in pixels from the top-left of the canvas, which is usually the size
+// of the target or viewport. Insets are scaled down if a panel is too small.
+// The quads are only uploaded when they change so it can render more than once
+// per frame, e.g. to several targets.
+
+pub struct NineSlice {
+    pub pipeline: crate::Pipeline,
+    pub texture_size: (f32, f32),
+    pub insets: Insets,
+    pub panels: Vec<Panel>,
+    pub uploaded: cell::RefCell<Vec<f32>>, // The quads that were last uploaded.
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Panel {
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    pub scale: f32, // Scales the border, e.g. by the window's scale factor.
+}
+
+impl NineSlice {
+    // The texture must have a sampler.
+    pub fn new(renderer: &crate::Renderer, texture: &crate::Texture, insets: Insets, blend_mode: crate::BlendMode, msaa_samples: u32, targets: Vec<crate::Target>) -> Self {
+        if texture.sampler.is_none() { panic!("The texture for a nine slice must have a sampler."); }
+
+        let (width, height, _) = texture.size();
+        let program = renderer.program_wgsl(VERTEX_SHADER, FRAGMENT_SHADER, vec![], vec![
+            renderer.instanced(),
+        ], vec![], vec![
+            (texture.clone(), crate::Visibility::FragmentShader),
+        ]);
+
+        let pipeline = renderer.pipeline(program, blend_mode, crate::Primitive::Quads, msaa_samples, targets);
+
+        Self { pipeline, texture_size: (width as f32, height as f32), insets, panels: vec![], uploaded: cell::RefCell::new(vec![]) }
+    }
+
+    pub fn add_panel(&mut self, position: (f32, f32), size: (f32, f32), scale: f32) {
+        self.panels.push(Panel { position, size, scale });
+    }
+
+    pub fn clear_panels(&mut self) {
+        self.panels.clear();
+    }
+
+    pub fn render(&self, renderer: &crate::Renderer, canvas_size: (f32, f32), clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>) {
+        let quads = self.panels.iter().flat_map(|p| self.quads(p, canvas_size)).collect::<Vec<_>>();
+        if quads.is_empty() { return; }
+
+        let mut uploaded = self.uploaded.borrow_mut();
+
+        if *uploaded != quads {
+            renderer.set_instanced(&self.pipeline, I_QUADS, &quads);
+            *uploaded = quads;
+        }
+
+        renderer.render(&self.pipeline, clear_color, viewport, ((uploaded.len() / FLOATS_PER_QUAD) as u32, 4));
+    }
+
+    // Each quad is (x0, y0, x1, y1) in clip space followed by (u0, v0, u1, v1).
+    fn quads(&self, panel: &Panel, (canvas_width, canvas_height): (f32, f32)) -> Vec<f32> {
+        let Insets { left, top, right, bottom } = self.insets;
+        let (x, y) = panel.position;
+        let (width, height) = panel.size;
+        let (texture_width, texture_height) = self.texture_size;
+
+        let scale_x = panel.scale.min(width / (left + right).max(f32::EPSILON));
+        let scale_y = panel.scale.min(height / (top + bottom).max(f32::EPSILON));
+
+        let xs = [x, x + left * scale_x, x + width - right * scale_x, x + width].map(|x| x / canvas_width * 2. - 1.);
+        let ys = [y, y + top * scale_y, y + height - bottom * scale_y, y + height].map(|y| 1. - y / canvas_height * 2.);
+
+        let us = [0., left / texture_width, 1. - right / texture_width, 1.];
+        let vs = [0., top / texture_height, 1. - bottom / texture_height, 1.];
+
+        let mut quads = Vec::with_capacity(FLOATS_PER_QUAD * 9);
+
+        for row in 0..3 {
+            for column in 0..3 {
+                if xs[column] == xs[column + 1] || ys[row] == ys[row + 1] { continue; }
+
+                quads.extend([xs[column], ys[row], xs[column + 1], ys[row + 1]]);
+                quads.extend([us[column], vs[row], us[column + 1], vs[row + 1]]);
+            }
+        }
+
+        quads
+    }
+}
+
+impl Insets {
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self { left, top, right, bottom }
+    }
+
+    pub fn uniform(inset: f32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+const I_QUADS: (usize, usize) = (0, 0);
+const FLOATS_PER_QUAD: usize = 8;
+
+// The quads primitive draws four vertices around the perimeter of each instance.
+const VERTEX_SHADER: &str = "
+@group(0) @binding(0) var<storage, read> quads: array<vec4<f32>>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+}
+
+@vertex
+fn main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 4>(vec2(0.0, 0.0), vec2(0.0, 1.0), vec2(1.0, 1.0), vec2(1.0, 0.0));
+    let corner = corners[vertex % 4u];
+
+    let rect = quads[instance * 2u];
+    let uvs = quads[instance * 2u + 1u];
+
+    let position = mix(rect.xy, rect.zw, corner);
+    let tex_coord = mix(uvs.xy, uvs.zw, corner);
+
+    return VertexOutput(vec4<f32>(position, 0.0, 1.0), tex_coord);
+}
+";
+
+const FRAGMENT_SHADER: &str = "
+@group(0) @binding(1) var t_texture: texture_2d<f32>;
+@group(0) @binding(2) var texture_sampler: sampler;
+
+@fragment
+fn main(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(t_texture, texture_sampler, tex_coord);
+}
+";
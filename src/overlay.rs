@@ -0,0 +1,220 @@
+use std::{collections::VecDeque, time};
+
+// Draws the frame rate, a graph of recent frame times and the recording status
+// (a red dot and how full the recording buffers are) in the top-left corner of
+// the screen. It is drawn at the end of each frame that rendered to the screen so
+// it never appears in recordings, which are copied from their own attachment.
+
+pub struct Overlay {
+    pub frame_times: VecDeque<f32>, // In milliseconds, oldest first.
+    pub last_frame_at: Option<time::Instant>,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let descriptor = wgpu::BufferDescriptor { label: None, size: (UNIFORM_LEN * 4) as u64, usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, mapped_at_creation: false };
+        let buffer = device.create_buffer(&descriptor);
+
+        let layout = create_bind_group_layout(device);
+        let entries = [wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }];
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &layout, entries: &entries });
+
+        let pipeline = create_pipeline(device, &layout);
+
+        Self { frame_times: VecDeque::new(), last_frame_at: None, buffer, bind_group, pipeline }
+    }
+
+    // Called once per frame, whether or not the overlay is drawn.
+    pub fn tick(&mut self) {
+        let now = time::Instant::now();
+
+        if let Some(last_frame_at) = self.last_frame_at {
+            self.frame_times.push_back((now - last_frame_at).as_secs_f32() * 1000.);
+            if self.frame_times.len() > GRAPH_LEN { self.frame_times.pop_front(); }
+        }
+
+        self.last_frame_at = Some(now);
+    }
+
+    // The buffer fill is None if nothing is being recorded.
+    pub fn render(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, window_size: (u32, u32), buffer_fill: Option<f32>) {
+        let width = (OVERLAY_SIZE.0 * SCALE).min(window_size.0.saturating_sub(MARGIN));
+        let height = (OVERLAY_SIZE.1 * SCALE).min(window_size.1.saturating_sub(MARGIN));
+        if width == 0 || height == 0 { return; }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.uniform_data(buffer_fill)));
+
+        let ops = wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store };
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment { view, resolve_target: None, ops })];
+        let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None };
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+        render_pass.set_viewport(MARGIN as f32, MARGIN as f32, width as f32, height as f32, 0., 1.);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    // See the Overlay struct in the shader for the layout.
+    fn uniform_data(&self, buffer_fill: Option<f32>) -> [u32; UNIFORM_LEN] {
+        let mut data = [0; UNIFORM_LEN];
+
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+        let frame_rate = if average > 0. { 1000. / average } else { 0. };
+
+        let fps_text = format!("{:.0} FPS {:.1}MS", frame_rate, average);
+        let fill_text = buffer_fill.map(|f| format!("{:.0}%", f * 100.)).unwrap_or_default();
+
+        for (row, text) in [fps_text, fill_text].iter().enumerate() {
+            for (i, character) in text.chars().take(TEXT_LEN).enumerate() {
+                data[row * TEXT_LEN + i] = glyph_index(character);
+            }
+        }
+
+        // Right-align the graph so the newest frame is always on the right.
+        let offset = TEXT_LEN * 2 + GRAPH_LEN - self.frame_times.len();
+        for (i, frame_time) in self.frame_times.iter().enumerate() {
+            data[offset + i] = frame_time.to_bits();
+        }
+
+        let settings = [MARGIN as f32, MARGIN as f32, SCALE as f32, MAX_FRAME_TIME];
+        let recording = [buffer_fill.is_some() as u32 as f32, buffer_fill.unwrap_or(0.), 0., 0.];
+
+        for (i, value) in settings.iter().chain(&recording).enumerate() {
+            data[TEXT_LEN * 2 + GRAPH_LEN + i] = value.to_bits();
+        }
+
+        data
+    }
+}
+
+// Indexes into GLYPHS in the shader. Characters without a glyph are blank.
+fn glyph_index(character: char) -> u32 {
+    match character {
+        '0'..='9' => character as u32 - '0' as u32 + 1,
+        '.' => 11,
+        'F' => 12,
+        'P' => 13,
+        'S' => 14,
+        'M' => 15,
+        '%' => 16,
+        _ => 0,
+    }
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let ty = wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None };
+    let entries = [wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::FRAGMENT, ty, count: None }];
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries })
+}
+
+// The overlay is only drawn to the screen, which is always BgraU8.
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(OVERLAY_SHADER.into()) });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[layout], push_constant_ranges: &[] });
+    let target = wgpu::ColorTargetState { format: crate::Format::BgraU8.texture_format(), blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL };
+
+    let descriptor = wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &[Some(target)] }),
+        multiview: None,
+    };
+
+    device.create_render_pipeline(&descriptor)
+}
+
+const TEXT_LEN: usize = 16;  // Characters per row of text.
+const GRAPH_LEN: usize = 64; // Frame times in the graph.
+const UNIFORM_LEN: usize = TEXT_LEN * 2 + GRAPH_LEN + 8;
+
+const OVERLAY_SIZE: (u32, u32) = (68, 34); // In overlay pixels, before scaling.
+const SCALE: u32 = 2;
+const MARGIN: u32 = 8;
+const MAX_FRAME_TIME: f32 = 50.; // The frame time at the top of the graph.
+
+// Draws the overlay in pixels that are SCALE screen pixels wide. Glyphs are 3x5
+// bits, top row first. Bars are green up to 60 FPS, yellow to 30 FPS, then red.
+const OVERLAY_SHADER: &str = "
+struct Overlay {
+    text: array<vec4<u32>, 8>,    // Two rows of 16 glyph indexes.
+    graph: array<vec4<f32>, 16>,  // 64 frame times in milliseconds.
+    settings: vec4<f32>,          // (origin x, origin y, scale, max frame time)
+    recording: vec4<f32>,         // (is recording, buffer fill, _, _)
+}
+
+@group(0) @binding(0) var<uniform> overlay: Overlay;
+
+var<private> GLYPHS: array<u32, 17> = array<u32, 17>(
+    0u,      // ' '
+    31599u,  // 0
+    11415u,  // 1
+    29671u,  // 2
+    29647u,  // 3
+    23497u,  // 4
+    31183u,  // 5
+    31215u,  // 6
+    29257u,  // 7
+    31727u,  // 8
+    31695u,  // 9
+    2u,      // .
+    31140u,  // F
+    31716u,  // P
+    14478u,  // S
+    24557u,  // M
+    21157u,  // %
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn text_pixel(row: u32, p: vec2<i32>) -> bool {
+    if p.x < 0 || p.y < 0 || p.y > 4 || p.x % 4 == 3 { return false; }
+
+    let column = u32(p.x / 4);
+    if column >= 16u { return false; }
+
+    let i = row * 16u + column;
+    let bits = GLYPHS[overlay.text[i / 4u][i % 4u]];
+
+    return ((bits >> u32((4 - p.y) * 3 + (2 - p.x % 4))) & 1u) == 1u;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<i32>(floor((position.xy - overlay.settings.xy) / overlay.settings.z));
+    let white = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+
+    if text_pixel(0u, p - vec2<i32>(2, 2)) { return white; }
+
+    if overlay.recording.x > 0.5 {
+        if length(vec2<f32>(p - vec2<i32>(4, 11))) <= 2.2 { return vec4<f32>(1.0, 0.0, 0.0, 1.0); }
+        if text_pixel(1u, p - vec2<i32>(8, 9)) { return white; }
+    }
+
+    let bar = p.x - 2;
+
+    if bar >= 0 && bar < 64 && p.y >= 16 && p.y < 32 {
+        let frame_time = overlay.graph[bar / 4][bar % 4];
+        let height = i32(ceil(min(frame_time / overlay.settings.w, 1.0) * 16.0));
+
+        if 32 - p.y <= height {
+            let color = select(select(vec3<f32>(1.0, 0.2, 0.2), vec3<f32>(1.0, 0.8, 0.2), frame_time <= 33.4), vec3<f32>(0.2, 1.0, 0.2), frame_time <= 16.7);
+            return vec4<f32>(color, 1.0);
+        }
+    }
+
+    return vec4<f32>(0.0, 0.0, 0.0, 0.6);
+}
+";
@@ -0,0 +1,152 @@
+use std::{num, rc};
+
+// Keeps particles on the GPU in an instanced storage buffer so that they can be
+// updated by a compute shader and drawn by an instanced pipeline without going
+// through the CPU each frame. Each particle is floats_per_particle f32s. The
+// update shader is GLSL compiled to SPIR-V (e.g. a .comp file) like this:
+//
+//   layout(local_size_x = 64) in;
+//   layout(set = 0, binding = 0) readonly buffer Previous { Particle previous[]; };
+//   layout(set = 0, binding = 1) buffer Current { Particle current[]; };
+//   layout(set = 0, binding = 2) uniform Params { float time_step; uint count; };
+//
+// The particles are double-buffered. Each update runs the shader with the last
+// frame's state as previous and the other buffer as current, then swaps them so
+// that nothing is copied. The shader must write every particle. The swap bumps
+// the buffer's generation so pipelines that draw the particles rebind it. Call
+// update at most once per frame.
+
+pub struct ParticleSystem {
+    pub particles: crate::Instanced,
+    pub floats_per_particle: usize,
+    pub count: u32,
+    pub update: Option<ComputeUpdate>,
+}
+
+pub struct ComputeUpdate {
+    pub pipeline: wgpu::ComputePipeline,
+    pub layout: wgpu::BindGroupLayout,
+    pub params: wgpu::Buffer,
+    pub states: Option<States>,
+}
+
+// The two buffers and a bind group for each direction: 0 reads a and writes b.
+pub struct States {
+    pub a: rc::Rc<wgpu::Buffer>,
+    pub b: rc::Rc<wgpu::Buffer>,
+    pub bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl ParticleSystem {
+    pub fn new(renderer: &crate::Renderer, floats_per_particle: usize, update_shader: Option<&[u8]>) -> Self {
+        let particles = renderer.instanced();
+        let update = update_shader.map(|bytes| ComputeUpdate::new(&renderer.device, bytes));
+
+        Self { particles, floats_per_particle, count: 0, update }
+    }
+
+    // Replaces all of the particles, e.g. to spawn or reset them.
+    pub fn set_particles(&mut self, renderer: &crate::Renderer, data: &[f32]) {
+        assert_eq!(data.len() % self.floats_per_particle, 0, "The data length must be a multiple of floats_per_particle.");
+
+        renderer.set_buffer_data(&self.particles.buffer, data);
+        self.count = (data.len() / self.floats_per_particle) as u32;
+    }
+
+    // The particles are bound at set 0, binding 0 and the other bindings follow on.
+    pub fn program(&self, renderer: &crate::Renderer, vert: &[u8], frag: &[u8], attributes: crate::Attributes, uniforms: crate::Uniforms, textures: crate::Textures) -> crate::Program {
+        renderer.program(vert, frag, attributes, vec![self.particles.clone()], uniforms, textures)
+    }
+
+    pub fn update(&mut self, renderer: &crate::Renderer, time_step: f32) {
+        let update = match &mut self.update { Some(u) => u, _ => return };
+        if self.count == 0 { return; }
+
+        span!("particle_update", particles = self.count);
+
+        let current = self.particles.buffer.buffer();
+
+        // The states are recreated when set_particles replaces the buffer, e.g. to grow it.
+        let is_state = |s: &States| rc::Rc::ptr_eq(&s.a, &current) || rc::Rc::ptr_eq(&s.b, &current);
+        if !update.states.as_ref().map(is_state).unwrap_or(false) {
+            let inner = self.particles.buffer.inner.borrow();
+            update.states = Some(update.create_states(&renderer.device, &current, inner.size as u64, inner.usage));
+        }
+
+        let params = [time_step.to_bits(), self.count];
+        renderer.queue.write_buffer(&update.params, 0, bytemuck::cast_slice(&params));
+
+        let states = update.states.as_ref().unwrap();
+        let reads_a = rc::Rc::ptr_eq(&states.a, &current);
+        let (bind_group, next) = if reads_a { (&states.bind_groups[0], &states.b) } else { (&states.bind_groups[1], &states.a) };
+
+        let mut encoder = renderer.create_command_encoder();
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+        compute_pass.set_pipeline(&update.pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        drop(compute_pass);
+
+        let cbuffer = renderer.finish_command_encoder(encoder);
+        renderer.inner.borrow_mut().commands.push(cbuffer);
+
+        let mut inner = self.particles.buffer.inner.borrow_mut();
+        inner.buffer = rc::Rc::clone(next);
+        inner.generation += 1;
+    }
+
+    pub fn render(&self, renderer: &crate::Renderer, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, vertices_per_particle: u32) {
+        renderer.render(pipeline, clear_color, viewport, (self.count, vertices_per_particle));
+    }
+}
+
+impl ComputeUpdate {
+    pub fn new(device: &wgpu::Device, update_shader: &[u8]) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::util::make_spirv(update_shader) });
+
+        let entries = [storage_layout(0, true), storage_layout(1, false), params_layout(2)];
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[&layout], push_constant_ranges: &[] });
+        let descriptor = wgpu::ComputePipelineDescriptor { label: None, layout: Some(&pipeline_layout), module: &module, entry_point: "main" };
+        let pipeline = device.create_compute_pipeline(&descriptor);
+
+        let usage = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
+        let params = device.create_buffer(&wgpu::BufferDescriptor { label: None, size: PARAMS_SIZE, usage, mapped_at_creation: false });
+
+        Self { pipeline, layout, params, states: None }
+    }
+
+    fn create_states(&self, device: &wgpu::Device, current: &rc::Rc<wgpu::Buffer>, size: u64, usage: wgpu::BufferUsages) -> States {
+        let other = rc::Rc::new(device.create_buffer(&wgpu::BufferDescriptor { label: None, size, usage, mapped_at_creation: false }));
+        let bind_groups = [self.create_bind_group(device, current, &other), self.create_bind_group(device, &other, current)];
+
+        States { a: rc::Rc::clone(current), b: other, bind_groups }
+    }
+
+    fn create_bind_group(&self, device: &wgpu::Device, previous: &wgpu::Buffer, current: &wgpu::Buffer) -> wgpu::BindGroup {
+        let entries = [
+            wgpu::BindGroupEntry { binding: 0, resource: previous.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 1, resource: current.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 2, resource: self.params.as_entire_binding() },
+        ];
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &self.layout, entries: &entries })
+    }
+}
+
+fn storage_layout(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    let ty = wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only }, has_dynamic_offset: false, min_binding_size: None };
+
+    wgpu::BindGroupLayoutEntry { binding, visibility: wgpu::ShaderStages::COMPUTE, ty, count: None }
+}
+
+fn params_layout(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    let ty = wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: num::NonZeroU64::new(PARAMS_SIZE) };
+
+    wgpu::BindGroupLayoutEntry { binding, visibility: wgpu::ShaderStages::COMPUTE, ty, count: None }
+}
+
+const WORKGROUP_SIZE: u32 = 64;
+const PARAMS_SIZE: u64 = 16; // (time_step, count) padded to 16 bytes for uniform layout rules.
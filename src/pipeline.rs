@@ -1,109 +1,458 @@
-use std::{cell, ops};
+use std::{cell, num, rc};
+use std::sync::atomic;
 
+// The program and targets don't change after creation. Everything that can be
+// recreated (e.g. when a buffer grows or the blend mode changes) lives in InnerP.
 pub struct Pipeline {
+    pub id: usize, // Unique for the lifetime of the process, e.g. to identify its statistics.
+    pub program: crate::Program,
+    pub targets: Vec<crate::Target>,
     pub inner: cell::RefCell<InnerP>,
+    pub revision: rc::Rc<cell::Cell<u32>>, // Bumped when the wgpu pipeline, a bind group or the blend constant changes, e.g. so bundles know they're stale.
 }
 
 pub struct InnerP {
     pub pipeline: wgpu::RenderPipeline,
-    pub bind_groups: Vec<wgpu::BindGroup>,
-    pub program: crate::Program,
     pub blend_mode: crate::BlendMode,
     pub primitive: crate::Primitive,
+    pub rasterization: crate::Rasterization,
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    pub layouts: Vec<wgpu::BindGroupLayout>,
+    pub textures: crate::Textures, // The program's textures or those swapped in.
+    pub blend_constant: Option<crate::ClearColor>,
+    pub indices: Option<(wgpu::Buffer, u32, u32)>, // (buffer, vertices_per_instance, index_count)
     pub msaa_samples: u32,
-    pub msaa_texture: Option<crate::Texture>,
-    pub position_in_recording: RecordingPosition,
-    pub targets: Vec<crate::Target>,
+    pub msaa_textures: Vec<crate::Texture>, // One per target, each resolved into its target.
+    pub recordings: Vec<(crate::RecordingId, RecordingPosition, Vec<crate::Format>)>, // Sorted by id, one output per recording plus one per plane.
     pub window_size: (u32, u32),
     pub seen_generations: Vec<u32>,
+    pub depth: Option<crate::Depth>,
+    pub stencil: Option<crate::Stencil>,
+    pub stencil_reference: u32,
+    pub pre_pass_pipelines: Option<(wgpu::RenderPipeline, wgpu::RenderPipeline)>, // (depth only, color with depth-equal testing)
+    pub transparent_oit: bool,
+    pub exclude_from_recording: bool,
+    pub multiview: Option<u32>, // The number of views, i.e. layers of the targets.
 }
 
 // We only want to copy the VideoRecorder's texture to a buffer after the last
 // pipeline has finished. Otherwise, we'd record all intermediate writes as well.
+#[derive(Clone, Copy)]
 pub enum RecordingPosition { None, NotLast, Last }
 
 // At time of writing, wgpu limits the number of bind group sets to 8 and the
 // number of bindings per group to 4, so chunk the bindings into 4s.
 pub const BINDINGS_PER_GROUP: usize = 4;
 
+static NEXT_PIPELINE_ID: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
 impl Pipeline {
     pub fn new(device: &wgpu::Device, window_size: (u32, u32), program: crate::Program, blend_mode: crate::BlendMode, primitive: crate::Primitive, msaa_samples: u32, targets: Vec<crate::Target>) -> Self {
-        let msaa_texture = if msaa_samples > 1 { Some(create_msaa_texture(device, window_size, &targets, msaa_samples)) } else { None };
-        let position_in_recording = RecordingPosition::None;
+        let msaa_textures = create_msaa_textures(device, window_size, &targets, msaa_samples);
+        let recordings = vec![];
+        let rasterization = crate::Rasterization::default();
+
+        let textures = program.textures.clone();
+
+        let (bind_groups, layouts) = create_bind_groups(device, &program, &textures);
+        let color_states = create_color_target_states(&targets, &blend_mode, &recordings, false);
+        let pipeline = create_render_pipeline(device, &program, &primitive, &rasterization, &layouts, msaa_samples, Some(&color_states), None, None);
+        let seen_generations = program.latest_generations(&textures).collect();
 
-        let (bind_groups, layouts) = create_bind_groups(device, &program);
-        let color_states = create_color_target_states(&targets, &blend_mode, &position_in_recording);
-        let pipeline = create_render_pipeline(device, &program, &primitive, &layouts, msaa_samples, &color_states);
-        let seen_generations = program.latest_generations().collect();
+        let indices = None;
+        let blend_constant = None;
+        let depth = None;
+        let stencil = None;
+        let stencil_reference = 0;
+        let pre_pass_pipelines = None;
+        let transparent_oit = false;
+        let exclude_from_recording = false;
+        let multiview = None;
 
-        let inner = InnerP { pipeline, bind_groups, program, blend_mode, primitive, msaa_samples, msaa_texture, position_in_recording, targets, window_size, seen_generations };
+        let inner = InnerP { pipeline, blend_mode, primitive, rasterization, bind_groups, layouts, textures, blend_constant, indices, msaa_samples, msaa_textures, recordings, window_size, seen_generations, depth, stencil, stencil_reference, pre_pass_pipelines, transparent_oit, exclude_from_recording, multiview };
 
-        Self { inner: cell::RefCell::new(inner) }
+        let id = NEXT_PIPELINE_ID.fetch_add(1, atomic::Ordering::Relaxed);
+
+        Self { id, program, targets, inner: cell::RefCell::new(inner), revision: rc::Rc::default() }
     }
 
     pub fn recreate_on_buffer_or_texture_resize(&self, device: &wgpu::Device, window_size: (u32, u32), targets: &[crate::Target]) {
-        resize_msaa_texture(&self, device, window_size, targets);
+        resize_msaa_textures(&self, device, window_size, targets);
+
+        let inner = self.inner.borrow();
 
-        let actual = self.program.latest_generations();
-        let expected = &self.seen_generations;
+        let actual = self.program.latest_generations(&inner.textures);
+        let expected = &inner.seen_generations;
 
         if actual.zip(expected).all(|(g1, g2)| g1 == *g2) { return; }
-        let actual = self.program.latest_generations().collect();
+        let actual = self.program.latest_generations(&inner.textures).collect();
 
-        let (bind_groups, layouts) = create_bind_groups(device, &self.program);
-        let color_states = create_color_target_states(&self.targets, &self.blend_mode, &self.position_in_recording);
-        let pipeline = create_render_pipeline(device, &self.program, &self.primitive, &layouts, self.msaa_samples, &color_states);
+        let (bind_groups, layouts) = create_bind_groups(device, &self.program, &inner.textures);
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        let pipeline = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &layouts, inner.msaa_samples, Some(&color_states), depth_state(&inner), inner.multiview);
 
+        drop(inner);
         let mut inner = self.inner.borrow_mut();
+
         inner.bind_groups = bind_groups;
+        inner.layouts = layouts;
         inner.pipeline = pipeline;
+        inner.pre_pass_pipelines = None;
         inner.window_size = window_size;
         inner.seen_generations = actual;
+
+        self.bump_revision();
     }
 
-    pub fn set_msaa_samples(&self, device: &wgpu::Device, msaa_samples: u32) {
-        let msaa_texture = if msaa_samples > 1 { Some(create_msaa_texture(device, self.window_size, &self.targets, msaa_samples)) } else { None };
+    // The number of vertices in the attribute data that was last set, or None if
+    // the program doesn't have vertex attributes, e.g. it only pulls from storage.
+    // Pulled attributes are skipped because they may be shared through an index.
+    pub fn vertex_count(&self) -> Option<u32> {
+        self.program.vertex_attributes().map(|a| (a.buffer.inner.borrow().len / 4) as u32 / a.size).min()
+    }
+
+    pub fn generate_indices_if_needed(&self, device: &wgpu::Device, vertices_per_instance: u32) {
+        if let Some((_, v, _)) = &self.inner.borrow().indices { if *v == vertices_per_instance { return; } }
+        let indices = match self.inner.borrow().primitive.indices(vertices_per_instance) { Some(i) => i, _ => return };
+
+        let buffer = create_index_buffer(device, &indices);
+        self.inner.borrow_mut().indices = Some((buffer, vertices_per_instance, indices.len() as u32));
+    }
+
+    // Replaces the texture at texture_index and rebuilds only the bind group that
+    // contains it. The layout is reused so the new texture must be compatible.
+
+    pub fn swap_texture(&self, device: &wgpu::Device, group_index: usize, texture_index: usize, texture: &crate::Texture) {
+        let mut inner = self.inner.borrow_mut();
+        let (existing, _) = &inner.textures[texture_index];
+
+        let compatible = existing.format.texture_format() == texture.format.texture_format()
+            && existing.filter_mode.is_linear() == texture.filter_mode.is_linear()
+            && existing.sampler.is_some() == texture.sampler.is_some()
+            && existing.msaa_samples == texture.msaa_samples
+            && (existing.size().2 == 1) == (texture.size().2 == 1);
+
+        if !compatible {
+            panic!("Unable to swap texture binding. The format, filter mode, sampler, msaa samples and layering must match the existing texture.");
+        }
+
+        inner.textures[texture_index].0 = texture.clone();
+
+        let bind_group = {
+            let (buffers, views, array_views) = binding_resources(&self.program, &inner.textures);
+            let array_views = view_refs(&array_views);
+            let (entries, _) = bind_group_entries(&self.program, &inner.textures, &buffers, &views, &array_views);
+            let entries = entries.chunks(BINDINGS_PER_GROUP).nth(group_index).unwrap();
+
+            let descriptor = wgpu::BindGroupDescriptor { layout: &inner.layouts[group_index], entries, label: None };
+            device.create_bind_group(&descriptor)
+        };
+
+        inner.bind_groups[group_index] = bind_group;
+        inner.seen_generations = self.program.latest_generations(&inner.textures).collect();
+
+        self.bump_revision();
+    }
+
+    pub fn set_blend_constant(&self, color: crate::ClearColor) {
+        self.inner.borrow_mut().blend_constant = Some(color);
+        self.bump_revision();
+    }
+
+    fn bump_revision(&self) {
+        self.revision.set(self.revision.get().wrapping_add(1));
+    }
+
+    // The blend mode and primitive only affect the wgpu::RenderPipeline so the
+    // bind groups and layouts are kept, which makes these cheap to tweak live.
+
+    pub fn set_blend_mode(&self, device: &wgpu::Device, blend_mode: crate::BlendMode) {
+        self.inner.borrow_mut().blend_mode = blend_mode;
+        self.recreate_render_pipeline(device);
+    }
+
+    pub fn set_primitive(&self, device: &wgpu::Device, primitive: crate::Primitive) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.primitive = primitive;
+        inner.indices = None; // Regenerated for the new primitive on the next render.
+
+        drop(inner);
+        self.recreate_render_pipeline(device);
+    }
+
+    pub fn set_rasterization(&self, device: &wgpu::Device, rasterization: crate::Rasterization) {
+        if rasterization.conservative && rasterization.polygon_mode != crate::PolygonMode::Fill {
+            panic!("Conservative rasterization can only be used with PolygonMode::Fill.");
+        }
+
+        self.inner.borrow_mut().rasterization = rasterization;
+        self.recreate_render_pipeline(device);
+    }
+
+    fn recreate_render_pipeline(&self, device: &wgpu::Device) {
+        let mut inner = self.inner.borrow_mut();
+
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        inner.pipeline = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &inner.layouts, inner.msaa_samples, Some(&color_states), depth_state(&inner), inner.multiview);
+        inner.pre_pass_pipelines = None;
+
+        self.bump_revision();
+    }
 
-        let (bind_groups, layouts) = create_bind_groups(device, &self.program);
-        let color_states = create_color_target_states(&self.targets, &self.blend_mode, &self.position_in_recording);
-        let pipeline = create_render_pipeline(device, &self.program, &self.primitive, &layouts, msaa_samples, &color_states);
+    pub fn set_depth(&self, device: &wgpu::Device, depth: Option<crate::Depth>) {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(d) = &depth {
+            if d.buffer.msaa_samples != inner.msaa_samples { panic!("The depth buffer must have the same msaa samples as the pipeline."); }
+            if inner.multiview.is_some() { panic!("Multiview pipelines can't use a depth buffer."); }
+        }
+
+        inner.depth = depth;
+
+        drop(inner);
+        self.recreate_render_pipeline(device);
+    }
 
+    // The depth buffer must have been created with_stencil. See Stencil.
+    pub fn set_stencil(&self, device: &wgpu::Device, stencil: Option<crate::Stencil>) {
         let mut inner = self.inner.borrow_mut();
+
+        let with_stencil = inner.depth.as_ref().map(|d| d.buffer.with_stencil).unwrap_or(false);
+        if stencil.is_some() && !with_stencil { panic!("The pipeline needs a depth buffer with_stencil. Please call set_depth first."); }
+
+        inner.stencil = stencil;
+
+        drop(inner);
+        self.recreate_render_pipeline(device);
+    }
+
+    // The value that the stencil test compares with and Replace writes.
+    pub fn set_stencil_reference(&self, reference: u32) {
+        self.inner.borrow_mut().stencil_reference = reference;
+        self.bump_revision();
+    }
+
+    // See Transparency. The pipeline renders into the renderer's accumulation and
+    // revealage targets instead of its own targets.
+    pub fn set_transparent_oit(&self, device: &wgpu::Device, transparent_oit: bool) {
+        let mut inner = self.inner.borrow_mut();
+
+        if transparent_oit && inner.msaa_samples != 1 { panic!("Pipelines with transparent_oit can't use MSAA."); }
+        if transparent_oit && !inner.recordings.is_empty() { panic!("Pipelines with transparent_oit can't be recorded."); }
+        if transparent_oit && inner.multiview.is_some() { panic!("Multiview pipelines can't use transparent_oit."); }
+
+        inner.transparent_oit = transparent_oit;
+
+        drop(inner);
+        self.recreate_render_pipeline(device);
+    }
+
+    // The pipeline still renders to its targets and is still part of the recording
+    // (e.g. the last pipeline still triggers the copy) but it doesn't draw into the
+    // recording's attachments, so debug overlays and HUDs don't appear in captures.
+    pub fn set_exclude_from_recording(&self, device: &wgpu::Device, exclude_from_recording: bool) {
+        self.inner.borrow_mut().exclude_from_recording = exclude_from_recording;
+        self.recreate_render_pipeline(device);
+    }
+
+    // Renders each draw once per view into the layers of the targets, e.g. one
+    // per eye for stereo. The shader can use gl_ViewIndex to pick the view's
+    // matrices from a uniform (see Renderer::set_uniform_per_view). The targets
+    // must be textures with one layer per view.
+    pub fn set_multiview(&self, device: &wgpu::Device, views: Option<u32>) {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(views) = views {
+            let layered = self.targets.iter().all(|t| matches!(t, crate::Target::Texture(texture) if texture.size().2 == views));
+
+            if views < 2 { panic!("Multiview pipelines must have at least 2 views."); }
+            if !layered { panic!("The targets of multiview pipelines must be textures with one layer per view."); }
+            if inner.msaa_samples != 1 { panic!("Multiview pipelines can't use MSAA."); }
+            if inner.depth.is_some() { panic!("Multiview pipelines can't use a depth buffer. Please remove it with set_depth first."); }
+            if inner.transparent_oit { panic!("Multiview pipelines can't use transparent_oit."); }
+            if !inner.recordings.is_empty() { panic!("Multiview pipelines can't be recorded."); }
+        }
+
+        inner.multiview = views;
+
+        drop(inner);
+        self.recreate_render_pipeline(device);
+    }
+
+    // A depth pre-pass renders depth only with the pipeline's depth test and then
+    // renders color with depth-equal testing so each pixel is shaded once.
+    pub fn create_pre_pass_pipelines_if_needed(&self, device: &wgpu::Device) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.pre_pass_pipelines.is_some() { return; }
+
+        let depth = inner.depth.as_ref().expect("The pipeline needs a depth buffer for a depth pre-pass. Please call set_depth first.");
+        let depth_only_state = crate::depth_stencil_state(depth.buffer.format(), depth.test, true);
+        let mut depth_equal_state = crate::depth_stencil_state(depth.buffer.format(), crate::DepthTest::Equal, false);
+
+        // The stencil only applies to the color pass so that its ops aren't applied twice.
+        if let Some(stencil) = &inner.stencil { depth_equal_state.stencil = stencil.state(); }
+
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        let depth_only = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &inner.layouts, inner.msaa_samples, None, Some(depth_only_state), inner.multiview);
+        let color = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &inner.layouts, inner.msaa_samples, Some(&color_states), Some(depth_equal_state), inner.multiview);
+
+        inner.pre_pass_pipelines = Some((depth_only, color));
+    }
+
+    // Draws nothing with each of the pipeline's render pipelines into 1x1 textures
+    // that have the same formats as its attachments. Some drivers only compile a
+    // pipeline when it's first drawn with (see Renderer::warm_up).
+    pub fn warm_up(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let inner = self.inner.borrow();
+        let layers = inner.multiview.unwrap_or(1);
+
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        let color_views = color_states.iter().flatten().map(|s| create_warm_up_view(device, s.format, inner.msaa_samples, layers)).collect::<Vec<_>>();
+        let depth_view = inner.depth.as_ref().map(|d| (create_warm_up_view(device, d.buffer.format(), inner.msaa_samples, layers), d.buffer.with_stencil));
+        let buffers = self.program.vertex_attributes().map(|a| a.buffer.buffer()).collect::<Vec<_>>();
+
+        let mut render_pipelines = vec![(&inner.pipeline, true)];
+        if let Some((depth_only, color)) = &inner.pre_pass_pipelines { render_pipelines.extend([(depth_only, false), (color, true)]); }
+
+        for (render_pipeline, with_color) in render_pipelines {
+            let color_ops = wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Discard };
+            let color_attachments = color_views.iter().filter(|_| with_color).map(|view| Some(wgpu::RenderPassColorAttachment { view, resolve_target: None, ops: color_ops })).collect::<Vec<_>>();
+
+            let depth_stencil_attachment = depth_view.as_ref().map(|(view, with_stencil)| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.), store: wgpu::StoreOp::Discard }),
+                stencil_ops: if *with_stencil { Some(wgpu::Operations { load: wgpu::LoadOp::Clear(0), store: wgpu::StoreOp::Discard }) } else { None },
+            });
+
+            let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment, timestamp_writes: None, occlusion_query_set: None };
+            let mut render_pass = encoder.begin_render_pass(&descriptor);
+
+            render_pass.set_pipeline(render_pipeline);
+            render_pass.set_blend_constant(inner.blend_constant.map(|c| c.inner).unwrap_or(wgpu::Color::BLACK));
+
+            for (i, bind_group) in inner.bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(i as u32, bind_group, &[]);
+            }
+
+            for (slot, buffer) in buffers.iter().enumerate() {
+                render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
+            }
+
+            render_pass.draw(0..0, 0..0);
+        }
+    }
+
+    pub fn set_msaa_samples(&self, device: &wgpu::Device, msaa_samples: u32) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.transparent_oit && msaa_samples != 1 { panic!("Pipelines with transparent_oit can't use MSAA."); }
+        if inner.multiview.is_some() && msaa_samples != 1 { panic!("Multiview pipelines can't use MSAA."); }
+
+        if let Some(d) = &inner.depth {
+            if d.buffer.msaa_samples != msaa_samples { panic!("The depth buffer must have the same msaa samples as the pipeline. Please remove it with set_depth first."); }
+        }
+
+        let msaa_textures = create_msaa_textures(device, inner.window_size, &self.targets, msaa_samples);
+
+        let (bind_groups, layouts) = create_bind_groups(device, &self.program, &inner.textures);
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        let pipeline = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &layouts, msaa_samples, Some(&color_states), depth_state(&inner), inner.multiview);
+
         inner.msaa_samples = msaa_samples;
-        inner.msaa_texture = msaa_texture;
+        inner.msaa_textures = msaa_textures;
         inner.bind_groups = bind_groups;
+        inner.layouts = layouts;
         inner.pipeline = pipeline;
-    }
+        inner.pre_pass_pipelines = None;
 
-    pub fn set_stream_position(&self, device: &wgpu::Device, position_in_recording: RecordingPosition) {
-        let (bind_groups, layouts) = create_bind_groups(device, &self.program);
-        let color_states = create_color_target_states(&self.targets, &self.blend_mode, &position_in_recording);
-        let pipeline = create_render_pipeline(device, &self.program, &self.primitive, &layouts, self.msaa_samples, &color_states);
+        self.bump_revision();
+    }
 
+    pub fn set_stream_position(&self, device: &wgpu::Device, recording_id: crate::RecordingId, position_in_recording: RecordingPosition, plane_formats: &[crate::Format]) {
         let mut inner = self.inner.borrow_mut();
-        inner.position_in_recording = position_in_recording;
+
+        if inner.transparent_oit && !matches!(position_in_recording, RecordingPosition::None) { panic!("Pipelines with transparent_oit can't be recorded."); }
+        if inner.multiview.is_some() && !matches!(position_in_recording, RecordingPosition::None) { panic!("Multiview pipelines can't be recorded."); }
+        inner.recordings.retain(|(id, _, _)| *id != recording_id);
+
+        if !matches!(position_in_recording, RecordingPosition::None) {
+            inner.recordings.push((recording_id, position_in_recording, plane_formats.to_vec()));
+            inner.recordings.sort_by_key(|(id, _, _)| *id);
+        }
+
+        let (bind_groups, layouts) = create_bind_groups(device, &self.program, &inner.textures);
+        let color_states = create_color_target_states(&self.targets, &inner.blend_mode, attached_recordings(&inner), inner.transparent_oit);
+        let pipeline = create_render_pipeline(device, &self.program, &inner.primitive, &inner.rasterization, &layouts, inner.msaa_samples, Some(&color_states), depth_state(&inner), inner.multiview);
+
         inner.bind_groups = bind_groups;
+        inner.layouts = layouts;
         inner.pipeline = pipeline;
+        inner.pre_pass_pipelines = None;
+
+        self.bump_revision();
     }
 }
 
-fn create_bind_groups(device: &wgpu::Device, program: &crate::Program) -> (Vec<wgpu::BindGroup>, Vec<wgpu::BindGroupLayout>) {
-    let entries = &mut vec![];
-    let layouts = &mut vec![];
+fn create_bind_groups(device: &wgpu::Device, program: &crate::Program, textures: &crate::Textures) -> (Vec<wgpu::BindGroup>, Vec<wgpu::BindGroupLayout>) {
+    let (buffers, views, array_views) = binding_resources(program, textures);
+    let array_views = view_refs(&array_views);
+    let (entries, layouts) = bind_group_entries(program, textures, &buffers, &views, &array_views);
+
+    let wgpu_layouts = layouts.chunks(BINDINGS_PER_GROUP).map(|entries| {
+        let descriptor = wgpu::BindGroupLayoutDescriptor { entries, label: None };
+        device.create_bind_group_layout(&descriptor)
+    }).collect::<Vec<_>>();
+
+    let wgpu_groups = entries.chunks(BINDINGS_PER_GROUP).enumerate().map(|(i, entries)| {
+        let descriptor = wgpu::BindGroupDescriptor { layout: &wgpu_layouts[i], entries, label: None };
+        device.create_bind_group(&descriptor)
+    }).collect();
+
+    (wgpu_groups, wgpu_layouts)
+}
+
+// Buffers and views are replaced when they are resized so take Rcs to them that
+// live for as long as the entries that refer to them.
+fn binding_resources(program: &crate::Program, textures: &crate::Textures) -> (Vec<rc::Rc<wgpu::Buffer>>, Views, Vec<Views>) {
+    let instance_buffers = program.instances.iter().map(|i| i.buffer.buffer());
+    let uniform_buffers = program.uniforms.iter().map(|(u, _)| u.buffer.buffer());
+
+    let attribute_buffers = program.pulled_attributes().map(|a| a.buffer.buffer());
+
+    let buffers = instance_buffers.chain(uniform_buffers).chain(attribute_buffers).collect();
+    let views = textures.iter().map(|(t, _)| t.view()).collect();
+    let array_views = program.texture_arrays.iter().map(|(a, _)| a.views()).collect();
+
+    (buffers, views, array_views)
+}
+
+type Views = Vec<rc::Rc<wgpu::TextureView>>;
+
+// Binding arrays need a slice of references to the views.
+fn view_refs(array_views: &[Views]) -> Vec<Vec<&wgpu::TextureView>> {
+    array_views.iter().map(|views| views.iter().map(|v| &**v).collect()).collect()
+}
+
+fn bind_group_entries<'a>(program: &'a crate::Program, textures: &'a crate::Textures, buffers: &'a [rc::Rc<wgpu::Buffer>], views: &'a [rc::Rc<wgpu::TextureView>], array_views: &'a [Vec<&'a wgpu::TextureView>]) -> (Vec<wgpu::BindGroupEntry<'a>>, Vec<wgpu::BindGroupLayoutEntry>) {
+    let mut entries = vec![];
+    let mut layouts = vec![];
     let binding_id = &mut 0;
 
+    let mut buffers = buffers.iter();
+
     for instanced in &program.instances {
-        let (entry, layout) = instanced.binding(*binding_id);
+        let (entry, layout) = instanced.binding(buffers.next().unwrap(), *binding_id);
         entries.push(entry); layouts.push(layout); next(binding_id);
     }
 
     for (uniform, visibility) in &program.uniforms {
-        let (entry, layout) = uniform.binding(visibility, *binding_id);
+        let (entry, layout) = uniform.binding(buffers.next().unwrap(), visibility, *binding_id);
         entries.push(entry); layouts.push(layout); next(binding_id);
     }
 
-    for (texture, visibility) in &program.textures {
-        let (entry, layout) = texture.texture_binding(visibility, *binding_id);
+    for ((texture, visibility), view) in textures.iter().zip(views) {
+        let (entry, layout) = texture.texture_binding(view, visibility, *binding_id);
         entries.push(entry); layouts.push(layout); next(binding_id);
 
         if texture.sampler.is_some() {
@@ -112,17 +461,22 @@ fn create_bind_groups(device: &wgpu::Device, program: &crate::Program) -> (Vec<w
         }
     }
 
-    let wgpu_layouts = layouts.chunks(BINDINGS_PER_GROUP).map(|entries| {
-        let descriptor = wgpu::BindGroupLayoutDescriptor { entries, label: None };
-        device.create_bind_group_layout(&descriptor)
-    }).collect::<Vec<_>>();
+    for ((texture_array, visibility), views) in program.texture_arrays.iter().zip(array_views) {
+        let (entry, layout) = texture_array.texture_binding(views, visibility, *binding_id);
+        entries.push(entry); layouts.push(layout); next(binding_id);
 
-    let wgpu_groups = entries.chunks(BINDINGS_PER_GROUP).enumerate().map(|(i, entries)| {
-        let descriptor = wgpu::BindGroupDescriptor { layout: &wgpu_layouts[i], entries, label: None };
-        device.create_bind_group(&descriptor)
-    }).collect();
+        if texture_array.textures[0].sampler.is_some() {
+            let (entry, layout) = texture_array.sampler_binding(visibility, *binding_id);
+            entries.push(entry); layouts.push(layout); next(binding_id);
+        }
+    }
 
-    (wgpu_groups, wgpu_layouts)
+    for attribute in program.pulled_attributes() {
+        let (entry, layout) = attribute.binding(buffers.next().unwrap(), *binding_id);
+        entries.push(entry); layouts.push(layout); next(binding_id);
+    }
+
+    (entries, layouts)
 }
 
 fn next(binding_id: &mut u32) {
@@ -130,19 +484,38 @@ fn next(binding_id: &mut u32) {
     *binding_id %= BINDINGS_PER_GROUP as u32;
 }
 
-fn create_color_target_states(targets: &[crate::Target], blend_mode: &crate::BlendMode, stream_position: &RecordingPosition) -> Vec<Option<wgpu::ColorTargetState>> {
+// Planes replace rather than blend because their values might not be colors.
+fn create_color_target_states(targets: &[crate::Target], blend_mode: &crate::BlendMode, recordings: &[(crate::RecordingId, RecordingPosition, Vec<crate::Format>)], transparent_oit: bool) -> Vec<Option<wgpu::ColorTargetState>> {
+    if transparent_oit { return crate::Transparency::color_states(); }
+
     let mut color_target_states = targets.iter().map(|t| Some(blend_mode.state(t.format()))).collect::<Vec<_>>();
 
-    match stream_position {
-        RecordingPosition::None => {},
-        _ => color_target_states.push(Some(blend_mode.state(crate::Format::RgbaU8))),
+    for (_, _, plane_formats) in recordings {
+        color_target_states.push(Some(blend_mode.state(crate::Format::RgbaU8)));
+
+        for format in plane_formats {
+            color_target_states.push(Some(wgpu::ColorTargetState { format: format.texture_format(), blend: None, write_mask: wgpu::ColorWrites::ALL }));
+        }
     }
 
     color_target_states
 }
 
-fn create_render_pipeline(device: &wgpu::Device, program: &crate::Program, primitive: &crate::Primitive, layouts: &[wgpu::BindGroupLayout], msaa_samples: u32, color_states: &[Option<wgpu::ColorTargetState>]) -> wgpu::RenderPipeline {
-    let attribute_descriptors = attribute_descriptors(&program.attributes);
+// The recordings the pipeline draws into, which have an extra color target each.
+pub fn attached_recordings(inner: &InnerP) -> &[(crate::RecordingId, RecordingPosition, Vec<crate::Format>)] {
+    if inner.exclude_from_recording { &[] } else { &inner.recordings }
+}
+
+fn depth_state(inner: &InnerP) -> Option<wgpu::DepthStencilState> {
+    let stencil = inner.stencil.as_ref().filter(|_| inner.depth.as_ref().map(|d| d.buffer.with_stencil).unwrap_or(false));
+    inner.depth.as_ref().map(|d| wgpu::DepthStencilState { stencil: stencil.map(|s| s.state()).unwrap_or_default(), ..d.state() })
+}
+
+// The fragment stage is skipped if there are no color states, e.g. for a depth pre-pass.
+fn create_render_pipeline(device: &wgpu::Device, program: &crate::Program, primitive: &crate::Primitive, rasterization: &crate::Rasterization, layouts: &[wgpu::BindGroupLayout], msaa_samples: u32, color_states: Option<&[Option<wgpu::ColorTargetState>]>, depth_stencil: Option<wgpu::DepthStencilState>, multiview: Option<u32>) -> wgpu::RenderPipeline {
+    span!("create_render_pipeline");
+
+    let attribute_descriptors = attribute_descriptors(program.vertex_attributes());
     let vertex_buffers = vertex_buffers(&attribute_descriptors);
     let layout = create_layout(device, layouts);
     let multisample_state = multisample_state(msaa_samples);
@@ -151,20 +524,39 @@ fn create_render_pipeline(device: &wgpu::Device, program: &crate::Program, primi
         label: None,
         layout: Some(&layout),
         vertex: vertex_state(&program.vertex_shader, &vertex_buffers),
-        primitive: primitive_state(primitive),
-        depth_stencil: None,
+        primitive: primitive_state(primitive, rasterization),
+        depth_stencil,
         multisample: multisample_state,
-        fragment: Some(fragment_state(&program.fragment_shader, color_states)),
-        multiview: None,
+        fragment: color_states.map(|states| fragment_state(&program.fragment_shader, states)),
+        multiview: multiview.and_then(num::NonZeroU32::new),
     };
 
     device.create_render_pipeline(&descriptor)
 }
 
-fn create_msaa_texture(device: &wgpu::Device, window_size: (u32, u32), targets: &[crate::Target], msaa_samples: u32) -> crate::Texture {
-    // If there are multiple render targets, configure the MSAA texture based on the first one.
-    let target = &targets[0];
+fn create_warm_up_view(device: &wgpu::Device, format: wgpu::TextureFormat, msaa_samples: u32, layers: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: layers },
+        mip_level_count: 1,
+        sample_count: msaa_samples,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    let dimension = if layers > 1 { wgpu::TextureViewDimension::D2Array } else { wgpu::TextureViewDimension::D2 };
+    texture.create_view(&wgpu::TextureViewDescriptor { dimension: Some(dimension), ..Default::default() })
+}
+
+fn create_msaa_textures(device: &wgpu::Device, window_size: (u32, u32), targets: &[crate::Target], msaa_samples: u32) -> Vec<crate::Texture> {
+    if msaa_samples == 1 { return vec![]; }
 
+    targets.iter().map(|t| create_msaa_texture(device, window_size, t, msaa_samples)).collect()
+}
+
+fn create_msaa_texture(device: &wgpu::Device, window_size: (u32, u32), target: &crate::Target, msaa_samples: u32) -> crate::Texture {
     let size = target.size(window_size);
     let filter_mode = crate::FilterMode::Nearest; // Not used
     let format = target.format();
@@ -175,17 +567,37 @@ fn create_msaa_texture(device: &wgpu::Device, window_size: (u32, u32), targets:
     crate::Texture::new(device, size, filter_mode, format, msaa_samples, renderable, copyable, with_sampler)
 }
 
-fn resize_msaa_texture(pipeline: &Pipeline, device: &wgpu::Device, window_size: (u32, u32), targets: &[crate::Target]) {
-    let target = &targets[0];
-    let new_size = target.size(window_size);
-
+// The targets can differ from the pipeline's (see Renderer::render_to) so there
+// might be more or fewer of them than there are msaa textures.
+fn resize_msaa_textures(pipeline: &Pipeline, device: &wgpu::Device, window_size: (u32, u32), targets: &[crate::Target]) {
     let mut inner = pipeline.inner.borrow_mut();
+    if inner.msaa_samples == 1 { return; }
 
-    if let Some(texture) = inner.msaa_texture.as_mut() {
-        texture.resize(device, new_size);
+    let msaa_samples = inner.msaa_samples;
+    inner.msaa_textures.truncate(targets.len());
+
+    for (i, target) in targets.iter().enumerate() {
+        match inner.msaa_textures.get_mut(i) {
+            Some(texture) => texture.resize(device, target.size(window_size)),
+            None => inner.msaa_textures.push(create_msaa_texture(device, window_size, target, msaa_samples)),
+        }
     }
 }
 
+pub(crate) fn create_index_buffer(device: &wgpu::Device, indices: &[u32]) -> wgpu::Buffer {
+    let bytes = bytemuck::cast_slice(indices);
+    let size = bytes.len().max(std::mem::size_of::<u32>()) as u64;
+
+    let usage = wgpu::BufferUsages::INDEX;
+    let descriptor = wgpu::BufferDescriptor { label: None, size, usage, mapped_at_creation: true };
+    let buffer = device.create_buffer(&descriptor);
+
+    buffer.slice(0..bytes.len() as u64).get_mapped_range_mut().copy_from_slice(bytes);
+    buffer.unmap();
+
+    buffer
+}
+
 fn create_layout(device: &wgpu::Device, layouts: &[wgpu::BindGroupLayout]) -> wgpu::PipelineLayout {
     let layouts = layouts.iter().collect::<Vec<_>>();
 
@@ -194,15 +606,15 @@ fn create_layout(device: &wgpu::Device, layouts: &[wgpu::BindGroupLayout]) -> wg
     device.create_pipeline_layout(&descriptor)
 }
 
-fn primitive_state(primitive: &crate::Primitive) -> wgpu::PrimitiveState {
+fn primitive_state(primitive: &crate::Primitive, rasterization: &crate::Rasterization) -> wgpu::PrimitiveState {
     wgpu::PrimitiveState {
         topology: primitive.topology(),
         strip_index_format: None,
         front_face: wgpu::FrontFace::default(),
         cull_mode: None,
-        unclipped_depth: false,
-        polygon_mode: wgpu::PolygonMode::default(),
-        conservative: false,
+        unclipped_depth: rasterization.unclipped_depth,
+        polygon_mode: rasterization.polygon_mode.to_wgpu(),
+        conservative: rasterization.conservative,
     }
 }
 
@@ -212,8 +624,8 @@ fn multisample_state(msaa_samples: u32) -> wgpu::MultisampleState {
 
 type AttributesAndSize = (Vec<wgpu::VertexAttribute>, u32);
 
-fn attribute_descriptors(attributes: &[crate::Attribute]) -> Vec<AttributesAndSize> {
-    attributes.iter().map(|a| (vec![a.inner.clone()], a.size)).collect::<Vec<_>>()
+fn attribute_descriptors<'a>(attributes: impl Iterator<Item=&'a crate::Attribute>) -> Vec<AttributesAndSize> {
+    attributes.map(|a| (vec![a.inner.clone()], a.size)).collect::<Vec<_>>()
 }
 
 fn vertex_buffers(slice: &[AttributesAndSize]) -> Vec<wgpu::VertexBufferLayout> {
@@ -235,11 +647,3 @@ fn vertex_state<'a>(module: &'a wgpu::ShaderModule, buffers: &'a [wgpu::VertexBu
 fn fragment_state<'a>(module: &'a wgpu::ShaderModule, targets: &'a [Option<wgpu::ColorTargetState>]) -> wgpu::FragmentState<'a> {
     wgpu::FragmentState { module, entry_point: "main", targets }
 }
-
-impl ops::Deref for Pipeline {
-    type Target = InnerP;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.inner.try_borrow_unguarded().unwrap() }
-    }
-}
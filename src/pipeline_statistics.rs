@@ -0,0 +1,151 @@
+use std::rc;
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
+
+// Counts the primitives that each render emits (after clipping) and how many
+// times the fragment shader runs so that overdraw can be quantified, i.e. the
+// fragment invocations divided by the pixels covered. The queries are resolved
+// at the end of each frame and read back asynchronously so frame_timings returns
+// the latest frame that has finished on the GPU. Needs an adapter that supports
+// Features::PIPELINE_STATISTICS_QUERY (see supports_pipeline_statistics). If
+// the GPU falls behind, frames are skipped rather than queueing more readbacks.
+
+pub struct PipelineStatistics {
+    pub query_set: rc::Rc<wgpu::QuerySet>,
+    pub capacity: u32,
+    pub queries: Vec<usize>, // The pipeline id of each query this frame.
+    pub overflowed: bool,
+    pub readbacks: Vec<Readback>,
+    pub latest: Option<FrameTimings>,
+}
+
+pub struct Readback {
+    pub buffer: wgpu::Buffer,
+    pub queries: Vec<usize>,
+    pub frame_index: u64,
+    pub map_requested: bool,
+    pub state: Arc<AtomicUsize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameTimings {
+    pub frame_index: u64,
+    pub pipeline_stats: Vec<PipelineStats>, // In the order the pipelines first rendered.
+}
+
+#[derive(Clone, Debug)]
+pub struct PipelineStats {
+    pub pipeline_id: usize,
+    pub renders: u32,
+    pub primitives: u64,
+    pub fragment_invocations: u64,
+}
+
+impl PipelineStatistics {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = INITIAL_CAPACITY;
+        let query_set = rc::Rc::new(create_query_set(device, capacity));
+
+        Self { query_set, capacity, queries: vec![], overflowed: false, readbacks: vec![], latest: None }
+    }
+
+    // Returns None if there are no queries left this frame. There will be more next frame.
+    pub fn begin_query(&mut self, pipeline_id: usize) -> Option<(rc::Rc<wgpu::QuerySet>, u32)> {
+        if self.queries.len() as u32 == self.capacity { self.overflowed = true; return None; }
+
+        self.queries.push(pipeline_id);
+        Some((rc::Rc::clone(&self.query_set), self.queries.len() as u32 - 1))
+    }
+
+    pub fn resolve(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, frame_index: u64) {
+        let queries = std::mem::take(&mut self.queries);
+
+        if !queries.is_empty() && self.readbacks.len() < MAX_READBACKS {
+            let size = queries.len() as u64 * BYTES_PER_QUERY;
+
+            let resolve_buffer = create_buffer(device, size, wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC);
+            let buffer = create_buffer(device, size, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+
+            encoder.resolve_query_set(&self.query_set, 0..queries.len() as u32, &resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(&resolve_buffer, 0, &buffer, 0, size);
+
+            self.readbacks.push(Readback { buffer, queries, frame_index, map_requested: false, state: Arc::new(AtomicUsize::new(PENDING)) });
+        }
+
+        if self.overflowed {
+            self.capacity *= 2;
+            self.query_set = rc::Rc::new(create_query_set(device, self.capacity));
+            self.overflowed = false;
+        }
+    }
+
+    // Call this after the resolve has been submitted. Each buffer is only mapped once.
+    pub fn map_readback(&mut self) {
+        for readback in self.readbacks.iter_mut().filter(|r| !r.map_requested) {
+            let state = Arc::clone(&readback.state);
+            readback.map_requested = true;
+
+            readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                state.store(if result.is_ok() { MAPPED } else { FAILED }, Relaxed);
+            });
+        }
+    }
+
+    // The device must be polled first for the buffers to finish mapping. Frames
+    // whose buffer failed to map are dropped so they don't block later frames.
+    pub fn collect_mapped(&mut self) {
+        while self.readbacks.first().map_or(false, |r| r.state.load(Relaxed) != PENDING) {
+            let readback = self.readbacks.remove(0);
+
+            if readback.state.load(Relaxed) == FAILED {
+                eprintln!("Warning: Failed to map the pipeline statistics for frame {}.", readback.frame_index);
+                continue;
+            }
+
+            let data = readback.buffer.slice(..).get_mapped_range();
+            let counts: &[u64] = bytemuck::cast_slice(&data);
+
+            let mut pipeline_stats: Vec<PipelineStats> = vec![];
+
+            for (pipeline_id, counts) in readback.queries.iter().zip(counts.chunks(2)) {
+                let index = match pipeline_stats.iter().position(|s| s.pipeline_id == *pipeline_id) {
+                    Some(i) => i,
+                    None => { pipeline_stats.push(PipelineStats { pipeline_id: *pipeline_id, renders: 0, primitives: 0, fragment_invocations: 0 }); pipeline_stats.len() - 1 },
+                };
+
+                let stats = &mut pipeline_stats[index];
+                stats.renders += 1;
+                stats.primitives += counts[0];
+                stats.fragment_invocations += counts[1];
+            }
+
+            drop(data);
+            readback.buffer.unmap();
+
+            self.latest = Some(FrameTimings { frame_index: readback.frame_index, pipeline_stats });
+        }
+    }
+}
+
+impl FrameTimings {
+    pub fn stats_for(&self, pipeline: &crate::Pipeline) -> Option<&PipelineStats> {
+        self.pipeline_stats.iter().find(|s| s.pipeline_id == pipeline.id)
+    }
+}
+
+fn create_query_set(device: &wgpu::Device, count: u32) -> wgpu::QuerySet {
+    let types = wgpu::PipelineStatisticsTypes::CLIPPER_PRIMITIVES_OUT | wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS;
+
+    device.create_query_set(&wgpu::QuerySetDescriptor { label: None, ty: wgpu::QueryType::PipelineStatistics(types), count })
+}
+
+fn create_buffer(device: &wgpu::Device, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor { label: None, size, usage, mapped_at_creation: false })
+}
+
+const PENDING: usize = 0;
+const MAPPED: usize = 1;
+const FAILED: usize = 2;
+
+const INITIAL_CAPACITY: u32 = 64;
+const MAX_READBACKS: usize = 4;
+const BYTES_PER_QUERY: u64 = 16; // Two u64s ordered by their PipelineStatisticsTypes bits.
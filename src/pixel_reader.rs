@@ -0,0 +1,82 @@
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
+
+// Reads single pixels back from the GPU without stalling, e.g. for a color
+// picker. Each read copies one texel into a small persistent buffer which is
+// mapped once the commands are flushed, so the result arrives a frame later.
+pub struct PixelReader {
+    pub buffer: wgpu::Buffer,
+    pub state: Arc<AtomicUsize>,
+    pub format: crate::Format,
+    pub latest: Option<[u8; 4]>,
+}
+
+const IDLE: usize = 0;
+const COPIED: usize = 1;
+const MAPPING: usize = 2;
+const MAPPED: usize = 3;
+const FAILED: usize = 4;
+
+impl PixelReader {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let usage = wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ;
+        let size = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+        let descriptor = wgpu::BufferDescriptor { label: None, size, usage, mapped_at_creation: false };
+
+        Self { buffer: device.create_buffer(&descriptor), state: Arc::new(AtomicUsize::new(IDLE)), format: crate::Format::default(), latest: None }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.state.load(Relaxed) == IDLE
+    }
+
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, format: crate::Format, (x, y): (u32, u32)) {
+        if format.bytes_per_channel() != 1 {
+            panic!("Only 8-bit formats can be read with read_pixel. Please use read_texture_f32 instead.");
+        }
+
+        let image_copy = crate::Texture::image_copy_texture(texture, (x, y, 0));
+        let layout = wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT), rows_per_image: Some(1) };
+        let buffer_copy = wgpu::ImageCopyBuffer { buffer: &self.buffer, layout };
+        let extent = wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 };
+
+        encoder.copy_texture_to_buffer(image_copy, buffer_copy, extent);
+
+        self.format = format;
+        self.state.store(COPIED, Relaxed);
+    }
+
+    // The buffer can't be mapped until the copy has been submitted.
+    pub fn initiate_mapping(&self) {
+        if self.state.load(Relaxed) != COPIED { return; }
+        self.state.store(MAPPING, Relaxed);
+
+        let state = Arc::clone(&self.state);
+
+        self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            state.store(if result.is_ok() { MAPPED } else { FAILED }, Relaxed);
+        });
+    }
+
+    // Returns the most recent pixel that has been read back in RGBA order.
+    pub fn latest(&mut self) -> Option<[u8; 4]> {
+        match self.state.load(Relaxed) {
+            MAPPED => {
+                let bytes = self.buffer.slice(..).get_mapped_range();
+
+                self.latest = Some(match self.format {
+                    crate::Format::BgraU8 => [bytes[2], bytes[1], bytes[0], bytes[3]],
+                    crate::Format::RU8 => [bytes[0], 0, 0, 255],
+                    _ => [bytes[0], bytes[1], bytes[2], bytes[3]],
+                });
+
+                drop(bytes);
+                self.buffer.unmap();
+                self.state.store(IDLE, Relaxed);
+            },
+            FAILED => panic!("Failed to memory map buffer data for read_pixel."),
+            _ => {},
+        }
+
+        self.latest
+    }
+}
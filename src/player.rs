@@ -0,0 +1,98 @@
+use std::{thread, time, sync::Arc};
+use crossbeam_channel::{Receiver, TryRecvError};
+
+// Plays back compressed recordings inside the application. Frames are
+// decompressed in a background thread and uploaded into a texture at the
+// recorded frame rate so that the texture can be sampled by a pipeline.
+
+pub struct Player {
+    pub receiver: Receiver<crate::VideoFrame>,
+    pub thread: Option<thread::JoinHandle<()>>,
+    pub cancel_token: crate::CancelToken, // Stops the decompressor when the Player is dropped.
+    pub frame_rate: f32,
+    pub started_at: Option<time::Instant>,
+    pub frames_played: usize,
+    pub finished: bool,
+    pub unpadded_bytes: Vec<u8>,
+}
+
+impl Player {
+    pub fn new(directory: &str, frame_rate: f32) -> Self {
+        // Only decompress a couple of frames ahead of playback to keep memory usage down.
+        let (sender, receiver) = crossbeam_channel::bounded(2);
+        let directory = directory.to_string();
+        let cancel_token = crate::CancelToken::default();
+        let thread_token = cancel_token.clone();
+
+        let thread = thread::spawn(move || {
+            let mut decompressor = crate::Decompressor::new(&directory, false);
+            decompressor.cancel_token = thread_token;
+
+            decompressor.decompress_from_disk(Arc::new(|_video_frame, _timestamp| ()), Box::new(move |video_frame, _result, _timestamp| {
+                let _ = sender.send(video_frame);
+            }));
+        });
+
+        Self { receiver, thread: Some(thread), cancel_token, frame_rate, started_at: None, frames_played: 0, finished: false, unpadded_bytes: vec![] }
+    }
+
+    // Uploads the most recent frame that is due into the texture, resizing it if
+    // the recording changed resolution. Returns true if the texture was updated.
+    // Dropped and missing frames are skipped so the previous frame stays visible.
+    // Frames are converted to RgbaU8 so the texture should be created with that format.
+
+    pub fn update(&mut self, renderer: &crate::Renderer, texture: &mut crate::Texture) -> bool {
+        if self.finished { return false; }
+
+        let started_at = *self.started_at.get_or_insert_with(time::Instant::now);
+        let frames_due = (started_at.elapsed().as_secs_f32() * self.frame_rate) as usize + 1;
+
+        let mut latest_frame = None;
+
+        while self.frames_played < frames_due {
+            match self.receiver.try_recv() {
+                Ok(video_frame) => {
+                    self.frames_played += 1;
+                    if video_frame.image_data.is_some() { latest_frame = Some(video_frame); }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => { self.finished = true; break; },
+            }
+        }
+
+        let video_frame = match latest_frame { Some(f) => f, _ => return false };
+        let image_data = video_frame.image_data.as_ref().unwrap();
+
+        self.unpadded_bytes.clear();
+
+        for row in image_data.bytes().chunks(video_frame.padded_bytes_per_row) {
+            self.unpadded_bytes.extend(video_frame.format.to_rgba_u8(&row[..video_frame.unpadded_bytes_per_row], false));
+        }
+
+        let size = (video_frame.width as u32, video_frame.height as u32);
+
+        renderer.resize_texture(texture, (size.0, size.1, 1));
+        texture.set_data(&renderer.queue, (0, 0, 0), size, &self.unpadded_bytes);
+
+        true
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+
+        // Unblock the thread if it is waiting to send a frame. It stops sending
+        // once it sees the decompression has been cancelled.
+        while self.receiver.recv().is_ok() {}
+
+        let thread = match self.thread.take() { Some(t) => t, _ => return };
+        if thread.join().is_err() && !thread::panicking() {
+            panic!("The playback thread panicked.");
+        }
+    }
+}
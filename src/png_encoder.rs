@@ -2,38 +2,93 @@ use std::io::{Cursor, Write};
 
 pub struct PngEncoder;
 
+// Frames are converted to RgbaU8 based on their format so that BgraU8 channels
+// aren't swapped and float formats are clamped. Set linear_to_srgb for frames
+// recorded from linear targets (e.g. RgbaF16) so they aren't too dark. Set
+// tag_srgb to write an sRGB chunk so that viewers with color management show the
+// colors as they were on screen rather than guessing the color space.
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PngOptions {
+    pub linear_to_srgb: bool,
+    pub tag_srgb: bool,
+}
+
 impl PngEncoder {
     pub fn encode_to_bytes(video_frame: &crate::VideoFrame) -> Result<Vec<u8>, &'static str> {
+        Self::encode_to_bytes_with_options(video_frame, PngOptions::default())
+    }
+
+    pub fn encode_to_bytes_with_options(video_frame: &crate::VideoFrame, options: PngOptions) -> Result<Vec<u8>, &'static str> {
         let mut bytes = vec![];
 
         let cursor = Cursor::new(&mut bytes);
-        let result = Self::encode(video_frame, cursor);
+        let result = Self::encode_with_options(video_frame, cursor, options);
 
         result.map(|_| bytes)
     }
 
     pub fn encode<W: Write>(video_frame: &crate::VideoFrame, writer: W) -> Result<(), &'static str> {
+        Self::encode_with_options(video_frame, writer, PngOptions::default())
+    }
+
+    pub fn encode_with_options<W: Write>(video_frame: &crate::VideoFrame, writer: W, options: PngOptions) -> Result<(), &'static str> {
         if video_frame.image_data.is_none() {
             return Err("VideoFrame could not be written because image_data is None.")
         }
 
-        let mut png = png::Encoder::new(writer, video_frame.width as u32, video_frame.height as u32);
-
-        png.set_depth(png::BitDepth::Eight);
-        png.set_color(png::ColorType::Rgba);
+        // Frames recorded at a lower resolution are scaled back up (see VideoFrame::full_size).
+        let (width, height) = video_frame.full_size();
+        let downscaled = (width, height) != (video_frame.width, video_frame.height);
 
-        let mut png_writer = png.write_header().unwrap();
-        let mut stream_writer = png_writer.stream_writer_with_size(video_frame.unpadded_bytes_per_row).unwrap();
+        let png = rgba_encoder(writer, width as u32, height as u32, options.tag_srgb);
+        let mut png_writer = png.write_header().map_err(|_| WRITE_ERROR)?;
+        let mut stream_writer = png_writer.stream_writer_with_size(width * 4).map_err(|_| WRITE_ERROR)?;
 
         let image_data = video_frame.image_data.as_ref().unwrap();
+        let mut result = Ok(());
 
         image_data.bytes_fn(|bytes| {
-            for chunk in bytes.chunks(video_frame.padded_bytes_per_row) {
-                stream_writer.write_all(&chunk[..video_frame.unpadded_bytes_per_row]).unwrap();
-            }
+            let mut rows = bytes.chunks(video_frame.padded_bytes_per_row).map(|chunk| video_frame.format.to_rgba_u8(&chunk[..video_frame.unpadded_bytes_per_row], options.linear_to_srgb));
+
+            result = if downscaled {
+                let rgba = video_frame.upscale_rgba(rows.flatten().collect());
+                stream_writer.write_all(&rgba)
+            } else {
+                rows.try_for_each(|row| stream_writer.write_all(&row))
+            };
         });
 
-        stream_writer.finish().unwrap();
-        Ok(())
+        result.map_err(|_| WRITE_ERROR)?;
+        stream_writer.finish().map_err(|_| WRITE_ERROR)
+    }
+
+    // Encodes an image that wasn't recorded, e.g. a TiledImage. The rows of bytes
+    // mustn't be padded.
+    pub fn encode_image<W: Write>(bytes: &[u8], width: u32, height: u32, format: crate::Format, writer: W, options: PngOptions) -> Result<(), &'static str> {
+        let row_len = (width * format.bytes_per_texel()) as usize;
+        if bytes.len() != row_len * height as usize { return Err("The image could not be written because its length doesn't match its size."); }
+
+        let png = rgba_encoder(writer, width, height, options.tag_srgb);
+        let mut png_writer = png.write_header().map_err(|_| WRITE_ERROR)?;
+        let mut stream_writer = png_writer.stream_writer_with_size(width as usize * 4).map_err(|_| WRITE_ERROR)?;
+
+        for row in bytes.chunks(row_len) {
+            stream_writer.write_all(&format.to_rgba_u8(row, options.linear_to_srgb)).map_err(|_| WRITE_ERROR)?;
+        }
+
+        stream_writer.finish().map_err(|_| WRITE_ERROR)
     }
 }
+
+fn rgba_encoder<W: Write>(writer: W, width: u32, height: u32, tag_srgb: bool) -> png::Encoder<'static, W> {
+    let mut png = png::Encoder::new(writer, width, height);
+
+    png.set_depth(png::BitDepth::Eight);
+    png.set_color(png::ColorType::Rgba);
+
+    if tag_srgb { png.set_source_srgb(png::SrgbRenderingIntent::Perceptual); }
+    png
+}
+
+const WRITE_ERROR: &str = "The PNG could not be written, e.g. because the disk is full.";
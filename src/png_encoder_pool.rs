@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, fs, panic, thread, io::BufWriter};
+use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
+use crossbeam_channel::{Sender, Receiver};
+
+// Encodes VideoFrames to PNG on a pool of threads, e.g. from a recording's
+// process function. The queue is bounded so encode blocks rather than filling up
+// memory when the threads can't keep up. Frames are either written to the file
+// that the naming function returns or passed to an output function, which can
+// receive them in the order they were encoded or in the order they were queued.
+// Files that can't be written are collected and returned by finish rather than
+// stopping the pool, so the other frames are still written.
+
+pub struct PngEncoderPool {
+    pub threads: Vec<thread::JoinHandle<()>>,
+    pub output_thread: Option<thread::JoinHandle<()>>,
+    pub sender: Option<Sender<(usize, crate::VideoFrame)>>,
+    pub next_index: AtomicUsize,
+    pub errors: Arc<Mutex<Vec<String>>>,
+}
+
+pub type PngNamingFunction = Arc<dyn Fn(&crate::VideoFrame) -> String + Send + Sync>;
+pub type PngOutputFunction = Box<dyn FnMut(usize, Result<Vec<u8>, &'static str>) + Send>; // (frame_number, png)
+
+type Encoded = (usize, usize, Result<Vec<u8>, &'static str>); // (index, frame_number, png)
+
+impl PngEncoderPool {
+    // Frames without image data (dropped or missing) are skipped.
+    pub fn to_files(naming_function: PngNamingFunction, num_threads: usize, queue_size: usize, options: crate::PngOptions) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(queue_size);
+        let errors = Arc::new(Mutex::new(vec![]));
+
+        let threads = (0..num_threads.max(1)).map(|_| {
+            let receiver: Receiver<(usize, crate::VideoFrame)> = receiver.clone();
+            let naming_function = Arc::clone(&naming_function);
+            let errors = Arc::clone(&errors);
+
+            thread::spawn(move || {
+                while let Ok((_, video_frame)) = receiver.recv() {
+                    if video_frame.image_data.is_none() {
+                        eprintln!("Warning: Frame {} is {}. Skipping it.", video_frame.frame_number, video_frame.status);
+                        continue;
+                    }
+
+                    let path = naming_function(&video_frame);
+
+                    let result = fs::File::create(&path).map_err(|e| e.to_string())
+                        .and_then(|file| crate::PngEncoder::encode_with_options(&video_frame, BufWriter::new(file), options).map_err(String::from));
+
+                    if let Err(e) = result { errors.lock().unwrap().push(format!("Failed to write {}: {}", path, e)); }
+                }
+            })
+        }).collect();
+
+        Self { threads, output_thread: None, sender: Some(sender), next_index: AtomicUsize::new(0), errors }
+    }
+
+    // The output function is called on another thread with an error for frames
+    // without image data. If ordered, it's called in the order frames were queued.
+    pub fn with_output(mut output_function: PngOutputFunction, ordered: bool, num_threads: usize, queue_size: usize, options: crate::PngOptions) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(queue_size);
+        let (output_sender, output_receiver) = crossbeam_channel::bounded::<Encoded>(queue_size);
+
+        let threads = (0..num_threads.max(1)).map(|_| {
+            let receiver: Receiver<(usize, crate::VideoFrame)> = receiver.clone();
+            let output_sender = output_sender.clone();
+
+            thread::spawn(move || {
+                while let Ok((index, video_frame)) = receiver.recv() {
+                    let result = crate::PngEncoder::encode_to_bytes_with_options(&video_frame, options);
+                    let frame_number = video_frame.frame_number;
+
+                    drop(video_frame); // Release the GPU buffer before waiting for the output thread.
+                    if output_sender.send((index, frame_number, result)).is_err() { break; }
+                }
+            })
+        }).collect();
+
+        drop(output_sender); // The workers hold the remaining senders.
+
+        let output_thread = thread::spawn(move || {
+            let mut pending = BTreeMap::new();
+            let mut next_index = 0;
+
+            for (index, frame_number, result) in output_receiver {
+                if !ordered { output_function(frame_number, result); continue; }
+
+                pending.insert(index, (frame_number, result));
+
+                while let Some((frame_number, result)) = pending.remove(&next_index) {
+                    output_function(frame_number, result);
+                    next_index += 1;
+                }
+            }
+        });
+
+        Self { threads, output_thread: Some(output_thread), sender: Some(sender), next_index: AtomicUsize::new(0), errors: Arc::new(Mutex::new(vec![])) }
+    }
+
+    pub fn encode(&self, video_frame: crate::VideoFrame) {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.sender.as_ref().unwrap().send((index, video_frame)).expect("The PNG encoder threads have stopped. Call finish to see why.");
+    }
+
+    // Waits for the queued frames to be encoded and output. Returns the files that
+    // couldn't be written. Panics if one of the threads panicked, e.g. in the
+    // output function.
+    pub fn finish(&mut self) -> Result<(), Vec<String>> {
+        if self.sender.is_none() { return Ok(()); }
+
+        // Disconnect the channel so that the worker threads break.
+        let sender = self.sender.take().unwrap();
+        drop(sender);
+
+        // Wait for the worker threads to exit, which disconnects the output thread.
+        let threads = self.threads.drain(..).chain(self.output_thread.take());
+        let panics = threads.filter_map(|t| t.join().err()).collect::<Vec<_>>();
+
+        // Don't panic while panicking, e.g. if the pool is dropped while unwinding.
+        if let Some(payload) = panics.into_iter().next() {
+            if !thread::panicking() { panic::resume_unwind(payload); }
+        }
+
+        let errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+// The errors are printed if the pool is dropped without calling finish, e.g. by
+// capture_every when the recording stops.
+impl Drop for PngEncoderPool {
+    fn drop(&mut self) {
+        for error in self.finish().err().unwrap_or_default() {
+            eprintln!("Warning: {}", error);
+        }
+    }
+}
@@ -2,13 +2,29 @@
 pub enum Primitive {
     Triangle,
     TriangleStrip,
+    TriangleFan,
+    Quads,
 }
 
+// wgpu has no fan or quad topologies so these are drawn as triangle lists with
+// an index buffer that the pipeline generates. Quads are four vertices that go
+// around the perimeter (like GL_QUADS) and each instance can contain many quads.
+
 impl Primitive {
     pub fn topology(&self) -> wgpu::PrimitiveTopology {
         match self {
             Self::Triangle => wgpu::PrimitiveTopology::TriangleList,
             Self::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+            Self::TriangleFan => wgpu::PrimitiveTopology::TriangleList,
+            Self::Quads => wgpu::PrimitiveTopology::TriangleList,
+        }
+    }
+
+    pub fn indices(&self, vertices_per_instance: u32) -> Option<Vec<u32>> {
+        match self {
+            Self::Triangle | Self::TriangleStrip => None,
+            Self::TriangleFan => Some((1..vertices_per_instance.saturating_sub(1)).flat_map(|i| [0, i, i + 1]).collect()),
+            Self::Quads => Some((0..vertices_per_instance / 4).flat_map(|q| [0, 1, 2, 0, 2, 3].map(|i| q * 4 + i)).collect()),
         }
     }
 }
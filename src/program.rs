@@ -12,38 +12,82 @@ pub struct Inner {
     pub instances: Instances,
     pub uniforms: Uniforms,
     pub textures: Textures,
+    pub texture_arrays: TextureArrays, // Bound after the textures.
 }
 
 pub type Attributes = Vec<crate::Attribute>;
 pub type Instances = Vec<crate::Instanced>;
 pub type Uniforms = Vec<(crate::Uniform, crate::Visibility)>;
 pub type Textures = Vec<(crate::Texture, crate::Visibility)>;
+pub type TextureArrays = Vec<(crate::TextureArray, crate::Visibility)>;
 
 impl Program {
     pub fn new(device: &wgpu::Device, vert: &[u8], frag: &[u8], attributes: Attributes, instances: Instances, uniforms: Uniforms, textures: Textures) -> Self {
-        let inner = Inner {
-            vertex_shader: create_shader_module(device, vert),
-            fragment_shader: create_shader_module(device, frag),
-            attributes, instances, uniforms, textures,
-        };
+        Self::new_with_texture_arrays(device, vert, frag, attributes, instances, uniforms, textures, vec![])
+    }
+
+    pub fn new_with_texture_arrays(device: &wgpu::Device, vert: &[u8], frag: &[u8], attributes: Attributes, instances: Instances, uniforms: Uniforms, textures: Textures, texture_arrays: TextureArrays) -> Self {
+        if !texture_arrays.is_empty() && !device.features().contains(wgpu::Features::TEXTURE_BINDING_ARRAY) {
+            panic!("Texture arrays aren't supported by this adapter. Check renderer.supports_texture_arrays() first.");
+        }
 
-        Self { inner: rc::Rc::new(inner) }
+        let (vertex_shader, fragment_shader) = (spirv_module(device, vert), spirv_module(device, frag));
+        Self { inner: rc::Rc::new(Inner { vertex_shader, fragment_shader, attributes, instances, uniforms, textures, texture_arrays }) }
     }
 
-    pub fn latest_generations(&self) -> impl Iterator<Item=u32> + '_ {
+    // WGSL source, e.g. for the renderer's built-in shaders (see Material), which
+    // don't need the shader_compilation feature.
+    pub fn new_wgsl(device: &wgpu::Device, vert: &str, frag: &str, attributes: Attributes, instances: Instances, uniforms: Uniforms, textures: Textures) -> Self {
+        let (vertex_shader, fragment_shader) = (wgsl_module(device, vert), wgsl_module(device, frag));
+        Self { inner: rc::Rc::new(Inner { vertex_shader, fragment_shader, attributes, instances, uniforms, textures, texture_arrays: vec![] }) }
+    }
+
+    // Takes the textures separately because pipelines can swap them out.
+    pub fn latest_generations<'a>(&'a self, textures: &'a Textures) -> impl Iterator<Item=u32> + 'a {
         let g1 = self.attributes.iter().map(|a| a.buffer.generation());
         let g2 = self.instances.iter().map(|i| i.buffer.generation());
         let g3 = self.uniforms.iter().map(|(u, _)| u.buffer.generation());
-        let g4 = self.textures.iter().map(|(t, _)| t.generation);
+        let g4 = textures.iter().map(|(t, _)| t.generation());
+        let g5 = self.texture_arrays.iter().flat_map(|(a, _)| a.textures.iter().map(|t| t.generation()));
 
-        g1.chain(g2).chain(g3).chain(g4)
+        g1.chain(g2).chain(g3).chain(g4).chain(g5)
+    }
+
+    pub fn vertex_attributes(&self) -> impl Iterator<Item=&crate::Attribute> {
+        self.attributes.iter().filter(|a| !a.pulled)
+    }
+
+    pub fn pulled_attributes(&self) -> impl Iterator<Item=&crate::Attribute> {
+        self.attributes.iter().filter(|a| a.pulled)
+    }
+
+    // The (set, binding) of a pulled attribute. They follow on from the textures
+    // and texture arrays, including their samplers.
+    pub fn pulled_index_tuple(&self, location: usize) -> (usize, usize) {
+        let samplers = |has_sampler: bool| if has_sampler { 2 } else { 1 };
+
+        let before = self.instances.len() + self.uniforms.len()
+            + self.textures.iter().map(|(t, _)| samplers(t.sampler.is_some())).sum::<usize>()
+            + self.texture_arrays.iter().map(|(a, _)| samplers(a.textures[0].sampler.is_some())).sum::<usize>()
+            + self.pulled_attributes().position(|a| a.location == location).expect("There isn't a pulled attribute at that location.");
+
+        (before / crate::BINDINGS_PER_GROUP, before % crate::BINDINGS_PER_GROUP)
     }
 }
 
-fn create_shader_module(device: &wgpu::Device, bytes: &[u8]) -> wgpu::ShaderModule {
-    let spirv = wgpu::util::make_spirv(bytes);
-    let descriptor = wgpu::ShaderModuleDescriptor { label: None, source: spirv };
+// Checks the magic number so that passing WGSL or the wrong file fails with a
+// clear message rather than somewhere inside wgpu.
+fn spirv_module(device: &wgpu::Device, bytes: &[u8]) -> wgpu::ShaderModule {
+    if !bytes.starts_with(&SPIRV_MAGIC_NUMBER.to_le_bytes()) {
+        panic!("The shader isn't SPIR-V because it doesn't start with the magic number. Use program_wgsl for WGSL source.");
+    }
+
+    let descriptor = wgpu::ShaderModuleDescriptor { label: None, source: wgpu::util::make_spirv(bytes) };
+    device.create_shader_module(descriptor)
+}
 
+fn wgsl_module(device: &wgpu::Device, source: &str) -> wgpu::ShaderModule {
+    let descriptor = wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(source.into()) };
     device.create_shader_module(descriptor)
 }
 
@@ -54,3 +98,5 @@ impl ops::Deref for Program {
         &self.inner
     }
 }
+
+const SPIRV_MAGIC_NUMBER: u32 = 0x07230203;
@@ -0,0 +1,46 @@
+// How a pipeline's primitives are turned into fragments. Line and Point draw the
+// edges or vertices of triangles, e.g. for debug wireframes, and conservative
+// rasterization shades every pixel that a triangle touches rather than those
+// whose centers it covers, e.g. for voxelization. With unclipped_depth, depths
+// outside of 0 to 1 are clamped rather than clipped, e.g. so that shadow casters
+// behind the light's near plane still cast shadows. Each of these needs a feature
+// that not all adapters support (see Renderer::supports_rasterization).
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rasterization {
+    pub polygon_mode: PolygonMode,
+    pub conservative: bool,
+    pub unclipped_depth: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+    Point,
+}
+
+impl Rasterization {
+    pub fn required_features(&self) -> wgpu::Features {
+        let mut features = match self.polygon_mode {
+            PolygonMode::Fill => wgpu::Features::empty(),
+            PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        };
+
+        if self.conservative { features |= wgpu::Features::CONSERVATIVE_RASTERIZATION; }
+        if self.unclipped_depth { features |= wgpu::Features::DEPTH_CLIP_CONTROL; }
+        features
+    }
+}
+
+impl PolygonMode {
+    pub fn to_wgpu(&self) -> wgpu::PolygonMode {
+        match self {
+            Self::Fill => wgpu::PolygonMode::Fill,
+            Self::Line => wgpu::PolygonMode::Line,
+            Self::Point => wgpu::PolygonMode::Point,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::{collections::VecDeque, time};
+
+// Spreads recording copies across frames so that at most bytes_per_second are
+// copied into the readback buffers, e.g. to smooth out the frame time spikes of
+// copying whole frames at 4K. Every frame is still captured: its region is first
+// copied into a snapshot texture, which is cheap because it stays on the GPU, and
+// then its rows are copied into the buffers over the following frames. Frames are
+// only mapped and processed once all of their rows have been copied.
+
+pub struct ReadbackScheduler {
+    pub bytes_per_second: f64,
+    pub pending: VecDeque<PendingReadback>,
+    pub spare_snapshots: Vec<crate::Texture>,
+    pub scheduled_at: Option<time::Instant>,
+}
+
+pub struct PendingReadback {
+    pub frame_number: usize,
+    pub snapshots: Vec<(crate::Texture, usize)>, // One per buffer with its padded_bytes_per_row.
+    pub snapshots_copied: usize,
+    pub rows_copied: u32,
+}
+
+impl ReadbackScheduler {
+    pub fn new(bytes_per_second: f64) -> Self {
+        Self { bytes_per_second, pending: VecDeque::new(), spare_snapshots: vec![], scheduled_at: None }
+    }
+
+    pub fn is_pending(&self, frame_number: usize) -> bool {
+        self.pending.iter().any(|p| p.frame_number == frame_number)
+    }
+
+    // Copies the region of the texture into a snapshot that will be read back later.
+    pub fn snapshot(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &crate::Texture, origin: (u32, u32), extent: wgpu::Extent3d) -> crate::Texture {
+        let size = (extent.width, extent.height, 1);
+        let index = self.spare_snapshots.iter().position(|s| s.format.texture_format() == texture.format.texture_format() && s.size() == size);
+
+        let snapshot = match index {
+            Some(i) => self.spare_snapshots.swap_remove(i),
+            None => create_snapshot(device, size, texture.format),
+        };
+
+        let (wgpu_texture, wgpu_snapshot) = (texture.texture(), snapshot.texture());
+        let source_copy = crate::Texture::image_copy_texture(&wgpu_texture, (origin.0, origin.1, 0));
+        let snapshot_copy = crate::Texture::image_copy_texture(&wgpu_snapshot, (0, 0, 0));
+
+        encoder.copy_texture_to_texture(source_copy, snapshot_copy, extent);
+        snapshot
+    }
+
+    pub fn push(&mut self, frame_number: usize, snapshots: Vec<(crate::Texture, usize)>) {
+        self.pending.push_back(PendingReadback { frame_number, snapshots, snapshots_copied: 0, rows_copied: 0 });
+    }
+
+    // Copies as many rows as the budget allows since the last call, oldest frame
+    // first. At least one row is copied so that recording always makes progress.
+    // The buffers function returns the frame's buffers in the order of its snapshots.
+    pub fn copy_rows<'a>(&mut self, encoder: &mut wgpu::CommandEncoder, buffers: impl Fn(usize) -> Vec<&'a wgpu::Buffer>) -> bool {
+        let now = time::Instant::now();
+        let elapsed = self.scheduled_at.map(|t| (now - t).as_secs_f64()).unwrap_or(0.).min(MAX_ELAPSED_IN_SECONDS);
+
+        self.scheduled_at = Some(now);
+
+        let mut budget = if self.bytes_per_second.is_infinite() { f64::INFINITY } else { self.bytes_per_second * elapsed };
+        let mut copied_any = false;
+
+        while let Some(pending) = self.pending.front_mut() {
+            let frame_buffers = buffers(pending.frame_number);
+
+            while pending.snapshots_copied < pending.snapshots.len() {
+                let (snapshot, padded_bytes_per_row) = &pending.snapshots[pending.snapshots_copied];
+                let (width, height, _) = snapshot.size();
+
+                let affordable_rows = (budget / *padded_bytes_per_row as f64).min(height as f64) as u32;
+                let rows = affordable_rows.min(height - pending.rows_copied).max(if copied_any { 0 } else { 1 });
+
+                if rows == 0 { return copied_any; }
+
+                let wgpu_snapshot = snapshot.texture();
+                let image_copy = crate::Texture::image_copy_texture(&wgpu_snapshot, (0, pending.rows_copied, 0));
+
+                let mut layout = snapshot.image_data_layout(*padded_bytes_per_row as u32, height);
+                layout.offset = pending.rows_copied as u64 * *padded_bytes_per_row as u64;
+
+                let buffer_copy = wgpu::ImageCopyBuffer { buffer: frame_buffers[pending.snapshots_copied], layout };
+                encoder.copy_texture_to_buffer(image_copy, buffer_copy, wgpu::Extent3d { width, height: rows, depth_or_array_layers: 1 });
+
+                budget -= (rows as usize * padded_bytes_per_row) as f64;
+                copied_any = true;
+                pending.rows_copied += rows;
+
+                if pending.rows_copied == height {
+                    pending.snapshots_copied += 1;
+                    pending.rows_copied = 0;
+                }
+            }
+
+            // Only keep spare snapshots of the current size, e.g. in case the window was resized.
+            let finished = self.pending.pop_front().unwrap();
+            let sizes = finished.snapshots.iter().map(|(s, _)| s.size()).collect::<Vec<_>>();
+
+            self.spare_snapshots.retain(|s| sizes.contains(&s.size()));
+            self.spare_snapshots.extend(finished.snapshots.into_iter().map(|(s, _)| s));
+        }
+
+        copied_any
+    }
+}
+
+fn create_snapshot(device: &wgpu::Device, size: (u32, u32, u32), format: crate::Format) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest; // Not used
+    let renderable = false;
+    let copyable = true;
+    let with_sampler = false;
+
+    crate::Texture::new(device, size, filter_mode, format, 1, renderable, copyable, with_sampler)
+}
+
+// Don't let the budget build up while the app is paused, e.g. in a debugger.
+const MAX_ELAPSED_IN_SECONDS: f64 = 0.1;
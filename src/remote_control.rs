@@ -0,0 +1,240 @@
+use std::{io, net, sync, thread, collections::HashMap, io::{BufRead, BufReader, Read, Write}};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crossbeam_channel::{Sender, Receiver};
+
+// Lets external tools and test harnesses drive a RenderThread over TCP or a
+// Unix socket. Connections are handled in background threads but commands are
+// only run when the app calls poll (e.g. once per frame) so they don't race with
+// the app's own calls. Pipelines and textures are registered under names. The
+// protocol is one command per line and each command gets one reply line:
+//
+//   auth <token>                              -> ok (must be first if a token is set)
+//   set_uniform <pipeline> <i> <j> <f32>...   -> ok
+//   set_vsync <true|false>                    -> ok
+//   capture_frame                             -> ok
+//   start_recording <pipeline>...             -> ok <recording_id>
+//   stop_recording <recording_id> <pipeline>... -> ok
+//   screenshot <texture>                      -> ok <len> (followed by len raw bytes)
+//   ping                                      -> ok
+//
+// Failed commands reply with: error <message>
+//
+// TCP addresses must be loopback (e.g. 127.0.0.1:7878) unless allow_remote is
+// set, which also requires a token because anyone who can connect can read the
+// app's textures. An address that is just a port binds to 127.0.0.1.
+//
+// Connections beyond MAX_CONNECTIONS are refused and lines longer than
+// MAX_LINE_LEN (including before auth) close the connection.
+
+pub struct RemoteControl {
+    pub receiver: Receiver<(String, Sender<Vec<u8>>)>,
+    pub pipelines: HashMap<String, crate::PipelineRef>,
+    pub textures: HashMap<String, crate::TextureRef>,
+    pub recording_function: Option<RecordingFunction>,
+    _thread: thread::JoinHandle<()>,
+}
+
+// Returns the process_function for each recording started remotely.
+pub type RecordingFunction = Box<dyn FnMut() -> Box<dyn FnMut(crate::VideoFrame) + Send>>;
+
+#[derive(Clone, Debug)]
+pub enum RemoteAddress {
+    Tcp(String),
+    #[cfg(unix)] Unix(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RemoteOptions {
+    pub token: Option<String>, // Connections must send it with auth before other commands.
+    pub allow_remote: bool,    // Allow TCP addresses that aren't loopback.
+}
+
+pub const DEFAULT_REMOTE_PORT: u16 = 7878;
+pub const MAX_CONNECTIONS: usize = 16;
+pub const MAX_LINE_LEN: u64 = 64 * 1024;
+
+impl Default for RemoteAddress {
+    fn default() -> Self {
+        Self::Tcp(format!("127.0.0.1:{}", DEFAULT_REMOTE_PORT))
+    }
+}
+
+impl RemoteControl {
+    pub fn new(address: RemoteAddress, options: RemoteOptions) -> io::Result<Self> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let token = options.token.map(sync::Arc::new);
+        let connections = sync::Arc::new(AtomicUsize::new(0));
+
+        let _thread = match address {
+            RemoteAddress::Tcp(a) => {
+                let addresses = socket_addresses(&a)?;
+
+                if options.allow_remote && token.is_none() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "A token must be set to allow remote connections."));
+                }
+
+                if !options.allow_remote && !addresses.iter().all(|a| a.ip().is_loopback()) {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} isn't a loopback address. Set allow_remote to bind to it.", a)));
+                }
+
+                let listener = net::TcpListener::bind(&addresses[..])?;
+                thread::spawn(move || for stream in listener.incoming().flatten() { spawn_connection(stream, &sender, token.clone(), &connections); })
+            },
+            #[cfg(unix)] RemoteAddress::Unix(path) => {
+                let _ = std::fs::remove_file(&path);
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                thread::spawn(move || for stream in listener.incoming().flatten() { spawn_connection(stream, &sender, token.clone(), &connections); })
+            },
+        };
+
+        Ok(Self { receiver, pipelines: HashMap::new(), textures: HashMap::new(), recording_function: None, _thread })
+    }
+
+    pub fn register_pipeline(&mut self, name: &str, pipeline: crate::PipelineRef) {
+        self.pipelines.insert(name.to_string(), pipeline);
+    }
+
+    pub fn register_texture(&mut self, name: &str, texture: crate::TextureRef) {
+        self.textures.insert(name.to_string(), texture);
+    }
+
+    // Recordings can't be started remotely until this is set.
+    pub fn set_recording_function(&mut self, recording_function: RecordingFunction) {
+        self.recording_function = Some(recording_function);
+    }
+
+    // Runs the commands that have been received since the last call. Returns the
+    // number of commands that were run.
+    pub fn poll(&mut self, render_thread: &crate::RenderThread) -> usize {
+        let mut count = 0;
+
+        while let Ok((line, reply_sender)) = self.receiver.try_recv() {
+            let reply = match self.run(render_thread, &line) {
+                Ok(reply) => reply,
+                Err(message) => format!("error {}\n", message).into_bytes(),
+            };
+
+            let _ = reply_sender.send(reply);
+            count += 1;
+        }
+
+        count
+    }
+
+    fn run(&mut self, render_thread: &crate::RenderThread, line: &str) -> Result<Vec<u8>, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args = words.collect::<Vec<_>>();
+
+        match (command, &args[..]) {
+            ("ping", []) => {},
+            ("capture_frame", []) => render_thread.capture_frame(),
+            ("set_vsync", [boolean]) => render_thread.set_vsync(parse(boolean)?),
+            ("set_uniform", [pipeline, i, j, data @ ..]) => {
+                let data = data.iter().map(|f| parse(f)).collect::<Result<Vec<f32>, _>>()?;
+                render_thread.try_set_uniform(self.pipeline(pipeline)?, (parse(i)?, parse(j)?), data)?;
+            },
+            ("start_recording", pipelines) => {
+                let pipelines = pipelines.iter().map(|p| self.pipeline(p)).collect::<Result<Vec<_>, _>>()?;
+                let recording_function = self.recording_function.as_mut().ok_or("no recording function has been set")?;
+
+                let recording = render_thread.start_recording(pipelines, None, RECORDING_BUFFER_IN_MEGABYTES, recording_function());
+                return Ok(format!("ok {}\n", recording.0).into_bytes());
+            },
+            ("stop_recording", [recording, pipelines @ ..]) => {
+                let pipelines = pipelines.iter().map(|p| self.pipeline(p)).collect::<Result<Vec<_>, _>>()?;
+                render_thread.stop_recording(crate::RecordingId(parse(recording)?), pipelines);
+            },
+            ("screenshot", [texture]) => {
+                let texture = *self.textures.get(*texture).ok_or(format!("unknown texture '{}'", texture))?;
+                let bytes = render_thread.try_read_texture(texture)?;
+
+                let mut reply = format!("ok {}\n", bytes.len()).into_bytes();
+                reply.extend(bytes);
+
+                return Ok(reply);
+            },
+            ("auth", _) => return Err("already authenticated".to_string()),
+            _ => return Err(format!("unknown command '{}'", line.trim())),
+        }
+
+        Ok(b"ok\n".to_vec())
+    }
+
+    fn pipeline(&self, name: &str) -> Result<crate::PipelineRef, String> {
+        self.pipelines.get(name).copied().ok_or(format!("unknown pipeline '{}'", name))
+    }
+}
+
+const RECORDING_BUFFER_IN_MEGABYTES: f32 = 500.;
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse().map_err(|_| format!("invalid argument '{}'", word))
+}
+
+// An address that is just a port (e.g. "7878" or ":7878") binds to 127.0.0.1.
+fn socket_addresses(address: &str) -> io::Result<Vec<net::SocketAddr>> {
+    use net::ToSocketAddrs;
+
+    let port = address.strip_prefix(':').unwrap_or(address).parse::<u16>();
+    let addresses = match port { Ok(p) => vec![(net::Ipv4Addr::LOCALHOST, p).into()], _ => address.to_socket_addrs()?.collect() };
+
+    if addresses.is_empty() { return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} didn't resolve to an address.", address))); }
+    Ok(addresses)
+}
+
+// Forwards each line to the app and writes the reply back once poll has run it.
+// The connection is closed if the first line isn't auth with the token or a
+// line is too long. Connections beyond MAX_CONNECTIONS are refused.
+fn spawn_connection<S: io::Read + Write + Send + 'static>(mut stream: S, sender: &Sender<(String, Sender<Vec<u8>>)>, token: Option<sync::Arc<String>>, connections: &sync::Arc<AtomicUsize>) {
+    if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+        connections.fetch_sub(1, Ordering::SeqCst);
+        let _ = stream.write_all(b"error too many connections\n");
+        return;
+    }
+
+    let (sender, connections) = (sender.clone(), sync::Arc::clone(connections));
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        let mut authenticated = token.is_none();
+
+        loop {
+            line.clear();
+
+            let len = (&mut reader).take(MAX_LINE_LEN).read_line(&mut line).unwrap_or(0);
+            if len == 0 { break; }
+
+            if len as u64 == MAX_LINE_LEN && !line.ends_with('\n') {
+                let _ = reader.get_mut().write_all(b"error line too long\n");
+                break;
+            }
+
+            if line.trim().is_empty() { continue; }
+
+            if !authenticated {
+                let mut words = line.split_whitespace();
+                authenticated = words.next() == Some("auth") && words.next().map(|t| tokens_match(t, token.as_ref().unwrap())).unwrap_or(false) && words.next().is_none();
+
+                let reply: &[u8] = if authenticated { b"ok\n" } else { b"error invalid token\n" };
+                if reader.get_mut().write_all(reply).is_err() || !authenticated { break; }
+
+                continue;
+            }
+
+            let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+            if sender.send((line.clone(), reply_sender)).is_err() { break; }
+
+            let reply = match reply_receiver.recv() { Ok(r) => r, _ => break };
+            if reader.get_mut().write_all(&reply).is_err() { break; }
+        }
+
+        connections.fetch_sub(1, Ordering::SeqCst);
+    });
+}
+
+// Compares every byte so that the time taken doesn't reveal how much matched.
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -1,93 +1,244 @@
+use std::rc;
+
 pub struct RenderPass<'a, 'b> {
     renderer: &'a crate::Renderer<'b>,
 }
 
 type Clear = Option<crate::ClearColor>;
 type View<'a> = Option<&'a crate::Viewport>;
+type Views = Vec<rc::Rc<wgpu::TextureView>>;
+type Recorder<'a> = (&'a crate::VideoRecorder, crate::RecordingPosition);
+
+// Which half of a depth pre-pass is being rendered (see Renderer::render_draws).
+// The depth half has no color attachments so it isn't recorded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrePass { None, Depth, Color }
 
 impl<'a, 'b> RenderPass<'a, 'b> {
     pub fn new(renderer: &'a crate::Renderer<'b>) -> Self {
         Self { renderer }
     }
 
-    pub fn render(&self, targets: &[crate::Target], pipeline: &crate::Pipeline, clear: &Clear, viewport: View, count: (u32, u32)) -> wgpu::CommandBuffer {
+    pub fn render(&self, targets: &[crate::Target], pipeline: &crate::Pipeline, clear: &Clear, viewport: View, recording_viewport: View, count: (u32, u32), instance_offset: u32, pre_pass: PrePass) -> wgpu::CommandBuffer {
         let window_size = self.window_size();
-        let size = (window_size.0, window_size.1, 1);
 
         pipeline.recreate_on_buffer_or_texture_resize(&self.renderer.device, window_size, targets);
-        self.renderer.recorder.as_ref().map(|s| s.inner.borrow_mut().recording_texture.resize(&self.renderer.device, size));
+        pipeline.generate_indices_if_needed(&self.renderer.device, count.1);
+        if pre_pass != PrePass::None { pipeline.create_pre_pass_pipelines_if_needed(&self.renderer.device); }
+
+        // The recording textures are attachments of the render pass so they must match the size of the targets.
+        let size = targets.first().map(|t| t.size(window_size)).unwrap_or((window_size.0, window_size.1, 1));
+
+        let transparent_oit = pipeline.inner.borrow().transparent_oit && pre_pass != PrePass::Depth;
+        let transparency = if transparent_oit { Some(self.begin_transparency(size)) } else { None };
+
+        #[cfg(feature="pipeline_statistics")]
+        let statistics_query = if targets.is_empty() { None } else { self.renderer.inner.borrow_mut().statistics.as_mut().and_then(|s| s.begin_query(pipeline.id)) };
+
+        let renderer_inner = self.renderer.inner.borrow();
+        let state = pipeline.inner.borrow();
+        let recordings = if pre_pass == PrePass::Depth { &[][..] } else { &state.recordings[..] };
+        let recorders = recordings.iter().map(|(id, position, _)| (renderer_inner.recorder(*id), *position)).collect::<Vec<_>>();
+
+        for (recorder, _) in &recorders {
+            recorder.resize(&self.renderer.device, (size.0, size.1, 1));
+        }
 
-        let color_attachments = self.color_attachments(targets, pipeline, clear);
-        let descriptor = render_pass_descriptor(&color_attachments);
-        let attributes = &pipeline.program.attributes;
+        // Hold onto the views and buffers for the lifetime of the render pass.
+        let views = match &transparency { Some((v, _)) => v.clone(), _ => targets.iter().map(|t| t.view(&self.renderer)).collect::<Views>() };
+        let msaa_views = state.msaa_textures.iter().map(|t| t.view()).collect::<Views>();
+        let recording_views = recorders.iter().map(|(r, _)| r.views()).collect::<Vec<_>>();
+        let buffers = pipeline.program.vertex_attributes().map(|a| a.buffer.buffer()).collect::<Vec<_>>();
+
+        let depth_view = state.depth.as_ref().map(|d| { d.buffer.resize(&self.renderer.device, (size.0, size.1)); d.buffer.view() });
+        let first_use = state.depth.as_ref().map(|d| d.buffer.clear_if_first_use(renderer_inner.frame_index)).unwrap_or(false);
+        let with_stencil = state.depth.as_ref().map(|d| d.buffer.with_stencil).unwrap_or(false);
+
+        let mut color_attachments = match transparency {
+            Some((_, clear_transparency)) => crate::Transparency::color_attachments(&views, clear_transparency),
+            _ => self.color_attachments(&views, &msaa_views, &recorders, &recording_views, &state, clear),
+        };
+
+        if pre_pass == PrePass::Depth { color_attachments.clear(); }
+
+        let depth_attachment = depth_view.as_deref().map(|view| depth_stencil_attachment(view, first_use, with_stencil, clear));
+        let descriptor = render_pass_descriptor(&color_attachments, depth_attachment);
         let (instance_count, vertices_per_instance) = count;
+        let instances = instance_offset..instance_offset + instance_count;
+
+        let render_pipeline = match (pre_pass, &state.pre_pass_pipelines) {
+            (PrePass::Depth, Some((depth_only, _))) => depth_only,
+            (PrePass::Color, Some((_, color))) => color,
+            _ => &state.pipeline,
+        };
 
-        let mut encoder = create_command_encoder(&self.renderer.device);
-        if targets.is_empty() { return encoder.finish(); }
+        let mut encoder = self.renderer.create_command_encoder();
+        if targets.is_empty() { return self.renderer.finish_command_encoder(encoder); }
 
         let mut render_pass = encoder.begin_render_pass(&descriptor);
-        render_pass.set_pipeline(&pipeline.pipeline);
+        render_pass.set_pipeline(render_pipeline);
 
-        for (i, bind_group) in pipeline.bind_groups.iter().enumerate() {
+        #[cfg(feature="pipeline_statistics")]
+        if let Some((query_set, index)) = &statistics_query {
+            render_pass.begin_pipeline_statistics_query(query_set, *index);
+        }
+
+        if let Some(color) = state.blend_constant {
+            render_pass.set_blend_constant(color.inner);
+        }
+
+        if state.stencil.is_some() {
+            render_pass.set_stencil_reference(state.stencil_reference);
+        }
+
+        for (i, bind_group) in state.bind_groups.iter().enumerate() {
             render_pass.set_bind_group(i as u32, bind_group, &[]);
         }
 
-        for (slot, attribute) in attributes.iter().enumerate() {
-            render_pass.set_vertex_buffer(slot as u32, attribute.buffer.slice(..));
+        for (slot, buffer) in buffers.iter().enumerate() {
+            render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
         }
 
         if let Some(v) = viewport {
             render_pass.set_viewport(v.margin_x, v.margin_y, v.width, v.height, 0., 1.);
         }
 
-        render_pass.draw(0..vertices_per_instance, 0..instance_count);
+        if let Some((index_buffer, _, index_count)) = &state.indices {
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..*index_count, 0, instances);
+        } else {
+            render_pass.draw(0..vertices_per_instance, instances);
+        }
+
+        #[cfg(feature="pipeline_statistics")]
+        if statistics_query.is_some() { render_pass.end_pipeline_statistics_query(); }
+
         drop(render_pass);
 
-        if let crate::RecordingPosition::Last = pipeline.position_in_recording {
-            let recorder = self.renderer.recorder.as_ref().unwrap();
+        // Recording copies are submitted separately from the render work (see Renderer::flush).
+        let mut copy_encoder = None;
 
-            recorder.create_buffer_if_within_memory_limit(&self.renderer.device, viewport);
-            recorder.copy_texture_to_buffer_if_present(&mut encoder, viewport);
-        };
+        for (recorder, position) in &recorders {
+            if let crate::RecordingPosition::Last = position {
+                let copy_encoder = copy_encoder.get_or_insert_with(|| self.renderer.create_command_encoder());
+
+                recorder.create_buffer_if_within_memory_limit(&self.renderer.device, recording_viewport);
+                recorder.copy_texture_to_buffer_if_present(&self.renderer.device, copy_encoder, recording_viewport);
+            }
+        }
+
+        let readback = copy_encoder.map(|e| self.renderer.finish_command_encoder(e));
+
+        drop(recorders);
+        drop(renderer_inner);
+
+        if let Some(cbuffer) = readback {
+            self.renderer.inner.borrow_mut().readbacks.push(cbuffer);
+        }
+
+        self.renderer.finish_command_encoder(encoder)
+    }
+
+    pub fn render_bundle(&self, targets: &[crate::Target], bundle: &crate::Bundle, clear: &Clear, viewport: View) -> wgpu::CommandBuffer {
+        let load = match clear { Some(c) => wgpu::LoadOp::Clear(c.inner), _ => wgpu::LoadOp::Load };
+        let ops = wgpu::Operations { load, store: wgpu::StoreOp::Store };
+
+        let views = targets.iter().map(|t| t.view(&self.renderer)).collect::<Views>();
+
+        let color_attachments = views.iter().map(|view| {
+            Some(wgpu::RenderPassColorAttachment { view, resolve_target: None, ops })
+        }).collect::<Vec<_>>();
+
+        let descriptor = render_pass_descriptor(&color_attachments, None);
+        let mut encoder = self.renderer.create_command_encoder();
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+
+        if let Some(v) = viewport {
+            render_pass.set_viewport(v.margin_x, v.margin_y, v.width, v.height, 0., 1.);
+        }
+
+        if let Some(color) = bundle.blend_constant {
+            render_pass.set_blend_constant(color.inner);
+        }
+
+        render_pass.execute_bundles(std::iter::once(&bundle.render_bundle));
+        drop(render_pass);
 
-        encoder.finish()
+        self.renderer.finish_command_encoder(encoder)
+    }
+
+    // Returns the accumulation and revealage views and whether to clear them.
+    fn begin_transparency(&self, size: (u32, u32, u32)) -> (Views, bool) {
+        let mut inner = self.renderer.inner.borrow_mut();
+        let device = &self.renderer.device;
+
+        if inner.transparency.is_none() {
+            let transparency = crate::Transparency::new(device, (size.0, size.1));
+
+            inner.memory.track_texture(&transparency.accumulation);
+            inner.memory.track_texture(&transparency.revealage);
+            inner.transparency = Some(transparency);
+        }
+
+        let transparency = inner.transparency.as_mut().unwrap();
+        transparency.resize(device, (size.0, size.1));
+
+        let clear = transparency.begin();
+        (transparency.views(), clear)
     }
 
     fn window_size(&self) -> (u32, u32) {
-        (self.renderer.window_size.width, self.renderer.window_size.height)
+        let window_size = self.renderer.window_size();
+
+        (window_size.width, window_size.height)
     }
 
-    fn color_attachments(&self, targets: &'a [crate::Target], pipeline: &'a crate::Pipeline, clear: &Clear) -> Vec<Option<wgpu::RenderPassColorAttachment<'a>>> {
-        let mut attachments = targets.iter().map(|t| Some(self.color_attachment(t.view(&self.renderer), pipeline, clear))).collect::<Vec<_>>();
+    // With msaa, each target has its own multisampled texture that resolves into it.
+    fn color_attachments<'c>(&self, views: &'c Views, msaa_views: &'c Views, recorders: &[Recorder], recording_views: &'c [Views], state: &crate::InnerP, clear: &Clear) -> Vec<Option<wgpu::RenderPassColorAttachment<'c>>> {
+        let mut attachments = views.iter().enumerate().map(|(i, v)| Some(self.color_attachment(v, msaa_views.get(i).map(|m| &**m), state.msaa_samples, clear))).collect::<Vec<_>>();
+
+        if state.exclude_from_recording { return attachments; }
 
-        match pipeline.position_in_recording {
-            crate::RecordingPosition::None => {},
-            _ => attachments.push(Some(self.renderer.recorder.as_ref().unwrap().color_attachment())),
+        for ((recorder, _), views) in recorders.iter().zip(recording_views) {
+            attachments.extend(recorder.color_attachments(views).into_iter().map(Some));
         }
 
         attachments
     }
 
-    fn color_attachment(&self, texture_view: &'a wgpu::TextureView, pipeline: &'a crate::Pipeline, clear: &Clear) -> wgpu::RenderPassColorAttachment<'a> {
+    fn color_attachment<'c>(&self, texture_view: &'c wgpu::TextureView, msaa_view: Option<&'c wgpu::TextureView>, msaa_samples: u32, clear: &Clear) -> wgpu::RenderPassColorAttachment<'c> {
         let load = match clear { Some(c) => wgpu::LoadOp::Clear(c.inner), _ => wgpu::LoadOp::Load };
         let store = wgpu::StoreOp::Store;
         let ops = wgpu::Operations { load, store };
 
-        let (view, resolve_target) = match pipeline.msaa_samples {
+        let (view, resolve_target) = match msaa_samples {
             1 => (texture_view, None),
-            _ => (&pipeline.msaa_texture.as_ref().unwrap().view, Some(texture_view)),
+            _ => (msaa_view.unwrap(), Some(texture_view)),
         };
 
         wgpu::RenderPassColorAttachment { view, resolve_target, ops }
     }
 }
 
-fn render_pass_descriptor<'a>(color_attachments: &'a [Option<wgpu::RenderPassColorAttachment>]) -> wgpu::RenderPassDescriptor<'a, 'a> {
-    wgpu::RenderPassDescriptor { label: None, color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None }
+fn render_pass_descriptor<'a>(color_attachments: &'a [Option<wgpu::RenderPassColorAttachment>], depth_stencil_attachment: Option<wgpu::RenderPassDepthStencilAttachment<'a>>) -> wgpu::RenderPassDescriptor<'a, 'a> {
+    wgpu::RenderPassDescriptor { label: None, color_attachments, depth_stencil_attachment, timestamp_writes: None, occlusion_query_set: None }
+}
+
+// The clear values take precedence. Otherwise, the buffer is cleared on first use.
+fn depth_stencil_attachment<'a>(view: &'a wgpu::TextureView, first_use: bool, with_stencil: bool, clear: &Clear) -> wgpu::RenderPassDepthStencilAttachment<'a> {
+    let depth_ops = Some(clear_or_load(clear.and_then(|c| c.depth), 1., first_use));
+    let stencil_ops = if with_stencil { Some(clear_or_load(clear.and_then(|c| c.stencil), 0, first_use)) } else { None };
+
+    wgpu::RenderPassDepthStencilAttachment { view, depth_ops, stencil_ops }
 }
 
-fn create_command_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
-    let descriptor = wgpu::CommandEncoderDescriptor { label: None };
+fn clear_or_load<V>(value: Option<V>, default: V, first_use: bool) -> wgpu::Operations<V> {
+    let load = match value {
+        Some(v) => wgpu::LoadOp::Clear(v),
+        None if first_use => wgpu::LoadOp::Clear(default),
+        None => wgpu::LoadOp::Load,
+    };
 
-    device.create_command_encoder(&descriptor)
+    wgpu::Operations { load, store: wgpu::StoreOp::Store }
 }
@@ -12,26 +12,122 @@ enum FunctionCall {
     Synchronize,
     ResizeSwapChain { new_size: dpi::PhysicalSize<u32> },
     ResizeTexture { texture: TextureRef, new_size: (u32, u32, u32) },
+    SetResizeWithWindow { texture: TextureRef, scale: Option<f32> },
     Render { pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32) },
     RenderTo { targets: Vec<TargetRef>, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32) },
+    RenderInstances { pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32), instance_offset: u32 },
+    RenderInferred { pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, instances: Option<((usize, usize), usize)> },
+    InferredCount { pipeline: PipelineRef, instances: Option<((usize, usize), usize)> },
+    RenderBundle { bundle: BundleRef, targets: Vec<TargetRef>, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport> },
+    RenderFrame { items: Vec<FrameItemRef> },
+    RenderSideBySide { pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32), index_tuple: (usize, usize), eyes_data: [Vec<f32>; 2] },
+    RenderDraws { draws: Vec<DrawRef>, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, depth_pre_pass: bool },
+    GrabPass { pipeline: PipelineRef },
+    SetParticles { system: ParticleSystemRef, data: Vec<f32> },
+    UpdateParticles { system: ParticleSystemRef, time_step: f32 },
+    RenderParticles { system: ParticleSystemRef, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, vertices_per_particle: u32 },
+    GrabTexture { format: crate::Format },
     FinishFrame,
+    BeginFrame,
+    EndFrame,
     Flush,
+    PushDebugGroup { name: String },
+    PopDebugGroup,
+    CaptureFrame,
+    PushViewport { viewport: crate::Viewport },
+    PopViewport,
     SetAttribute { pipeline: PipelineRef, location: usize, data: Vec<f32> },
+    SetAttributeU32 { pipeline: PipelineRef, location: usize, data: Vec<u32> },
     SetInstanced { pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32> },
-    SetUniform { pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32> },
+    SetInstancedRelative { pipeline: PipelineRef, index_tuple: (usize, usize), camera_position: Vec<f64>, data: Vec<f64>, stride: usize },
+    SetUniform { pipeline: PipelineRef, index_tuple: (usize, usize), data: crate::UniformData },
+    SetUniforms { pipeline: PipelineRef, uniforms: Vec<((usize, usize), crate::UniformData)> },
+    SetUniformPerView { pipeline: PipelineRef, index_tuple: (usize, usize), views_data: Vec<Vec<f32>> },
     SetTexture { pipeline: PipelineRef, index_tuple: (usize, usize), layers_data: Vec<Vec<u8>> },
     SetPartOfTexture { pipeline: PipelineRef, index_tuple: (usize, usize), offset: (u32, u32, u32), size: (u32, u32), data: Vec<u8> },
+    UploadTexture { texture: TextureRef, offset: (u32, u32, u32), size: (u32, u32), data: Vec<u8> },
+    FlushTransfers,
+    FrameFence,
+    WaitForFence { fence: crate::FrameFence },
+    WaitForGpuIdle,
+    SetShrinkPolicy { shrink_policy: Option<crate::ShrinkPolicy> },
+    CompactBuffers,
+    SetMemoryBudget { budget_in_megabytes: f32, on_out_of_memory: Box<dyn FnMut(&crate::Renderer, &crate::MemoryReport) + Send> },
+    MemoryUsedInBytes,
+    OnUncapturedError { handler: Box<dyn wgpu::UncapturedErrorHandler> },
+    SwapTextureBinding { pipeline: PipelineRef, index_tuple: (usize, usize), texture: TextureRef },
     SetVsync { boolean: bool },
+    SetOccluded { occluded: bool },
+    SetRecordingFrameRate { frame_rate: usize },
+    IsHidden,
+    SetBlendConstant { pipeline: PipelineRef, color: crate::ClearColor },
+    SetBlendMode { pipeline: PipelineRef, blend_mode: crate::BlendMode },
+    SetPrimitive { pipeline: PipelineRef, primitive: crate::Primitive },
     SetMsaaSamples { pipeline: PipelineRef, msaa_samples: u32 },
+    SetDepth { pipeline: PipelineRef, depth: Option<(DepthBufferRef, crate::DepthTest, bool)> },
+    SetStencil { pipeline: PipelineRef, stencil: Option<crate::Stencil> },
+    SetStencilReference { pipeline: PipelineRef, reference: u32 },
+    SetTransparentOit { pipeline: PipelineRef, transparent_oit: bool },
+    SetRasterization { pipeline: PipelineRef, rasterization: crate::Rasterization },
+    SetMultiview { pipeline: PipelineRef, views: Option<u32> },
+    SetExcludeFromRecording { pipeline: PipelineRef, exclude_from_recording: bool },
+    CompositeTransparency { target: TargetRef },
+    SetOverlay { enabled: bool },
     StartRecording {  pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send> },
-    StopRecording {  pipelines: Vec<PipelineRef> },
+    StartRecordingWithPlanes {  pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, plane_formats: Vec<crate::Format>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send> },
+    StartRecordingWithArea {  pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, area: crate::RecordingArea, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send> },
+    StopRecording {  recording: crate::RecordingId, pipelines: Vec<PipelineRef> },
+    IsRecording { recording: crate::RecordingId },
+    SetRecordingRegion { recording: crate::RecordingId, region: Option<(u32, u32, u32, u32)> },
+    SetAdaptiveQuality { recording: crate::RecordingId, enabled: bool },
+    SetDeltaEncoding { recording: crate::RecordingId, keyframe_interval: Option<usize> },
+    SetReadbackBudget { recording: crate::RecordingId, gigabytes_per_second: Option<f32> },
+    #[cfg(feature="frame_to_png")] CaptureEvery { n: usize, directory: String, pipelines: Vec<PipelineRef> },
     AdapterInfo,
+    ElapsedTime,
+    Backend,
+    Diagnostics,
     Pipeline { program: ProgramRef, blend_mode: crate::BlendMode, primitive: crate::Primitive, msaa_samples: u32, targets: Vec<TargetRef> },
+    BakeBundle { draws: Vec<DrawRef> },
+    DepthBuffer { msaa_samples: u32, with_stencil: bool },
     Attribute { location: usize, size: u32 },
+    AttributeU32 { location: usize, size: u32 },
+    PulledAttribute { location: usize, size: u32 },
     Instanced,
+    StorageBuffer { access: crate::StorageAccess, visibility: Vis },
     Uniform,
+    InstancedWithData { data: Vec<f32> },
+    UniformWithData { data: Vec<f32> },
+    TextureWithData { width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool, layers_data: Vec<Vec<u8>> },
+    BuiltinUniform,
+    ReadPixel { target: TargetRef, x: u32, y: u32 },
+    FrameGraph { pipelines: Vec<(String, PipelineRef)> },
+    Validate { pipeline: PipelineRef },
+    ReadTexture { texture: TextureRef },
+    TryReadTexture { texture: TextureRef },
+    TrySetUniform { pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32> },
+    Screenshot,
+    ReadTextureF32 { texture: TextureRef },
+    RenderTiled { size: (u32, u32), tile_size: (u32, u32), format: crate::Format, draws: Vec<(PipelineRef, (u32, u32))>, clear_color: Option<crate::ClearColor> },
     Texture { width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool },
     Program { vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)> },
+    ProgramWgsl { vert: String, frag: String, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)> },
+    MaterialProgram { material: crate::Material, texture: Option<TextureRef> },
+    ParticleSystem { floats_per_particle: usize, update_shader: Option<Vec<u8>> },
+    ParticleProgram { system: ParticleSystemRef, vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)> },
+    ProgramWithTextureArrays { vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>, texture_arrays: Vec<(TextureArrayRef, Vis)> },
+    TextureArray { textures: Vec<TextureRef> },
+    SupportsTextureArrays,
+    SupportsMultiview,
+    WarmUp { pipelines: Vec<PipelineRef> },
+    SupportsRasterization { rasterization: crate::Rasterization },
+    PipelineId { pipeline: PipelineRef },
+    RegisterPipeline { name: String, pipeline: PipelineRef },
+    PipelineNamed { name: String },
+    UnregisterPipeline { name: String },
+    PipelineNames,
+    #[cfg(feature="pipeline_statistics")] SupportsPipelineStatistics,
+    #[cfg(feature="pipeline_statistics")] FrameTimings,
 }
 
 type Vis = crate::Visibility;
@@ -39,40 +135,92 @@ type Vis = crate::Visibility;
 enum ReturnValue {
     Synchronized,
     AdapterInfo(wgpu::AdapterInfo),
+    Backend(wgpu::Backend),
+    Diagnostics(crate::Diagnostics),
     PipelineRef(PipelineRef),
+    OptionalPipelineRef(Option<PipelineRef>),
+    Strings(Vec<String>),
+    BundleRef(BundleRef),
+    DepthBufferRef(DepthBufferRef),
     AttributeRef(AttributeRef),
     InstancedRef(InstancedRef),
     UniformRef(UniformRef),
     TextureRef(TextureRef),
+    TextureArrayRef(TextureArrayRef),
     ProgramRef(ProgramRef),
+    ParticleSystemRef(ParticleSystemRef),
+    Bytes(Vec<u8>),
+    BytesOrError(Result<Vec<u8>, String>),
+    UnitOrError(Result<(), String>),
+    Floats(Vec<f32>),
+    TiledImage(crate::TiledImage),
+    String(String),
+    Boolean(bool),
+    F32(f32),
+    Count((u32, u32)),
+    Usize(usize),
+    U64(u64),
+    Pixel(Option<[u8; 4]>),
+    FrameFence(crate::FrameFence),
+    RecordingId(crate::RecordingId),
+    #[cfg(feature="frame_to_png")] RecordingIdOrError(std::io::Result<crate::RecordingId>),
+    #[cfg(feature="pipeline_statistics")] FrameTimings(Option<crate::FrameTimings>),
 }
 
 #[derive(Clone, Copy)] pub struct PipelineRef(usize);
+#[derive(Clone, Copy)] pub struct BundleRef(usize);
+#[derive(Clone, Copy)] pub struct DepthBufferRef(usize);
 #[derive(Clone, Copy)] pub struct AttributeRef(usize);
 #[derive(Clone, Copy)] pub struct InstancedRef(usize);
 #[derive(Clone, Copy)] pub struct UniformRef(usize);
 #[derive(Clone, Copy)] pub struct TextureRef(usize);
+#[derive(Clone, Copy)] pub struct TextureArrayRef(usize);
 #[derive(Clone, Copy)] pub struct ProgramRef(usize);
+#[derive(Clone, Copy)] pub struct ParticleSystemRef(usize);
 #[derive(Clone, Copy)] pub enum TargetRef { Screen, TextureRef(TextureRef) }
+#[derive(Clone, Copy)] pub struct DrawRef { pub pipeline: PipelineRef, pub count: (u32, u32), pub instance_offset: u32 }
+
+// See FrameItem. The render thread keeps one RetainedFrame for all render_frame calls.
+pub struct FrameItemRef {
+    pub pipeline: PipelineRef,
+    pub attributes: Vec<(usize, Vec<f32>)>,
+    pub instanced: Vec<((usize, usize), Vec<f32>)>,
+    pub uniforms: Vec<((usize, usize), Vec<f32>)>,
+    pub clear_color: Option<crate::ClearColor>,
+    pub viewport: Option<crate::Viewport>,
+    pub count: (u32, u32),
+    pub instance_offset: u32,
+    pub cacheable: bool,
+}
 
 impl RenderThread {
     pub fn new(window: sync::Arc<window::Window>) -> Self {
+        Self::new_with_options(window, crate::RendererOptions::default())
+    }
+
+    pub fn new_with_options(window: sync::Arc<window::Window>, options: crate::RendererOptions) -> Self {
         let window_size = window.inner_size();
 
         let (fn_sender, fn_receiver) = crossbeam_channel::unbounded::<FunctionCall>();
         let (rv_sender, rv_receiver) = crossbeam_channel::bounded::<ReturnValue>(1);
 
-        let (instance, surface) = crate::Renderer::create_surface(window.clone());
+        let (instance, surface) = crate::Renderer::create_surface_with_options(window.clone(), &options);
 
         let _thread = thread::spawn(move || {
             let renderer = crate::Renderer::new_with_surface(window_size, instance, surface);
 
             let mut pipelines: Vec<crate::Pipeline> = vec![];
+            let mut bundles: Vec<crate::Bundle> = vec![];
+            let mut depth_buffers: Vec<crate::DepthBuffer> = vec![];
             let mut attributes: Vec<crate::Attribute> = vec![];
             let mut instances: Vec<crate::Instanced> = vec![];
             let mut uniforms: Vec<crate::Uniform> = vec![];
             let mut textures: Vec<crate::Texture> = vec![];
+            let mut texture_arrays: Vec<crate::TextureArray> = vec![];
             let mut programs: Vec<crate::Program> = vec![];
+            let mut particle_systems: Vec<crate::ParticleSystem> = vec![];
+            let mut named_pipelines: Vec<(String, PipelineRef)> = vec![];
+            let mut retained_frame = crate::RetainedFrame::new();
 
             while let Ok(message) = fn_receiver.recv() {
                 match message {
@@ -85,6 +233,9 @@ impl RenderThread {
                     FunctionCall::ResizeTexture { texture, new_size } => {
                         let _: () = renderer.resize_texture(&mut textures[texture.0], new_size);
                     },
+                    FunctionCall::SetResizeWithWindow { texture, scale } => {
+                        let _: () = renderer.set_resize_with_window(&textures[texture.0], scale);
+                    },
                     FunctionCall::Render { pipeline, clear_color, viewport, count } => {
                         let _: () = renderer.render(&pipelines[pipeline.0], clear_color, viewport.as_ref(), count);
                     },
@@ -92,20 +243,99 @@ impl RenderThread {
                         let targets = targets.iter().map(|r| r.to_target(&textures)).collect::<Vec<_>>();
                         let _: () = renderer.render_to(&targets, &pipelines[pipeline.0], clear_color, viewport.as_ref(), count);
                     },
+                    FunctionCall::RenderInstances { pipeline, clear_color, viewport, count, instance_offset } => {
+                        let _: () = renderer.render_instances(&pipelines[pipeline.0], clear_color, viewport.as_ref(), count, instance_offset);
+                    },
+                    FunctionCall::RenderInferred { pipeline, clear_color, viewport, instances } => {
+                        let _: () = renderer.render_inferred(&pipelines[pipeline.0], clear_color, viewport.as_ref(), instances);
+                    },
+                    FunctionCall::InferredCount { pipeline, instances } => {
+                        let count = renderer.inferred_count(&pipelines[pipeline.0], instances);
+                        rv_sender.send(ReturnValue::Count(count)).unwrap();
+                    },
+                    FunctionCall::RenderBundle { bundle, targets, clear_color, viewport } => {
+                        let targets = targets.iter().map(|r| r.to_target(&textures)).collect::<Vec<_>>();
+                        let _: () = renderer.render_bundle(&bundles[bundle.0], &targets, clear_color, viewport.as_ref());
+                    },
+                    FunctionCall::RenderFrame { items } => {
+                        let items = items.iter().map(|i| i.to_frame_item(&pipelines)).collect::<Vec<_>>();
+                        let _: () = renderer.render_frame(&mut retained_frame, &items);
+                    },
+                    FunctionCall::RenderSideBySide { pipeline, clear_color, viewport, count, index_tuple, eyes_data } => {
+                        let [left, right] = &eyes_data;
+                        let _: () = renderer.render_side_by_side(&pipelines[pipeline.0], clear_color, viewport.as_ref(), count, index_tuple, [left, right]);
+                    },
+                    FunctionCall::RenderDraws { draws, clear_color, viewport, depth_pre_pass } => {
+                        let draws = draws.iter().map(|d| d.to_draw(&pipelines)).collect::<Vec<_>>();
+                        let _: () = renderer.render_draws(&draws, clear_color, viewport.as_ref(), depth_pre_pass);
+                    },
+                    FunctionCall::GrabPass { pipeline } => {
+                        let _: () = renderer.grab_pass(&pipelines[pipeline.0]);
+                    },
+                    FunctionCall::SetParticles { system, data } => {
+                        let _: () = particle_systems[system.0].set_particles(&renderer, &data);
+                    },
+                    FunctionCall::UpdateParticles { system, time_step } => {
+                        let _: () = particle_systems[system.0].update(&renderer, time_step);
+                    },
+                    FunctionCall::RenderParticles { system, pipeline, clear_color, viewport, vertices_per_particle } => {
+                        let _: () = particle_systems[system.0].render(&renderer, &pipelines[pipeline.0], clear_color, viewport.as_ref(), vertices_per_particle);
+                    },
+                    FunctionCall::GrabTexture { format } => {
+                        textures.push(renderer.grab_texture(format));
+                        rv_sender.send(ReturnValue::TextureRef(TextureRef(textures.len() - 1))).unwrap();
+                    },
                     FunctionCall::FinishFrame => {
                         let _: () = renderer.finish_frame();
                     },
+                    FunctionCall::BeginFrame => {
+                        let _: () = renderer.begin_frame();
+                    },
+                    FunctionCall::EndFrame => {
+                        let _: () = renderer.end_frame();
+                    },
                     FunctionCall::Flush => {
                         let _: () = renderer.flush();
                     },
+                    FunctionCall::PushDebugGroup { name } => {
+                        let _: () = renderer.push_debug_group(&name);
+                    },
+                    FunctionCall::PopDebugGroup => {
+                        let _: () = renderer.pop_debug_group();
+                    },
+                    FunctionCall::PushViewport { viewport } => {
+                        let _: () = renderer.push_viewport(&viewport);
+                    },
+                    FunctionCall::PopViewport => {
+                        let _: () = renderer.pop_viewport();
+                    },
+                    FunctionCall::CaptureFrame => {
+                        let _: () = renderer.capture_frame();
+                    },
                     FunctionCall::SetAttribute { pipeline: r, location, data } => {
                         let _: () = renderer.set_attribute(&pipelines[r.0], location, &data);
                     },
+                    FunctionCall::SetAttributeU32 { pipeline: r, location, data } => {
+                        let _: () = renderer.set_attribute_u32(&pipelines[r.0], location, &data);
+                    },
                     FunctionCall::SetInstanced { pipeline: r, index_tuple, data } => {
                         let _: () = renderer.set_instanced(&pipelines[r.0], index_tuple, &data);
                     },
+                    FunctionCall::SetInstancedRelative { pipeline: r, index_tuple, camera_position, data, stride } => {
+                        let mut camera = crate::CameraRelative::new(&camera_position);
+                        let _: () = renderer.set_instanced_relative(&pipelines[r.0], index_tuple, &mut camera, &data, stride);
+                    },
                     FunctionCall::SetUniform { pipeline: r, index_tuple, data } => {
-                        let _: () = renderer.set_uniform(&pipelines[r.0], index_tuple, &data);
+                        let _: () = renderer.set_uniform_data(&pipelines[r.0], index_tuple, &data);
+                    },
+                    FunctionCall::SetUniforms { pipeline: r, uniforms } => {
+                        for (index_tuple, data) in uniforms {
+                            let _: () = renderer.set_uniform_data(&pipelines[r.0], index_tuple, &data);
+                        }
+                    },
+                    FunctionCall::SetUniformPerView { pipeline: r, index_tuple, views_data } => {
+                        let views_data = views_data.iter().map(|data| &data[..]).collect::<Vec<_>>();
+                        let _: () = renderer.set_uniform_per_view(&pipelines[r.0], index_tuple, &views_data);
                     },
                     FunctionCall::SetTexture { pipeline: r, index_tuple, layers_data } => {
                         let layers_data = layers_data.iter().map(|data| &data[..]).collect::<Vec<_>>();
@@ -114,23 +344,144 @@ impl RenderThread {
                     FunctionCall::SetPartOfTexture { pipeline: r, index_tuple, offset, size, data } => {
                         let _: () = renderer.set_part_of_texture(&pipelines[r.0], index_tuple, offset, size, &data);
                     },
+                    FunctionCall::UploadTexture { texture, offset, size, data } => {
+                        let _: () = renderer.upload_texture(&textures[texture.0], offset, size, &data);
+                    },
+                    FunctionCall::FlushTransfers => {
+                        let _: () = renderer.flush_transfers();
+                    },
+                    FunctionCall::FrameFence => {
+                        rv_sender.send(ReturnValue::FrameFence(renderer.frame_fence())).unwrap();
+                    },
+                    FunctionCall::WaitForFence { fence } => {
+                        let _: () = renderer.wait_for_fence(&fence);
+                        rv_sender.send(ReturnValue::Synchronized).unwrap();
+                    },
+                    FunctionCall::WaitForGpuIdle => {
+                        let _: () = renderer.wait_for_gpu_idle();
+                        rv_sender.send(ReturnValue::Synchronized).unwrap();
+                    },
+                    FunctionCall::SetShrinkPolicy { shrink_policy } => {
+                        let _: () = renderer.set_shrink_policy(shrink_policy);
+                    },
+                    FunctionCall::CompactBuffers => {
+                        rv_sender.send(ReturnValue::Usize(renderer.compact_buffers())).unwrap();
+                    },
+                    FunctionCall::SetMemoryBudget { budget_in_megabytes, on_out_of_memory } => {
+                        let _: () = renderer.set_memory_budget(budget_in_megabytes, on_out_of_memory);
+                    },
+                    FunctionCall::OnUncapturedError { handler } => {
+                        let _: () = renderer.on_uncaptured_error(handler);
+                    },
+                    FunctionCall::MemoryUsedInBytes => {
+                        rv_sender.send(ReturnValue::U64(renderer.memory_used_in_bytes())).unwrap();
+                    },
+                    FunctionCall::SwapTextureBinding { pipeline: r, index_tuple, texture } => {
+                        let _: () = renderer.swap_texture_binding(&pipelines[r.0], index_tuple, &textures[texture.0]);
+                    },
                     FunctionCall::SetVsync { boolean } => {
                         let _: () = renderer.set_vsync(boolean);
                     },
+                    FunctionCall::SetOccluded { occluded } => {
+                        let _: () = renderer.set_occluded(occluded);
+                    },
+                    FunctionCall::SetRecordingFrameRate { frame_rate } => {
+                        let _: () = renderer.set_recording_frame_rate(frame_rate);
+                    },
+                    FunctionCall::IsHidden => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.is_hidden())).unwrap();
+                    },
+                    FunctionCall::SetBlendConstant { pipeline, color } => {
+                        let _: () = renderer.set_blend_constant(&pipelines[pipeline.0], color);
+                    },
+                    FunctionCall::SetBlendMode { pipeline, blend_mode } => {
+                        let _: () = renderer.set_blend_mode(&pipelines[pipeline.0], blend_mode);
+                    },
+                    FunctionCall::SetPrimitive { pipeline, primitive } => {
+                        let _: () = renderer.set_primitive(&pipelines[pipeline.0], primitive);
+                    },
                     FunctionCall::SetMsaaSamples { pipeline, msaa_samples } => {
                         let _: () = renderer.set_msaa_samples(&pipelines[pipeline.0], msaa_samples);
                     },
+                    FunctionCall::SetTransparentOit { pipeline, transparent_oit } => {
+                        let _: () = renderer.set_transparent_oit(&pipelines[pipeline.0], transparent_oit);
+                    },
+                    FunctionCall::SetRasterization { pipeline, rasterization } => {
+                        let _: () = renderer.set_rasterization(&pipelines[pipeline.0], rasterization);
+                    },
+                    FunctionCall::SetMultiview { pipeline, views } => {
+                        let _: () = renderer.set_multiview(&pipelines[pipeline.0], views);
+                    },
+                    FunctionCall::SetExcludeFromRecording { pipeline, exclude_from_recording } => {
+                        let _: () = renderer.set_exclude_from_recording(&pipelines[pipeline.0], exclude_from_recording);
+                    },
+                    FunctionCall::CompositeTransparency { target } => {
+                        let _: () = renderer.composite_transparency(&target.to_target(&textures));
+                    },
+                    FunctionCall::SetDepth { pipeline, depth } => {
+                        let depth = depth.map(|(r, test, write)| crate::Depth::new(&depth_buffers[r.0], test, write));
+                        let _: () = renderer.set_depth(&pipelines[pipeline.0], depth);
+                    },
+                    FunctionCall::SetStencil { pipeline, stencil } => {
+                        let _: () = renderer.set_stencil(&pipelines[pipeline.0], stencil);
+                    },
+                    FunctionCall::SetStencilReference { pipeline, reference } => {
+                        let _: () = renderer.set_stencil_reference(&pipelines[pipeline.0], reference);
+                    },
+                    FunctionCall::SetOverlay { enabled } => {
+                        let _: () = renderer.set_overlay(enabled);
+                    },
                     FunctionCall::StartRecording { pipelines: p, clear_color, max_buffer_size_in_megabytes, process_function } => {
                         let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
-                        let _: () = renderer.start_recording(&pipelines, clear_color, max_buffer_size_in_megabytes, process_function);
+                        let recording = renderer.start_recording(&pipelines, clear_color, max_buffer_size_in_megabytes, process_function);
+                        rv_sender.send(ReturnValue::RecordingId(recording)).unwrap();
+                    },
+                    FunctionCall::StartRecordingWithPlanes { pipelines: p, clear_color, plane_formats, max_buffer_size_in_megabytes, process_function } => {
+                        let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
+                        let recording = renderer.start_recording_with_planes(&pipelines, clear_color, plane_formats, max_buffer_size_in_megabytes, process_function);
+                        rv_sender.send(ReturnValue::RecordingId(recording)).unwrap();
+                    },
+                    FunctionCall::StartRecordingWithArea { pipelines: p, clear_color, area, max_buffer_size_in_megabytes, process_function } => {
+                        let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
+                        let recording = renderer.start_recording_with_area(&pipelines, clear_color, area, max_buffer_size_in_megabytes, process_function);
+                        rv_sender.send(ReturnValue::RecordingId(recording)).unwrap();
+                    },
+                    FunctionCall::StopRecording { recording, pipelines: p } => {
+                        let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
+                        let _: () = renderer.stop_recording(recording, &pipelines);
                     },
-                    FunctionCall::StopRecording { pipelines: p } => {
+                    FunctionCall::IsRecording { recording } => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.is_recording(recording))).unwrap();
+                    },
+                    FunctionCall::SetRecordingRegion { recording, region } => {
+                        let _: () = renderer.set_recording_region(recording, region);
+                    },
+                    FunctionCall::SetAdaptiveQuality { recording, enabled } => {
+                        let _: () = renderer.set_adaptive_quality(recording, enabled);
+                    },
+                    FunctionCall::SetDeltaEncoding { recording, keyframe_interval } => {
+                        let _: () = renderer.set_delta_encoding(recording, keyframe_interval);
+                    },
+                    FunctionCall::SetReadbackBudget { recording, gigabytes_per_second } => {
+                        let _: () = renderer.set_readback_budget(recording, gigabytes_per_second);
+                    },
+                    #[cfg(feature="frame_to_png")] FunctionCall::CaptureEvery { n, directory, pipelines: p } => {
                         let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
-                        let _: () = renderer.stop_recording(&pipelines);
+                        let result = renderer.capture_every(n, &directory, &pipelines);
+                        rv_sender.send(ReturnValue::RecordingIdOrError(result)).unwrap();
                     },
                     FunctionCall::AdapterInfo => {
                         rv_sender.send(ReturnValue::AdapterInfo(renderer.adapter_info())).unwrap();
                     },
+                    FunctionCall::ElapsedTime => {
+                        rv_sender.send(ReturnValue::F32(renderer.elapsed_time())).unwrap();
+                    },
+                    FunctionCall::Backend => {
+                        rv_sender.send(ReturnValue::Backend(renderer.backend())).unwrap();
+                    },
+                    FunctionCall::Diagnostics => {
+                        rv_sender.send(ReturnValue::Diagnostics(renderer.diagnostics())).unwrap();
+                    },
                     FunctionCall::Pipeline { program, blend_mode, primitive, msaa_samples, targets } => {
                         let program = programs[program.0].clone();
                         let targets = targets.iter().map(|r| r.to_target(&textures)).collect();
@@ -138,22 +489,108 @@ impl RenderThread {
                         pipelines.push(renderer.pipeline(program, blend_mode, primitive, msaa_samples, targets));
                         rv_sender.send(ReturnValue::PipelineRef(PipelineRef(pipelines.len() - 1))).unwrap();
                     },
+                    FunctionCall::BakeBundle { draws } => {
+                        let draws = draws.iter().map(|d| d.to_draw(&pipelines)).collect::<Vec<_>>();
+
+                        bundles.push(renderer.bake_bundle(&draws));
+                        rv_sender.send(ReturnValue::BundleRef(BundleRef(bundles.len() - 1))).unwrap();
+                    },
+                    FunctionCall::DepthBuffer { msaa_samples, with_stencil } => {
+                        depth_buffers.push(renderer.depth_buffer(msaa_samples, with_stencil));
+                        rv_sender.send(ReturnValue::DepthBufferRef(DepthBufferRef(depth_buffers.len() - 1))).unwrap();
+                    },
                     FunctionCall::Attribute { location, size } => {
                         attributes.push(renderer.attribute(location, size));
                         rv_sender.send(ReturnValue::AttributeRef(AttributeRef(attributes.len() - 1))).unwrap();
                     },
+                    FunctionCall::AttributeU32 { location, size } => {
+                        attributes.push(renderer.attribute_u32(location, size));
+                        rv_sender.send(ReturnValue::AttributeRef(AttributeRef(attributes.len() - 1))).unwrap();
+                    },
+                    FunctionCall::PulledAttribute { location, size } => {
+                        attributes.push(renderer.pulled_attribute(location, size));
+                        rv_sender.send(ReturnValue::AttributeRef(AttributeRef(attributes.len() - 1))).unwrap();
+                    },
                     FunctionCall::Instanced => {
                         instances.push(renderer.instanced());
                         rv_sender.send(ReturnValue::InstancedRef(InstancedRef(instances.len() - 1))).unwrap();
                     },
+                    FunctionCall::StorageBuffer { access, visibility } => {
+                        instances.push(renderer.storage_buffer(access, visibility));
+                        rv_sender.send(ReturnValue::InstancedRef(InstancedRef(instances.len() - 1))).unwrap();
+                    },
                     FunctionCall::Uniform => {
                         uniforms.push(renderer.uniform());
                         rv_sender.send(ReturnValue::UniformRef(UniformRef(uniforms.len() - 1))).unwrap();
                     },
+                    FunctionCall::InstancedWithData { data } => {
+                        instances.push(renderer.instanced_with_data(&data));
+                        rv_sender.send(ReturnValue::InstancedRef(InstancedRef(instances.len() - 1))).unwrap();
+                    },
+                    FunctionCall::UniformWithData { data } => {
+                        uniforms.push(renderer.uniform_with_data(&data));
+                        rv_sender.send(ReturnValue::UniformRef(UniformRef(uniforms.len() - 1))).unwrap();
+                    },
+                    FunctionCall::ReadTexture { texture: r } => {
+                        let bytes = renderer.read_texture(&textures[r.0]);
+                        rv_sender.send(ReturnValue::Bytes(bytes)).unwrap();
+                    },
+                    FunctionCall::TryReadTexture { texture: r } => {
+                        let result = textures.get(r.0).ok_or("unknown texture".to_string()).and_then(|t| renderer.try_read_texture(t));
+                        rv_sender.send(ReturnValue::BytesOrError(result)).unwrap();
+                    },
+                    FunctionCall::TrySetUniform { pipeline: r, index_tuple, data } => {
+                        let result = pipelines.get(r.0).ok_or("unknown pipeline".to_string()).and_then(|p| renderer.try_set_uniform(p, index_tuple, &data));
+                        rv_sender.send(ReturnValue::UnitOrError(result)).unwrap();
+                    },
+                    FunctionCall::Screenshot => {
+                        let result = renderer.screenshot();
+                        rv_sender.send(ReturnValue::BytesOrError(result)).unwrap();
+                    },
+                    FunctionCall::ReadTextureF32 { texture: r } => {
+                        let floats = renderer.read_texture_f32(&textures[r.0]);
+                        rv_sender.send(ReturnValue::Floats(floats)).unwrap();
+                    },
+                    FunctionCall::RenderTiled { size, tile_size, format, draws, clear_color } => {
+                        let tiled_render = crate::TiledRender::new(&renderer, size, tile_size, format);
+                        let targets = [tiled_render.target()];
+
+                        let image = tiled_render.render(&renderer, |_tile| {
+                            let mut clear_color = clear_color;
+
+                            for (pipeline, count) in &draws {
+                                renderer.render_to(&targets, &pipelines[pipeline.0], clear_color.take(), None, *count);
+                            }
+                        });
+
+                        rv_sender.send(ReturnValue::TiledImage(image)).unwrap();
+                    },
+                    FunctionCall::FrameGraph { pipelines: refs } => {
+                        let named = refs.iter().map(|(name, r)| (&name[..], &pipelines[r.0])).collect::<Vec<_>>();
+                        let dot = renderer.frame_graph(&named);
+                        rv_sender.send(ReturnValue::String(dot)).unwrap();
+                    },
+                    FunctionCall::Validate { pipeline: r } => {
+                        let problems = renderer.validate(&pipelines[r.0]);
+                        rv_sender.send(ReturnValue::Strings(problems)).unwrap();
+                    },
+                    FunctionCall::ReadPixel { target, x, y } => {
+                        let pixel = renderer.read_pixel(&target.to_target(&textures), x, y);
+                        rv_sender.send(ReturnValue::Pixel(pixel)).unwrap();
+                    },
+                    FunctionCall::BuiltinUniform => {
+                        uniforms.push(renderer.builtin_uniform());
+                        rv_sender.send(ReturnValue::UniformRef(UniformRef(uniforms.len() - 1))).unwrap();
+                    },
                     FunctionCall::Texture { width, height, layers, filter_mode, format, renderable, copyable, with_sampler } => {
                         textures.push(renderer.texture(width, height, layers, filter_mode, format, renderable, copyable, with_sampler));
                         rv_sender.send(ReturnValue::TextureRef(TextureRef(textures.len() - 1))).unwrap();
                     }
+                    FunctionCall::TextureWithData { width, height, layers, filter_mode, format, renderable, copyable, with_sampler, layers_data } => {
+                        let layers_data = layers_data.iter().map(|data| &data[..]).collect::<Vec<_>>();
+                        textures.push(renderer.texture_with_data(width, height, layers, filter_mode, format, renderable, copyable, with_sampler, &layers_data));
+                        rv_sender.send(ReturnValue::TextureRef(TextureRef(textures.len() - 1))).unwrap();
+                    }
                     FunctionCall::Program { vert, frag, attributes: a, instances: i, uniforms: u, textures: t } => {
                         let attributes = a.into_iter().map(|r| attributes[r.0].clone()).collect::<Vec<_>>();
                         let instances = i.into_iter().map(|r| instances[r.0].clone()).collect::<Vec<_>>();
@@ -162,7 +599,89 @@ impl RenderThread {
 
                         programs.push(renderer.program(&vert, &frag, attributes, instances, uniforms, textures));
                         rv_sender.send(ReturnValue::ProgramRef(ProgramRef(programs.len() - 1))).unwrap();
-                    }
+                    },
+                    FunctionCall::ProgramWgsl { vert, frag, attributes: a, instances: i, uniforms: u, textures: t } => {
+                        let attributes = a.into_iter().map(|r| attributes[r.0].clone()).collect::<Vec<_>>();
+                        let instances = i.into_iter().map(|r| instances[r.0].clone()).collect::<Vec<_>>();
+                        let uniforms = u.into_iter().map(|(r, v)| (uniforms[r.0].clone(), v)).collect::<Vec<_>>();
+                        let textures = t.into_iter().map(|(r, v)| (textures[r.0].clone(), v)).collect::<Vec<_>>();
+
+                        programs.push(renderer.program_wgsl(&vert, &frag, attributes, instances, uniforms, textures));
+                        rv_sender.send(ReturnValue::ProgramRef(ProgramRef(programs.len() - 1))).unwrap();
+                    },
+                    FunctionCall::MaterialProgram { material, texture } => {
+                        programs.push(renderer.material_program(material, texture.map(|r| textures[r.0].clone())));
+                        rv_sender.send(ReturnValue::ProgramRef(ProgramRef(programs.len() - 1))).unwrap();
+                    },
+                    FunctionCall::ParticleSystem { floats_per_particle, update_shader } => {
+                        particle_systems.push(crate::ParticleSystem::new(&renderer, floats_per_particle, update_shader.as_deref()));
+                        rv_sender.send(ReturnValue::ParticleSystemRef(ParticleSystemRef(particle_systems.len() - 1))).unwrap();
+                    },
+                    FunctionCall::ParticleProgram { system, vert, frag, attributes: a, uniforms: u, textures: t } => {
+                        let attributes = a.into_iter().map(|r| attributes[r.0].clone()).collect::<Vec<_>>();
+                        let uniforms = u.into_iter().map(|(r, v)| (uniforms[r.0].clone(), v)).collect::<Vec<_>>();
+                        let textures = t.into_iter().map(|(r, v)| (textures[r.0].clone(), v)).collect::<Vec<_>>();
+
+                        programs.push(particle_systems[system.0].program(&renderer, &vert, &frag, attributes, uniforms, textures));
+                        rv_sender.send(ReturnValue::ProgramRef(ProgramRef(programs.len() - 1))).unwrap();
+                    },
+                    FunctionCall::ProgramWithTextureArrays { vert, frag, attributes: a, instances: i, uniforms: u, textures: t, texture_arrays: ta } => {
+                        let attributes = a.into_iter().map(|r| attributes[r.0].clone()).collect::<Vec<_>>();
+                        let instances = i.into_iter().map(|r| instances[r.0].clone()).collect::<Vec<_>>();
+                        let uniforms = u.into_iter().map(|(r, v)| (uniforms[r.0].clone(), v)).collect::<Vec<_>>();
+                        let texture_arrays = ta.into_iter().map(|(r, v)| (texture_arrays[r.0].clone(), v)).collect::<Vec<_>>();
+                        let textures = t.into_iter().map(|(r, v)| (textures[r.0].clone(), v)).collect::<Vec<_>>();
+
+                        programs.push(renderer.program_with_texture_arrays(&vert, &frag, attributes, instances, uniforms, textures, texture_arrays));
+                        rv_sender.send(ReturnValue::ProgramRef(ProgramRef(programs.len() - 1))).unwrap();
+                    },
+                    FunctionCall::TextureArray { textures: t } => {
+                        let textures = t.into_iter().map(|r| textures[r.0].clone()).collect::<Vec<_>>();
+
+                        texture_arrays.push(renderer.texture_array(textures));
+                        rv_sender.send(ReturnValue::TextureArrayRef(TextureArrayRef(texture_arrays.len() - 1))).unwrap();
+                    },
+                    FunctionCall::SupportsTextureArrays => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.supports_texture_arrays())).unwrap();
+                    },
+                    FunctionCall::SupportsMultiview => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.supports_multiview())).unwrap();
+                    },
+                    FunctionCall::WarmUp { pipelines: p } => {
+                        let pipelines = p.iter().map(|r| &pipelines[r.0]).collect::<Vec<_>>();
+                        let _: () = renderer.warm_up(&pipelines);
+                    },
+                    FunctionCall::SupportsRasterization { rasterization } => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.supports_rasterization(&rasterization))).unwrap();
+                    },
+                    FunctionCall::PipelineId { pipeline } => {
+                        rv_sender.send(ReturnValue::Usize(pipelines[pipeline.0].id)).unwrap();
+                    },
+                    FunctionCall::RegisterPipeline { name, pipeline } => {
+                        named_pipelines.retain(|(n, _)| *n != name);
+                        named_pipelines.push((name, pipeline));
+                    },
+                    FunctionCall::PipelineNamed { name } => {
+                        let pipeline = named_pipelines.iter().find(|(n, _)| *n == name).map(|(_, p)| *p);
+                        rv_sender.send(ReturnValue::OptionalPipelineRef(pipeline)).unwrap();
+                    },
+                    FunctionCall::UnregisterPipeline { name } => {
+                        let index = named_pipelines.iter().position(|(n, _)| *n == name);
+                        let pipeline = index.map(|i| named_pipelines.remove(i).1);
+                        rv_sender.send(ReturnValue::OptionalPipelineRef(pipeline)).unwrap();
+                    },
+                    FunctionCall::PipelineNames => {
+                        let names = named_pipelines.iter().map(|(n, _)| n.clone()).collect();
+                        rv_sender.send(ReturnValue::Strings(names)).unwrap();
+                    },
+                    #[cfg(feature="pipeline_statistics")]
+                    FunctionCall::SupportsPipelineStatistics => {
+                        rv_sender.send(ReturnValue::Boolean(renderer.supports_pipeline_statistics())).unwrap();
+                    },
+                    #[cfg(feature="pipeline_statistics")]
+                    FunctionCall::FrameTimings => {
+                        rv_sender.send(ReturnValue::FrameTimings(renderer.frame_timings())).unwrap();
+                    },
                 }
             }
         });
@@ -190,7 +709,35 @@ impl RenderThread {
     pub fn resize_swap_chain(&mut self, new_size: &dpi::PhysicalSize<u32>) {
         let function_call = FunctionCall::ResizeSwapChain { new_size: *new_size };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
-        self.window_size = *new_size;
+
+        // The renderer keeps its size while the window is minimized.
+        if new_size.width > 0 && new_size.height > 0 { self.window_size = *new_size; }
+    }
+
+    pub fn set_occluded(&self, occluded: bool) {
+        let function_call = FunctionCall::SetOccluded { occluded };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_recording_frame_rate(&self, frame_rate: usize) {
+        let function_call = FunctionCall::SetRecordingFrameRate { frame_rate };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        let function_call = FunctionCall::IsHidden;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    // Must be called from the main thread because it changes the window.
+    pub fn set_fullscreen(&mut self, window: &window::Window, mode: Option<window::Fullscreen>) {
+        let new_size = crate::renderer::fullscreen_size(window, &mode);
+
+        window.set_fullscreen(mode);
+        self.resize_swap_chain(&new_size);
     }
 
     pub fn resize_texture(&self, texture: TextureRef, new_size: (u32, u32, u32)) {
@@ -198,6 +745,11 @@ impl RenderThread {
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
+    pub fn set_resize_with_window(&self, texture: TextureRef, scale: Option<f32>) {
+        let function_call = FunctionCall::SetResizeWithWindow { texture, scale };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
     pub fn render(&self, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32)) {
         let function_call = FunctionCall::Render { pipeline, clear_color, viewport, count };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
@@ -208,135 +760,795 @@ impl RenderThread {
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn finish_frame(&self) {
-        let function_call = FunctionCall::FinishFrame;
+    pub fn render_instances(&self, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32), instance_offset: u32) {
+        let function_call = FunctionCall::RenderInstances { pipeline, clear_color, viewport, count, instance_offset };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn flush(&self) {
-        let function_call = FunctionCall::Flush;
+    pub fn render_inferred(&self, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, instances: Option<((usize, usize), usize)>) {
+        let function_call = FunctionCall::RenderInferred { pipeline, clear_color, viewport, instances };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_attribute(&self, pipeline: PipelineRef, location: usize, data: Vec<f32>) {
-        let function_call = FunctionCall::SetAttribute { pipeline, location, data };
+    pub fn inferred_count(&self, pipeline: PipelineRef, instances: Option<((usize, usize), usize)>) -> (u32, u32) {
+        let function_call = FunctionCall::InferredCount { pipeline, instances };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Count(c) = return_value { c } else { unreachable!() }
     }
 
-    pub fn set_instanced(&self, pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32>) {
-        let function_call = FunctionCall::SetInstanced { pipeline, index_tuple, data };
+    pub fn render_bundle(&self, bundle: BundleRef, targets: Vec<TargetRef>, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>) {
+        let function_call = FunctionCall::RenderBundle { bundle, targets, clear_color, viewport };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_uniform(&self, pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32>) {
-        let function_call = FunctionCall::SetUniform { pipeline, index_tuple, data };
+    pub fn render_draws(&self, draws: Vec<DrawRef>, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, depth_pre_pass: bool) {
+        let function_call = FunctionCall::RenderDraws { draws, clear_color, viewport, depth_pre_pass };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_texture(&self, pipeline: PipelineRef, index_tuple: (usize, usize), layers_data: Vec<Vec<u8>>) {
-        let function_call = FunctionCall::SetTexture { pipeline, index_tuple, layers_data };
+    pub fn render_side_by_side(&self, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, count: (u32, u32), index_tuple: (usize, usize), eyes_data: [Vec<f32>; 2]) {
+        let function_call = FunctionCall::RenderSideBySide { pipeline, clear_color, viewport, count, index_tuple, eyes_data };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_part_of_texture(&self, pipeline: PipelineRef, index_tuple: (usize, usize), offset: (u32, u32, u32), size: (u32, u32), data: Vec<u8>) {
-        let function_call = FunctionCall::SetPartOfTexture { pipeline, index_tuple, offset, size, data };
+    pub fn render_frame(&self, items: Vec<FrameItemRef>) {
+        let function_call = FunctionCall::RenderFrame { items };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_vsync(&self, boolean: bool) {
-        let function_call = FunctionCall::SetVsync { boolean };
+    pub fn grab_pass(&self, pipeline: PipelineRef) {
+        let function_call = FunctionCall::GrabPass { pipeline };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn set_msaa_samples(&self, pipeline: PipelineRef, msaa_samples: u32) {
-        let function_call = FunctionCall::SetMsaaSamples { pipeline, msaa_samples };
+    pub fn set_particles(&self, system: ParticleSystemRef, data: Vec<f32>) {
+        let function_call = FunctionCall::SetParticles { system, data };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn start_recording(&self, pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send>) {
-        let function_call = FunctionCall::StartRecording { pipelines, clear_color, max_buffer_size_in_megabytes, process_function };
+    pub fn update_particles(&self, system: ParticleSystemRef, time_step: f32) {
+        let function_call = FunctionCall::UpdateParticles { system, time_step };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn stop_recording(&self, pipelines: Vec<PipelineRef>) {
-        let function_call = FunctionCall::StopRecording { pipelines };
+    pub fn render_particles(&self, system: ParticleSystemRef, pipeline: PipelineRef, clear_color: Option<crate::ClearColor>, viewport: Option<crate::Viewport>, vertices_per_particle: u32) {
+        let function_call = FunctionCall::RenderParticles { system, pipeline, clear_color, viewport, vertices_per_particle };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
-        let function_call = FunctionCall::AdapterInfo;
+    pub fn grab_texture(&self, format: crate::Format) -> TextureRef {
+        let function_call = FunctionCall::GrabTexture { format };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
 
         let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::AdapterInfo(i) = return_value { i } else { unreachable!() }
+        if let ReturnValue::TextureRef(r) = return_value { r } else { unreachable!() }
     }
 
-    pub fn pipeline(&self, program: ProgramRef, blend_mode: crate::BlendMode, primitive: crate::Primitive, msaa_samples: u32, targets: Vec<TargetRef>) -> PipelineRef {
-        let function_call = FunctionCall::Pipeline { program, blend_mode, primitive, msaa_samples, targets };
+    pub fn finish_frame(&self) {
+        let function_call = FunctionCall::FinishFrame;
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::PipelineRef(r) = return_value { r } else { unreachable!() }
+    pub fn begin_frame(&self) {
+        let function_call = FunctionCall::BeginFrame;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn attribute(&self, location: usize, size: u32) -> AttributeRef {
-        let function_call = FunctionCall::Attribute { location, size };
+    pub fn end_frame(&self) {
+        let function_call = FunctionCall::EndFrame;
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::AttributeRef(r) = return_value { r } else { unreachable!() }
+    pub fn flush(&self) {
+        let function_call = FunctionCall::Flush;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn instanced(&self) -> InstancedRef {
-        let function_call = FunctionCall::Instanced;
+    pub fn push_debug_group(&self, name: &str) {
+        let function_call = FunctionCall::PushDebugGroup { name: name.to_string() };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::InstancedRef(r) = return_value { r } else { unreachable!() }
+    pub fn pop_debug_group(&self) {
+        let function_call = FunctionCall::PopDebugGroup;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn uniform(&self) -> UniformRef {
-        let function_call = FunctionCall::Uniform;
+    pub fn push_viewport(&self, viewport: crate::Viewport) {
+        let function_call = FunctionCall::PushViewport { viewport };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::UniformRef(r) = return_value { r } else { unreachable!() }
+    pub fn pop_viewport(&self) {
+        let function_call = FunctionCall::PopViewport;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn texture(&self, width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool) -> TextureRef {
-        let function_call = FunctionCall::Texture { width, height, layers, filter_mode, format, renderable, copyable, with_sampler };
+    pub fn capture_frame(&self) {
+        let function_call = FunctionCall::CaptureFrame;
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::TextureRef(r) = return_value { r } else { unreachable!() }
+    pub fn set_attribute(&self, pipeline: PipelineRef, location: usize, data: Vec<f32>) {
+        let function_call = FunctionCall::SetAttribute { pipeline, location, data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn program(&self, vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>) -> ProgramRef {
-        let function_call = FunctionCall::Program { vert, frag, attributes, instances, uniforms, textures };
+    pub fn set_attribute_u32(&self, pipeline: PipelineRef, location: usize, data: Vec<u32>) {
+        let function_call = FunctionCall::SetAttributeU32 { pipeline, location, data };
         self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
 
-        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
-        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    pub fn set_instanced(&self, pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32>) {
+        let function_call = FunctionCall::SetInstanced { pipeline, index_tuple, data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
 
-    pub fn viewport(&self, aspect_x: f32, aspect_y: f32) -> crate::Viewport {
-        crate::Viewport::new(aspect_x, aspect_y, self.window_size.width as f32, self.window_size.height as f32)
+    // Sorts on the calling thread so that the sorter keeps its order between calls.
+    pub fn set_instanced_sorted(&self, pipeline: PipelineRef, index_tuple: (usize, usize), sorter: &mut crate::InstanceSorter, data: &[f32], stride: usize) {
+        self.set_instanced(pipeline, index_tuple, sorter.sort(data, stride).to_vec());
     }
 
-    pub fn screen_target() -> TargetRef {
-        TargetRef::Screen
+    // Culls on the calling thread so that only the visible instances are sent.
+    pub fn set_instanced_culled(&self, pipeline: PipelineRef, index_tuple: (usize, usize), culling: &mut crate::Culling, data: &[f32], stride: usize, radius_index: Option<usize>) -> u32 {
+        let (visible, count) = culling.cull(data, stride, radius_index);
+
+        self.set_instanced(pipeline, index_tuple, visible.to_vec());
+        count
     }
 
-    pub fn texture_target(texture: TextureRef) -> TargetRef {
-        TargetRef::TextureRef(texture)
+    pub fn set_instanced_relative(&self, pipeline: PipelineRef, index_tuple: (usize, usize), camera_position: Vec<f64>, data: Vec<f64>, stride: usize) {
+        let function_call = FunctionCall::SetInstancedRelative { pipeline, index_tuple, camera_position, data, stride };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
     }
-}
 
-impl TargetRef {
-    pub fn to_target(&self, textures: &[crate::Texture]) -> crate::Target {
-        match self {
-            Self::Screen => crate::Target::Screen,
-            Self::TextureRef(r) => crate::Target::Texture(textures[r.0].clone()),
+    // Takes a Vec of f32s, u32s, i32s, f64s or bytes (see UniformData).
+
+    pub fn set_uniform(&self, pipeline: PipelineRef, index_tuple: (usize, usize), data: impl Into<crate::UniformData>) {
+        let function_call = FunctionCall::SetUniform { pipeline, index_tuple, data: data.into() };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    // Sets several of the pipeline's uniforms with one message to the render thread.
+
+    pub fn set_uniforms(&self, pipeline: PipelineRef, uniforms: Vec<((usize, usize), crate::UniformData)>) {
+        let function_call = FunctionCall::SetUniforms { pipeline, uniforms };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_uniform_per_view(&self, pipeline: PipelineRef, index_tuple: (usize, usize), views_data: Vec<Vec<f32>>) {
+        let function_call = FunctionCall::SetUniformPerView { pipeline, index_tuple, views_data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_texture<T: bytemuck::Pod>(&self, pipeline: PipelineRef, index_tuple: (usize, usize), layers_data: Vec<Vec<T>>) {
+        let layers_data = layers_data.iter().map(|data| bytemuck::cast_slice(data).to_vec()).collect();
+        let function_call = FunctionCall::SetTexture { pipeline, index_tuple, layers_data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_part_of_texture<T: bytemuck::Pod>(&self, pipeline: PipelineRef, index_tuple: (usize, usize), offset: (u32, u32, u32), size: (u32, u32), data: Vec<T>) {
+        let data = bytemuck::cast_slice(&data).to_vec();
+        let function_call = FunctionCall::SetPartOfTexture { pipeline, index_tuple, offset, size, data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn upload_texture<T: bytemuck::Pod>(&self, texture: TextureRef, offset: (u32, u32, u32), size: (u32, u32), data: Vec<T>) {
+        let data = bytemuck::cast_slice(&data).to_vec();
+        let function_call = FunctionCall::UploadTexture { texture, offset, size, data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn flush_transfers(&self) {
+        let function_call = FunctionCall::FlushTransfers;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    // Unlike synchronize, which only waits for the render thread to catch up, these
+    // wait for the GPU. The fence's is_done can be checked from this thread.
+
+    pub fn frame_fence(&self) -> crate::FrameFence {
+        let function_call = FunctionCall::FrameFence;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::FrameFence(f) = return_value { f } else { unreachable!() }
+    }
+
+    pub fn wait_for_fence(&self, fence: crate::FrameFence) {
+        let function_call = FunctionCall::WaitForFence { fence };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Synchronized = return_value { } else { unreachable!() }
+    }
+
+    pub fn wait_for_gpu_idle(&self) {
+        let function_call = FunctionCall::WaitForGpuIdle;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Synchronized = return_value { } else { unreachable!() }
+    }
+
+    pub fn set_shrink_policy(&self, shrink_policy: Option<crate::ShrinkPolicy>) {
+        let function_call = FunctionCall::SetShrinkPolicy { shrink_policy };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn compact_buffers(&self) -> usize {
+        let function_call = FunctionCall::CompactBuffers;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Usize(n) = return_value { n } else { unreachable!() }
+    }
+
+    // The renderer passed to on_out_of_memory is the one on the render thread.
+    pub fn set_memory_budget(&self, budget_in_megabytes: f32, on_out_of_memory: Box<dyn FnMut(&crate::Renderer, &crate::MemoryReport) + Send>) {
+        let function_call = FunctionCall::SetMemoryBudget { budget_in_megabytes, on_out_of_memory };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn on_uncaptured_error(&self, handler: Box<dyn wgpu::UncapturedErrorHandler>) {
+        let function_call = FunctionCall::OnUncapturedError { handler };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn memory_used_in_bytes(&self) -> u64 {
+        let function_call = FunctionCall::MemoryUsedInBytes;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::U64(n) = return_value { n } else { unreachable!() }
+    }
+
+    pub fn swap_texture_binding(&self, pipeline: PipelineRef, index_tuple: (usize, usize), texture: TextureRef) {
+        let function_call = FunctionCall::SwapTextureBinding { pipeline, index_tuple, texture };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_vsync(&self, boolean: bool) {
+        let function_call = FunctionCall::SetVsync { boolean };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_blend_constant(&self, pipeline: PipelineRef, color: crate::ClearColor) {
+        let function_call = FunctionCall::SetBlendConstant { pipeline, color };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_blend_mode(&self, pipeline: PipelineRef, blend_mode: crate::BlendMode) {
+        let function_call = FunctionCall::SetBlendMode { pipeline, blend_mode };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_primitive(&self, pipeline: PipelineRef, primitive: crate::Primitive) {
+        let function_call = FunctionCall::SetPrimitive { pipeline, primitive };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_msaa_samples(&self, pipeline: PipelineRef, msaa_samples: u32) {
+        let function_call = FunctionCall::SetMsaaSamples { pipeline, msaa_samples };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_transparent_oit(&self, pipeline: PipelineRef, transparent_oit: bool) {
+        let function_call = FunctionCall::SetTransparentOit { pipeline, transparent_oit };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_rasterization(&self, pipeline: PipelineRef, rasterization: crate::Rasterization) {
+        let function_call = FunctionCall::SetRasterization { pipeline, rasterization };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_multiview(&self, pipeline: PipelineRef, views: Option<u32>) {
+        let function_call = FunctionCall::SetMultiview { pipeline, views };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_exclude_from_recording(&self, pipeline: PipelineRef, exclude_from_recording: bool) {
+        let function_call = FunctionCall::SetExcludeFromRecording { pipeline, exclude_from_recording };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn composite_transparency(&self, target: TargetRef) {
+        let function_call = FunctionCall::CompositeTransparency { target };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    // The depth is (depth_buffer, test, write).
+    pub fn set_depth(&self, pipeline: PipelineRef, depth: Option<(DepthBufferRef, crate::DepthTest, bool)>) {
+        let function_call = FunctionCall::SetDepth { pipeline, depth };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_stencil(&self, pipeline: PipelineRef, stencil: Option<crate::Stencil>) {
+        let function_call = FunctionCall::SetStencil { pipeline, stencil };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_stencil_reference(&self, pipeline: PipelineRef, reference: u32) {
+        let function_call = FunctionCall::SetStencilReference { pipeline, reference };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_overlay(&self, enabled: bool) {
+        let function_call = FunctionCall::SetOverlay { enabled };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn start_recording(&self, pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send>) -> crate::RecordingId {
+        let function_call = FunctionCall::StartRecording { pipelines, clear_color, max_buffer_size_in_megabytes, process_function };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::RecordingId(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn start_recording_with_planes(&self, pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, plane_formats: Vec<crate::Format>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send>) -> crate::RecordingId {
+        let function_call = FunctionCall::StartRecordingWithPlanes { pipelines, clear_color, plane_formats, max_buffer_size_in_megabytes, process_function };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::RecordingId(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn start_recording_with_area(&self, pipelines: Vec<PipelineRef>, clear_color: Option<crate::ClearColor>, area: crate::RecordingArea, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame) + Send>) -> crate::RecordingId {
+        let function_call = FunctionCall::StartRecordingWithArea { pipelines, clear_color, area, max_buffer_size_in_megabytes, process_function };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::RecordingId(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn stop_recording(&self, recording: crate::RecordingId, pipelines: Vec<PipelineRef>) {
+        let function_call = FunctionCall::StopRecording { recording, pipelines };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn is_recording(&self, recording: crate::RecordingId) -> bool {
+        let function_call = FunctionCall::IsRecording { recording };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    pub fn set_recording_region(&self, recording: crate::RecordingId, region: Option<(u32, u32, u32, u32)>) {
+        let function_call = FunctionCall::SetRecordingRegion { recording, region };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_adaptive_quality(&self, recording: crate::RecordingId, enabled: bool) {
+        let function_call = FunctionCall::SetAdaptiveQuality { recording, enabled };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_delta_encoding(&self, recording: crate::RecordingId, keyframe_interval: Option<usize>) {
+        let function_call = FunctionCall::SetDeltaEncoding { recording, keyframe_interval };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn set_readback_budget(&self, recording: crate::RecordingId, gigabytes_per_second: Option<f32>) {
+        let function_call = FunctionCall::SetReadbackBudget { recording, gigabytes_per_second };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    #[cfg(feature="frame_to_png")]
+    pub fn capture_every(&self, n: usize, directory: &str, pipelines: Vec<PipelineRef>) -> std::io::Result<crate::RecordingId> {
+        let function_call = FunctionCall::CaptureEvery { n, directory: directory.to_string(), pipelines };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::RecordingIdOrError(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        let function_call = FunctionCall::AdapterInfo;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::AdapterInfo(i) = return_value { i } else { unreachable!() }
+    }
+
+    pub fn elapsed_time(&self) -> f32 {
+        let function_call = FunctionCall::ElapsedTime;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::F32(t) = return_value { t } else { unreachable!() }
+    }
+
+    pub fn backend(&self) -> wgpu::Backend {
+        let function_call = FunctionCall::Backend;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Backend(b) = return_value { b } else { unreachable!() }
+    }
+
+    pub fn diagnostics(&self) -> crate::Diagnostics {
+        let function_call = FunctionCall::Diagnostics;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Diagnostics(d) = return_value { d } else { unreachable!() }
+    }
+
+    pub fn pipeline(&self, program: ProgramRef, blend_mode: crate::BlendMode, primitive: crate::Primitive, msaa_samples: u32, targets: Vec<TargetRef>) -> PipelineRef {
+        let function_call = FunctionCall::Pipeline { program, blend_mode, primitive, msaa_samples, targets };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::PipelineRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn depth_buffer(&self, msaa_samples: u32, with_stencil: bool) -> DepthBufferRef {
+        let function_call = FunctionCall::DepthBuffer { msaa_samples, with_stencil };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::DepthBufferRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn bake_bundle(&self, draws: Vec<DrawRef>) -> BundleRef {
+        let function_call = FunctionCall::BakeBundle { draws };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::BundleRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn attribute(&self, location: usize, size: u32) -> AttributeRef {
+        let function_call = FunctionCall::Attribute { location, size };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::AttributeRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn attribute_u32(&self, location: usize, size: u32) -> AttributeRef {
+        let function_call = FunctionCall::AttributeU32 { location, size };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::AttributeRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn pulled_attribute(&self, location: usize, size: u32) -> AttributeRef {
+        let function_call = FunctionCall::PulledAttribute { location, size };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::AttributeRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn instanced(&self) -> InstancedRef {
+        let function_call = FunctionCall::Instanced;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::InstancedRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn storage_buffer(&self, access: crate::StorageAccess, visibility: crate::Visibility) -> InstancedRef {
+        let function_call = FunctionCall::StorageBuffer { access, visibility };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::InstancedRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn uniform(&self) -> UniformRef {
+        let function_call = FunctionCall::Uniform;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::UniformRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn instanced_with_data(&self, data: Vec<f32>) -> InstancedRef {
+        let function_call = FunctionCall::InstancedWithData { data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::InstancedRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn uniform_with_data(&self, data: Vec<f32>) -> UniformRef {
+        let function_call = FunctionCall::UniformWithData { data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::UniformRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn read_texture(&self, texture: TextureRef) -> Vec<u8> {
+        let function_call = FunctionCall::ReadTexture { texture };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Bytes(b) = return_value { b } else { unreachable!() }
+    }
+
+    pub fn try_read_texture(&self, texture: TextureRef) -> Result<Vec<u8>, String> {
+        let function_call = FunctionCall::TryReadTexture { texture };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::BytesOrError(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn try_set_uniform(&self, pipeline: PipelineRef, index_tuple: (usize, usize), data: Vec<f32>) -> Result<(), String> {
+        let function_call = FunctionCall::TrySetUniform { pipeline, index_tuple, data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::UnitOrError(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn screenshot(&self) -> Result<Vec<u8>, String> {
+        let function_call = FunctionCall::Screenshot;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::BytesOrError(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn read_texture_f32(&self, texture: TextureRef) -> Vec<f32> {
+        let function_call = FunctionCall::ReadTextureF32 { texture };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Floats(f) = return_value { f } else { unreachable!() }
+    }
+
+    // Renders the pipelines into each tile of a TiledRender in order, with the
+    // clear color applied to the first. Their shaders should apply u_tile from
+    // the builtin uniform and their formats should match the tiled format.
+    pub fn render_tiled(&self, size: (u32, u32), tile_size: (u32, u32), format: crate::Format, draws: Vec<(PipelineRef, (u32, u32))>, clear_color: Option<crate::ClearColor>) -> crate::TiledImage {
+        let function_call = FunctionCall::RenderTiled { size, tile_size, format, draws, clear_color };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::TiledImage(i) = return_value { i } else { unreachable!() }
+    }
+
+    pub fn frame_graph(&self, pipelines: Vec<(String, PipelineRef)>) -> String {
+        let function_call = FunctionCall::FrameGraph { pipelines };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::String(s) = return_value { s } else { unreachable!() }
+    }
+
+    pub fn validate(&self, pipeline: PipelineRef) -> Vec<String> {
+        let function_call = FunctionCall::Validate { pipeline };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Strings(s) = return_value { s } else { unreachable!() }
+    }
+
+    pub fn read_pixel(&self, target: TargetRef, x: u32, y: u32) -> Option<[u8; 4]> {
+        let function_call = FunctionCall::ReadPixel { target, x, y };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Pixel(p) = return_value { p } else { unreachable!() }
+    }
+
+    pub fn builtin_uniform(&self) -> UniformRef {
+        let function_call = FunctionCall::BuiltinUniform;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::UniformRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn texture(&self, width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool) -> TextureRef {
+        let function_call = FunctionCall::Texture { width, height, layers, filter_mode, format, renderable, copyable, with_sampler };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::TextureRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn texture_with_data<T: bytemuck::Pod>(&self, width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool, layers_data: Vec<Vec<T>>) -> TextureRef {
+        let layers_data = layers_data.iter().map(|data| bytemuck::cast_slice(data).to_vec()).collect();
+        let function_call = FunctionCall::TextureWithData { width, height, layers, filter_mode, format, renderable, copyable, with_sampler, layers_data };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::TextureRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn program(&self, vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>) -> ProgramRef {
+        let function_call = FunctionCall::Program { vert, frag, attributes, instances, uniforms, textures };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn program_wgsl(&self, vert: String, frag: String, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>) -> ProgramRef {
+        let function_call = FunctionCall::ProgramWgsl { vert, frag, attributes, instances, uniforms, textures };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn material_program(&self, material: crate::Material, texture: Option<TextureRef>) -> ProgramRef {
+        let function_call = FunctionCall::MaterialProgram { material, texture };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn particle_system(&self, floats_per_particle: usize, update_shader: Option<Vec<u8>>) -> ParticleSystemRef {
+        let function_call = FunctionCall::ParticleSystem { floats_per_particle, update_shader };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ParticleSystemRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn particle_program(&self, system: ParticleSystemRef, vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>) -> ProgramRef {
+        let function_call = FunctionCall::ParticleProgram { system, vert, frag, attributes, uniforms, textures };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn program_with_texture_arrays(&self, vert: Vec<u8>, frag: Vec<u8>, attributes: Vec<AttributeRef>, instances: Vec<InstancedRef>, uniforms: Vec<(UniformRef, Vis)>, textures: Vec<(TextureRef, Vis)>, texture_arrays: Vec<(TextureArrayRef, Vis)>) -> ProgramRef {
+        let function_call = FunctionCall::ProgramWithTextureArrays { vert, frag, attributes, instances, uniforms, textures, texture_arrays };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::ProgramRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn texture_array(&self, textures: Vec<TextureRef>) -> TextureArrayRef {
+        let function_call = FunctionCall::TextureArray { textures };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::TextureArrayRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn supports_texture_arrays(&self) -> bool {
+        let function_call = FunctionCall::SupportsTextureArrays;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    pub fn warm_up(&self, pipelines: Vec<PipelineRef>) {
+        let function_call = FunctionCall::WarmUp { pipelines };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn supports_multiview(&self) -> bool {
+        let function_call = FunctionCall::SupportsMultiview;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    pub fn supports_rasterization(&self, rasterization: crate::Rasterization) -> bool {
+        let function_call = FunctionCall::SupportsRasterization { rasterization };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    // Compare this with the pipeline_id of PipelineStats.
+    pub fn pipeline_id(&self, pipeline: PipelineRef) -> usize {
+        let function_call = FunctionCall::PipelineId { pipeline };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Usize(u) = return_value { u } else { unreachable!() }
+    }
+
+    // The pipelines are owned by the render thread so only their refs are registered.
+    pub fn register_pipeline(&self, name: &str, pipeline: PipelineRef) {
+        let function_call = FunctionCall::RegisterPipeline { name: name.to_string(), pipeline };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+    }
+
+    pub fn pipeline_named(&self, name: &str) -> Option<PipelineRef> {
+        let function_call = FunctionCall::PipelineNamed { name: name.to_string() };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::OptionalPipelineRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn unregister_pipeline(&self, name: &str) -> Option<PipelineRef> {
+        let function_call = FunctionCall::UnregisterPipeline { name: name.to_string() };
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::OptionalPipelineRef(r) = return_value { r } else { unreachable!() }
+    }
+
+    pub fn pipeline_names(&self) -> Vec<String> {
+        let function_call = FunctionCall::PipelineNames;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Strings(s) = return_value { s } else { unreachable!() }
+    }
+
+    #[cfg(feature="pipeline_statistics")]
+    pub fn supports_pipeline_statistics(&self) -> bool {
+        let function_call = FunctionCall::SupportsPipelineStatistics;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::Boolean(b) = return_value { b } else { unreachable!() }
+    }
+
+    #[cfg(feature="pipeline_statistics")]
+    pub fn frame_timings(&self) -> Option<crate::FrameTimings> {
+        let function_call = FunctionCall::FrameTimings;
+        self.fn_sender.as_ref().unwrap().send(function_call).unwrap();
+
+        let return_value = self.rv_receiver.as_ref().unwrap().recv().unwrap();
+        if let ReturnValue::FrameTimings(t) = return_value { t } else { unreachable!() }
+    }
+
+    pub fn viewport(&self, aspect_x: f32, aspect_y: f32) -> crate::Viewport {
+        crate::Viewport::new(aspect_x, aspect_y, self.window_size.width as f32, self.window_size.height as f32)
+    }
+
+    pub fn screen_target() -> TargetRef {
+        TargetRef::Screen
+    }
+
+    pub fn texture_target(texture: TextureRef) -> TargetRef {
+        TargetRef::TextureRef(texture)
+    }
+}
+
+impl TargetRef {
+    pub fn to_target(&self, textures: &[crate::Texture]) -> crate::Target {
+        match self {
+            Self::Screen => crate::Target::Screen,
+            Self::TextureRef(r) => crate::Target::Texture(textures[r.0].clone()),
+        }
+    }
+}
+
+impl DrawRef {
+    pub fn to_draw<'a>(&self, pipelines: &'a [crate::Pipeline]) -> crate::Draw<'a> {
+        crate::Draw { pipeline: &pipelines[self.pipeline.0], count: self.count, instance_offset: self.instance_offset }
+    }
+}
+
+impl FrameItemRef {
+    pub fn to_frame_item<'a>(&'a self, pipelines: &'a [crate::Pipeline]) -> crate::FrameItem<'a> {
+        crate::FrameItem {
+            pipeline: &pipelines[self.pipeline.0],
+            attributes: self.attributes.iter().map(|(l, d)| (*l, &d[..])).collect(),
+            instanced: self.instanced.iter().map(|(t, d)| (*t, &d[..])).collect(),
+            uniforms: self.uniforms.iter().map(|(t, d)| (*t, &d[..])).collect(),
+            clear_color: self.clear_color,
+            viewport: self.viewport.as_ref(),
+            count: self.count,
+            instance_offset: self.instance_offset,
+            cacheable: self.cacheable,
         }
     }
 }
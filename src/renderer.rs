@@ -1,232 +1,1633 @@
 use crate::*;
-use std::{cell, ops};
-use std::sync::{atomic, Arc};
+use std::{cell, mem, rc, thread, time};
+use std::sync::{atomic, mpsc, Arc};
 use futures::executor;
 use winit::{dpi, window};
 
+// The wgpu handles never change so they are fields of the Renderer. Everything
+// that changes from frame to frame lives in InnerR behind a RefCell. Headless
+// renderers have no surface (see new_headless).
 pub struct Renderer<'a> {
-    pub inner: cell::RefCell<InnerR<'a>>,
-}
-
-pub struct InnerR<'a> {
-    pub window_size: dpi::PhysicalSize<u32>,
     pub instance: wgpu::Instance,
-    pub surface: wgpu::Surface<'a>,
+    pub surface: Option<wgpu::Surface<'a>>,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    pub flushes: atomic::AtomicU64,
+    pub presents: atomic::AtomicU64,
+    pub inner: cell::RefCell<InnerR>,
+}
+
+pub struct InnerR {
+    pub window_size: dpi::PhysicalSize<u32>,
     pub vsync: bool,
-    pub frame: Option<wgpu::SurfaceTexture>,
-    pub frame_view: Option<wgpu::TextureView>,
+    pub surface_configured: bool,
+    pub frame_open: bool,
+    pub frame: Option<ScreenFrame>,
+    pub headless_texture: Option<wgpu::Texture>, // Reused between frames.
+    pub frame_view: Option<rc::Rc<wgpu::TextureView>>,
     pub commands: Vec<wgpu::CommandBuffer>,
-    pub recorder: Option<crate::VideoRecorder>,
-    pub flushes: atomic::AtomicU64,
+    pub transfers: Vec<wgpu::CommandBuffer>,
+    pub staging_pool: crate::StagingPool,
+    pub readbacks: Vec<wgpu::CommandBuffer>,
+    pub recorders: Vec<(crate::RecordingId, crate::VideoRecorder)>,
+    pub next_recording_id: usize,
+    pub grab_textures: Vec<crate::Texture>,
+    pub debug_groups: Vec<String>,
+    pub viewports: Vec<crate::Viewport>,
+    pub pixel_reader: Option<crate::PixelReader>,
+    pub capturing: bool,
+    pub started_at: time::Instant,
+    pub frame_index: u64,
+    pub builtin_uniform: Option<(crate::Uniform, u64)>, // (uniform, frame_index it was last set)
+    pub tile: Option<([f32; 16], (u32, u32))>, // (projection, image size) while rendering a TiledRender
+    pub memory: crate::MemoryTracker,
+    pub memory_budget: Option<crate::MemoryBudget>,
+    pub error_handler: crate::ErrorHandler,
+    pub shrink_policy: Option<crate::ShrinkPolicy>,
+    pub transparency: Option<crate::Transparency>,
+    pub named_pipelines: Vec<(String, rc::Rc<crate::Pipeline>)>,
+    pub window_sized_textures: Vec<(crate::Texture, f32)>, // (texture, scale) resized with the swap chain
+    pub minimized: bool,
+    pub occluded: bool,
+    pub frame_ended_at: time::Instant,
+    pub recording_frame_rate: usize,
+    pub overlay: Option<crate::Overlay>,
+    #[cfg(feature="pipeline_statistics")]
+    pub statistics: Option<crate::PipelineStatistics>,
+}
+
+// Headless renderers render the screen into a texture that is never presented.
+pub enum ScreenFrame {
+    Surface(wgpu::SurfaceTexture),
+    Headless(wgpu::Texture),
+}
+
+impl InnerR {
+    pub fn recorder(&self, recording_id: crate::RecordingId) -> &crate::VideoRecorder {
+        let (_, recorder) = self.recorders.iter().find(|(id, _)| *id == recording_id).expect("The recording has been stopped.");
+        recorder
+    }
 }
 
 impl<'a> Renderer<'a> {
     pub fn new(window: Arc<window::Window>) -> Self {
-        let (instance, surface) = Self::create_surface(window.clone());
+        Self::new_with_options(window, crate::RendererOptions::default())
+    }
+
+    pub fn new_with_options(window: Arc<window::Window>, options: crate::RendererOptions) -> Self {
+        let (instance, surface) = Self::create_surface_with_options(window.clone(), &options);
         Self::new_with_surface(window.inner_size(), instance, surface)
     }
 
     pub fn create_surface(window: Arc<window::Window>) -> (wgpu::Instance, wgpu::Surface<'a>) {
-        let instance = get_instance();
+        Self::create_surface_with_options(window, &crate::RendererOptions::default())
+    }
+
+    pub fn create_surface_with_options(window: Arc<window::Window>, options: &crate::RendererOptions) -> (wgpu::Instance, wgpu::Surface<'a>) {
+        let instance = get_instance(options.backends());
         let surface = instance.create_surface(window).unwrap(); // Must be called in main thread.
 
         (instance, surface)
     }
 
-    pub fn new_with_surface(window_size: dpi::PhysicalSize<u32>, instance: wgpu::Instance, surface: wgpu::Surface<'a>) -> Self {
-        let adapter = get_adapter(&instance, &surface);
-        let (device, queue) = get_device(&adapter);
-        let vsync = true;
+    pub fn new_with_surface(window_size: dpi::PhysicalSize<u32>, instance: wgpu::Instance, surface: wgpu::Surface<'a>) -> Self {
+        Self::_new(window_size, instance, Some(surface)).expect(NO_ADAPTER)
+    }
+
+    // Renders without a window, e.g. for tests or on servers. The screen is a
+    // texture of the given size so pipelines that target it still work.
+    pub fn new_headless(size: (u32, u32), options: crate::RendererOptions) -> Self {
+        Self::try_new_headless(size, options).expect(NO_ADAPTER)
+    }
+
+    // Returns None if there's no adapter, e.g. so that tests can be skipped on CI
+    // machines without a GPU.
+    pub fn try_new_headless(size: (u32, u32), options: crate::RendererOptions) -> Option<Self> {
+        let instance = get_instance(options.backends());
+        Self::_new(dpi::PhysicalSize::new(size.0, size.1), instance, None)
+    }
+
+    fn _new(window_size: dpi::PhysicalSize<u32>, instance: wgpu::Instance, surface: Option<wgpu::Surface<'a>>) -> Option<Self> {
+        let adapter = get_adapter(&instance, surface.as_ref())?;
+        let (device, queue) = get_device(&adapter);
+        let vsync = true;
+
+        // The surface is configured and acquired when something first renders to
+        // the screen so that offscreen-only workloads never touch the swap chain.
+        let surface_configured = false;
+        let frame_open = false;
+        let frame = None;
+        let headless_texture = None;
+        let frame_view = None;
+        let commands = vec![];
+        let transfers = vec![];
+        let staging_pool = crate::StagingPool::default();
+        let readbacks = vec![];
+        let recorders = vec![];
+        let next_recording_id = 0;
+        let grab_textures = vec![];
+        let debug_groups = vec![];
+        let viewports = vec![];
+        let pixel_reader = None;
+        let capturing = false;
+        let started_at = time::Instant::now();
+        let frame_index = 0;
+        let builtin_uniform = None;
+        let tile = None;
+        let memory = crate::MemoryTracker::default();
+        let memory_budget = None;
+        let error_handler = crate::ErrorHandler::default();
+        let shrink_policy = None;
+        let transparency = None;
+        let named_pipelines = vec![];
+        let window_sized_textures = vec![];
+        let minimized = false;
+        let occluded = false;
+        let frame_ended_at = time::Instant::now();
+        let recording_frame_rate = crate::DEFAULT_FRAME_RATE;
+        let overlay = None;
+        #[cfg(feature="pipeline_statistics")]
+        let statistics = if device.features().contains(wgpu::Features::PIPELINE_STATISTICS_QUERY) { Some(crate::PipelineStatistics::new(&device)) } else { None };
+        let flushes = atomic::AtomicU64::new(0);
+        let presents = atomic::AtomicU64::new(0);
+        let inner = InnerR { window_size, vsync, surface_configured, frame_open, frame, headless_texture, frame_view, commands, transfers, staging_pool, readbacks, recorders, next_recording_id, grab_textures, debug_groups, viewports, pixel_reader, capturing, started_at, frame_index, builtin_uniform, tile, memory, memory_budget, error_handler, shrink_policy, transparency, named_pipelines, window_sized_textures, minimized, occluded, frame_ended_at, recording_frame_rate, overlay, #[cfg(feature="pipeline_statistics")] statistics };
+
+        Some(Self { instance, surface, adapter, device, queue, flushes, presents, inner: cell::RefCell::new(inner) })
+    }
+
+    pub fn window_size(&self) -> dpi::PhysicalSize<u32> {
+        self.inner.borrow().window_size
+    }
+
+    // In seconds since the renderer was created. The same as the builtin uniform's time.
+    pub fn elapsed_time(&self) -> f32 {
+        self.inner.borrow().started_at.elapsed().as_secs_f32()
+    }
+
+    // Windows are resized to zero when they're minimized (on some platforms).
+    pub fn resize_swap_chain(&self, new_size: &dpi::PhysicalSize<u32>) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.minimized = new_size.width == 0 || new_size.height == 0;
+        if inner.minimized { return; }
+
+        inner.window_size = *new_size;
+        inner.frame = None;
+        inner.frame_view = None;
+
+        if let (true, Some(surface)) = (inner.surface_configured, &self.surface) {
+            configure_surface(surface, &self.adapter, &self.device, new_size, inner.vsync);
+        }
+
+        // Stop resizing textures that are only still alive because of this list.
+        inner.window_sized_textures.retain(|(t, _)| rc::Rc::strong_count(&t.inner) > 1);
+        let textures = inner.window_sized_textures.clone();
+
+        drop(inner);
+
+        for (mut texture, scale) in textures {
+            let size = window_sized(new_size, scale, texture.size().2);
+            texture.resize(&self.device, size);
+        }
+
+        self.check_memory_budget();
+    }
+
+    // Call this on winit's WindowEvent::Occluded. While the window is occluded or
+    // minimized, passes that render to the screen or are recorded are skipped and
+    // recordings get Skipped frames instead so that their timing carries on.
+    // Frames are throttled to the recording frame rate so that apps which keep
+    // rendering don't busy-loop. Offscreen passes still render, e.g. for exports.
+
+    pub fn set_occluded(&self, occluded: bool) {
+        self.inner.borrow_mut().occluded = occluded;
+    }
+
+    // The rate that frames are throttled to while hidden. Set this to the frame
+    // rate recordings will be exported at, e.g. with FfmpegPipe::set_frame_rate.
+
+    pub fn set_recording_frame_rate(&self, frame_rate: usize) {
+        self.inner.borrow_mut().recording_frame_rate = frame_rate;
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        let inner = self.inner.borrow();
+        inner.minimized || inner.occluded
+    }
+
+    // Switches the window in or out of fullscreen and reconfigures the surface
+    // straight away rather than waiting for the resize event. If recording, the
+    // recording texture is resized on the next render and frame numbers carry on.
+
+    pub fn set_fullscreen(&self, window: &window::Window, mode: Option<window::Fullscreen>) {
+        let new_size = fullscreen_size(window, &mode);
+
+        window.set_fullscreen(mode);
+        self.resize_swap_chain(&new_size);
+    }
+
+    pub fn resize_texture(&self, texture: &mut crate::Texture, new_size: (u32, u32, u32)) {
+        texture.resize(&self.device, new_size);
+        self.check_memory_budget();
+    }
+
+    // Resizes the texture to the window's size times scale, straight away and then
+    // whenever the swap chain is resized, e.g. so that post-processing targets
+    // aren't stretched. Use 0.5 for a half-resolution target (e.g. for bloom).
+    // Its contents are lost when it's resized. None stops resizing it.
+
+    pub fn set_resize_with_window(&self, texture: &crate::Texture, scale: Option<f32>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.window_sized_textures.retain(|(t, _)| !rc::Rc::ptr_eq(&t.inner, &texture.inner));
+
+        let scale = match scale { Some(s) => s, _ => return };
+        if scale <= 0. { panic!("The scale must be greater than zero."); }
+
+        inner.window_sized_textures.push((texture.clone(), scale));
+        let size = window_sized(&inner.window_size, scale, texture.size().2);
+
+        drop(inner);
+        self.resize_texture(&mut texture.clone(), size);
+    }
+
+    pub fn render(&self, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32)) {
+        self.render_to(&pipeline.targets, pipeline, clear_color, viewport, count);
+    }
+
+    // You can render to different targets than those specified when setting up
+    // the pipeline but it will crash if the texture formats are different.
+
+    pub fn render_to(&self, targets: &[crate::Target], pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32)) {
+        self._render_to(targets, pipeline, clear_color, viewport, count, 0, crate::PrePass::None);
+    }
+
+    // Renders count.0 instances starting at instance_offset so that one large
+    // instanced buffer can be shared by several draws, e.g. one per layer. The
+    // offset is passed as the first instance so gl_InstanceIndex includes it.
+
+    pub fn render_instances(&self, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32), instance_offset: u32) {
+        self._render_to(&pipeline.targets, pipeline, clear_color, viewport, count, instance_offset, crate::PrePass::None);
+    }
+
+    // Renders with the count inferred from the data that was last set so that it
+    // can't drift out of sync with the uploads (see inferred_count).
+
+    pub fn render_inferred(&self, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, instances: Option<((usize, usize), usize)>) {
+        let count = self.inferred_count(pipeline, instances);
+        self._render_to(&pipeline.targets, pipeline, clear_color, viewport, count, 0, crate::PrePass::None);
+    }
+
+    // Vertices per instance is the number of vertices in the attributes. The
+    // instance count is the length of the instanced data at the index tuple
+    // divided by its floats per instance, which includes any padding, e.g. 4 for
+    // vec3s. It's 1 if instances is None.
+
+    pub fn inferred_count(&self, pipeline: &crate::Pipeline, instances: Option<((usize, usize), usize)>) -> (u32, u32) {
+        let vertices_per_instance = pipeline.vertex_count().expect("Can't infer the count of a pipeline without attributes.");
+
+        let instance_count = instances.map_or(1, |(index_tuple, floats_per_instance)| {
+            let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+            let instanced = pipeline.program.instances.get(index).expect("Tried to infer the instance count but there isn't instanced data in that slot.");
+
+            let floats = instanced.buffer.inner.borrow().len / 4;
+            (floats / floats_per_instance.max(1)) as u32
+        });
+
+        (instance_count, vertices_per_instance)
+    }
+
+    // Renders the draws in order with the clear color applied to the first. With
+    // depth_pre_pass, all of the draws are rendered depth-only first and then
+    // rendered again with depth-equal testing so that each pixel is only shaded
+    // once, which helps scenes with lots of overdraw. Their pipelines must share
+    // a depth buffer (see set_depth). Depth and stencil are only cleared before
+    // the depth-only draws so that the color draws can test against them.
+
+    pub fn render_draws(&self, draws: &[crate::Draw], clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, depth_pre_pass: bool) {
+        let passes = if depth_pre_pass { &[crate::PrePass::Depth, crate::PrePass::Color][..] } else { &[crate::PrePass::None][..] };
+
+        for pre_pass in passes {
+            let mut clear_color = if *pre_pass == crate::PrePass::Color { clear_color.map(|c| c.without_depth_stencil()) } else { clear_color };
+
+            for draw in draws {
+                self._render_to(&draw.pipeline.targets, draw.pipeline, clear_color.take(), viewport, draw.count, draw.instance_offset, *pre_pass);
+            }
+        }
+    }
+
+    // Renders a frame described as a list of items, skipping uploads and passes
+    // that haven't changed since the last frame (see RetainedFrame).
+
+    pub fn render_frame(&self, retained_frame: &mut crate::RetainedFrame, items: &[crate::FrameItem]) {
+        retained_frame.render(&self, items);
+    }
+
+    // Side-by-side stereo for targets that aren't layered or adapters without
+    // multiview. The pipeline is rendered into the left then the right half of the
+    // viewport with the eye's data in the uniform at index_tuple. The commands are
+    // flushed after each eye because the second upload would replace the first.
+
+    pub fn render_side_by_side(&self, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32), index_tuple: (usize, usize), eyes_data: [&[f32]; 2]) {
+        let window_size = (self.window_size().width, self.window_size().height);
+        let (width, height, _) = pipeline.targets.first().map(|t| t.size(window_size)).unwrap_or((window_size.0, window_size.1, 1));
+
+        let full = crate::Viewport { width: width as f32, height: height as f32, margin_x: 0., margin_y: 0., aspect: None };
+        let viewport = viewport.map(|v| v.resized(width as f32, height as f32)).unwrap_or(full);
+        let mut clear_color = clear_color;
+
+        for (eye, data) in viewport.side_by_side().iter().zip(eyes_data) {
+            self.set_uniform(pipeline, index_tuple, data);
+            self.render_instances(pipeline, clear_color.take(), Some(eye), count, 0);
+            self.flush();
+        }
+    }
+
+    fn _render_to(&self, targets: &[crate::Target], pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32), instance_offset: u32, pre_pass: crate::PrePass) {
+        span!("render", instances = count.0, instance_offset);
+
+        // Drawing past the end of the attributes fails deep inside wgpu so check
+        // the count against the data that was last set in debug builds.
+        #[cfg(debug_assertions)]
+        if let Some(vertex_count) = pipeline.vertex_count() {
+            if count.1 > vertex_count { panic!("Tried to render {} vertices per instance but the attributes only have {}.", count.1, vertex_count); }
+        }
+
+        if self._skip_while_hidden(targets, !crate::attached_recordings(&pipeline.inner.borrow()).is_empty()) { return; }
+
+        for target in targets {
+            if let crate::Target::Screen = target {
+                self._start_frame()
+            }
+        }
+
+        self._update_builtin_uniform();
+
+        let (viewport, recording_viewport) = self._resolve_viewport(viewport);
+
+        let render_pass = crate::RenderPass::new(&self);
+        let cbuffer = render_pass.render(targets, pipeline, &clear_color, viewport.as_ref(), recording_viewport.as_ref(), count, instance_offset, pre_pass);
+
+        self.inner.borrow_mut().commands.push(cbuffer);
+    }
+
+    fn _skip_while_hidden(&self, targets: &[crate::Target], recorded: bool) -> bool {
+        self.is_hidden() && (recorded || targets.iter().any(|t| matches!(t, crate::Target::Screen)))
+    }
+
+    // Renders a bundle baked with bake_bundle. The targets must have the same
+    // formats as the targets of the pipelines in the bundle.
+
+    pub fn render_bundle(&self, bundle: &crate::Bundle, targets: &[crate::Target], clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>) {
+        if bundle.is_stale() { panic!("A buffer or texture in the bundle has been resized or one of its pipelines has changed since it was baked. Please bake it again."); }
+        if self._skip_while_hidden(targets, false) { return; }
+
+        for target in targets {
+            if let crate::Target::Screen = target {
+                self._start_frame()
+            }
+        }
+
+        self._update_builtin_uniform();
+
+        let (viewport, _) = self._resolve_viewport(viewport);
+
+        let render_pass = crate::RenderPass::new(&self);
+        let cbuffer = render_pass.render_bundle(targets, bundle, &clear_color, viewport.as_ref());
+
+        self.inner.borrow_mut().commands.push(cbuffer);
+    }
+
+    // Copies the contents of the pipeline's first target into a grab texture so
+    // that pipelines rendered afterwards in the same frame can sample from it.
+    // Texture targets must be copyable for this to work.
+
+    pub fn grab_pass(&self, pipeline: &crate::Pipeline) {
+        let target = &pipeline.targets[0];
+
+        if let crate::Target::Screen = target {
+            self._start_frame()
+        }
+
+        let window_size = self.window_size();
+        let (width, height, _) = target.size((window_size.width, window_size.height));
+
+        let mut texture = self.grab_texture(target.format());
+        texture.resize(&self.device, (width, height, 1));
+
+        let target_texture = match target { crate::Target::Texture(t) => Some(t.texture()), _ => None };
+        let grab_texture = texture.texture();
+
+        let mut encoder = self.create_command_encoder();
+        let inner = self.inner.borrow();
+
+        let source_texture: &wgpu::Texture = match &target_texture { Some(t) => t, None => inner.frame.as_ref().unwrap().texture() };
+
+        if !source_texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            match target {
+                crate::Target::Screen => panic!("Unable to grab_pass from the screen because the surface doesn't support COPY_SRC on this platform. Render to a texture instead."),
+                _ => panic!("Unable to grab_pass from a texture that isn't copyable. Create the texture with copyable set to true."),
+            }
+        }
+
+        let source = crate::Texture::image_copy_texture(source_texture, (0, 0, 0));
+
+        encoder.copy_texture_to_texture(source, crate::Texture::image_copy_texture(&grab_texture, (0, 0, 0)), texture.extent());
+        drop(inner);
+
+        let cbuffer = self.finish_command_encoder(encoder);
+        self.inner.borrow_mut().commands.push(cbuffer);
+    }
+
+    // Copies the first layer of the texture back from the GPU and returns its
+    // texels without row padding. This waits for the GPU to finish so it's meant
+    // for extracting results rather than every frame. It must be copyable.
+
+    pub fn read_texture(&self, texture: &crate::Texture) -> Vec<u8> {
+        self._read_texture(texture).unwrap_or_else(|error| panic!("Failed to read the texture back from the GPU: {}", error))
+    }
+
+    // The map result is recorded rather than unwrapped in the callback so that a
+    // failure (e.g. device loss) is reported here instead of from device.poll.
+    fn _read_texture(&self, texture: &crate::Texture) -> Result<Vec<u8>, String> {
+        span!("read_texture");
+        self.flush();
+
+        let (width, height, _) = texture.size();
+        let unpadded_bytes_per_row = (width * texture.format.bytes_per_texel()) as usize;
+
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+        let row_padding = (alignment - unpadded_bytes_per_row % alignment) % alignment;
+        let padded_bytes_per_row = unpadded_bytes_per_row + row_padding;
+
+        let usage = wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ;
+        let descriptor = wgpu::BufferDescriptor { label: None, size: (padded_bytes_per_row * height as usize) as u64, usage, mapped_at_creation: false };
+        let buffer = self.device.create_buffer(&descriptor);
+
+        let wgpu_texture = texture.texture();
+        let image_copy = crate::Texture::image_copy_texture(&wgpu_texture, (0, 0, 0));
+        let buffer_copy = wgpu::ImageCopyBuffer { buffer: &buffer, layout: texture.image_data_layout(padded_bytes_per_row as u32, height) };
+        let extent = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+
+        let mut encoder = self.create_command_encoder();
+        encoder.copy_texture_to_buffer(image_copy, buffer_copy, extent);
+        self.queue.submit(Some(self.finish_command_encoder(encoder)));
+
+        let (sender, receiver) = mpsc::channel();
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        match receiver.try_recv() {
+            Ok(Ok(())) => {},
+            Ok(Err(error)) => return Err(format!("the buffer couldn't be mapped ({})", error)),
+            Err(_) => return Err("the buffer wasn't mapped after waiting for the GPU".to_string()),
+        }
+
+        let mut bytes = Vec::with_capacity(unpadded_bytes_per_row * height as usize);
+
+        for row in slice.get_mapped_range().chunks(padded_bytes_per_row) {
+            bytes.extend_from_slice(&row[..unpadded_bytes_per_row]);
+        }
+
+        Ok(bytes)
+    }
+
+    // Reads back what has been rendered to the screen so far this frame in BGRA
+    // order, e.g. to save a screenshot. Like read_texture, this waits for the GPU.
+    // Returns an error if the surface can't be copied from on this platform, in
+    // which case render to a texture and read that instead.
+
+    pub fn screenshot(&self) -> Result<Vec<u8>, String> {
+        self._start_frame();
+
+        let usage = self.inner.borrow().frame.as_ref().unwrap().texture().usage();
+        if !usage.contains(wgpu::TextureUsages::COPY_SRC) { return Err("the surface doesn't support COPY_SRC on this platform".to_string()); }
+
+        let window_size = self.window_size();
+        let texture = create_screenshot_texture(&self.device, (window_size.width, window_size.height, 1));
+        let wgpu_texture = texture.texture();
+
+        let mut encoder = self.create_command_encoder();
+        let inner = self.inner.borrow();
+
+        let source = inner.frame.as_ref().unwrap().texture().as_image_copy();
+        encoder.copy_texture_to_texture(source, crate::Texture::image_copy_texture(&wgpu_texture, (0, 0, 0)), texture.extent());
+        drop(inner);
+
+        let cbuffer = self.finish_command_encoder(encoder);
+        self.inner.borrow_mut().commands.push(cbuffer);
+
+        self._read_texture(&texture)
+    }
+
+    // Reads back RgbaF16 and RgbaF32 textures (as well as the 8-bit formats) as
+    // f32s in RGBA order, e.g. to extract the numeric results of a GPU pass.
+
+    pub fn read_texture_f32(&self, texture: &crate::Texture) -> Vec<f32> {
+        texture.format.to_f32(&self.read_texture(texture))
+    }
+
+    // Like read_texture but returns an error instead of panicking if the texture
+    // isn't copyable, is empty or can't be mapped.
+
+    pub fn try_read_texture(&self, texture: &crate::Texture) -> Result<Vec<u8>, String> {
+        let (width, height, _) = texture.size();
+
+        if !texture.copyable { return Err("the texture isn't copyable".to_string()); }
+        if width == 0 || height == 0 { return Err("the texture is empty".to_string()); }
+
+        self._read_texture(texture)
+    }
+
+    // Returns a Graphviz DOT description of the pipelines in the order given.
+    // Name each pipeline so that it's easy to find in the graph.
+
+    pub fn frame_graph(&self, pipelines: &[(&str, &crate::Pipeline)]) -> String {
+        crate::FrameGraph::dot(pipelines, &self.inner.borrow().grab_textures)
+    }
+
+    // Returns a description of each problem that would stop the pipeline from
+    // rendering, e.g. to call once after setting up its data. Empty if it's fine.
+
+    pub fn validate(&self, pipeline: &crate::Pipeline) -> Vec<String> {
+        let inner = self.inner.borrow();
+        let window_size = (inner.window_size.width, inner.window_size.height);
+        let builtin_uniform = inner.builtin_uniform.as_ref().map(|(u, _)| u);
+
+        crate::Validation::problems(&self.adapter, pipeline, window_size, builtin_uniform)
+    }
+
+    // Copies the pixel at (x, y) of the target into a small buffer and returns
+    // the pixel from a previous call (usually the previous frame) in RGBA order.
+    // This avoids stalling so it's fine to call every frame, e.g. for a picker.
+    // The target must be copyable and have an 8-bit format.
+
+    pub fn read_pixel(&self, target: &crate::Target, x: u32, y: u32) -> Option<[u8; 4]> {
+        if let crate::Target::Screen = target {
+            self._start_frame()
+        }
+
+        self.device.poll(wgpu::Maintain::Poll);
+
+        let mut reader = self.inner.borrow_mut().pixel_reader.take().unwrap_or_else(|| crate::PixelReader::new(&self.device));
+        let latest = reader.latest();
+
+        let window_size = self.window_size();
+        let (width, height, _) = target.size((window_size.width, window_size.height));
+
+        // Zero-sized targets (e.g. a minimized window) have no pixels to copy.
+        let max_position = width.checked_sub(1).zip(height.checked_sub(1));
+
+        if let (true, Some((max_x, max_y))) = (reader.is_idle(), max_position) {
+            let position = (x.min(max_x), y.min(max_y));
+
+            let target_texture = match target { crate::Target::Texture(t) => Some(t.texture()), _ => None };
+            let mut encoder = self.create_command_encoder();
+            let inner = self.inner.borrow();
+
+            let texture = match &target_texture {
+                Some(t) => t,
+                None => inner.frame.as_ref().unwrap().texture(),
+            };
+
+            reader.copy(&mut encoder, texture, target.format(), position);
+            drop(inner);
+
+            let cbuffer = self.finish_command_encoder(encoder);
+            self.inner.borrow_mut().commands.push(cbuffer);
+        }
+
+        self.inner.borrow_mut().pixel_reader = Some(reader);
+        latest
+    }
+
+    // There is one grab texture per format because copies between textures
+    // require the formats to match. Add it to a program's textures to sample it.
+
+    pub fn grab_texture(&self, format: crate::Format) -> crate::Texture {
+        let mut inner = self.inner.borrow_mut();
+        let existing = inner.grab_textures.iter().find(|t| t.format.texture_format() == format.texture_format());
+
+        if let Some(texture) = existing { return texture.clone(); }
+
+        let size = (inner.window_size.width, inner.window_size.height, 1);
+        let texture = create_grab_texture(&self.device, size, format);
+
+        inner.grab_textures.push(texture.clone());
+        inner.memory.track_texture(&texture);
+        drop(inner);
+
+        self.check_memory_budget();
+        texture
+    }
+
+    fn _start_frame(&self) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.frame.is_some() { return; }
+        span!("acquire_frame");
+
+        let surface = match &self.surface {
+            Some(s) => s,
+            None => {
+                let window_size = (inner.window_size.width, inner.window_size.height);
+                let texture = inner.headless_texture.take().filter(|t| (t.width(), t.height()) == window_size);
+                let texture = texture.unwrap_or_else(|| create_headless_texture(&self.device, window_size));
+
+                inner.frame_view = Some(rc::Rc::new(texture.create_view(&wgpu::TextureViewDescriptor::default())));
+                inner.frame = Some(ScreenFrame::Headless(texture));
+                return;
+            },
+        };
+
+        if !inner.surface_configured {
+            configure_surface(surface, &self.adapter, &self.device, &inner.window_size, inner.vsync);
+            inner.surface_configured = true;
+        }
+
+        let frame = surface.get_current_texture().unwrap();
+
+        inner.frame_view = Some(rc::Rc::new(frame.texture.create_view(&wgpu::TextureViewDescriptor::default())));
+        inner.frame = Some(ScreenFrame::Surface(frame));
+    }
+
+    // Frames can be bracketed with begin_frame and end_frame. The swap chain is only
+    // acquired if something renders to the screen and is only presented if it was.
+    // Offscreen-only workloads, e.g. batch exports, can use these without a window.
+
+    pub fn begin_frame(&self) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.frame_open { panic!("The previous frame hasn't ended. Please call end_frame first."); }
+        inner.frame_open = true;
+    }
+
+    pub fn end_frame(&self) {
+        span!("end_frame");
+
+        #[cfg(feature="pipeline_statistics")]
+        self._resolve_statistics();
+
+        self._render_overlay();
+        self._copy_scheduled_readbacks();
+        self.flush();
+        self.device.poll(wgpu::Maintain::Poll); // Signals any FrameFences that are done.
+
+        #[cfg(feature="pipeline_statistics")]
+        if let Some(statistics) = &mut self.inner.borrow_mut().statistics { statistics.map_readback(); }
+
+        let hidden = self.is_hidden();
+        let mut inner = self.inner.borrow_mut();
+        inner.frame_open = false;
+
+        for (_, recorder) in &mut inner.recorders {
+            if hidden { recorder.skip_frame(); }
+
+            recorder.initiate_buffer_mapping();
+            recorder.process_mapped_buffers();
+            recorder.finish_frame();
+        }
+
+        if let Some(frame) = inner.frame.take() {
+            match frame {
+                ScreenFrame::Surface(frame) => frame.present(),
+                ScreenFrame::Headless(texture) => inner.headless_texture = Some(texture),
+            }
+
+            inner.frame_view = None;
+            self.presents.fetch_add(1, atomic::Ordering::Relaxed);
+        }
+
+        if inner.capturing {
+            self.device.stop_capture();
+            inner.capturing = false;
+        }
+
+        if hidden {
+            let frame_interval = time::Duration::from_secs_f64(1. / inner.recording_frame_rate.max(1) as f64);
+            thread::sleep(frame_interval.saturating_sub(inner.frame_ended_at.elapsed()));
+        }
+
+        inner.frame_ended_at = time::Instant::now();
+        inner.frame_index += 1;
+    }
+
+    pub fn finish_frame(&self) {
+        self.end_frame();
+    }
+
+    // Shows the frame rate, a frame time graph and whether anything is being
+    // recorded in the corner of the screen. See Overlay.
+    pub fn set_overlay(&self, enabled: bool) {
+        let mut inner = self.inner.borrow_mut();
+
+        match (enabled, &inner.overlay) {
+            (true, None) => inner.overlay = Some(crate::Overlay::new(&self.device)),
+            (false, Some(_)) => inner.overlay = None,
+            _ => {},
+        }
+    }
+
+    // The overlay is only drawn if something rendered to the screen this frame.
+    fn _render_overlay(&self) {
+        if self.inner.borrow().overlay.is_none() { return; }
+        let mut encoder = self.create_command_encoder();
+
+        {
+            let mut inner = self.inner.borrow_mut();
+            let inner = &mut *inner;
+
+            let overlay = match &mut inner.overlay { Some(o) => o, _ => return };
+            overlay.tick();
+
+            let view = match &inner.frame_view { Some(v) => v, _ => return };
+            let window_size = (inner.window_size.width, inner.window_size.height);
+            let buffer_fill = inner.recorders.iter().map(|(_, r)| r.buffer_fill()).reduce(f32::max);
+
+            overlay.render(&self.queue, &mut encoder, view, window_size, buffer_fill);
+        }
+
+        let cbuffer = self.finish_command_encoder(encoder);
+        self.inner.borrow_mut().commands.push(cbuffer);
+    }
+
+    pub fn is_recording(&self, recording_id: crate::RecordingId) -> bool {
+        self.inner.borrow().recorders.iter().any(|(id, _)| *id == recording_id)
+    }
+
+    fn _update_builtin_uniform(&self) {
+        let mut inner = self.inner.borrow_mut();
+
+        let (tile_projection, resolution) = match inner.tile {
+            Some((projection, size)) => (projection, size),
+            None => (IDENTITY, (inner.window_size.width, inner.window_size.height)),
+        };
+
+        let mut data = vec![inner.started_at.elapsed().as_secs_f32(), inner.frame_index as f32, resolution.0 as f32, resolution.1 as f32];
+        data.extend_from_slice(&tile_projection);
+
+        let frame_index = inner.frame_index;
+        let shrink_policy = inner.shrink_policy;
+        let (uniform, set_at) = match &mut inner.builtin_uniform { Some(u) => u, _ => return };
+
+        if *set_at == frame_index { return; }
+        *set_at = frame_index;
+
+        let flushes = self.flushes.load(atomic::Ordering::Relaxed);
+        uniform.buffer.set_data(&self.device, &self.queue, &data, flushes, shrink_policy.as_ref().map(|p| (p, frame_index)));
+    }
+
+    // Debug groups show up as labelled regions in graphics debuggers such as
+    // RenderDoc. Each render is recorded into its own command encoder so the
+    // groups that are open are pushed onto every encoder until they are popped.
+
+    pub fn push_debug_group(&self, name: &str) {
+        self.inner.borrow_mut().debug_groups.push(name.to_string());
+    }
+
+    pub fn pop_debug_group(&self) {
+        self.inner.borrow_mut().debug_groups.pop().expect("There is no debug group to pop.");
+    }
+
+    // Viewports that are pushed apply to every render until they are popped and
+    // each one is nested inside the one before it. The viewport passed to render
+    // is nested inside them as well. Recordings are cropped to the outermost one.
+
+    pub fn push_viewport(&self, viewport: &crate::Viewport) {
+        self.inner.borrow_mut().viewports.push(viewport.clone());
+    }
+
+    pub fn pop_viewport(&self) {
+        self.inner.borrow_mut().viewports.pop().expect("There is no viewport to pop.");
+    }
+
+    // Returns the viewport to render with and the viewport to crop recordings to.
+    fn _resolve_viewport(&self, viewport: Option<&crate::Viewport>) -> (Option<crate::Viewport>, Option<crate::Viewport>) {
+        let inner = self.inner.borrow();
+        let size = inner.window_size;
+
+        let window = crate::Viewport { width: size.width as f32, height: size.height as f32, margin_x: 0., margin_y: 0., aspect: None };
+
+        let mut region = None;
+        let mut outermost = None;
+
+        for (i, v) in inner.viewports.iter().chain(viewport).enumerate() {
+            let resolved = v.within(region.as_ref().unwrap_or(&window));
+
+            if i == 0 { outermost = Some(resolved.clone()); }
+            region = Some(resolved);
+        }
+
+        (region, outermost)
+    }
+
+    // Starts a RenderDoc capture that ends when finish_frame is next called.
+    // This does nothing unless the application was launched from RenderDoc.
+
+    pub fn capture_frame(&self) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.capturing { return; }
+
+        self.device.start_capture();
+        inner.capturing = true;
+    }
+
+    pub fn create_command_encoder(&self) -> wgpu::CommandEncoder {
+        let descriptor = wgpu::CommandEncoderDescriptor { label: None };
+        let mut encoder = self.device.create_command_encoder(&descriptor);
+
+        for name in &self.inner.borrow().debug_groups { encoder.push_debug_group(name); }
+
+        encoder
+    }
+
+    pub fn finish_command_encoder(&self, mut encoder: wgpu::CommandEncoder) -> wgpu::CommandBuffer {
+        for _ in &self.inner.borrow().debug_groups { encoder.pop_debug_group(); }
+
+        encoder.finish()
+    }
+
+    pub fn flush(&self) {
+        span!("flush");
+
+        let mut inner = self.inner.borrow_mut();
+
+        // Uploads and recording copies are submitted in their own command buffers
+        // but wgpu only has one queue so they still run in order with the render
+        // work rather than asynchronously. This just lets uploads start earlier.
+        if !inner.transfers.is_empty() { self.queue.submit(inner.transfers.drain(..)); }
+        inner.staging_pool.recycle();
+        self.queue.submit(inner.commands.drain(..));
+        if !inner.readbacks.is_empty() { self.queue.submit(inner.readbacks.drain(..)); }
+
+        self.flushes.fetch_add(1, atomic::Ordering::Relaxed);
+
+        if let Some(reader) = &inner.pixel_reader {
+            reader.initiate_mapping();
+        }
+
+        drop(inner);
+        self.check_memory_budget();
+    }
+
+    // Flushes so that the fence covers everything rendered and uploaded so far,
+    // rather than needing to synchronize with a RenderThread. See FrameFence.
+
+    pub fn frame_fence(&self) -> crate::FrameFence {
+        self.flush();
+        crate::FrameFence::new(&self.queue)
+    }
+
+    // Blocks until the fence is done. This waits for all submitted work, which
+    // includes any work submitted after the fence was created.
+
+    pub fn wait_for_fence(&self, fence: &crate::FrameFence) {
+        if !fence.is_done() { self.device.poll(wgpu::Maintain::Wait); }
+    }
+
+    pub fn wait_for_gpu_idle(&self) {
+        self.flush();
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    pub fn set_attribute(&self, pipeline: &crate::Pipeline, location: usize, data: &[f32]) {
+        let attribute = pipeline.program.attributes.iter().find(|a| a.location == location).unwrap();
+        self.set_buffer_data(&attribute.buffer, data);
+    }
+
+    // For attributes made with attribute_u32. The data is uploaded bit for bit.
+    pub fn set_attribute_u32(&self, pipeline: &crate::Pipeline, location: usize, data: &[u32]) {
+        self.set_attribute(pipeline, location, bytemuck::cast_slice(data));
+    }
+
+    pub fn set_instanced(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &[f32]) {
+        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+
+        let instanced = &pipeline.program.instances[index];
+        self.set_buffer_data(&instanced.buffer, data);
+    }
+
+    // Uploads f64 instance data as offsets from the camera (see CameraRelative).
+
+    pub fn set_instanced_relative(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), camera: &mut crate::CameraRelative, data: &[f64], stride: usize) {
+        let offsets = camera.offsets(data, stride);
+
+        self.set_instanced(pipeline, index_tuple, offsets);
+    }
+
+    // Uploads the instances in the sorter's order (see InstanceSorter).
+
+    pub fn set_instanced_sorted(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), sorter: &mut crate::InstanceSorter, data: &[f32], stride: usize) {
+        let sorted = sorter.sort(data, stride);
+
+        self.set_instanced(pipeline, index_tuple, sorted);
+    }
+
+    // Uploads only the instances that are on screen and returns how many there
+    // are, which is the instance count to render with (see Culling).
+
+    pub fn set_instanced_culled(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), culling: &mut crate::Culling, data: &[f32], stride: usize, radius_index: Option<usize>) -> u32 {
+        let (visible, count) = culling.cull(data, stride, radius_index);
+
+        self.set_instanced(pipeline, index_tuple, visible);
+        count
+    }
+
+    pub fn set_uniform(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &[f32]) {
+        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+        let relative_index = uniform_index(index, &pipeline.program);
+
+        let (uniform, _) = &pipeline.program.uniforms[relative_index];
+        self.set_buffer_data(&uniform.buffer, data);
+    }
+
+    // Like set_uniform but returns an error instead of panicking if there's no
+    // uniform at the index, the data is a different length to the data that was
+    // previously set or it has already been set this frame, e.g. for remote input.
+
+    pub fn try_set_uniform(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &[f32]) -> Result<(), String> {
+        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+        let uniforms = &pipeline.program.uniforms;
+
+        let relative_index = index.checked_sub(pipeline.program.instances.len()).filter(|i| *i < uniforms.len());
+        let (uniform, _) = &uniforms[relative_index.ok_or(format!("there is no uniform at {:?}", index_tuple))?];
+
+        let (previous, len) = { let inner = uniform.buffer.inner.borrow(); (inner.previous, inner.len) };
+        let expected = len / mem::size_of::<f32>();
+
+        if len != 0 && data.len() != expected { return Err(format!("expected {} floats but got {}", expected, data.len())); }
+        if previous == self.flushes.load(atomic::Ordering::Relaxed) { return Err("the uniform has already been set this frame".to_string()); }
+
+        self.set_buffer_data(&uniform.buffer, data);
+        Ok(())
+    }
+
+    pub fn set_uniform_data(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &crate::UniformData) {
+        self.set_uniform(pipeline, index_tuple, &data.to_f32s());
+    }
+
+    // Uploads one uniform per view as an array, e.g. the view-projection matrix of
+    // each eye for a multiview pipeline to index with gl_ViewIndex.
+
+    pub fn set_uniform_per_view(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), views_data: &[&[f32]]) {
+        self.set_uniform(pipeline, index_tuple, &views_data.concat());
+    }
+
+    pub fn set_texture<T: bytemuck::Pod>(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), layers_data: &[&[T]]) {
+        for (layer, data) in layers_data.iter().enumerate() {
+            self.set_part_of_texture(pipeline, index_tuple, (0, 0, layer as u32), (0, 0), data);
+        }
+    }
+
+    pub fn set_part_of_texture<T: bytemuck::Pod>(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), offset: (u32, u32, u32), size: (u32, u32), data: &[T]) {
+        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+        let relative_index = texture_index(index, &pipeline.program);
+
+        let texture = pipeline.inner.borrow().textures[relative_index].0.clone();
+        texture.set_data(&self.queue, offset, size, data);
+    }
+
+    // Copies the data into a staging buffer and queues a copy into the texture
+    // that is submitted ahead of the render work, rather than writing through
+    // the queue like set_data. Call flush_transfers to start large uploads early,
+    // e.g. before recording the frame's render passes. Size (0, 0) is the whole texture.
+    // The copy isn't asynchronous: it runs on the same queue as everything else.
+
+    pub fn upload_texture<T: bytemuck::Pod>(&self, texture: &crate::Texture, offset: (u32, u32, u32), size: (u32, u32), data: &[T]) {
+        span!("upload_texture");
+
+        let mut upload = self.stage_texture_upload(texture, offset, size);
+        upload.copy_rows(bytemuck::cast_slice::<T, u8>(data));
+
+        self.finish_texture_upload(upload);
+    }
+
+    // Like upload_texture but the app writes the data into the mapped staging
+    // buffer itself, saving a copy for large uploads (see TextureUpload).
+
+    pub fn stage_texture_upload(&self, texture: &crate::Texture, offset: (u32, u32, u32), size: (u32, u32)) -> crate::TextureUpload {
+        crate::TextureUpload::new(&self.device, &mut self.inner.borrow_mut().staging_pool, texture, offset, size)
+    }
+
+    pub fn finish_texture_upload(&self, upload: crate::TextureUpload) {
+        let mut encoder = self.create_command_encoder();
+        let staging_buffer = upload.encode(&mut encoder);
+
+        let cbuffer = self.finish_command_encoder(encoder);
+        let mut inner = self.inner.borrow_mut();
+
+        inner.transfers.push(cbuffer);
+        inner.staging_pool.submitting.push(staging_buffer);
+    }
+
+    // Sets the data of a buffer that isn't looked up through a pipeline, e.g. a ParticleSystem's.
+    pub fn set_buffer_data(&self, buffer: &crate::Buffer, data: &[f32]) {
+        let flushes = self.flushes.load(atomic::Ordering::Relaxed);
+        let (shrink_policy, frame_index) = { let inner = self.inner.borrow(); (inner.shrink_policy, inner.frame_index) };
+        let generation = buffer.generation();
+
+        buffer.set_data(&self.device, &self.queue, data, flushes, shrink_policy.as_ref().map(|p| (p, frame_index)));
+        if buffer.generation() != generation { self.check_memory_budget(); }
+    }
+
+    // Buffers grow when more data is set but never shrink unless a policy is set.
+    // With a policy, buffers that have been below its utilization for its number
+    // of frames are recreated at a smaller size the next time their data is set.
+    // Setting a policy also lets compact_buffers copy the buffers on the GPU.
+
+    pub fn set_shrink_policy(&self, shrink_policy: Option<crate::ShrinkPolicy>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.shrink_policy = shrink_policy;
+
+        if shrink_policy.is_none() { return; }
+        inner.memory.retain_live();
+
+        for buffer in inner.memory.buffers.iter().filter_map(|b| b.upgrade()) {
+            crate::Buffer { inner: buffer }.allow_compaction(&self.device);
+        }
+    }
+
+    // Shrinks every buffer made by the renderer to fit the data that was last set,
+    // e.g. after a level is unloaded. Their generations are bumped so pipelines
+    // rebuild their bind groups. Returns the number of buffers that shrank. Only
+    // buffers that can be copied from are compacted, which requires a shrink
+    // policy (see Buffer::allow_compaction).
+
+    pub fn compact_buffers(&self) -> usize {
+        span!("compact_buffers");
+
+        let mut encoder = self.create_command_encoder();
+
+        let count = {
+            let mut inner = self.inner.borrow_mut();
+            inner.memory.retain_live();
 
-        configure_surface(&surface, &device, &window_size, vsync);
+            let buffers = inner.memory.buffers.iter().filter_map(|b| b.upgrade()).map(|inner| crate::Buffer { inner });
+            buffers.filter(|b| b.compact(&self.device, &mut encoder)).count()
+        };
 
-        let frame = Some(surface.get_current_texture().unwrap());
-        let frame_view = Some(frame.as_ref().unwrap().texture.create_view(&wgpu::TextureViewDescriptor::default()));
-        let commands = vec![];
-        let recorder = None;
-        let flushes = atomic::AtomicU64::new(0);
-        let inner = InnerR { window_size, instance, surface, adapter, device, queue, vsync, frame, frame_view, commands, recorder, flushes };
+        // Submit now so that data set later in the frame isn't overwritten by the copies.
+        self.queue.submit(std::iter::once(self.finish_command_encoder(encoder)));
 
-        Self { inner: cell::RefCell::new(inner) }
+        count
     }
 
-    pub fn resize_swap_chain(&self, new_size: &dpi::PhysicalSize<u32>) {
-        if new_size.width == 0 || new_size.height == 0 { return; }
-
-        let mut inner = self.inner.borrow_mut();
+    // Textures and buffers made by the renderer count towards the budget. See
+    // MemoryBudget for when on_out_of_memory is called.
 
-        inner.window_size = *new_size;
-        inner.frame = None;
-        inner.frame_view = None;
+    pub fn set_memory_budget(&self, budget_in_megabytes: f32, on_out_of_memory: crate::OutOfMemoryFunction) {
+        let memory_budget = crate::MemoryBudget::new(&self.device, budget_in_megabytes, on_out_of_memory, &self.inner.borrow().error_handler);
 
-        configure_surface(&inner.surface, &inner.device, &new_size, inner.vsync);
+        self.inner.borrow_mut().memory_budget = Some(memory_budget);
+        self.check_memory_budget();
     }
 
-    pub fn resize_texture(&self, texture: &mut crate::Texture, new_size: (u32, u32, u32)) {
-        texture.resize(&self.device, new_size);
+    // Use this rather than device.on_uncaptured_error so that the handler isn't
+    // replaced by the memory budget's, which only handles out of memory errors.
+
+    pub fn on_uncaptured_error(&self, handler: Box<dyn wgpu::UncapturedErrorHandler>) {
+        let inner = self.inner.borrow();
+        *inner.error_handler.lock().unwrap() = Some(handler);
+
+        if inner.memory_budget.is_none() {
+            let error_handler = Arc::clone(&inner.error_handler);
+            self.device.on_uncaptured_error(Box::new(move |error| crate::forward_error(&error_handler, error)));
+        }
     }
 
-    pub fn render(&self, pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32)) {
-        self.render_to(&pipeline.targets, pipeline, clear_color, viewport, count);
+    pub fn memory_used_in_bytes(&self) -> u64 {
+        self.inner.borrow().memory.used_in_bytes()
     }
 
-    // You can render to different targets than those specified when setting up
-    // the pipeline but it will crash if the texture formats are different.
+    // The budget is taken out of InnerR while on_out_of_memory is called so that
+    // it can call methods on the renderer, e.g. resize_texture.
+    fn check_memory_budget(&self) {
+        let mut guard = self.inner.borrow_mut();
+        let inner = &mut *guard;
 
-    pub fn render_to(&self, targets: &[crate::Target], pipeline: &crate::Pipeline, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>, count: (u32, u32)) {
-        for target in targets {
-            if let crate::Target::Screen = target {
-                self._start_frame()
-            }
-        }
+        let budget = match &mut inner.memory_budget { Some(b) => b, _ => return };
+        inner.memory.retain_live();
 
-        let render_pass = crate::RenderPass::new(&self);
-        let cbuffer = render_pass.render(targets, pipeline, &clear_color, viewport, count);
+        let report = match budget.check(&inner.memory) { Some(r) => r, _ => return };
+        let mut budget = inner.memory_budget.take().unwrap();
+        drop(guard);
 
-        self.inner.borrow_mut().commands.push(cbuffer);
+        (budget.on_out_of_memory)(self, &report);
+
+        let mut inner = self.inner.borrow_mut();
+        if inner.memory_budget.is_none() { inner.memory_budget = Some(budget); }
     }
 
-    fn _start_frame(&self) {
-        if self.frame.is_some() { return; }
+    pub fn flush_transfers(&self) {
+        span!("flush_transfers");
 
         let mut inner = self.inner.borrow_mut();
-        let frame = inner.surface.get_current_texture().unwrap();
+        if inner.transfers.is_empty() { return; }
 
-        inner.frame_view = Some(frame.texture.create_view(&wgpu::TextureViewDescriptor::default()));
-        inner.frame = Some(frame);
+        self.queue.submit(inner.transfers.drain(..));
+        inner.staging_pool.recycle();
     }
 
-    pub fn finish_frame(&self) {
-        self.flush();
+    // Binds a different texture at index_tuple without recreating the pipeline,
+    // e.g. to switch sprite sheets. It must have the same format and filtering.
+
+    pub fn swap_texture_binding(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), texture: &crate::Texture) {
+        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+        let relative_index = texture_index(index, &pipeline.program);
 
+        pipeline.swap_texture(&self.device, index_tuple.0, relative_index, texture);
+    }
+
+    pub fn set_vsync(&self, boolean: bool) {
         let mut inner = self.inner.borrow_mut();
 
-        if let Some(recorder) = &mut inner.recorder {
-            recorder.initiate_buffer_mapping();
-            recorder.process_mapped_buffers();
-            recorder.finish_frame();
+        inner.vsync = boolean;
+        inner.frame = None;
+        inner.frame_view = None;
+
+        if let (true, Some(surface)) = (inner.surface_configured, &self.surface) {
+            configure_surface(surface, &self.adapter, &self.device, &inner.window_size, boolean);
         }
+    }
 
-        if inner.frame.is_none() { return; }
+    // The color used by BlendFactor::Constant, e.g. in Renderer::constant_blend.
+    pub fn set_blend_constant(&self, pipeline: &crate::Pipeline, color: crate::ClearColor) {
+        pipeline.set_blend_constant(color);
+    }
 
-        inner.frame.take().unwrap().present();
-        inner.frame_view = None;
+    pub fn set_blend_mode(&self, pipeline: &crate::Pipeline, blend_mode: crate::BlendMode) {
+        pipeline.set_blend_mode(&self.device, blend_mode);
     }
 
-    pub fn flush(&self) {
-        self.queue.submit(self.inner.borrow_mut().commands.drain(..));
-        self.flushes.fetch_add(1, atomic::Ordering::Relaxed);
+    pub fn set_primitive(&self, pipeline: &crate::Pipeline, primitive: crate::Primitive) {
+        pipeline.set_primitive(&self.device, primitive);
     }
 
-    pub fn set_attribute(&self, pipeline: &crate::Pipeline, location: usize, data: &[f32]) {
-        let attribute = pipeline.program.attributes.iter().find(|a| a.location == location).unwrap();
-        let flushes = self.flushes.load(atomic::Ordering::Relaxed);
+    pub fn set_rasterization(&self, pipeline: &crate::Pipeline, rasterization: crate::Rasterization) {
+        if !self.supports_rasterization(&rasterization) { panic!("The rasterization isn't supported by this adapter. Please check supports_rasterization first."); }
+        pipeline.set_rasterization(&self.device, rasterization);
+    }
 
-        attribute.buffer.set_data(&self.device, &self.queue, data, flushes);
+    pub fn set_msaa_samples(&self, pipeline: &crate::Pipeline, msaa_samples: u32) {
+        pipeline.set_msaa_samples(&self.device, msaa_samples);
     }
 
-    pub fn set_instanced(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &[f32]) {
-        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
+    // Pipelines with transparent_oit don't need their draws to be sorted. They
+    // accumulate into shared targets that are blended onto a target by calling
+    // composite_transparency after they've rendered (see Transparency).
 
-        let instanced = &pipeline.program.instances[index];
-        let flushes = self.flushes.load(atomic::Ordering::Relaxed);
+    pub fn set_transparent_oit(&self, pipeline: &crate::Pipeline, transparent_oit: bool) {
+        pipeline.set_transparent_oit(&self.device, transparent_oit);
+    }
 
-        instanced.buffer.set_data(&self.device, &self.queue, data, flushes);
+    // Stops the pipeline drawing into the recordings it's part of, e.g. for a debug
+    // overlay that should only appear on the screen (see Pipeline::set_exclude_from_recording).
+
+    pub fn set_exclude_from_recording(&self, pipeline: &crate::Pipeline, exclude_from_recording: bool) {
+        pipeline.set_exclude_from_recording(&self.device, exclude_from_recording);
     }
 
-    pub fn set_uniform(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), data: &[f32]) {
-        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
-        let relative_index = uniform_index(index, &pipeline.program);
+    pub fn composite_transparency(&self, target: &crate::Target) {
+        if let crate::Target::Screen = target {
+            self._start_frame()
+        }
 
-        let (uniform, _) = &pipeline.program.uniforms[relative_index];
-        let flushes = self.flushes.load(atomic::Ordering::Relaxed);
+        let view = target.view(&self);
+        let mut encoder = self.create_command_encoder();
+
+        let mut inner = self.inner.borrow_mut();
+        let transparency = match &mut inner.transparency { Some(t) => t, _ => return };
+
+        if !transparency.composite(&self.device, &mut encoder, &view, target.format()) { return; }
+        drop(inner);
 
-        uniform.buffer.set_data(&self.device, &self.queue, data, flushes);
+        let cbuffer = self.finish_command_encoder(encoder);
+        self.inner.borrow_mut().commands.push(cbuffer);
     }
 
-    pub fn set_texture<T: bytemuck::Pod>(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), layers_data: &[&[T]]) {
-        for (layer, data) in layers_data.iter().enumerate() {
-            self.set_part_of_texture(pipeline, index_tuple, (0, 0, layer as u32), (0, 0), data);
-        }
+    // Pipelines that share a depth buffer test against each other's geometry.
+    pub fn set_depth(&self, pipeline: &crate::Pipeline, depth: Option<crate::Depth>) {
+        pipeline.set_depth(&self.device, depth);
     }
 
-    pub fn set_part_of_texture<T: bytemuck::Pod>(&self, pipeline: &crate::Pipeline, index_tuple: (usize, usize), offset: (u32, u32, u32), size: (u32, u32), data: &[T]) {
-        let index = index_tuple.0 * BINDINGS_PER_GROUP + index_tuple.1;
-        let relative_index = texture_index(index, &pipeline.program);
+    pub fn set_stencil(&self, pipeline: &crate::Pipeline, stencil: Option<crate::Stencil>) {
+        pipeline.set_stencil(&self.device, stencil);
+    }
 
-        let (texture, _) = &pipeline.program.textures[relative_index];
-        texture.set_data(&self.queue, offset, size, data);
+    pub fn set_stencil_reference(&self, pipeline: &crate::Pipeline, reference: u32) {
+        pipeline.set_stencil_reference(reference);
     }
 
-    pub fn set_vsync(&self, boolean: bool) {
-        let mut inner = self.inner.borrow_mut();
+    // Layered stereo, which needs supports_multiview (see Pipeline::set_multiview).
 
-        inner.vsync = boolean;
-        inner.frame = None;
-        inner.frame_view = None;
+    pub fn set_multiview(&self, pipeline: &crate::Pipeline, views: Option<u32>) {
+        if views.is_some() && !self.supports_multiview() { panic!("Multiview isn't supported by this adapter. Please use render_side_by_side instead."); }
+        pipeline.set_multiview(&self.device, views);
+    }
 
-        configure_surface(&inner.surface, &inner.device, &inner.window_size, boolean);
+    // Several recordings can run at once, e.g. of the screen and an offscreen
+    // target, each with their own pipelines, buffer budget and process function.
+    // A pipeline in more than one recording has an extra output per recording,
+    // ordered by RecordingId, so its shader must write to each of them.
+
+    pub fn start_recording(&self, pipelines: &[&crate::Pipeline], clear_color: Option<crate::ClearColor>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> crate::RecordingId {
+        self.start_recording_with_planes(pipelines, clear_color, vec![], max_buffer_size_in_megabytes, process_function)
     }
 
-    pub fn set_msaa_samples(&self, pipeline: &crate::Pipeline, msaa_samples: u32) {
-        pipeline.set_msaa_samples(&self.device, msaa_samples);
+    // Also captures a plane per format each frame, e.g. depth or normals for
+    // compositing, into VideoFrame::planes. Each recording's output is followed by
+    // its planes so the shader must write the recording then each plane in order.
+
+    pub fn start_recording_with_planes(&self, pipelines: &[&crate::Pipeline], clear_color: Option<crate::ClearColor>, plane_formats: Vec<crate::Format>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> crate::RecordingId {
+        self._start_recording(pipelines, clear_color, plane_formats, crate::RecordingArea::Viewport, max_buffer_size_in_megabytes, process_function)
     }
 
-    pub fn start_recording(&self, pipelines: &[&crate::Pipeline], clear_color: Option<crate::ClearColor>, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame)>) {
+    // Records the full target including the letterbox margins, or the area inside
+    // them scaled to a logical resolution, rather than the viewport's area at the
+    // size it was drawn. See RecordingArea.
+
+    pub fn start_recording_with_area(&self, pipelines: &[&crate::Pipeline], clear_color: Option<crate::ClearColor>, area: crate::RecordingArea, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> crate::RecordingId {
+        self._start_recording(pipelines, clear_color, vec![], area, max_buffer_size_in_megabytes, process_function)
+    }
+
+    fn _start_recording(&self, pipelines: &[&crate::Pipeline], clear_color: Option<crate::ClearColor>, plane_formats: Vec<crate::Format>, area: crate::RecordingArea, max_buffer_size_in_megabytes: f32, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> crate::RecordingId {
         let max_size_in_bytes = (max_buffer_size_in_megabytes * 1024. * 1024.) as usize;
-        let recorder = crate::VideoRecorder::new(&self, clear_color, max_size_in_bytes, process_function);
-        self.inner.borrow_mut().recorder = Some(recorder);
+        let recorder = crate::VideoRecorder::new(&self, clear_color, &plane_formats, area, max_size_in_bytes, process_function);
+
+        let mut inner = self.inner.borrow_mut();
+        let recording_id = crate::RecordingId(inner.next_recording_id);
+
+        inner.next_recording_id += 1;
+        inner.recorders.push((recording_id, recorder));
+        drop(inner);
 
         for (i, pipeline) in pipelines.iter().enumerate() {
             let is_last = i == pipelines.len() - 1;
             let position = if is_last { crate::RecordingPosition::Last } else { crate::RecordingPosition::NotLast };
-            pipeline.set_stream_position(&self.device, position);
+            pipeline.set_stream_position(&self.device, recording_id, position, &plane_formats);
         }
+
+        recording_id
     }
 
-    pub fn stop_recording(&self, pipelines: &[&crate::Pipeline]) {
-        self.inner.borrow_mut().recorder = None;
+    pub fn stop_recording(&self, recording_id: crate::RecordingId, pipelines: &[&crate::Pipeline]) {
+        self.inner.borrow_mut().recorders.retain(|(id, _)| *id != recording_id);
 
         for pipeline in pipelines {
             let position = crate::RecordingPosition::None;
-            pipeline.set_stream_position(&self.device, position);
+            pipeline.set_stream_position(&self.device, recording_id, position, &[]);
+        }
+    }
+
+    // Records at half resolution instead of dropping frames when the recording's
+    // buffers are full. VideoFrame::scale_factor is 0.5 for those frames and the
+    // PngEncoder scales them back up so that exports keep the same resolution.
+    pub fn set_adaptive_quality(&self, recording_id: crate::RecordingId, enabled: bool) {
+        self.inner.borrow().recorder(recording_id).set_adaptive_quality(enabled);
+    }
+
+    // Changes the (x, y, width, height) region of the target that is recorded from
+    // the next frame onwards, e.g. as a user drags out a selection with the cursor.
+    // Each VideoFrame stores the region it was recorded from. None records it all.
+    pub fn set_recording_region(&self, recording_id: crate::RecordingId, region: Option<(u32, u32, u32, u32)>) {
+        self.inner.borrow().recorder(recording_id).set_region(region);
+    }
+
+    // XORs each frame with a keyframe on the GPU so that unchanged pixels are zero
+    // and compress well. A keyframe is stored every keyframe_interval frames. The
+    // Decompressor reconstructs the frames so this is meant for the Compressor and
+    // ReplayBuffer. Other process functions receive the XORed image_data.
+    pub fn set_delta_encoding(&self, recording_id: crate::RecordingId, keyframe_interval: Option<usize>) {
+        self.inner.borrow().recorder(recording_id).set_delta_encoding(&self.device, keyframe_interval);
+    }
+
+    // Limits how quickly frames are copied into the recording's readback buffers,
+    // e.g. Some(2.) for 2 GB/s, by spreading each frame's copy over the following
+    // frames. Every frame is still captured but is processed a few frames later.
+    pub fn set_readback_budget(&self, recording_id: crate::RecordingId, gigabytes_per_second: Option<f32>) {
+        let bytes_per_second = gigabytes_per_second.map(|g| g as f64 * 1024. * 1024. * 1024.);
+        self.inner.borrow().recorder(recording_id).set_readback_budget(bytes_per_second);
+    }
+
+    // Readbacks are submitted after the frame's renders so the snapshots are ready.
+    fn _copy_scheduled_readbacks(&self) {
+        let mut encoder = self.create_command_encoder();
+        let copied = self.inner.borrow().recorders.iter().fold(false, |copied, (_, r)| r.copy_scheduled_readbacks(&mut encoder) || copied);
+
+        if copied {
+            let cbuffer = self.finish_command_encoder(encoder);
+            self.inner.borrow_mut().readbacks.push(cbuffer);
         }
     }
 
+    // Writes every nth frame (starting with the first) to frame_000001.png,
+    // frame_000002.png, etc. in the directory, e.g. for sprite sheets. Frames are
+    // encoded in a thread pool. Call stop_recording to finish the capture. Returns
+    // an error if the directory can't be created. Images that can't be written are
+    // printed as warnings.
+    #[cfg(feature="frame_to_png")]
+    pub fn capture_every(&self, n: usize, directory: &str, pipelines: &[&crate::Pipeline]) -> std::io::Result<crate::RecordingId> {
+        let num_threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        std::fs::create_dir_all(directory)?;
+
+        let directory = directory.to_string();
+        let naming_function = Arc::new(move |f: &crate::VideoFrame| std::path::Path::new(&directory).join(format!("frame_{:06}.png", f.frame_number)).to_string_lossy().into_owned());
+        let png_pool = crate::PngEncoderPool::to_files(naming_function, num_threads, num_threads * 2, crate::PngOptions::default());
+
+        let mut images_written = 0;
+
+        let recording_id = self.start_recording(pipelines, None, CAPTURE_BUFFER_IN_MEGABYTES, Box::new(move |mut video_frame| {
+            if (video_frame.frame_number - 1) % n.max(1) != 0 { return; }
+
+            // The pool prints a warning and skips frames that were dropped.
+            if video_frame.image_data.is_none() { return png_pool.encode(video_frame); }
+
+            // Number the images sequentially rather than by frame.
+            images_written += 1;
+            video_frame.frame_number = images_written;
+
+            png_pool.encode(video_frame);
+        }));
+
+        // Frames between captures aren't copied from the GPU at all.
+        self.inner.borrow().recorder(recording_id).set_capture_interval(n);
+        Ok(recording_id)
+    }
+
     pub fn adapter_info(&self) -> wgpu::AdapterInfo {
         self.adapter.get_info()
     }
 
+    pub fn backend(&self) -> wgpu::Backend {
+        self.adapter.get_info().backend
+    }
+
+    // Lists the adapters that were considered, e.g. to include in bug reports.
+    pub fn diagnostics(&self) -> crate::Diagnostics {
+        crate::Diagnostics::new(&self.instance, self.surface.as_ref(), &self.adapter)
+    }
+
     pub fn pipeline(&self, program: crate::Program, blend_mode: crate::BlendMode, primitive: crate::Primitive, msaa_samples: u32, targets: Vec<crate::Target>) -> crate::Pipeline {
-        let window_size = (self.window_size.width, self.window_size.height);
+        let window_size = (self.window_size().width, self.window_size().height);
         crate::Pipeline::new(&self.device, window_size, program, blend_mode, primitive, msaa_samples, targets)
     }
 
+    // Stores the pipeline under a name so other modules can look it up instead of
+    // passing it around. Registering a name again replaces the previous pipeline.
+    pub fn register_pipeline(&self, name: &str, pipeline: crate::Pipeline) -> rc::Rc<crate::Pipeline> {
+        let pipeline = rc::Rc::new(pipeline);
+        let mut inner = self.inner.borrow_mut();
+
+        inner.named_pipelines.retain(|(n, _)| n != name);
+        inner.named_pipelines.push((name.to_string(), rc::Rc::clone(&pipeline)));
+
+        pipeline
+    }
+
+    pub fn pipeline_named(&self, name: &str) -> Option<rc::Rc<crate::Pipeline>> {
+        self.inner.borrow().named_pipelines.iter().find(|(n, _)| n == name).map(|(_, p)| rc::Rc::clone(p))
+    }
+
+    pub fn unregister_pipeline(&self, name: &str) -> Option<rc::Rc<crate::Pipeline>> {
+        let mut inner = self.inner.borrow_mut();
+        let index = inner.named_pipelines.iter().position(|(n, _)| n == name)?;
+
+        Some(inner.named_pipelines.remove(index).1)
+    }
+
+    pub fn pipeline_names(&self) -> Vec<String> {
+        self.inner.borrow().named_pipelines.iter().map(|(n, _)| n.clone()).collect()
+    }
+
+    // The size is set from the targets when it is first rendered with.
+    pub fn depth_buffer(&self, msaa_samples: u32, with_stencil: bool) -> crate::DepthBuffer {
+        let window_size = self.window_size();
+        crate::DepthBuffer::new(&self.device, (window_size.width, window_size.height), msaa_samples, with_stencil)
+    }
+
+    pub fn bake_bundle(&self, draws: &[crate::Draw]) -> crate::Bundle {
+        crate::Bundle::new(&self, draws)
+    }
+
     pub fn attribute(&self, location: usize, size: u32) -> crate::Attribute {
-        crate::Attribute::new(&self.device, location, size)
+        let attribute = crate::Attribute::new(&self.device, location, size);
+        self.track_buffer(&attribute.buffer);
+
+        attribute
+    }
+
+    pub fn attribute_u32(&self, location: usize, size: u32) -> crate::Attribute {
+        let attribute = crate::Attribute::new_u32(&self.device, location, size);
+        self.track_buffer(&attribute.buffer);
+
+        attribute
+    }
+
+    pub fn pulled_attribute(&self, location: usize, size: u32) -> crate::Attribute {
+        let attribute = crate::Attribute::new_pulled(&self.device, location, size);
+        self.track_buffer(&attribute.buffer);
+
+        attribute
     }
 
     pub fn instanced(&self) -> crate::Instanced {
-        crate::Instanced::new(&self.device)
+        let instanced = crate::Instanced::new(&self.device);
+        self.track_buffer(&instanced.buffer);
+
+        instanced
+    }
+
+    pub fn storage_buffer(&self, access: crate::StorageAccess, visibility: crate::Visibility) -> crate::Instanced {
+        let storage_buffer = crate::Instanced::new_storage_buffer(&self.device, access, visibility);
+        self.track_buffer(&storage_buffer.buffer);
+
+        storage_buffer
     }
 
     pub fn uniform(&self) -> crate::Uniform {
-        crate::Uniform::new(&self.device)
+        let uniform = crate::Uniform::new(&self.device);
+        self.track_buffer(&uniform.buffer);
+
+        uniform
+    }
+
+    // These upload the data when they're created so that pipelines never render
+    // uninitialized buffers or textures, e.g. on the first frame. The initial data
+    // doesn't count as this frame's set_data so it can be replaced before rendering.
+
+    pub fn instanced_with_data(&self, data: &[f32]) -> crate::Instanced {
+        let instanced = self.instanced();
+        self.set_initial_data(&instanced.buffer, data);
+
+        instanced
+    }
+
+    pub fn uniform_with_data(&self, data: &[f32]) -> crate::Uniform {
+        let uniform = self.uniform();
+        self.set_initial_data(&uniform.buffer, data);
+
+        uniform
+    }
+
+    fn set_initial_data(&self, buffer: &crate::Buffer, data: &[f32]) {
+        self.set_buffer_data(buffer, data);
+        buffer.inner.borrow_mut().previous = u64::MAX;
+    }
+
+    // Buffers are tracked so that compact_buffers and the memory budget can find
+    // them. They are weak references so they are freed when pipelines are dropped.
+    fn track_buffer(&self, buffer: &crate::Buffer) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.shrink_policy.is_some() { buffer.allow_compaction(&self.device); }
+        inner.memory.track_buffer(buffer);
+    }
+
+    // A uniform that the renderer sets once per frame for shadertoy-style effects.
+    // Add it to a program like any other uniform and declare it in GLSL as:
+    //
+    // layout(set=X, binding=Y) uniform Builtin { float u_time; float u_frame; vec2 u_resolution; mat4 u_tile; };
+    //
+    // u_time is in seconds since the renderer was created and u_frame counts frames.
+    // u_tile is the identity except in a TiledRender, where it maps the tile being
+    // rendered to the target and u_resolution is the size of the whole image. Apply
+    // it after the projection (gl_Position = u_tile * projection * position).
+
+    pub fn builtin_uniform(&self) -> crate::Uniform {
+        let mut inner = self.inner.borrow_mut();
+        if inner.builtin_uniform.is_none() {
+            let uniform = crate::Uniform::new(&self.device);
+
+            if inner.shrink_policy.is_some() { uniform.buffer.allow_compaction(&self.device); }
+            inner.memory.track_buffer(&uniform.buffer);
+            inner.builtin_uniform = Some((uniform, u64::MAX));
+        }
+
+        inner.builtin_uniform.as_ref().unwrap().0.clone()
+    }
+
+    // Sets u_tile for each tile of a TiledRender and resets it afterwards. The
+    // builtin uniform is set again on the next render even within the same frame.
+    pub(crate) fn set_tile(&self, tile: Option<([f32; 16], (u32, u32))>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.tile = tile;
+
+        if let Some((_, set_at)) = &mut inner.builtin_uniform { *set_at = u64::MAX; }
     }
 
     pub fn texture(&self, width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool) -> crate::Texture {
-        crate::Texture::new(&self.device, (width, height, layers), filter_mode, format, 1, renderable, copyable, with_sampler)
+        let texture = crate::Texture::new(&self.device, (width, height, layers), filter_mode, format, 1, renderable, copyable, with_sampler);
+
+        self.inner.borrow_mut().memory.track_texture(&texture);
+        self.check_memory_budget();
+
+        texture
+    }
+
+    pub fn texture_with_data<T: bytemuck::Pod>(&self, width: u32, height: u32, layers: u32, filter_mode: crate::FilterMode, format: crate::Format, renderable: bool, copyable: bool, with_sampler: bool, layers_data: &[&[T]]) -> crate::Texture {
+        let bytes_per_layer = (width * height * format.bytes_per_texel()) as usize;
+
+        if layers_data.len() != layers as usize { panic!("The texture has {} layers but data was provided for {}.", layers, layers_data.len()); }
+        if let Some(l) = layers_data.iter().position(|data| mem::size_of_val(*data) != bytes_per_layer) {
+            panic!("Layer {} has {} bytes of data but {} were expected.", l, mem::size_of_val(layers_data[l]), bytes_per_layer);
+        }
+
+        let texture = self.texture(width, height, layers, filter_mode, format, renderable, copyable, with_sampler);
+
+        for (layer, data) in layers_data.iter().enumerate() {
+            texture.set_data(&self.queue, (0, 0, layer as u32), (0, 0), data);
+        }
+
+        texture
     }
 
     pub fn program(&self, vert: &[u8], frag: &[u8], attributes: crate::Attributes, instances: crate::Instances, uniforms: crate::Uniforms, textures: crate::Textures) -> crate::Program {
         crate::Program::new(&self.device, vert, frag, attributes, instances, uniforms, textures)
     }
 
+    pub fn program_wgsl(&self, vert: &str, frag: &str, attributes: crate::Attributes, instances: crate::Instances, uniforms: crate::Uniforms, textures: crate::Textures) -> crate::Program {
+        crate::Program::new_wgsl(&self.device, vert, frag, attributes, instances, uniforms, textures)
+    }
+
+    // See Material. Only the textured material takes a texture.
+    pub fn material_program(&self, material: crate::Material, texture: Option<crate::Texture>) -> crate::Program {
+        material.program(self, texture)
+    }
+
+    pub fn program_with_texture_arrays(&self, vert: &[u8], frag: &[u8], attributes: crate::Attributes, instances: crate::Instances, uniforms: crate::Uniforms, textures: crate::Textures, texture_arrays: crate::TextureArrays) -> crate::Program {
+        crate::Program::new_with_texture_arrays(&self.device, vert, frag, attributes, instances, uniforms, textures, texture_arrays)
+    }
+
+    pub fn texture_array(&self, textures: Vec<crate::Texture>) -> crate::TextureArray {
+        crate::TextureArray::new(textures)
+    }
+
+    pub fn supports_texture_arrays(&self) -> bool {
+        self.device.features().contains(wgpu::Features::TEXTURE_BINDING_ARRAY)
+    }
+
+    pub fn supports_rasterization(&self, rasterization: &crate::Rasterization) -> bool {
+        self.device.features().contains(rasterization.required_features())
+    }
+
+    // Draws nothing with each pipeline off-screen so that drivers which compile
+    // pipelines on first use do so now, e.g. while loading rather than mid-game.
+    // Waits for the GPU so that the work has been done when this returns.
+    pub fn warm_up(&self, pipelines: &[&crate::Pipeline]) {
+        let window_size = self.window_size();
+        let mut encoder = self.create_command_encoder();
+
+        for pipeline in pipelines {
+            pipeline.recreate_on_buffer_or_texture_resize(&self.device, (window_size.width, window_size.height), &pipeline.targets);
+            pipeline.warm_up(&self.device, &mut encoder);
+        }
+
+        self.queue.submit(Some(self.finish_command_encoder(encoder)));
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    pub fn supports_multiview(&self) -> bool {
+        self.device.features().contains(wgpu::Features::MULTIVIEW)
+    }
+
+    #[cfg(feature="pipeline_statistics")]
+    pub fn supports_pipeline_statistics(&self) -> bool {
+        self.device.features().contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+    }
+
+    // Returns the statistics of the latest frame that has been read back. See PipelineStatistics.
+    #[cfg(feature="pipeline_statistics")]
+    pub fn frame_timings(&self) -> Option<crate::FrameTimings> {
+        self.device.poll(wgpu::Maintain::Poll);
+
+        let mut inner = self.inner.borrow_mut();
+        let statistics = inner.statistics.as_mut()?;
+
+        statistics.collect_mapped();
+        statistics.latest.clone()
+    }
+
+    // The resolve is submitted with the readbacks so it runs after the frame's renders.
+    #[cfg(feature="pipeline_statistics")]
+    fn _resolve_statistics(&self) {
+        if self.inner.borrow().statistics.is_none() { return; }
+
+        let mut encoder = self.create_command_encoder();
+        let mut inner = self.inner.borrow_mut();
+        let frame_index = inner.frame_index;
+
+        inner.statistics.as_mut().unwrap().resolve(&self.device, &mut encoder, frame_index);
+
+        drop(inner);
+        let cbuffer = self.finish_command_encoder(encoder);
+        self.inner.borrow_mut().readbacks.push(cbuffer);
+    }
+
     pub fn viewport(&self, aspect_x: f32, aspect_y: f32) -> crate::Viewport {
-        crate::Viewport::new(aspect_x, aspect_y, self.window_size.width as f32, self.window_size.height as f32)
+        let window_size = self.window_size();
+        crate::Viewport::new(aspect_x, aspect_y, window_size.width as f32, window_size.height as f32)
     }
 
     pub fn screen_target() -> crate::Target {
@@ -285,6 +1686,10 @@ impl<'a> Renderer<'a> {
         crate::BlendMode::pre_multiplied_alpha()
     }
 
+    pub fn constant_blend() -> crate::BlendMode {
+        crate::BlendMode::constant()
+    }
+
     pub fn triangle_primitive() -> crate::Primitive {
         crate::Primitive::Triangle
     }
@@ -293,14 +1698,44 @@ impl<'a> Renderer<'a> {
         crate::Primitive::TriangleStrip
     }
 
+    pub fn triangle_fan_primitive() -> crate::Primitive {
+        crate::Primitive::TriangleFan
+    }
+
+    pub fn quads_primitive() -> crate::Primitive {
+        crate::Primitive::Quads
+    }
+
     pub fn clear_color(red: f32, green: f32, blue: f32, alpha: f32) -> crate::ClearColor {
         crate::ClearColor::new(red, green, blue, alpha)
     }
+
+    pub fn clear_color_from_hex(hex: &str) -> crate::ClearColor {
+        crate::Color::from_srgb_hex(hex).into()
+    }
+
+    pub fn try_clear_color_from_hex(hex: &str) -> Option<crate::ClearColor> {
+        crate::Color::try_from_srgb_hex(hex).map(Into::into)
+    }
+}
+
+// The window's inner size isn't updated until the resize event so work out
+// the new size from the video mode or monitor instead.
+pub(crate) fn fullscreen_size(window: &window::Window, mode: &Option<window::Fullscreen>) -> dpi::PhysicalSize<u32> {
+    match mode {
+        Some(window::Fullscreen::Exclusive(video_mode)) => video_mode.size(),
+        Some(window::Fullscreen::Borderless(Some(monitor))) => monitor.size(),
+        Some(window::Fullscreen::Borderless(None)) => window.current_monitor().map(|m| m.size()).unwrap_or(window.inner_size()),
+        None => window.inner_size(),
+    }
 }
 
-fn configure_surface(surface: &wgpu::Surface, device: &wgpu::Device, window_size: &dpi::PhysicalSize<u32>, vsync: bool) {
+fn configure_surface(surface: &wgpu::Surface, adapter: &wgpu::Adapter, device: &wgpu::Device, window_size: &dpi::PhysicalSize<u32>, vsync: bool) {
     let format = crate::Target::Screen.format();
 
+    // Allow the screen to be copied from (for grab passes) if the surface supports it.
+    let copy_src = surface.get_capabilities(adapter).usages & wgpu::TextureUsages::COPY_SRC;
+
     let present_mode = match vsync {
         true => wgpu::PresentMode::AutoVsync,
         false => wgpu::PresentMode::AutoNoVsync,
@@ -309,7 +1744,7 @@ fn configure_surface(surface: &wgpu::Surface, device: &wgpu::Device, window_size
     surface.configure(device, &wgpu::SurfaceConfiguration {
         width: window_size.width,
         height: window_size.height,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | copy_src,
         format: format.texture_format(),
         view_formats: vec![format.texture_format()],
         present_mode,
@@ -318,9 +1753,45 @@ fn configure_surface(surface: &wgpu::Surface, device: &wgpu::Device, window_size
     });
 }
 
-fn get_instance() -> wgpu::Instance {
+fn create_grab_texture(device: &wgpu::Device, size: (u32, u32, u32), format: crate::Format) -> crate::Texture {
+    let filter_mode = match format { crate::Format::RgbaF32 => crate::FilterMode::Nearest, _ => crate::FilterMode::Linear };
+    let msaa_samples = 1;
+    let renderable = false;
+    let copyable = false;
+    let with_sampler = true;
+
+    crate::Texture::new(device, size, filter_mode, format, msaa_samples, renderable, copyable, with_sampler)
+}
+
+fn create_headless_texture(device: &wgpu::Device, size: (u32, u32)) -> wgpu::Texture {
+    let format = crate::Target::Screen.format().texture_format();
+
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+fn create_screenshot_texture(device: &wgpu::Device, size: (u32, u32, u32)) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest; // Not used
+    let format = crate::Format::BgraU8;
+    let msaa_samples = 1;
+    let renderable = false;
+    let copyable = true;
+    let with_sampler = false;
+
+    crate::Texture::new(device, size, filter_mode, format, msaa_samples, renderable, copyable, with_sampler)
+}
+
+fn get_instance(backends: wgpu::Backends) -> wgpu::Instance {
     let descriptor = wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
+        backends,
         dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
         flags: wgpu::InstanceFlags::default(),
         gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
@@ -329,23 +1800,39 @@ fn get_instance() -> wgpu::Instance {
     wgpu::Instance::new(descriptor)
 }
 
-fn get_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> wgpu::Adapter {
+fn window_sized(window_size: &dpi::PhysicalSize<u32>, scale: f32, layers: u32) -> (u32, u32, u32) {
+    let width = (window_size.width as f32 * scale).round().max(1.) as u32;
+    let height = (window_size.height as f32 * scale).round().max(1.) as u32;
+
+    (width, height, layers)
+}
+
+fn get_adapter(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>) -> Option<wgpu::Adapter> {
     let options = wgpu::RequestAdapterOptions {
         power_preference: wgpu::PowerPreference::HighPerformance,
         force_fallback_adapter: false,
-        compatible_surface: Some(surface)
+        compatible_surface: surface,
     };
 
     let future = instance.request_adapter(&options);
 
-    executor::block_on(future).unwrap()
+    executor::block_on(future)
 }
 
 fn get_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    // Enable texture arrays (indexed per instance) if the adapter supports them.
+    let optional_features = wgpu::Features::TEXTURE_BINDING_ARRAY | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING;
+    let optional_features = optional_features | wgpu::Features::MULTIVIEW;
+    let optional_features = optional_features | wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT | wgpu::Features::CONSERVATIVE_RASTERIZATION | wgpu::Features::DEPTH_CLIP_CONTROL;
+
+    #[cfg(feature="pipeline_statistics")]
+    let optional_features = optional_features | wgpu::Features::PIPELINE_STATISTICS_QUERY;
+    let max_sampled_textures_per_shader_stage = adapter.limits().max_sampled_textures_per_shader_stage;
+
     let descriptor = wgpu::DeviceDescriptor {
         label: None,
-        required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
-        required_limits: wgpu::Limits::default(),
+        required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE | (adapter.features() & optional_features),
+        required_limits: wgpu::Limits { max_sampled_textures_per_shader_stage, ..wgpu::Limits::default() },
     };
 
     let future = adapter.request_device(&descriptor, None);
@@ -377,10 +1864,20 @@ fn texture_index(index: usize, program: &crate::Program) -> usize {
     panic!("Tried to a get a texture but nothing is in that slot.");
 }
 
-impl<'a> ops::Deref for Renderer<'a> {
-    type Target = InnerR<'a>;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.inner.try_borrow_unguarded().unwrap() }
+impl ScreenFrame {
+    pub fn texture(&self) -> &wgpu::Texture {
+        match self {
+            Self::Surface(frame) => &frame.texture,
+            Self::Headless(texture) => texture,
+        }
     }
 }
+
+
+// Capturing is frame-perfect so allow more frames to queue up than usual.
+#[cfg(feature="frame_to_png")]
+const CAPTURE_BUFFER_IN_MEGABYTES: f32 = 1024.;
+
+const NO_ADAPTER: &str = "Failed to find a GPU adapter. Try setting RENDERER_BACKEND to another backend.";
+
+const IDENTITY: [f32; 16] = [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.];
@@ -0,0 +1,212 @@
+use std::{collections::VecDeque, fs, path::Path, thread, time};
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use crossbeam_channel::{Sender, Receiver};
+use lzzzz::lz4f;
+
+// Keeps the most recent frames compressed in memory rather than on disk so that
+// the last N seconds can be saved on demand, e.g. when the user presses a key.
+// Frames are evicted once max_size_in_bytes or max_duration is exceeded.
+
+pub struct ReplayBuffer {
+    pub max_size_in_bytes: usize,
+    pub max_duration: Option<time::Duration>,
+    pub lz4_compression_level: u8,
+    pub threads: Vec<thread::JoinHandle<()>>,
+    pub sender: Option<Sender<(crate::VideoFrame, time::Instant)>>,
+    pub ring: Arc<Mutex<Ring>>,
+}
+
+#[derive(Default)]
+pub struct Ring {
+    pub packets: VecDeque<Arc<Packet>>, // Shared so save_replay can snapshot them cheaply.
+    pub size_in_bytes: usize,
+    pub evicted_up_to: usize,
+}
+
+pub struct Packet {
+    pub frame_number: usize,
+    pub captured_at: time::Instant,
+    pub video_frame_bytes: Vec<u8>,
+    pub compressed_image_data: Option<Vec<u8>>,
+}
+
+impl ReplayBuffer {
+    pub fn new(max_size_in_megabytes: f32, max_duration: Option<time::Duration>, lz4_compression_level: u8) -> Self {
+        let is_valid_level = lz4_compression_level as i32 <= lz4f::CLEVEL_MAX;
+        assert!(is_valid_level, "Please choose a compression level in the range 0..={}", lz4f::CLEVEL_MAX);
+
+        let max_size_in_bytes = (max_size_in_megabytes * 1024. * 1024.) as usize;
+        let ring = Arc::new(Mutex::new(Ring::default()));
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        let threads = (0..num_cpus::get()).map(|_| {
+            spawn_thread(&receiver, &ring, max_size_in_bytes, max_duration, lz4_compression_level)
+        }).collect();
+
+        Self { max_size_in_bytes, max_duration, lz4_compression_level, threads, sender: Some(sender), ring }
+    }
+
+    pub fn push(&self, video_frame: crate::VideoFrame) {
+        self.sender.as_ref().unwrap().send((video_frame, time::Instant::now())).unwrap();
+    }
+
+    // Writes the frames currently in the ring to a new .sz file in the directory
+    // using the same packet format as the Compressor so that the Decompressor can
+    // read it. Frames are renumbered from 1. Returns the filename's timestamp.
+    // Delta encoded frames whose keyframe was evicted are written as dropped.
+    // Errors are returned rather than panicking, e.g. if the disk is full.
+
+    pub fn save_replay(&self, directory: &str) -> io::Result<String> {
+        // Take a snapshot rather than holding the lock during file I/O, which
+        // would block the worker threads from inserting frames.
+        let packets = self.ring.lock().unwrap().packets.iter().cloned().collect::<Vec<_>>();
+
+        fs::create_dir_all(directory)?;
+
+        let timestamp = crate::compressor::generate_timestamp();
+        let filename = format!("{}--0.sz", timestamp);
+        let path = Path::new(directory).join(filename);
+
+        let mut file_writer = BufWriter::new(fs::File::create(path)?);
+        crate::compressor::write_file_header(&mut file_writer)?;
+
+        let compress_config = crate::compressor::compression_config(self.lz4_compression_level);
+        let mut writer = lz4f::WriteCompressor::new(file_writer, compress_config)?;
+
+        let config = crate::compressor::encoding_config();
+
+        let first_frame_number = match packets.first() { Some(p) => p.frame_number, _ => return Ok(timestamp) };
+        let mut image_data_bytes = vec![];
+
+        for (i, packet) in packets.iter().enumerate() {
+            // Stop at the first gap in case a worker thread is still compressing a frame.
+            if packet.frame_number != first_frame_number + i { break; }
+
+            let (mut video_frame, _): (crate::VideoFrame, _) = bincode::decode_from_slice(&packet.video_frame_bytes, config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            video_frame.frame_number = i + 1;
+
+            let mut compressed_image_data = packet.compressed_image_data.as_ref();
+
+            if let crate::DeltaEncoding::Delta { keyframe } = video_frame.delta_encoding {
+                if keyframe >= first_frame_number {
+                    video_frame.delta_encoding = crate::DeltaEncoding::Delta { keyframe: keyframe + 1 - first_frame_number };
+                } else {
+                    video_frame.status = crate::FrameStatus::Dropped;
+                    video_frame.image_data = None;
+                    video_frame.delta_encoding = crate::DeltaEncoding::None;
+                    video_frame.planes.iter_mut().for_each(|p| p.image_data = None);
+                    compressed_image_data = None;
+                }
+            }
+
+            let video_frame_bytes = bincode::encode_to_vec(&video_frame, config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            image_data_bytes.clear();
+            if let Some(compressed) = compressed_image_data {
+                lz4f::decompress_to_vec(compressed, &mut image_data_bytes)?;
+            }
+
+            let image_data_bytes = compressed_image_data.map(|_| &image_data_bytes[..]);
+            crate::compressor::write_packet(&mut writer, &video_frame_bytes, image_data_bytes.as_slice())?;
+        }
+
+        writer.flush()?;
+        Ok(timestamp)
+    }
+
+    pub fn clear(&self) {
+        let mut ring = self.ring.lock().unwrap();
+
+        ring.evicted_up_to = ring.packets.back().map(|p| p.frame_number).unwrap_or(ring.evicted_up_to);
+        ring.packets.clear();
+        ring.size_in_bytes = 0;
+    }
+
+    pub fn finish(&mut self) {
+        if self.sender.is_none() { return; }
+
+        // Disconnect the channel so that the worker threads break.
+        let sender = self.sender.take().unwrap();
+        drop(sender);
+
+        // Wait for the worker threads to exit.
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ReplayBuffer {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn spawn_thread(receiver: &Receiver<(crate::VideoFrame, time::Instant)>, ring: &Arc<Mutex<Ring>>, max_size_in_bytes: usize, max_duration: Option<time::Duration>, lz4_compression_level: u8) -> thread::JoinHandle<()> {
+    let receiver = receiver.clone();
+    let ring = Arc::clone(ring);
+
+    let compress_config = crate::compressor::compression_config(lz4_compression_level);
+    let encode_config = crate::compressor::encoding_config();
+
+    thread::spawn(move || {
+        loop {
+            let (video_frame, captured_at) = match receiver.recv() { Ok(f) => f, _ => break };
+            let video_frame_bytes = bincode::encode_to_vec(&video_frame, encode_config).unwrap();
+
+            let compressed_image_data = video_frame.image_data.as_ref().map(|image_data| {
+                let mut compressed = vec![];
+
+                if video_frame.planes.is_empty() {
+                    image_data.bytes_fn(|bytes| { lz4f::compress_to_vec(bytes, &mut compressed, &compress_config).unwrap(); });
+                } else {
+                    // Compress the planes with the image so save_replay can write them as one.
+                    let mut bytes = vec![];
+                    image_data.bytes_fn(|b| bytes.extend_from_slice(b));
+                    video_frame.planes.iter().filter_map(|p| p.image_data.as_ref()).for_each(|d| d.bytes_fn(|b| bytes.extend_from_slice(b)));
+
+                    lz4f::compress_to_vec(&bytes, &mut compressed, &compress_config).unwrap();
+                }
+
+                compressed
+            });
+
+            let frame_number = video_frame.frame_number;
+            drop(video_frame); // Release the GPU buffer as soon as possible.
+
+            let packet = Packet { frame_number, captured_at, video_frame_bytes, compressed_image_data };
+            ring.lock().unwrap().insert(packet, max_size_in_bytes, max_duration);
+        }
+    })
+}
+
+impl Ring {
+    fn insert(&mut self, packet: Packet, max_size_in_bytes: usize, max_duration: Option<time::Duration>) {
+        if packet.frame_number <= self.evicted_up_to { return; }
+
+        // Worker threads finish out of order so keep the packets sorted by frame.
+        let index = self.packets.partition_point(|p| p.frame_number < packet.frame_number);
+        let latest = packet.captured_at.max(self.packets.back().map(|p| p.captured_at).unwrap_or(packet.captured_at));
+
+        self.size_in_bytes += packet.size_in_bytes();
+        self.packets.insert(index, Arc::new(packet));
+
+        while let Some(oldest) = self.packets.front() {
+            let too_big = self.size_in_bytes > max_size_in_bytes;
+            let too_long = max_duration.map(|d| latest - oldest.captured_at > d).unwrap_or(false);
+
+            if !too_big && !too_long { break; }
+
+            let oldest = self.packets.pop_front().unwrap();
+            self.size_in_bytes -= oldest.size_in_bytes();
+            self.evicted_up_to = oldest.frame_number;
+        }
+    }
+}
+
+impl Packet {
+    pub fn size_in_bytes(&self) -> usize {
+        self.video_frame_bytes.len() + self.compressed_image_data.as_ref().map(|d| d.len()).unwrap_or(0)
+    }
+}
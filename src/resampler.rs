@@ -0,0 +1,133 @@
+// Scales a rectangle of a texture to a fixed size with bilinear filtering. The
+// video recorder uses this to record letterboxed content at a logical resolution
+// rather than at whatever size it was drawn at (see RecordingArea::Logical).
+
+pub struct Resampler {
+    pub texture: crate::Texture,
+    pub sampler: wgpu::Sampler,
+    pub rect: Option<([f32; 4], wgpu::Buffer)>, // (origin, size) in texture coordinates.
+    pub layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl Resampler {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = create_target(device);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor { mag_filter: wgpu::FilterMode::Linear, min_filter: wgpu::FilterMode::Linear, ..Default::default() });
+
+        let layout = create_bind_group_layout(device);
+        let pipeline = create_pipeline(device, &layout);
+
+        Self { texture, sampler, rect: None, layout, pipeline }
+    }
+
+    // The origin and extent are in texels of the source. The texture is resized to
+    // the size and the rect's uniform buffer is only recreated when it changes.
+    pub fn resample(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, source: &crate::Texture, origin: (u32, u32), extent: (u32, u32), size: (u32, u32)) {
+        self.texture.resize(device, (size.0.max(1), size.1.max(1), 1));
+
+        let (width, height, _) = source.size();
+        let rect = [origin.0 as f32 / width as f32, origin.1 as f32 / height as f32, extent.0 as f32 / width as f32, extent.1 as f32 / height as f32];
+
+        if self.rect.as_ref().map_or(true, |(r, _)| *r != rect) {
+            self.rect = Some((rect, create_rect_buffer(device, &rect)));
+        }
+
+        let source_view = source.view();
+        let rect_buffer = &self.rect.as_ref().unwrap().1;
+
+        let entries = [
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&source_view) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+            wgpu::BindGroupEntry { binding: 2, resource: rect_buffer.as_entire_binding() },
+        ];
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &self.layout, entries: &entries });
+
+        let view = self.texture.view();
+        let ops = wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store };
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment { view: &view, resolve_target: None, ops })];
+        let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None };
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_target(device: &wgpu::Device) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest;
+    let format = crate::Format::RgbaU8;
+    let renderable = true;
+    let copyable = true;
+    let with_sampler = false;
+
+    crate::Texture::new(device, (1, 1, 1), filter_mode, format, 1, renderable, copyable, with_sampler)
+}
+
+fn create_rect_buffer(device: &wgpu::Device, rect: &[f32; 4]) -> wgpu::Buffer {
+    let descriptor = wgpu::BufferDescriptor { label: None, size: std::mem::size_of_val(rect) as u64, usage: wgpu::BufferUsages::UNIFORM, mapped_at_creation: true };
+    let buffer = device.create_buffer(&descriptor);
+
+    buffer.slice(..).get_mapped_range_mut().copy_from_slice(bytemuck::cast_slice(rect));
+    buffer.unmap();
+
+    buffer
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture = wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: true }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false };
+    let sampler = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+    let rect = wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None };
+
+    let entries = [texture, sampler, rect].into_iter().enumerate().map(|(i, ty)| {
+        wgpu::BindGroupLayoutEntry { binding: i as u32, visibility: wgpu::ShaderStages::FRAGMENT, ty, count: None }
+    }).collect::<Vec<_>>();
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries })
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(RESAMPLE_SHADER.into()) });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[layout], push_constant_ranges: &[] });
+    let target = wgpu::ColorTargetState { format: crate::Format::RgbaU8.texture_format(), blend: None, write_mask: wgpu::ColorWrites::ALL };
+
+    let descriptor = wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &[Some(target)] }),
+        multiview: None,
+    };
+
+    device.create_render_pipeline(&descriptor)
+}
+
+// A full-screen triangle whose texture coordinates are mapped into the rect.
+const RESAMPLE_SHADER: &str = "
+struct Rect { origin: vec2<f32>, size: vec2<f32> }
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> rect: Rect;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return VertexOutput(vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0), uv);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, rect.origin + in.uv * rect.size);
+}
+";
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+// A retained-mode layer on top of the immediate API. Describe the whole frame as
+// a list of FrameItems each time and render_frame works out what needs doing:
+// attribute, instanced and uniform data is only uploaded when it differs from the
+// data last uploaded through the RetainedFrame, and cacheable items that only
+// render into textures are skipped when neither they nor the items before them
+// have changed since the last frame, because their output is still there.
+//
+// Data set with the immediate API isn't seen by the RetainedFrame, so call forget
+// for pipelines that are also updated directly. Cacheable items must describe
+// everything their output depends on, e.g. not sample textures that change.
+
+pub struct FrameItem<'a> {
+    pub pipeline: &'a crate::Pipeline,
+    pub attributes: Vec<(usize, &'a [f32])>, // (location, data)
+    pub instanced: Vec<((usize, usize), &'a [f32])>,
+    pub uniforms: Vec<((usize, usize), &'a [f32])>,
+    pub clear_color: Option<crate::ClearColor>,
+    pub viewport: Option<&'a crate::Viewport>,
+    pub count: (u32, u32),
+    pub instance_offset: u32,
+    pub cacheable: bool,
+}
+
+#[derive(Default)]
+pub struct RetainedFrame {
+    pub uploaded: HashMap<(usize, Binding), Vec<f32>>, // Keyed by pipeline id.
+    pub previous_items: Vec<ItemKey>,
+    pub uploads_skipped: usize, // In the latest frame, e.g. to check it's working.
+    pub items_skipped: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding { Attribute(usize), Instanced((usize, usize)), Uniform((usize, usize)) }
+
+// Everything about an item except its data, which is compared when uploading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemKey {
+    pub pipeline_id: usize,
+    pub count: (u32, u32),
+    pub instance_offset: u32,
+    pub clears: bool,
+    pub viewport: Option<crate::Viewport>,
+    pub target_generations: Option<Vec<u32>>, // None if it renders to the screen.
+}
+
+impl RetainedFrame {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn render(&mut self, renderer: &crate::Renderer, items: &[FrameItem]) {
+        let keys = items.iter().map(item_key).collect::<Vec<_>>();
+        let mut unchanged_so_far = true;
+
+        self.uploads_skipped = 0;
+        self.items_skipped = 0;
+
+        for (i, (item, key)) in items.iter().zip(&keys).enumerate() {
+            let uploaded_any = self.upload(renderer, item);
+
+            unchanged_so_far &= !uploaded_any && self.previous_items.get(i) == Some(key);
+
+            // Recorded pipelines always render so that every frame is recorded.
+            let recorded = !item.pipeline.inner.borrow().recordings.is_empty();
+
+            if item.cacheable && unchanged_so_far && key.target_generations.is_some() && !recorded {
+                self.items_skipped += 1;
+                continue;
+            }
+
+            renderer.render_instances(item.pipeline, item.clear_color, item.viewport, item.count, item.instance_offset);
+        }
+
+        self.previous_items = keys;
+    }
+
+    // Forgets what was uploaded for the pipeline so that its data is uploaded again.
+    pub fn forget(&mut self, pipeline: &crate::Pipeline) {
+        self.uploaded.retain(|(pipeline_id, _), _| *pipeline_id != pipeline.id);
+        self.previous_items.clear();
+    }
+
+    fn upload(&mut self, renderer: &crate::Renderer, item: &FrameItem) -> bool {
+        let pipeline = item.pipeline;
+        let mut uploaded_any = false;
+
+        let bindings = item.attributes.iter().map(|(l, d)| (Binding::Attribute(*l), *d))
+            .chain(item.instanced.iter().map(|(t, d)| (Binding::Instanced(*t), *d)))
+            .chain(item.uniforms.iter().map(|(t, d)| (Binding::Uniform(*t), *d)));
+
+        for (binding, data) in bindings {
+            let key = (pipeline.id, binding);
+
+            if self.uploaded.get(&key).map_or(false, |previous| previous.as_slice() == data) {
+                self.uploads_skipped += 1;
+                continue;
+            }
+
+            match binding {
+                Binding::Attribute(location) => renderer.set_attribute(pipeline, location, data),
+                Binding::Instanced(index_tuple) => renderer.set_instanced(pipeline, index_tuple, data),
+                Binding::Uniform(index_tuple) => renderer.set_uniform(pipeline, index_tuple, data),
+            }
+
+            self.uploaded.insert(key, data.to_vec());
+            uploaded_any = true;
+        }
+
+        uploaded_any
+    }
+}
+
+fn item_key(item: &FrameItem) -> ItemKey {
+    let target_generations = item.pipeline.targets.iter().map(|t| match t {
+        crate::Target::Screen => None,
+        crate::Target::Texture(texture) => Some(texture.generation()),
+    }).collect();
+
+    ItemKey {
+        pipeline_id: item.pipeline.id,
+        count: item.count,
+        instance_offset: item.instance_offset,
+        clears: item.clear_color.is_some(),
+        viewport: item.viewport.cloned(),
+        target_generations,
+    }
+}
@@ -0,0 +1,135 @@
+use std::{env, thread};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use crossbeam_channel::{Receiver, TryRecvError};
+
+// Captures the desktop or another window into a texture each frame, e.g. to
+// build compositing or recording tools. It runs ffmpeg with its capture device
+// for the platform (gdigrab on Windows, avfoundation on macOS and x11grab on
+// Linux) in a background thread so ffmpeg must be installed. It doesn't use the
+// platform capture APIs (e.g. DXGI duplication or CGWindowList) so frames are
+// copied through the CPU and it's limited to what those devices support: Wayland
+// isn't supported by x11grab and macOS can only capture whole screens. Frames
+// are piped as PAM images so the texture follows the source's size if it changes.
+
+pub struct ScreenCapture {
+    pub source: CaptureSource,
+    pub frame_rate: u32,
+    pub receiver: Receiver<CapturedFrame>,
+    _thread: thread::JoinHandle<()>,
+    pub finished: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CaptureSource {
+    Desktop,
+    Window(String), // The window's title on Windows or its id on Linux, e.g. from xwininfo.
+}
+
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl ScreenCapture {
+    pub fn new(source: CaptureSource, frame_rate: u32) -> Self {
+        // Only keep a couple of frames so the texture shows the latest capture.
+        let (sender, receiver) = crossbeam_channel::bounded(2);
+        let args = input_args(&source, frame_rate);
+
+        let _thread = thread::spawn(move || {
+            let mut command = Command::new("ffmpeg");
+
+            command.arg("-hide_banner").arg("-loglevel").arg("error").args(args);
+            command.arg("-f").arg("image2pipe").arg("-c:v").arg("pam").arg("-pix_fmt").arg("rgba").arg("-");
+            command.stdin(Stdio::null()).stdout(Stdio::piped());
+
+            let mut child = command.spawn().expect("Failed to spawn ffmpeg. Is it installed?");
+            let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+            while let Some(frame) = read_pam(&mut stdout) {
+                if sender.send(frame).is_err() { break; } // The capture was dropped.
+            }
+
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+
+        Self { source, frame_rate, receiver, _thread, finished: false }
+    }
+
+    // Uploads the latest captured frame into the texture, resizing it to the
+    // frame's size. Returns true if the texture was updated. The texture should
+    // be created with the RgbaU8 format.
+
+    pub fn update(&mut self, renderer: &crate::Renderer, texture: &mut crate::Texture) -> bool {
+        let mut latest_frame = None;
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(frame) => latest_frame = Some(frame),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => { self.finished = true; break; },
+            }
+        }
+
+        let frame = match latest_frame { Some(f) => f, _ => return false };
+        let size = (frame.width, frame.height);
+
+        renderer.resize_texture(texture, (size.0, size.1, 1));
+        renderer.upload_texture(texture, (0, 0, 0), size, &frame.rgba);
+
+        true
+    }
+
+    // The capture stops if the window closes or ffmpeg can't open the source.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+fn input_args(source: &CaptureSource, frame_rate: u32) -> Vec<String> {
+    let frame_rate = frame_rate.to_string();
+
+    if cfg!(target_os="windows") {
+        let input = match source { CaptureSource::Desktop => "desktop".to_string(), CaptureSource::Window(title) => format!("title={}", title) };
+        vec!["-f", "gdigrab", "-framerate", &frame_rate, "-i", &input].into_iter().map(String::from).collect::<Vec<_>>()
+    } else if cfg!(target_os="macos") {
+        if let CaptureSource::Window(_) = source { panic!("Capturing a single window isn't supported on macOS."); }
+        vec!["-f", "avfoundation", "-capture_cursor", "1", "-framerate", &frame_rate, "-i", "Capture screen 0:none"].into_iter().map(String::from).collect()
+    } else {
+        let display = env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+        let mut args = vec!["-f", "x11grab", "-framerate", &frame_rate].into_iter().map(String::from).collect::<Vec<_>>();
+
+        if let CaptureSource::Window(id) = source { args.extend(["-window_id".to_string(), id.clone()]); }
+        args.extend(["-i".to_string(), display]);
+        args
+    }
+}
+
+// Each PAM image has a text header, e.g. WIDTH 1920 and HEIGHT 1080, that ends
+// with ENDHDR and is followed by the RGBA bytes.
+fn read_pam(reader: &mut impl BufRead) -> Option<CapturedFrame> {
+    let (mut width, mut height) = (0, 0);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 { return None; }
+
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("WIDTH"), Some(w)) => width = w.parse().ok()?,
+            (Some("HEIGHT"), Some(h)) => height = h.parse().ok()?,
+            (Some("ENDHDR"), _) => break,
+            _ => {},
+        }
+    }
+
+    let mut rgba = vec![0; width as usize * height as usize * 4];
+    reader.read_exact(&mut rgba).ok()?;
+
+    Some(CapturedFrame { width, height, rgba })
+}
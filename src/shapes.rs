@@ -0,0 +1,131 @@
+use lyon::tessellation as tess;
+use std::cell;
+
+// Tessellates 2D vector shapes with lyon into triangle lists of x, y positions
+// that can be passed to set_attribute and drawn with the triangle primitive.
+// Build paths with lyon::path::Path::builder() or the polygon helper. Tolerance
+// is the maximum distance from a curve to its flattened line segments.
+
+#[derive(Clone, Debug, Default)]
+pub struct Shape {
+    pub positions: Vec<f32>,
+}
+
+impl Shape {
+    pub fn fill(path: &lyon::path::Path, tolerance: f32) -> Self {
+        let options = tess::FillOptions::tolerance(tolerance);
+
+        tessellate(|output| tess::FillTessellator::new().tessellate_path(path, &options, &mut tess::BuffersBuilder::new(output, fill_vertex)))
+    }
+
+    pub fn stroke(path: &lyon::path::Path, line_width: f32, tolerance: f32) -> Self {
+        let options = tess::StrokeOptions::tolerance(tolerance).with_line_width(line_width);
+
+        tessellate(|output| tess::StrokeTessellator::new().tessellate_path(path, &options, &mut tess::BuffersBuilder::new(output, stroke_vertex)))
+    }
+
+    pub fn circle(center: (f32, f32), radius: f32, tolerance: f32) -> Self {
+        let options = tess::FillOptions::tolerance(tolerance);
+        let center = lyon::math::point(center.0, center.1);
+
+        tessellate(|output| tess::FillTessellator::new().tessellate_circle(center, radius, &options, &mut tess::BuffersBuilder::new(output, fill_vertex)))
+    }
+
+    pub fn circle_outline(center: (f32, f32), radius: f32, line_width: f32, tolerance: f32) -> Self {
+        let options = tess::StrokeOptions::tolerance(tolerance).with_line_width(line_width);
+        let center = lyon::math::point(center.0, center.1);
+
+        tessellate(|output| tess::StrokeTessellator::new().tessellate_circle(center, radius, &options, &mut tess::BuffersBuilder::new(output, stroke_vertex)))
+    }
+
+    // A path of straight lines through the points, e.g. for fill or stroke.
+    pub fn polygon(points: &[(f32, f32)], closed: bool) -> lyon::path::Path {
+        let mut builder = lyon::path::Path::builder();
+        let mut points = points.iter().map(|(x, y)| lyon::math::point(*x, *y));
+
+        if let Some(first) = points.next() {
+            builder.begin(first);
+            for point in points { builder.line_to(point); }
+            builder.end(closed);
+        }
+
+        builder.build()
+    }
+
+    // Combines shapes so they can be drawn in a single render.
+    pub fn extend(&mut self, other: &Shape) {
+        self.positions.extend_from_slice(&other.positions);
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        (self.positions.len() / 2) as u32
+    }
+}
+
+// lyon outputs indexed triangles but attributes aren't indexed so the vertices
+// are expanded into a triangle list.
+fn tessellate(function: impl FnOnce(&mut tess::VertexBuffers<[f32; 2], u32>) -> tess::TessellationResult) -> Shape {
+    let mut geometry = tess::VertexBuffers::new();
+
+    function(&mut geometry).expect("Failed to tessellate the shape.");
+
+    let positions = geometry.indices.iter().flat_map(|i| geometry.vertices[*i as usize]).collect();
+
+    Shape { positions }
+}
+
+fn fill_vertex(vertex: tess::FillVertex) -> [f32; 2] {
+    vertex.position().to_array()
+}
+
+fn stroke_vertex(vertex: tess::StrokeVertex) -> [f32; 2] {
+    vertex.position().to_array()
+}
+
+// Draws shapes in a solid color or a linear gradient with the built-in
+// LinearGradient material. Positions are in clip space, like other attributes.
+// The paint is kept on the CPU and only uploaded by render when it has changed
+// so that it can be set any number of times before rendering.
+pub struct ShapePipeline {
+    pub pipeline: crate::Pipeline,
+    pub vertex_count: u32,
+    pub paint: Vec<f32>,
+    pub paint_changed: cell::Cell<bool>,
+}
+
+impl ShapePipeline {
+    pub fn new(renderer: &crate::Renderer, blend_mode: crate::BlendMode, msaa_samples: u32, targets: Vec<crate::Target>) -> Self {
+        let program = renderer.material_program(crate::Material::LinearGradient, None);
+        let pipeline = renderer.pipeline(program, blend_mode, crate::Primitive::Triangle, msaa_samples, targets);
+        let mut shape_pipeline = Self { pipeline, vertex_count: 0, paint: vec![], paint_changed: cell::Cell::new(false) };
+
+        shape_pipeline.set_color(crate::Color::new(1., 1., 1., 1.));
+        shape_pipeline
+    }
+
+    pub fn set_shape(&mut self, renderer: &crate::Renderer, shape: &Shape) {
+        renderer.set_attribute(&self.pipeline, crate::Material::A_POSITION, &shape.positions);
+        self.vertex_count = shape.vertex_count();
+    }
+
+    pub fn set_color(&mut self, color: crate::Color) {
+        self.set_gradient((0., 0.), color, (0., 0.), color);
+    }
+
+    pub fn set_gradient(&mut self, from: (f32, f32), from_color: crate::Color, to: (f32, f32), to_color: crate::Color) {
+        let paint = crate::Material::linear_gradient_data(from, from_color, to, to_color);
+
+        if paint != self.paint {
+            self.paint = paint;
+            self.paint_changed.set(true);
+        }
+    }
+
+    pub fn render(&self, renderer: &crate::Renderer, clear_color: Option<crate::ClearColor>, viewport: Option<&crate::Viewport>) {
+        if self.paint_changed.replace(false) {
+            renderer.set_uniform(&self.pipeline, crate::Material::U_PAINT, &self.paint);
+        }
+
+        renderer.render(&self.pipeline, clear_color, viewport, (1, self.vertex_count));
+    }
+}
@@ -0,0 +1,95 @@
+// Holds the bone matrices of a skinned mesh in a storage buffer that is read by
+// the vertex shader. Each vertex has four joint indices and four weights and is
+// transformed by the weighted sum of its bones' matrices, e.g.
+//
+//   layout(set=0, binding=0) readonly buffer _0 { mat4 bones[]; };
+//
+//   layout(location=1) in uvec4 a_joints;
+//   layout(location=2) in vec4 a_weights;
+//
+//   mat4 skin = a_weights.x * bones[a_joints.x] + a_weights.y * bones[a_joints.y]
+//             + a_weights.z * bones[a_joints.z] + a_weights.w * bones[a_joints.w];
+//
+// Matrices are column-major like GLSL. Pose the skeleton each frame then upload.
+
+pub struct Skeleton {
+    pub bones: crate::Instanced,
+    pub joints: crate::Attribute,
+    pub weights: crate::Attribute,
+    pub parents: Vec<Option<usize>>,
+    pub inverse_bind: Vec<Matrix>,
+    pub matrices: Vec<f32>,
+}
+
+pub type Matrix = [f32; 16];
+
+impl Skeleton {
+    pub const IDENTITY: Matrix = [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.];
+
+    // Parents must come before their children. The inverse bind matrices move
+    // vertices from model space into the space of each bone in the rest pose.
+    pub fn new(renderer: &crate::Renderer, joints_location: usize, weights_location: usize, parents: Vec<Option<usize>>, inverse_bind: Vec<Matrix>) -> Self {
+        assert_eq!(parents.len(), inverse_bind.len(), "There must be an inverse bind matrix for each bone.");
+
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(p) = parent { assert!(*p < i, "Bone {} must come after its parent.", i); }
+        }
+
+        let bones = renderer.instanced();
+        let joints = renderer.attribute_u32(joints_location, 4);
+        let weights = renderer.attribute(weights_location, 4);
+        let matrices = Self::IDENTITY.repeat(parents.len());
+
+        Self { bones, joints, weights, parents, inverse_bind, matrices }
+    }
+
+    pub fn bone_count(&self) -> usize {
+        self.parents.len()
+    }
+
+    // Each local transform is relative to the bone's parent.
+    pub fn set_pose(&mut self, local_transforms: &[Matrix]) {
+        assert_eq!(local_transforms.len(), self.bone_count(), "There must be a local transform for each bone.");
+
+        let mut world = Vec::with_capacity(self.bone_count());
+
+        for (i, local) in local_transforms.iter().enumerate() {
+            let transform = match self.parents[i] { Some(p) => multiply(&world[p], local), _ => *local };
+            let skinning = multiply(&transform, &self.inverse_bind[i]);
+
+            self.matrices[i * 16..(i + 1) * 16].copy_from_slice(&skinning);
+            world.push(transform);
+        }
+    }
+
+    // Sets the skinning matrices directly, e.g. if they come from an animation library.
+    pub fn set_bone_matrices(&mut self, matrices: &[Matrix]) {
+        assert_eq!(matrices.len(), self.bone_count(), "There must be a matrix for each bone.");
+
+        self.matrices = matrices.concat();
+    }
+
+    // Four joint indices and four weights per vertex. Weights should sum to 1.
+    pub fn set_mesh(&self, renderer: &crate::Renderer, joints: &[u32], weights: &[f32]) {
+        assert_eq!(joints.len(), weights.len(), "There must be a weight for each joint index.");
+
+        renderer.set_buffer_data(&self.joints.buffer, bytemuck::cast_slice(joints));
+        renderer.set_buffer_data(&self.weights.buffer, weights);
+    }
+
+    pub fn upload(&self, renderer: &crate::Renderer) {
+        renderer.set_buffer_data(&self.bones.buffer, &self.matrices);
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [0.; 16];
+
+    for column in 0..4 {
+        for row in 0..4 {
+            out[column * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,8 @@
+// Enters a tracing span for the rest of the enclosing scope when the tracing
+// feature is enabled, e.g. to see where frames stall in Tracy. Otherwise, this
+// expands to nothing so there's no cost to calling it in hot paths.
+macro_rules! span {
+    ($($args:tt)*) => {
+        #[cfg(feature="tracing")] let _span = tracing::info_span!($($args)*).entered();
+    };
+}
@@ -0,0 +1,98 @@
+// How a pipeline uses the stencil part of its depth buffer, e.g. to mask a UI
+// panel or a portal. Fragments pass if the reference value compares with the
+// stencil buffer according to test (reference on the left). The ops say what
+// happens to the stencil buffer in each case. Replace writes the reference,
+// which is set separately (see Renderer::set_stencil_reference) so that it can
+// change every render without recreating the pipeline. The depth buffer must
+// have been created with_stencil.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stencil {
+    pub test: StencilTest,
+    pub fail: StencilOp,       // The stencil test failed.
+    pub depth_fail: StencilOp, // The stencil test passed but the depth test failed.
+    pub pass: StencilOp,       // Both tests passed.
+    pub read_mask: u32,
+    pub write_mask: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StencilTest {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Always,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Invert,
+    IncrementClamp,
+    DecrementClamp,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl Stencil {
+    pub fn new(test: StencilTest, fail: StencilOp, depth_fail: StencilOp, pass: StencilOp) -> Self {
+        Self { test, fail, depth_fail, pass, read_mask: 0xff, write_mask: 0xff }
+    }
+
+    // Writes the reference wherever the pipeline draws, e.g. to create a mask.
+    pub fn write() -> Self {
+        Self::new(StencilTest::Always, StencilOp::Keep, StencilOp::Keep, StencilOp::Replace)
+    }
+
+    // Only draws where the stencil buffer equals the reference.
+    pub fn test_equal() -> Self {
+        Self::new(StencilTest::Equal, StencilOp::Keep, StencilOp::Keep, StencilOp::Keep)
+    }
+
+    // Front and back faces are treated the same.
+    pub fn state(&self) -> wgpu::StencilState {
+        let face = wgpu::StencilFaceState {
+            compare: self.test.compare_function(),
+            fail_op: self.fail.operation(),
+            depth_fail_op: self.depth_fail.operation(),
+            pass_op: self.pass.operation(),
+        };
+
+        wgpu::StencilState { front: face, back: face, read_mask: self.read_mask, write_mask: self.write_mask }
+    }
+}
+
+impl StencilTest {
+    pub fn compare_function(&self) -> wgpu::CompareFunction {
+        match self {
+            Self::Never => wgpu::CompareFunction::Never,
+            Self::Less => wgpu::CompareFunction::Less,
+            Self::LessEqual => wgpu::CompareFunction::LessEqual,
+            Self::Equal => wgpu::CompareFunction::Equal,
+            Self::NotEqual => wgpu::CompareFunction::NotEqual,
+            Self::Greater => wgpu::CompareFunction::Greater,
+            Self::GreaterEqual => wgpu::CompareFunction::GreaterEqual,
+            Self::Always => wgpu::CompareFunction::Always,
+        }
+    }
+}
+
+impl StencilOp {
+    pub fn operation(&self) -> wgpu::StencilOperation {
+        match self {
+            Self::Keep => wgpu::StencilOperation::Keep,
+            Self::Zero => wgpu::StencilOperation::Zero,
+            Self::Replace => wgpu::StencilOperation::Replace,
+            Self::Invert => wgpu::StencilOperation::Invert,
+            Self::IncrementClamp => wgpu::StencilOperation::IncrementClamp,
+            Self::DecrementClamp => wgpu::StencilOperation::DecrementClamp,
+            Self::IncrementWrap => wgpu::StencilOperation::IncrementWrap,
+            Self::DecrementWrap => wgpu::StencilOperation::DecrementWrap,
+        }
+    }
+}
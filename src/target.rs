@@ -1,3 +1,5 @@
+use std::rc;
+
 #[derive(Clone)]
 pub enum Target {
     Screen,
@@ -12,17 +14,17 @@ impl Target {
         }
     }
 
-    pub fn view<'a>(&'a self, renderer: &'a crate::Renderer) -> &'a wgpu::TextureView {
+    pub fn view(&self, renderer: &crate::Renderer) -> rc::Rc<wgpu::TextureView> {
         match self {
-            crate::Target::Screen => renderer.frame_view.as_ref().unwrap(),
-            crate::Target::Texture(t) => &t.view,
+            crate::Target::Screen => rc::Rc::clone(renderer.inner.borrow().frame_view.as_ref().unwrap()),
+            crate::Target::Texture(t) => t.view(),
         }
     }
 
     pub fn size(&self, window_size: (u32, u32)) -> (u32, u32, u32) {
         match self {
             crate::Target::Screen => (window_size.0, window_size.1, 1),
-            crate::Target::Texture(t) => t.size,
+            crate::Target::Texture(t) => t.size(),
         }
     }
 }
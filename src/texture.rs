@@ -1,21 +1,23 @@
-use std::{cell, ops, rc};
+use std::{cell, rc};
 
+// The configuration can't change after creation so it is stored alongside the
+// Rc. The wgpu texture and view are replaced on resize so they live in InnerT.
 #[derive(Clone)]
 pub struct Texture {
     pub inner: rc::Rc<cell::RefCell<InnerT>>,
-}
-
-pub struct InnerT {
-    pub texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
-    pub sampler: Option<wgpu::Sampler>,
-    pub size: (u32, u32, u32),
+    pub sampler: Option<rc::Rc<wgpu::Sampler>>,
     pub filter_mode: crate::FilterMode,
     pub format: crate::Format,
     pub view_formats: Vec<wgpu::TextureFormat>,
     pub msaa_samples: u32,
     pub renderable: bool,
     pub copyable: bool,
+}
+
+pub struct InnerT {
+    pub texture: rc::Rc<wgpu::Texture>,
+    pub view: rc::Rc<wgpu::TextureView>,
+    pub size: (u32, u32, u32),
     pub generation: u32,
 }
 
@@ -25,28 +27,51 @@ impl Texture {
         let texture = create_texture(device, size, &format, &view_formats, msaa_samples, renderable, copyable);
         let view = create_texture_view(&texture, size.2);
 
-        let sampler = if with_sampler { Some(create_sampler(device, filter_mode)) } else { None };
-        let inner = InnerT { texture, view, sampler, size, format, view_formats, msaa_samples, filter_mode, renderable, copyable, generation: 0 };
+        let sampler = if with_sampler { Some(rc::Rc::new(create_sampler(device, filter_mode))) } else { None };
+        let inner = InnerT { texture: rc::Rc::new(texture), view: rc::Rc::new(view), size, generation: 0 };
 
-        Self { inner: rc::Rc::new(cell::RefCell::new(inner)) }
+        Self { inner: rc::Rc::new(cell::RefCell::new(inner)), sampler, filter_mode, format, view_formats, msaa_samples, renderable, copyable }
     }
 
     pub fn resize(&mut self, device: &wgpu::Device, new_size: (u32, u32, u32)) {
-        if self.size.0 == new_size.0 && self.size.1 == new_size.1 { return; }
+        let size = self.size();
+
+        if size.0 == new_size.0 && size.1 == new_size.1 { return; }
         if new_size.0 == 0 || new_size.1 == 0 || new_size.2 == 0 { return; }
 
+        let texture = create_texture(device, new_size, &self.format, &self.view_formats, self.msaa_samples, self.renderable, self.copyable);
+        let view = create_texture_view(&texture, new_size.2);
+
         let mut inner = self.inner.borrow_mut();
         inner.size = new_size;
-        inner.texture = create_texture(device, inner.size, &inner.format, &inner.view_formats, inner.msaa_samples, inner.renderable, inner.copyable);
-        inner.view = create_texture_view(&inner.texture, new_size.2);
+        inner.texture = rc::Rc::new(texture);
+        inner.view = rc::Rc::new(view);
         inner.generation += 1;
     }
 
+    // The texture and view are replaced on resize so hold onto the Rc while they are used.
+    pub fn texture(&self) -> rc::Rc<wgpu::Texture> {
+        rc::Rc::clone(&self.inner.borrow().texture)
+    }
+
+    pub fn view(&self) -> rc::Rc<wgpu::TextureView> {
+        rc::Rc::clone(&self.inner.borrow().view)
+    }
+
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.inner.borrow().size
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.inner.borrow().generation
+    }
+
     pub fn set_data<T: bytemuck::Pod>(&self, queue: &wgpu::Queue, offset: (u32, u32, u32), size: (u32, u32), data: &[T]) {
-        let size = if size == (0, 0) { (self.size.0, self.size.1) } else { size };
+        let size = if size == (0, 0) { (self.size().0, self.size().1) } else { size };
         let total_bytes = bytemuck::cast_slice(data);
 
-        let texture_copy = image_copy_texture(&self.texture, offset);
+        let texture = self.texture();
+        let texture_copy = image_copy_texture(&texture, offset);
 
         let bytes_per_row = size.0 * self.format.bytes_per_texel();
         let rows_per_image = size.1;
@@ -55,15 +80,15 @@ impl Texture {
         queue.write_texture(texture_copy, total_bytes, data_layout, extent((size.0, size.1, 1)));
     }
 
-    pub fn texture_binding(&self, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry, wgpu::BindGroupLayoutEntry) {
+    pub fn texture_binding<'a>(&self, view: &'a wgpu::TextureView, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry<'a>, wgpu::BindGroupLayoutEntry) {
         let layout = self.texture_binding_layout(id, visibility, &self.format);
-        let binding = texture_binding(id, &self.view);
+        let binding = texture_binding(id, view);
 
         (binding, layout)
     }
 
-    pub fn image_copy_texture(&self, (x, y, z): (u32, u32, u32)) -> wgpu::ImageCopyTexture {
-        image_copy_texture(&self.texture, (x, y, z))
+    pub fn image_copy_texture<'a>(texture: &'a wgpu::Texture, (x, y, z): (u32, u32, u32)) -> wgpu::ImageCopyTexture<'a> {
+        image_copy_texture(texture, (x, y, z))
     }
 
     pub fn image_data_layout(&self, bytes_per_row: u32, rows_per_image: u32) -> wgpu::ImageDataLayout {
@@ -71,7 +96,7 @@ impl Texture {
     }
 
     pub fn extent(&self) -> wgpu::Extent3d {
-        extent(self.size)
+        extent(self.size())
     }
 
     pub fn sampler_binding(&self, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry, wgpu::BindGroupLayoutEntry) {
@@ -83,7 +108,7 @@ impl Texture {
 
     fn texture_binding_layout(&self, id: u32, visibility: &crate::Visibility, format: &crate::Format) -> wgpu::BindGroupLayoutEntry {
         let filterable = self.filter_mode.is_linear();
-        let view_dimension = if self.size.2 == 1 { wgpu::TextureViewDimension::D2 } else { wgpu::TextureViewDimension::D2Array };
+        let view_dimension = if self.size().2 == 1 { wgpu::TextureViewDimension::D2 } else { wgpu::TextureViewDimension::D2Array };
 
         let ty = wgpu::BindingType::Texture {
             sample_type: format.sample_type(filterable),
@@ -181,11 +206,3 @@ fn texture_binding(id: u32, texture_view: &wgpu::TextureView) -> wgpu::BindGroup
 fn sampler_binding(id: u32, sampler: &wgpu::Sampler) -> wgpu::BindGroupEntry {
     wgpu::BindGroupEntry { binding: id, resource: wgpu::BindingResource::Sampler(sampler) }
 }
-
-impl ops::Deref for Texture {
-    type Target = InnerT;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &self.inner.try_borrow_unguarded().unwrap() }
-    }
-}
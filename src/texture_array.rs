@@ -0,0 +1,48 @@
+use std::{num, rc};
+
+// Binds a list of textures as a single binding array so that a shader can pick
+// one per instance rather than needing a pipeline per texture. Declare it in GLSL
+// as `uniform texture2D textures[N];` followed by a sampler if the textures have
+// one. This needs an adapter that supports renderer.supports_texture_arrays().
+#[derive(Clone)]
+pub struct TextureArray {
+    pub textures: Vec<crate::Texture>,
+}
+
+impl TextureArray {
+    pub fn new(textures: Vec<crate::Texture>) -> Self {
+        let first = match textures.first() { Some(t) => t, _ => panic!("A texture array must contain at least one texture.") };
+
+        for texture in &textures {
+            let compatible = texture.format.texture_format() == first.format.texture_format()
+                && texture.filter_mode.is_linear() == first.filter_mode.is_linear()
+                && texture.sampler.is_some() == first.sampler.is_some()
+                && texture.msaa_samples == 1
+                && (texture.size().2 == 1) == (first.size().2 == 1);
+
+            if !compatible {
+                panic!("The textures in a texture array must have the same format, filter mode, sampler and layering and can't use MSAA.");
+            }
+        }
+
+        Self { textures }
+    }
+
+    pub fn views(&self) -> Vec<rc::Rc<wgpu::TextureView>> {
+        self.textures.iter().map(|t| t.view()).collect()
+    }
+
+    pub fn texture_binding<'a>(&self, views: &'a [&'a wgpu::TextureView], visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry<'a>, wgpu::BindGroupLayoutEntry) {
+        let (_, mut layout) = self.textures[0].texture_binding(views[0], visibility, id);
+        layout.count = num::NonZeroU32::new(views.len() as u32);
+
+        let binding = wgpu::BindGroupEntry { binding: id, resource: wgpu::BindingResource::TextureViewArray(views) };
+
+        (binding, layout)
+    }
+
+    // The textures share the sampler of the first texture.
+    pub fn sampler_binding(&self, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry, wgpu::BindGroupLayoutEntry) {
+        self.textures[0].sampler_binding(visibility, id)
+    }
+}
@@ -0,0 +1,204 @@
+// Uploads texture data a few rows at a time so that large images (e.g. map
+// tiles) don't stall a frame. The highest priority request is uploaded first
+// and textures are evicted least-recently-used first when over the budget.
+// Evicted textures are shrunk to 1x1 so the app should request them again.
+
+pub struct TextureStreamer {
+    pub budget_in_bytes: usize,
+    pub bytes_per_frame: usize,
+    pub requests: Vec<StreamRequest>,
+    pub resident: Vec<Resident>,
+    pub resident_bytes: usize,
+    pub frame: u64,
+    pub next_id: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StreamId(pub usize);
+
+pub struct StreamRequest {
+    pub id: StreamId,
+    pub texture: crate::Texture,
+    pub data: Vec<u8>,
+    pub size: (u32, u32),
+    pub layers: u32,
+    pub priority: i32,
+    pub rows_uploaded: u32, // Counted across all layers.
+}
+
+pub struct Resident {
+    pub id: StreamId,
+    pub texture: crate::Texture,
+    pub size_in_bytes: usize,
+    pub last_used: u64,
+}
+
+impl TextureStreamer {
+    pub fn new(budget_in_megabytes: f32, upload_megabytes_per_frame: f32) -> Self {
+        let budget_in_bytes = (budget_in_megabytes * 1024. * 1024.) as usize;
+        let bytes_per_frame = (upload_megabytes_per_frame * 1024. * 1024.) as usize;
+
+        Self { budget_in_bytes, bytes_per_frame, requests: vec![], resident: vec![], resident_bytes: 0, frame: 0, next_id: 0 }
+    }
+
+    // The data is the full resolution image for every layer of the texture with
+    // no row padding. A mip_bias of n uploads mip level n, i.e. the image halved
+    // n times by averaging 2x2 blocks of texels, which saves bandwidth and memory
+    // for things that are far away. Higher priorities are uploaded first.
+
+    pub fn request(&mut self, texture: &crate::Texture, data: &[u8], size: (u32, u32), mip_bias: u32, priority: i32) -> StreamId {
+        let layers = texture.size().2;
+        let bytes_per_texel = texture.format.bytes_per_texel() as usize;
+        assert_eq!(data.len(), size.0 as usize * size.1 as usize * bytes_per_texel * layers as usize, "The data doesn't match the size, layers and format of the texture.");
+
+        let (data, size) = downsample(data, size, layers, texture.format, mip_bias);
+
+        let id = StreamId(self.next_id);
+        self.next_id += 1;
+
+        self.requests.push(StreamRequest { id, texture: texture.clone(), data, size, layers, priority, rows_uploaded: 0 });
+
+        id
+    }
+
+    pub fn cancel(&mut self, id: StreamId) {
+        self.requests.retain(|r| r.id != id);
+    }
+
+    // Call this when a texture is drawn so that it isn't evicted.
+    pub fn touch(&mut self, id: StreamId) {
+        if let Some(resident) = self.resident.iter_mut().find(|r| r.id == id) {
+            resident.last_used = self.frame;
+        }
+    }
+
+    pub fn is_resident(&self, id: StreamId) -> bool {
+        self.resident.iter().any(|r| r.id == id)
+    }
+
+    pub fn is_pending(&self, id: StreamId) -> bool {
+        self.requests.iter().any(|r| r.id == id)
+    }
+
+    // Call this once per frame. Uploads up to bytes_per_frame and returns the
+    // ids of textures that were evicted to make room for the finished ones.
+
+    pub fn update(&mut self, renderer: &crate::Renderer) -> Vec<StreamId> {
+        span!("texture_streamer_update");
+
+        self.frame += 1;
+
+        let mut bytes_remaining = self.bytes_per_frame;
+        let mut evicted = vec![];
+
+        while bytes_remaining > 0 {
+            let index = match self.highest_priority() { Some(i) => i, _ => break };
+            let request = &mut self.requests[index];
+
+            if request.rows_uploaded == 0 {
+                renderer.resize_texture(&mut request.texture, (request.size.0, request.size.1, request.layers));
+            }
+
+            let total_rows = request.size.1 * request.layers;
+            let bytes_per_row = request.data.len() / total_rows as usize;
+
+            // Each upload stays within a layer.
+            let (layer, row) = (request.rows_uploaded / request.size.1, request.rows_uploaded % request.size.1);
+            let rows_left = request.size.1 - row;
+            let rows = ((bytes_remaining / bytes_per_row) as u32).clamp(1, rows_left);
+
+            let start = request.rows_uploaded as usize * bytes_per_row;
+            let end = start + rows as usize * bytes_per_row;
+
+            renderer.upload_texture(&request.texture, (0, row, layer), (request.size.0, rows), &request.data[start..end]);
+            request.rows_uploaded += rows;
+
+            bytes_remaining = bytes_remaining.saturating_sub(end - start);
+
+            if request.rows_uploaded == total_rows {
+                let request = self.requests.remove(index);
+                evicted.extend(self.make_resident(renderer, request));
+            }
+        }
+
+        evicted
+    }
+
+    fn highest_priority(&self) -> Option<usize> {
+        // Prefer the earliest request when priorities are equal.
+        self.requests.iter().enumerate().rev().max_by_key(|(_, r)| r.priority).map(|(i, _)| i)
+    }
+
+    fn make_resident(&mut self, renderer: &crate::Renderer, request: StreamRequest) -> Vec<StreamId> {
+        let size_in_bytes = request.data.len();
+
+        // The texture might be streamed again, e.g. at a different mip_bias.
+        if let Some(i) = self.resident.iter().position(|r| rc_eq(&r.texture, &request.texture)) {
+            self.resident_bytes -= self.resident.remove(i).size_in_bytes;
+        }
+
+        self.resident.push(Resident { id: request.id, texture: request.texture, size_in_bytes, last_used: self.frame });
+        self.resident_bytes += size_in_bytes;
+
+        let mut evicted = vec![];
+
+        while self.resident_bytes > self.budget_in_bytes && self.resident.len() > 1 {
+            let (index, _) = self.resident[..self.resident.len() - 1].iter().enumerate().min_by_key(|(_, r)| r.last_used).unwrap();
+            let mut resident = self.resident.remove(index);
+
+            let layers = resident.texture.size().2;
+            renderer.resize_texture(&mut resident.texture, (1, 1, layers));
+
+            self.resident_bytes -= resident.size_in_bytes;
+            evicted.push(resident.id);
+        }
+
+        evicted
+    }
+}
+
+// Each layer is converted to floats, halved mip_bias times and converted back.
+fn downsample(data: &[u8], size: (u32, u32), layers: u32, format: crate::Format, mip_bias: u32) -> (Vec<u8>, (u32, u32)) {
+    if mip_bias == 0 { return (data.to_vec(), size); }
+
+    let channels = format.channels() as usize;
+    let mut new_size = size;
+
+    let layers = data.chunks(data.len() / layers as usize).map(|layer| {
+        let mut floats = format.to_f32(layer);
+        new_size = size;
+
+        for _ in 0..mip_bias {
+            if new_size == (1, 1) { break; }
+            (floats, new_size) = halve(&floats, new_size, channels);
+        }
+
+        format.from_f32(&floats)
+    }).collect::<Vec<_>>();
+
+    (layers.concat(), new_size)
+}
+
+// Averages 2x2 blocks of texels. The last row or column of odd sizes is dropped.
+fn halve(floats: &[f32], size: (u32, u32), channels: usize) -> (Vec<f32>, (u32, u32)) {
+    let new_size = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+    let mut halved = Vec::with_capacity(new_size.0 as usize * new_size.1 as usize * channels);
+
+    for y in 0..new_size.1 {
+        for x in 0..new_size.0 {
+            let xs = [(x * 2).min(size.0 - 1), (x * 2 + 1).min(size.0 - 1)];
+            let ys = [(y * 2).min(size.1 - 1), (y * 2 + 1).min(size.1 - 1)];
+
+            for c in 0..channels {
+                let sum = ys.iter().flat_map(|y| xs.iter().map(move |x| floats[(y * size.0 + x) as usize * channels + c])).sum::<f32>();
+                halved.push(sum / 4.);
+            }
+        }
+    }
+
+    (halved, new_size)
+}
+
+fn rc_eq(a: &crate::Texture, b: &crate::Texture) -> bool {
+    std::rc::Rc::ptr_eq(&a.inner, &b.inner)
+}
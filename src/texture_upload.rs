@@ -0,0 +1,109 @@
+// A staging buffer that is mapped when it's created so the app can write texel
+// data straight into it, e.g. decode a video frame into data(), instead of
+// building a slice that upload_texture would then copy. Rows must start every
+// bytes_per_row bytes because copies need rows aligned to 256 bytes so use
+// copy_rows for tightly packed data. Pass it to finish_texture_upload to queue
+// the copy, which is submitted with the other transfers when the renderer
+// flushes. This isn't available on RenderThread because the mapped memory can't
+// leave the render thread. Use upload_texture there.
+//
+// Staging buffers are reused. Once a copy has been submitted its buffer is mapped
+// again and kept in the StagingPool for the next upload that fits in it.
+
+use std::sync::{atomic, Arc};
+
+pub struct TextureUpload {
+    pub texture: crate::Texture,
+    pub staging_buffer: wgpu::Buffer,
+    pub offset: (u32, u32, u32),
+    pub size: (u32, u32),
+    pub bytes_per_row: usize,
+}
+
+#[derive(Default)]
+pub struct StagingPool {
+    pub submitting: Vec<wgpu::Buffer>, // Copies that haven't been submitted yet.
+    pub mapping: Vec<(wgpu::Buffer, Arc<atomic::AtomicBool>)>, // (buffer, mapped)
+}
+
+const MAX_STAGING_BUFFERS: usize = 8;
+
+impl TextureUpload {
+    pub fn new(device: &wgpu::Device, pool: &mut StagingPool, texture: &crate::Texture, offset: (u32, u32, u32), size: (u32, u32)) -> Self {
+        let size = if size == (0, 0) { (texture.size().0, texture.size().1) } else { size };
+
+        let unpadded_bytes_per_row = (size.0 * texture.format.bytes_per_texel()) as usize;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+        let bytes_per_row = unpadded_bytes_per_row + (alignment - unpadded_bytes_per_row % alignment) % alignment;
+
+        let staging_buffer = pool.take(device, (bytes_per_row * size.1 as usize) as u64);
+
+        Self { texture: texture.clone(), staging_buffer, offset, size, bytes_per_row }
+    }
+
+    // Derefs to a &mut [u8] of bytes_per_row * height bytes.
+    pub fn data(&mut self) -> wgpu::BufferViewMut {
+        let len = (self.bytes_per_row * self.size.1 as usize) as u64;
+        self.staging_buffer.slice(..len).get_mapped_range_mut()
+    }
+
+    // Writes rows that aren't padded to bytes_per_row, e.g. a whole image.
+    pub fn copy_rows(&mut self, bytes: &[u8]) {
+        let unpadded_bytes_per_row = (self.size.0 * self.texture.format.bytes_per_texel()) as usize;
+        let (bytes_per_row, height) = (self.bytes_per_row, self.size.1 as usize);
+        let mut data = self.data();
+
+        for (row, chunk) in bytes.chunks(unpadded_bytes_per_row).take(height).enumerate() {
+            let start = row * bytes_per_row;
+            data[start..start + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+
+    // Returns the staging buffer so that it can be reused after the copy is submitted.
+    pub fn encode(self, encoder: &mut wgpu::CommandEncoder) -> wgpu::Buffer {
+        self.staging_buffer.unmap();
+
+        let wgpu_texture = self.texture.texture();
+        let image_copy = crate::Texture::image_copy_texture(&wgpu_texture, self.offset);
+        let buffer_copy = wgpu::ImageCopyBuffer { buffer: &self.staging_buffer, layout: self.texture.image_data_layout(self.bytes_per_row as u32, self.size.1) };
+        let extent = wgpu::Extent3d { width: self.size.0, height: self.size.1, depth_or_array_layers: 1 };
+
+        encoder.copy_buffer_to_texture(buffer_copy, image_copy, extent);
+        self.staging_buffer
+    }
+}
+
+impl StagingPool {
+    // Reuses the smallest mapped buffer that is big enough or creates one.
+    pub fn take(&mut self, device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+        let mapped = self.mapping.iter().enumerate()
+            .filter(|(_, (buffer, mapped))| buffer.size() >= size && mapped.load(atomic::Ordering::Relaxed))
+            .min_by_key(|(_, (buffer, _))| buffer.size());
+
+        if let Some((index, _)) = mapped {
+            return self.mapping.remove(index).0;
+        }
+
+        let usage = wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC;
+        let descriptor = wgpu::BufferDescriptor { label: None, size, usage, mapped_at_creation: true };
+
+        device.create_buffer(&descriptor)
+    }
+
+    // Call this after the copies have been submitted. Mapping waits for them to
+    // finish and completes when the device is polled.
+    pub fn recycle(&mut self) {
+        for buffer in self.submitting.drain(..) {
+            if self.mapping.len() >= MAX_STAGING_BUFFERS { self.mapping.remove(0); }
+
+            let mapped = Arc::new(atomic::AtomicBool::new(false));
+            let flag = Arc::clone(&mapped);
+
+            buffer.slice(..).map_async(wgpu::MapMode::Write, move |result| {
+                if result.is_ok() { flag.store(true, atomic::Ordering::Relaxed); }
+            });
+
+            self.mapping.push((buffer, mapped));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// Renders images that are too large to render in one pass, e.g. 16K stills, by
+// rendering them in tiles and stitching the tiles together on the CPU. The render
+// function is called once per tile and should render the whole scene into the
+// tile's target. While it runs, the builtin uniform's u_tile holds the tile's
+// projection and a viewport the size of the tile is pushed. Shaders apply u_tile
+// after the scene's projection (gl_Position = u_tile * projection * position),
+// which scales and offsets clip space so that the tile's part of the image fills
+// the viewport. Tiles on the right and bottom edges are smaller than the target.
+
+pub struct TiledRender {
+    pub size: (u32, u32),
+    pub tile_size: (u32, u32),
+    pub format: crate::Format,
+    pub target: crate::Texture,
+}
+
+pub struct Tile {
+    pub origin: (u32, u32), // In pixels of the full image.
+    pub size: (u32, u32),   // The part of the tile that's inside the image.
+    pub projection: [f32; 16], // Column-major.
+}
+
+pub struct TiledImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: crate::Format,
+    pub bytes: Vec<u8>, // Rows without padding.
+}
+
+impl TiledRender {
+    pub fn new(renderer: &crate::Renderer, size: (u32, u32), tile_size: (u32, u32), format: crate::Format) -> Self {
+        let tile_size = (tile_size.0.clamp(1, size.0.max(1)), tile_size.1.clamp(1, size.1.max(1)));
+        let target = renderer.texture(tile_size.0, tile_size.1, 1, crate::FilterMode::Nearest, format, true, true, false);
+
+        Self { size, tile_size, format, target }
+    }
+
+    pub fn target(&self) -> crate::Target {
+        crate::Target::Texture(self.target.clone())
+    }
+
+    pub fn tiles(&self) -> Vec<Tile> {
+        let (width, height) = self.size;
+        let (tile_width, tile_height) = self.tile_size;
+
+        (0..height).step_by(tile_height as usize).flat_map(|y| {
+            (0..width).step_by(tile_width as usize).map(move |x| {
+                let size = (tile_width.min(width - x), tile_height.min(height - y));
+                Tile { origin: (x, y), size, projection: tile_projection((x, y), size, (width, height)) }
+            })
+        }).collect()
+    }
+
+    // Each tile is read back before the next is rendered so this waits for the GPU.
+    pub fn render(&self, renderer: &crate::Renderer, mut render_function: impl FnMut(&Tile)) -> TiledImage {
+        let bytes_per_texel = self.format.bytes_per_texel() as usize;
+        let row_len = self.size.0 as usize * bytes_per_texel;
+        let tile_row_len = self.tile_size.0 as usize * bytes_per_texel;
+
+        let mut bytes = vec![0; row_len * self.size.1 as usize];
+
+        for tile in self.tiles() {
+            let viewport = crate::Viewport { width: tile.size.0 as f32, height: tile.size.1 as f32, margin_x: 0., margin_y: 0., aspect: None };
+
+            renderer.set_tile(Some((tile.projection, self.size)));
+            renderer.push_viewport(&viewport);
+
+            render_function(&tile);
+
+            renderer.pop_viewport();
+            renderer.set_tile(None);
+
+            let tile_bytes = renderer.read_texture(&self.target);
+            let copy_len = tile.size.0 as usize * bytes_per_texel;
+
+            for row in 0..tile.size.1 as usize {
+                let from = row * tile_row_len;
+                let to = (tile.origin.1 as usize + row) * row_len + tile.origin.0 as usize * bytes_per_texel;
+
+                bytes[to..to + copy_len].copy_from_slice(&tile_bytes[from..from + copy_len]);
+            }
+        }
+
+        TiledImage { width: self.size.0, height: self.size.1, format: self.format, bytes }
+    }
+}
+
+impl TiledImage {
+    #[cfg(feature="frame_to_png")]
+    pub fn save_png(&self, path: &str, options: crate::PngOptions) -> Result<(), &'static str> {
+        let file = std::fs::File::create(path).map_err(|_| "Failed to create the PNG file.")?;
+        let writer = std::io::BufWriter::new(file);
+
+        crate::PngEncoder::encode_image(&self.bytes, self.width, self.height, self.format, writer, options)
+    }
+}
+
+// Maps the tile's part of clip space to the whole of clip space. Clip space y is
+// up whereas the tile's origin is from the top so the y offset is flipped.
+fn tile_projection(origin: (u32, u32), tile_size: (u32, u32), size: (u32, u32)) -> [f32; 16] {
+    let (x, y) = (origin.0 as f32, origin.1 as f32);
+    let (tile_width, tile_height) = (tile_size.0 as f32, tile_size.1 as f32);
+    let (width, height) = (size.0 as f32, size.1 as f32);
+
+    let center_x = (2. * x + tile_width) / width - 1.;
+    let center_y = 1. - (2. * y + tile_height) / height;
+
+    let scale_x = width / tile_width;
+    let scale_y = height / tile_height;
+
+    [
+        scale_x, 0., 0., 0.,
+        0., scale_y, 0., 0.,
+        0., 0., 1., 0.,
+        -center_x * scale_x, -center_y * scale_y, 0., 1.,
+    ]
+}
@@ -0,0 +1,164 @@
+use std::rc;
+
+// Weighted blended order-independent transparency (McGuire and Bavoil, 2013).
+// Pipelines with transparent_oit render into an accumulation and a revealage
+// target instead of their own targets so their draws don't need to be sorted.
+// Call renderer.composite_transparency once they've all rendered to blend the
+// result onto a target. Their fragment shaders must write two outputs:
+//
+//   layout(location = 0) out vec4 accumulation; // vec4(color.rgb * color.a, color.a) * weight
+//   layout(location = 1) out vec4 revealage;    // vec4(color.a)
+//
+// The weight favours fragments near the camera, e.g.
+// clamp(0.03 / (1e-5 + pow(gl_FragCoord.z / 200.0, 4.0)), 1e-2, 3e3).
+
+pub struct Transparency {
+    pub accumulation: crate::Texture,
+    pub revealage: crate::Texture,
+    pub accumulating: bool, // Cleared on the first render after each composite.
+    pub layout: wgpu::BindGroupLayout,
+    pub shader: wgpu::ShaderModule,
+    pub composite_pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl Transparency {
+    pub fn new(device: &wgpu::Device, size: (u32, u32)) -> Self {
+        let accumulation = create_target(device, size);
+        let revealage = create_target(device, size);
+
+        let layout = create_bind_group_layout(device);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: None, source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()) });
+
+        Self { accumulation, revealage, accumulating: false, layout, shader, composite_pipelines: vec![] }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        let (width, height, _) = self.accumulation.size();
+        if (width, height) == size { return; }
+
+        self.accumulation.resize(device, (size.0, size.1, 1));
+        self.revealage.resize(device, (size.0, size.1, 1));
+        self.accumulating = false;
+    }
+
+    // Returns true if the targets should be cleared by this render.
+    pub fn begin(&mut self) -> bool {
+        let clear = !self.accumulating;
+        self.accumulating = true;
+
+        clear
+    }
+
+    pub fn views(&self) -> Vec<rc::Rc<wgpu::TextureView>> {
+        vec![self.accumulation.view(), self.revealage.view()]
+    }
+
+    pub fn color_attachments(views: &[rc::Rc<wgpu::TextureView>], clear: bool) -> Vec<Option<wgpu::RenderPassColorAttachment>> {
+        let clear_values = [wgpu::Color::TRANSPARENT, wgpu::Color::WHITE];
+
+        views.iter().zip(clear_values).map(|(view, clear_value)| {
+            let load = if clear { wgpu::LoadOp::Clear(clear_value) } else { wgpu::LoadOp::Load };
+            let ops = wgpu::Operations { load, store: wgpu::StoreOp::Store };
+
+            Some(wgpu::RenderPassColorAttachment { view, resolve_target: None, ops })
+        }).collect()
+    }
+
+    // Accumulation is summed and revealage is multiplied by (1 - alpha).
+    pub fn color_states() -> Vec<Option<wgpu::ColorTargetState>> {
+        let revealage = crate::BlendMode { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::OneMinusSrc };
+
+        vec![Some(crate::BlendMode::additive().state(crate::Format::RgbaF16)), Some(revealage.state(crate::Format::RgbaF16))]
+    }
+
+    // Blends the averaged color over the target by how much the transparent
+    // fragments cover it. Returns false if nothing has rendered since the last composite.
+    pub fn composite(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, target_view: &wgpu::TextureView, target_format: crate::Format) -> bool {
+        if !self.accumulating { return false; }
+        self.accumulating = false;
+
+        let format = target_format.texture_format();
+
+        if !self.composite_pipelines.iter().any(|(f, _)| *f == format) {
+            let pipeline = create_composite_pipeline(device, &self.layout, &self.shader, target_format);
+            self.composite_pipelines.push((format, pipeline));
+        }
+
+        let (_, pipeline) = self.composite_pipelines.iter().find(|(f, _)| *f == format).unwrap();
+        let views = self.views();
+
+        let entries = views.iter().enumerate().map(|(i, view)| wgpu::BindGroupEntry { binding: i as u32, resource: wgpu::BindingResource::TextureView(view) }).collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout: &self.layout, entries: &entries });
+
+        let ops = wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store };
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment { view: target_view, resolve_target: None, ops })];
+        let descriptor = wgpu::RenderPassDescriptor { label: None, color_attachments: &color_attachments, depth_stencil_attachment: None, timestamp_writes: None, occlusion_query_set: None };
+
+        let mut render_pass = encoder.begin_render_pass(&descriptor);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        true
+    }
+}
+
+fn create_target(device: &wgpu::Device, (width, height): (u32, u32)) -> crate::Texture {
+    let filter_mode = crate::FilterMode::Nearest;
+    let format = crate::Format::RgbaF16;
+    let renderable = true;
+    let copyable = false;
+    let with_sampler = false;
+
+    crate::Texture::new(device, (width.max(1), height.max(1), 1), filter_mode, format, 1, renderable, copyable, with_sampler)
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let entries = (0..2).map(|binding| {
+        let ty = wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false };
+        wgpu::BindGroupLayoutEntry { binding, visibility: wgpu::ShaderStages::FRAGMENT, ty, count: None }
+    }).collect::<Vec<_>>();
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: None, entries: &entries })
+}
+
+fn create_composite_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, shader: &wgpu::ShaderModule, target_format: crate::Format) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: None, bind_group_layouts: &[layout], push_constant_ranges: &[] });
+    let blend_mode = crate::BlendMode { src_factor: wgpu::BlendFactor::SrcAlpha, dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha };
+
+    let descriptor = wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: shader, entry_point: "vs_main", buffers: &[] },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState { module: shader, entry_point: "fs_main", targets: &[Some(blend_mode.state(target_format))] }),
+        multiview: None,
+    };
+
+    device.create_render_pipeline(&descriptor)
+}
+
+// A full-screen triangle that reads the targets texel by texel.
+const COMPOSITE_SHADER: &str = "
+@group(0) @binding(0) var accumulation: texture_2d<f32>;
+@group(0) @binding(1) var revealage: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    let accumulated = textureLoad(accumulation, coords, 0);
+    let revealed = textureLoad(revealage, coords, 0).r;
+
+    if (revealed >= 1.0) { discard; }
+
+    return vec4<f32>(accumulated.rgb / max(accumulated.a, 0.00001), 1.0 - revealed);
+}
+";
@@ -5,6 +5,19 @@ pub struct Uniform {
     pub buffer: crate::Buffer,
 }
 
+// Typed uniform data, e.g. to send mixed types to the render thread. Uniform
+// buffers hold 32-bit values so everything is uploaded bit for bit except F64,
+// which is narrowed to f32 because most adapters can't use doubles in shaders.
+// Bytes are for structs (e.g. via bytemuck) and must be a multiple of 4 long.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UniformData {
+    F32(Vec<f32>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    F64(Vec<f64>),
+    Bytes(Vec<u8>),
+}
+
 impl Uniform {
     pub fn new(device: &wgpu::Device) -> Self {
         let usage = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
@@ -13,16 +26,16 @@ impl Uniform {
         Self { buffer }
     }
 
-    pub fn binding(&self, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry, wgpu::BindGroupLayoutEntry) {
+    pub fn binding<'a>(&self, buffer: &'a wgpu::Buffer, visibility: &crate::Visibility, id: u32) -> (wgpu::BindGroupEntry<'a>, wgpu::BindGroupLayoutEntry) {
         let layout = uniform_binding_layout(id, visibility, &self.buffer);
-        let binding = uniform_binding(id, &self.buffer, self.buffer.inner.borrow().size);
+        let binding = uniform_binding(id, buffer, self.buffer.size());
 
         (binding, layout)
     }
 }
 
 fn uniform_binding_layout(id: u32, visibility: &crate::Visibility, buffer: &crate::Buffer) -> wgpu::BindGroupLayoutEntry {
-    let size = num::NonZeroU64::new(buffer.inner.borrow().size as u64);
+    let size = num::NonZeroU64::new(buffer.size() as u64);
     let uniform = wgpu::BufferBindingType::Uniform;
 
     let ty = wgpu::BindingType::Buffer { ty: uniform, has_dynamic_offset: false, min_binding_size: size };
@@ -36,3 +49,26 @@ fn uniform_binding(id: u32, buffer: &wgpu::Buffer, size: usize) -> wgpu::BindGro
 
     wgpu::BindGroupEntry { binding: id, resource: wgpu::BindingResource::Buffer(binding) }
 }
+
+impl UniformData {
+    pub fn to_f32s(&self) -> Vec<f32> {
+        match self {
+            Self::F32(data) => data.clone(),
+            Self::U32(data) => bytemuck::cast_slice(data).to_vec(),
+            Self::I32(data) => bytemuck::cast_slice(data).to_vec(),
+            Self::F64(data) => data.iter().map(|d| *d as f32).collect(),
+            Self::Bytes(data) => {
+                if data.len() % 4 != 0 { panic!("Uniform bytes must be a multiple of 4 long."); }
+
+                // The bytes might not be aligned to 4 so they can't be cast in place.
+                data.chunks_exact(4).map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]])).collect()
+            },
+        }
+    }
+}
+
+impl From<Vec<f32>> for UniformData { fn from(data: Vec<f32>) -> Self { Self::F32(data) } }
+impl From<Vec<u32>> for UniformData { fn from(data: Vec<u32>) -> Self { Self::U32(data) } }
+impl From<Vec<i32>> for UniformData { fn from(data: Vec<i32>) -> Self { Self::I32(data) } }
+impl From<Vec<f64>> for UniformData { fn from(data: Vec<f64>) -> Self { Self::F64(data) } }
+impl From<Vec<u8>> for UniformData { fn from(data: Vec<u8>) -> Self { Self::Bytes(data) } }
@@ -0,0 +1,175 @@
+#[derive(Clone, Copy, Debug)]
+pub enum LayoutRules {
+    Std140,
+    Std430,
+}
+
+#[derive(Clone, Debug)]
+pub enum LayoutType {
+    Scalar, // float, int, uint or bool
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat2,
+    Mat3,
+    Mat4,
+    Array(Box<LayoutType>, usize),
+    Struct(Vec<LayoutType>),
+}
+
+// Describes a uniform (or storage) block as it is declared in GLSL so that the
+// f32 data uploaded from Rust can be checked against the padding rules. Most
+// "my uniform reads garbage" bugs are a vec3 that wasn't padded to 16 bytes.
+#[derive(Clone, Debug)]
+pub struct UniformLayout {
+    pub rules: LayoutRules,
+    pub members: Vec<(String, LayoutType)>,
+}
+
+const N: usize = std::mem::size_of::<f32>();
+
+impl UniformLayout {
+    pub fn new(rules: LayoutRules, members: Vec<(&str, LayoutType)>) -> Self {
+        let members = members.into_iter().map(|(name, ty)| (name.to_string(), ty)).collect();
+
+        Self { rules, members }
+    }
+
+    pub fn offsets(&self) -> Vec<usize> {
+        let types = self.members.iter().map(|(_, ty)| ty.clone()).collect::<Vec<_>>();
+
+        member_offsets(&types, self.rules)
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        let types = self.members.iter().map(|(_, ty)| ty.clone()).collect();
+
+        LayoutType::Struct(types).size(self.rules)
+    }
+
+    // Takes the number of f32s that will be uploaded for each member (in order)
+    // and returns an error describing the first member that is misaligned.
+
+    pub fn check(&self, rust_sizes: &[usize]) -> Result<(), String> {
+        if rust_sizes.len() != self.members.len() {
+            return Err(format!("Expected sizes for {} uniform members but got {}.", self.members.len(), rust_sizes.len()));
+        }
+
+        let mut rust_offset = 0;
+
+        for ((name, ty), (expected_offset, rust_size)) in self.members.iter().zip(self.offsets().into_iter().zip(rust_sizes)) {
+            if rust_offset != expected_offset {
+                let difference = (rust_offset as i64 - expected_offset as i64).abs() / N as i64;
+                let advice = if rust_offset < expected_offset { "Add" } else { "Remove" };
+
+                return Err(format!(
+                    "Uniform member '{}' ({:?}) starts at byte {} but {:?} expects byte {}. {} {} float(s) of padding before it.",
+                    name, ty, rust_offset, self.rules, expected_offset, advice, difference,
+                ));
+            }
+
+            rust_offset += rust_size * N;
+        }
+
+        // An empty layout has no members to check the end of.
+        if let (Some((name, ty)), Some(last_offset)) = (self.members.last(), self.offsets().last()) {
+            let expected_end = last_offset + ty.size(self.rules);
+
+            if rust_offset < expected_end {
+                return Err(format!("Uniform member '{}' ({:?}) ends at byte {} but {:?} expects byte {}.", name, ty, rust_offset, self.rules, expected_end));
+            }
+        }
+
+        if rust_offset > self.size_in_bytes() {
+            return Err(format!("Uniform data is {} bytes but the {:?} block is only {} bytes.", rust_offset, self.rules, self.size_in_bytes()));
+        }
+
+        Ok(())
+    }
+
+    // Panics with the error from check in debug builds and does nothing in release builds.
+
+    pub fn debug_check(&self, rust_sizes: &[usize]) {
+        if !cfg!(debug_assertions) { return; }
+
+        if let Err(message) = self.check(rust_sizes) {
+            panic!("{}", message);
+        }
+    }
+}
+
+impl LayoutType {
+    pub fn alignment(&self, rules: LayoutRules) -> usize {
+        match self {
+            Self::Scalar => N,
+            Self::Vec2 => 2 * N,
+            Self::Vec3 | Self::Vec4 => 4 * N,
+            Self::Mat2 | Self::Mat3 | Self::Mat4 => self.as_array().alignment(rules),
+            Self::Array(element, _) => array_alignment(element, rules),
+            Self::Struct(members) => struct_alignment(members, rules),
+        }
+    }
+
+    pub fn size(&self, rules: LayoutRules) -> usize {
+        match self {
+            Self::Scalar => N,
+            Self::Vec2 => 2 * N,
+            Self::Vec3 => 3 * N,
+            Self::Vec4 => 4 * N,
+            Self::Mat2 | Self::Mat3 | Self::Mat4 => self.as_array().size(rules),
+            Self::Array(element, length) => array_stride(element, rules) * length,
+            Self::Struct(members) => struct_size(members, rules),
+        }
+    }
+
+    // Matrices are laid out as an array of column vectors.
+    fn as_array(&self) -> Self {
+        match self {
+            Self::Mat2 => Self::Array(Box::new(Self::Vec2), 2),
+            Self::Mat3 => Self::Array(Box::new(Self::Vec3), 3),
+            Self::Mat4 => Self::Array(Box::new(Self::Vec4), 4),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn array_alignment(element: &LayoutType, rules: LayoutRules) -> usize {
+    match rules {
+        LayoutRules::Std140 => round_up(element.alignment(rules), 4 * N),
+        LayoutRules::Std430 => element.alignment(rules),
+    }
+}
+
+fn array_stride(element: &LayoutType, rules: LayoutRules) -> usize {
+    round_up(element.size(rules), array_alignment(element, rules))
+}
+
+fn struct_alignment(members: &[LayoutType], rules: LayoutRules) -> usize {
+    let max_alignment = members.iter().map(|m| m.alignment(rules)).max().unwrap_or(N);
+
+    match rules {
+        LayoutRules::Std140 => round_up(max_alignment, 4 * N),
+        LayoutRules::Std430 => max_alignment,
+    }
+}
+
+fn struct_size(members: &[LayoutType], rules: LayoutRules) -> usize {
+    let offsets = member_offsets(members, rules);
+    let end = members.last().map(|m| offsets.last().unwrap() + m.size(rules)).unwrap_or(0);
+
+    round_up(end, struct_alignment(members, rules))
+}
+
+fn member_offsets(members: &[LayoutType], rules: LayoutRules) -> Vec<usize> {
+    let mut offset = 0;
+
+    members.iter().map(|member| {
+        let aligned = round_up(offset, member.alignment(rules));
+        offset = aligned + member.size(rules);
+        aligned
+    }).collect()
+}
+
+fn round_up(value: usize, multiple: usize) -> usize {
+    (value + multiple - 1) / multiple * multiple
+}
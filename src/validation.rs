@@ -0,0 +1,110 @@
+use std::rc;
+
+// Checks a pipeline for problems that would otherwise fail deep inside wgpu (or
+// render nothing) on the first draw, e.g. bindings that haven't been set or
+// attributes with different numbers of vertices. Returns a description of each
+// problem so that they can all be fixed at once. Textures aren't checked for
+// data because they're always bound, even before anything has been uploaded.
+
+pub struct Validation;
+
+impl Validation {
+    pub fn problems(adapter: &wgpu::Adapter, pipeline: &crate::Pipeline, window_size: (u32, u32), builtin_uniform: Option<&crate::Uniform>) -> Vec<String> {
+        let mut problems = vec![];
+
+        check_attributes(adapter, pipeline, &mut problems);
+        check_bindings(pipeline, builtin_uniform, &mut problems);
+        check_targets(adapter, pipeline, window_size, &mut problems);
+
+        problems
+    }
+}
+
+// Pulled attributes are checked for data but not vertex counts because they may
+// be shorter than the draw when they're shared through an index.
+fn check_attributes(adapter: &wgpu::Adapter, pipeline: &crate::Pipeline, problems: &mut Vec<String>) {
+    let program = &pipeline.program;
+    let mut vertex_counts = vec![];
+
+    for attribute in &program.attributes {
+        let floats = attribute.buffer.inner.borrow().len / 4;
+
+        if floats == 0 {
+            problems.push(format!("The attribute at location {} hasn't been set.", attribute.location));
+        } else if floats % attribute.size as usize != 0 {
+            problems.push(format!("The attribute at location {} has {} values, which isn't a multiple of its size ({}).", attribute.location, floats, attribute.size));
+        } else if !attribute.pulled {
+            vertex_counts.push((attribute.location, floats / attribute.size as usize));
+        }
+    }
+
+    // GL and WebGL don't support storage buffers in the vertex stage at all.
+    let is_vertex = |v: &crate::Visibility| v.shader_stage().contains(wgpu::ShaderStages::VERTEX);
+    let pulled = program.pulled_attributes().count();
+    let storage_buffers = pulled + program.instances.iter().filter(|i| is_vertex(&i.visibility)).count();
+    let max_storage_buffers = adapter.limits().max_storage_buffers_per_shader_stage as usize;
+
+    if pulled > 0 && storage_buffers > max_storage_buffers {
+        problems.push(format!("The vertex shader uses {} storage buffers but this adapter supports {}, so attributes can't be pulled.", storage_buffers, max_storage_buffers));
+    }
+
+    if vertex_counts.windows(2).any(|w| w[0].1 != w[1].1) {
+        let counts = vertex_counts.iter().map(|(l, c)| format!("location {} has {}", l, c)).collect::<Vec<_>>();
+        problems.push(format!("The attributes have different numbers of vertices: {}.", counts.join(", ")));
+    }
+}
+
+// Read-write storage buffers are skipped because shaders can fill them.
+fn check_bindings(pipeline: &crate::Pipeline, builtin_uniform: Option<&crate::Uniform>, problems: &mut Vec<String>) {
+    let program = &pipeline.program;
+    let index_tuple = |index: usize| (index / crate::BINDINGS_PER_GROUP, index % crate::BINDINGS_PER_GROUP);
+
+    for (i, instanced) in program.instances.iter().enumerate() {
+        if instanced.access == crate::StorageAccess::ReadOnly && instanced.buffer.inner.borrow().len == 0 {
+            problems.push(format!("The instanced data at {:?} hasn't been set.", index_tuple(i)));
+        }
+    }
+
+    for (i, (uniform, _)) in program.uniforms.iter().enumerate() {
+        let is_builtin = builtin_uniform.map_or(false, |u| rc::Rc::ptr_eq(&u.buffer.inner, &uniform.buffer.inner));
+
+        if !is_builtin && uniform.buffer.inner.borrow().len == 0 {
+            problems.push(format!("The uniform at {:?} hasn't been set.", index_tuple(program.instances.len() + i)));
+        }
+    }
+}
+
+fn check_targets(adapter: &wgpu::Adapter, pipeline: &crate::Pipeline, window_size: (u32, u32), problems: &mut Vec<String>) {
+    let inner = pipeline.inner.borrow();
+    let mut sizes = vec![];
+
+    for (i, target) in pipeline.targets.iter().enumerate() {
+        let format = target.format();
+        let flags = adapter.get_texture_format_features(format.texture_format()).flags;
+
+        if !flags.sample_count_supported(inner.msaa_samples) {
+            problems.push(format!("Target {} ({:?}) doesn't support {} msaa samples on this adapter.", i, format, inner.msaa_samples));
+        }
+
+        if let crate::Target::Texture(texture) = target {
+            if !texture.renderable { problems.push(format!("Target {} isn't a renderable texture.", i)); }
+            if texture.msaa_samples != 1 { problems.push(format!("Target {} is a multisampled texture. Set msaa_samples on the pipeline instead.", i)); }
+
+            if inner.textures.iter().any(|(t, _)| rc::Rc::ptr_eq(&t.inner, &texture.inner)) {
+                problems.push(format!("Target {} is also sampled by the pipeline, which isn't allowed in the same pass.", i));
+            }
+        }
+
+        let (width, height, layers) = target.size(window_size);
+
+        if let Some(views) = inner.multiview {
+            if layers != views { problems.push(format!("Target {} has {} layers but the pipeline renders {} views.", i, layers, views)); }
+        }
+
+        sizes.push((width, height));
+    }
+
+    if sizes.windows(2).any(|w| w[0] != w[1]) {
+        problems.push(format!("The targets have different sizes: {:?}.", sizes));
+    }
+}
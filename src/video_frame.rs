@@ -1,7 +1,11 @@
 use std::fmt;
 use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
 
-#[derive(Debug, Default)]
+// Frames are exported at this rate unless it is configured, e.g. with
+// FfmpegPipe::set_frame_rate and Renderer::set_recording_frame_rate.
+pub const DEFAULT_FRAME_RATE: usize = 60;
+
+#[derive(Debug)]
 #[cfg_attr(feature="bincode", derive(bincode::Encode, bincode::Decode))]
 pub struct VideoFrame {
     pub status: FrameStatus,
@@ -9,15 +13,33 @@ pub struct VideoFrame {
 
     pub width: usize,
     pub height: usize,
+    pub scale_factor: f32, // 0.5 if adaptive quality recorded the frame at half resolution (see full_size).
+    pub region: Option<(u32, u32, u32, u32)>, // (x, y, width, height) in the target if only a region was recorded.
     pub format: crate::Format,
 
     pub unpadded_bytes_per_row: usize,
     pub padded_bytes_per_row: usize,
 
     pub frame_number: usize,
+    pub delta_encoding: DeltaEncoding,
+    pub elapsed_time: f64, // Seconds from the start of the recording until the frame was captured.
 
-    pub frame_size_in_bytes: usize,
+    pub frame_size_in_bytes: usize, // Includes the planes.
     pub buffer_size_in_bytes: Arc<AtomicUsize>,
+
+    pub planes: Vec<Plane>, // Extra outputs that were captured alongside the image (see start_recording_with_planes).
+}
+
+// The same size as the frame's image but it can have a different format, e.g.
+// an object id per pixel for masks and segmentation.
+#[derive(Debug)]
+#[cfg_attr(feature="bincode", derive(bincode::Encode, bincode::Decode))]
+pub struct Plane {
+    pub format: crate::Format,
+    pub image_data: Option<ImageData>,
+
+    pub unpadded_bytes_per_row: usize,
+    pub padded_bytes_per_row: usize,
 }
 
 #[derive(Debug)]
@@ -26,6 +48,16 @@ pub enum FrameStatus {
     Captured, // The frame was captured successfully (image_data=Some)
     Dropped,  // The frame was dropped to save memory (image_data=None)
     Missing,  // The frame was missing from the compressed files (image_data=None)
+    Skipped,  // The window was minimized or occluded so nothing was rendered (image_data=None)
+}
+
+// Only the image_data is delta encoded. The planes are always stored as they are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature="bincode", derive(bincode::Encode, bincode::Decode))]
+pub enum DeltaEncoding {
+    None,
+    Keyframe,
+    Delta { keyframe: usize }, // The image_data is XORed with this frame's image_data.
 }
 
 impl fmt::Display for FrameStatus {
@@ -34,6 +66,7 @@ impl fmt::Display for FrameStatus {
             Self::Captured => write!(f, "captured"),
             Self::Dropped => write!(f, "dropped"),
             Self::Missing => write!(f, "missing"),
+            Self::Skipped => write!(f, "skipped"),
         }
     }
 }
@@ -44,6 +77,37 @@ pub enum ImageData {
     Bytes(Vec<u8>),
 }
 
+impl VideoFrame {
+    // The size the frame was rendered at. Frames that adaptive quality recorded at
+    // a lower resolution are scaled back up to it when they're exported so that
+    // videos and image sequences keep the same resolution.
+    pub fn full_size(&self) -> (usize, usize) {
+        if self.scale_factor <= 0. || self.scale_factor >= 1. { return (self.width, self.height); }
+
+        let scale = |n: usize| (n as f32 / self.scale_factor).round() as usize;
+        (scale(self.width), scale(self.height))
+    }
+
+    // Scales tightly packed RgbaU8 rows up to the full_size by repeating pixels.
+    pub fn upscale_rgba(&self, rgba: Vec<u8>) -> Vec<u8> {
+        let (full_width, full_height) = self.full_size();
+        if (full_width, full_height) == (self.width, self.height) { return rgba; }
+
+        let mut upscaled = Vec::with_capacity(full_width * full_height * 4);
+
+        for y in 0..full_height {
+            let row = y * self.height / full_height * self.width;
+
+            for x in 0..full_width {
+                let i = (row + x * self.width / full_width) * 4;
+                upscaled.extend_from_slice(&rgba[i..i + 4]);
+            }
+        }
+
+        upscaled
+    }
+}
+
 impl ImageData {
     pub fn buffer(&self) -> &wgpu::Buffer {
         match self {
@@ -76,12 +140,40 @@ impl Drop for VideoFrame {
     }
 }
 
+impl Default for VideoFrame {
+    fn default() -> Self {
+        Self {
+            status: FrameStatus::default(),
+            image_data: None,
+            width: 0,
+            height: 0,
+            scale_factor: 1.,
+            region: None,
+            format: crate::Format::default(),
+            unpadded_bytes_per_row: 0,
+            padded_bytes_per_row: 0,
+            frame_number: 0,
+            delta_encoding: DeltaEncoding::default(),
+            elapsed_time: 0.,
+            frame_size_in_bytes: 0,
+            buffer_size_in_bytes: Arc::default(),
+            planes: vec![],
+        }
+    }
+}
+
 impl Default for FrameStatus {
     fn default() -> Self {
         FrameStatus::Missing
     }
 }
 
+impl Default for DeltaEncoding {
+    fn default() -> Self {
+        DeltaEncoding::None
+    }
+}
+
 #[cfg(feature="bincode")]
 impl bincode::Encode for ImageData {
     fn encode<E: bincode::enc::Encoder>(&self, _encoder: &mut E) -> Result<(), bincode::error::EncodeError> {
@@ -0,0 +1,156 @@
+use std::{io::Read, thread, time};
+use std::process::{Command, Stdio};
+use crossbeam_channel::{Receiver, TryRecvError};
+
+// Plays a video file (anything ffmpeg can decode, e.g. an mp4) into a texture so
+// that it can be sampled by a pipeline, e.g. for menus and cutscenes. Frames are
+// decoded to RgbaU8 by an ffmpeg process in a background thread and uploaded at
+// the video's frame rate. ffmpeg and ffprobe must be installed. Audio isn't played.
+
+pub struct VideoPlayer {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f32,
+    pub looping: bool,
+    pub receiver: Receiver<Vec<u8>>,
+    _thread: thread::JoinHandle<()>,
+    pub started_at: Option<time::Instant>,
+    pub frames_played: usize,
+    pub finished: bool,
+}
+
+impl VideoPlayer {
+    // Returns an error if ffprobe can't be run, the file can't be read or it
+    // doesn't have a video stream.
+    pub fn new(path: &str, looping: bool) -> Result<Self, String> {
+        let (width, height, frame_rate) = probe(path)?;
+        let (receiver, _thread) = spawn_decoder(path, width, height);
+
+        Ok(Self { path: path.to_string(), width, height, frame_rate, looping, receiver, _thread, started_at: None, frames_played: 0, finished: false })
+    }
+
+    // Uploads the most recent frame that is due into the texture, resizing it to
+    // the video's size. Returns true if the texture was updated. The texture
+    // should be created with the RgbaU8 format.
+
+    pub fn update(&mut self, renderer: &crate::Renderer, texture: &mut crate::Texture) -> bool {
+        if self.finished { return false; }
+
+        let started_at = *self.started_at.get_or_insert_with(time::Instant::now);
+        let frames_due = (started_at.elapsed().as_secs_f32() * self.frame_rate) as usize + 1;
+
+        let mut latest_frame = None;
+
+        while self.frames_played < frames_due {
+            match self.receiver.try_recv() {
+                Ok(frame) => { self.frames_played += 1; latest_frame = Some(frame); },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => { self.reached_end(); break; },
+            }
+        }
+
+        let frame = match latest_frame { Some(f) => f, _ => return false };
+        let size = (self.width, self.height);
+
+        renderer.resize_texture(texture, (size.0, size.1, 1));
+        renderer.upload_texture(texture, (0, 0, 0), size, &frame);
+
+        true
+    }
+
+    // Videos that ended without decoding any frames aren't looped, otherwise ffmpeg
+    // would be spawned again every update, e.g. if the file has been deleted.
+    fn reached_end(&mut self) {
+        if self.looping && self.frames_played > 0 { self.restart(); } else { self.finished = true; }
+    }
+
+    // Plays the video again from the start.
+    pub fn restart(&mut self) {
+        let (receiver, thread) = spawn_decoder(&self.path, self.width, self.height);
+
+        self.receiver = receiver;
+        self._thread = thread;
+        self.started_at = None;
+        self.frames_played = 0;
+        self.finished = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+// Returns the (width, height, frame_rate) of the first video stream's decoded
+// frames. ffmpeg applies the rotation metadata of videos recorded on phones so
+// the size is swapped if they're rotated by 90 or 270 degrees.
+fn probe(path: &str) -> Result<(u32, u32, f32), String> {
+    let mut command = Command::new("ffprobe");
+
+    command.arg("-v").arg("error").arg("-select_streams").arg("v:0");
+    command.arg("-show_entries").arg("stream=width,height,r_frame_rate:stream_tags=rotate:stream_side_data=rotation");
+    command.arg("-of").arg("default=noprint_wrappers=1");
+
+    let output = command.arg(path).output().map_err(|e| format!("Failed to run ffprobe. Is ffmpeg installed? {}", e))?;
+    let (stdout, stderr) = (String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+
+    if !output.status.success() { return Err(format!("Failed to probe {}: {}", path, stderr.trim())); }
+
+    // Each line is key=value, e.g. TAG:rotate=90 (older files) or rotation=-90.
+    let field = |key: &str| stdout.lines().filter_map(|l| l.split_once('=')).find(|(k, _)| k.trim() == key).map(|(_, v)| v.trim().to_string());
+    let parse = |key: &str| field(key).and_then(|s| s.parse::<u32>().ok());
+
+    // ffprobe succeeds with no output if the file doesn't have a video stream, e.g. audio only.
+    let (width, height) = match (parse("width"), parse("height")) {
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err(format!("Failed to read the video size of {}. Does it have a video stream?", path)),
+    };
+
+    let rotation = field("rotation").or_else(|| field("TAG:rotate")).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.);
+    let quarter_turns = (rotation / 90.).round() as i32;
+    let (width, height) = if quarter_turns % 2 != 0 { (height, width) } else { (width, height) };
+
+    // The frame rate is a fraction, e.g. 30000/1001.
+    let frame_rate = field("r_frame_rate").and_then(|s| {
+        let (numerator, denominator) = s.split_once('/').unwrap_or((&s, "1"));
+        Some(numerator.parse::<f32>().ok()? / denominator.parse::<f32>().ok()?)
+    });
+
+    Ok((width, height, frame_rate.filter(|f| f.is_finite() && *f > 0.).unwrap_or(FALLBACK_PLAYBACK_FRAME_RATE)))
+}
+
+fn spawn_decoder(path: &str, width: u32, height: u32) -> (Receiver<Vec<u8>>, thread::JoinHandle<()>) {
+    // Only decode a couple of frames ahead of playback to keep memory usage down.
+    let (sender, receiver) = crossbeam_channel::bounded(2);
+    let path = path.to_string();
+
+    let thread = thread::spawn(move || {
+        let mut command = Command::new("ffmpeg");
+
+        command.arg("-hide_banner").arg("-loglevel").arg("error").arg("-i").arg(&path);
+        command.arg("-f").arg("rawvideo").arg("-pix_fmt").arg("rgba").arg("-");
+        command.stdin(Stdio::null()).stdout(Stdio::piped());
+
+        // The player finishes without any frames if ffmpeg can't be spawned.
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => { eprintln!("Warning: Failed to spawn ffmpeg. Is it installed? {}", e); return; },
+        };
+        let mut stdout = child.stdout.take().unwrap();
+
+        loop {
+            let mut frame = vec![0; width as usize * height as usize * 4];
+
+            if stdout.read_exact(&mut frame).is_err() { break; }
+            if sender.send(frame).is_err() { break; } // The player was dropped or restarted.
+        }
+
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+
+    (receiver, thread)
+}
+
+// Used if ffprobe doesn't report a valid frame rate, e.g. 0/0 for some streams.
+const FALLBACK_PLAYBACK_FRAME_RATE: f32 = 30.;
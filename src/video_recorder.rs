@@ -1,6 +1,20 @@
-use std::{collections::VecDeque, rc, cell};
+use std::{collections::VecDeque, rc, cell, time};
 use std::sync::{Arc, atomic::{AtomicUsize, Ordering::Relaxed}};
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct RecordingId(pub usize);
+
+// Which part of the target is recorded. Viewport records the area inside the
+// letterbox margins at the size it was drawn. FullTarget includes the margins
+// and Logical scales the area inside them to a fixed resolution, e.g. so that a
+// 16:9 game is always recorded at 1920x1080 whatever the window's size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordingArea {
+    Viewport,
+    FullTarget,
+    Logical(u32, u32),
+}
+
 pub struct VideoRecorder {
     pub max_buffer_size_in_bytes: usize,
     pub process_function: Box<dyn FnMut(crate::VideoFrame)>,
@@ -9,122 +23,368 @@ pub struct VideoRecorder {
 
 pub struct InnerV {
     pub recording_texture: crate::Texture,
+    pub plane_textures: Vec<crate::Texture>,
     pub clear_color: Option<crate::ClearColor>,
     pub cleared_this_frame: bool,
 
+    pub area: RecordingArea,
+    pub region: Option<(u32, u32, u32, u32)>,
+    pub resampler: Option<crate::Resampler>,
+    pub adaptive_quality: bool,
+    pub downscaled: bool,
+    pub downscaler: Option<crate::Downscaler>,
+    pub delta_encoder: Option<crate::DeltaEncoder>,
+    pub readback_scheduler: Option<crate::ReadbackScheduler>,
+    pub capture_interval: usize,
+
     pub buffer_size_in_bytes: Arc<AtomicUsize>,
     pub video_frames: VecDeque<crate::VideoFrame>,
-    pub frame_states: VecDeque<Arc<FrameState>>,
+    pub frame_states: VecDeque<Vec<Arc<FrameState>>>, // One per buffer in the frame.
 
     pub frame_number: usize,
+    pub started_at: Option<time::Instant>,
 }
 
-type FrameState = AtomicUsize; // 0=dropped, 1=mapping, 2=mapped, 3=failed-to-map
+type FrameState = AtomicUsize; // 0=dropped, 1=mapping, 2=mapped, 3=failed-to-map (see combined_state)
 
 impl VideoRecorder {
-    pub fn new(renderer: &crate::Renderer, clear_color: Option<crate::ClearColor>, max_buffer_size_in_bytes: usize, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> Self {
-        let size = (renderer.window_size.width, renderer.window_size.height, 1);
+    pub fn new(renderer: &crate::Renderer, clear_color: Option<crate::ClearColor>, plane_formats: &[crate::Format], area: RecordingArea, max_buffer_size_in_bytes: usize, process_function: Box<dyn FnMut(crate::VideoFrame)>) -> Self {
+        let is_logical = matches!(area, RecordingArea::Logical(..));
+        assert!(plane_formats.is_empty() || !is_logical, "Planes can't be recorded at a logical resolution.");
+
+        let window_size = renderer.window_size();
+        let size = (window_size.width, window_size.height, 1);
 
         let inner = InnerV {
-            recording_texture: create_recording_texture(&renderer.device, size),
+            recording_texture: create_recording_texture(&renderer.device, size, crate::Format::RgbaU8),
+            plane_textures: plane_formats.iter().map(|f| create_recording_texture(&renderer.device, size, *f)).collect(),
             cleared_this_frame: false,
             clear_color,
 
+            area,
+            region: None,
+            resampler: None,
+            adaptive_quality: false,
+            downscaled: false,
+            downscaler: None,
+            delta_encoder: None,
+            readback_scheduler: None,
+            capture_interval: 1,
+
             buffer_size_in_bytes: Arc::new(AtomicUsize::new(0)),
             video_frames: VecDeque::new(),
             frame_states: VecDeque::new(),
 
             frame_number: 0,
+            started_at: None,
         };
 
         Self { max_buffer_size_in_bytes, process_function, inner: rc::Rc::new(cell::RefCell::new(inner)) }
     }
 
-    pub fn color_attachment(&self) -> wgpu::RenderPassColorAttachment {
+    // The fraction of max_buffer_size_in_bytes used by frames that haven't been processed.
+    pub fn buffer_fill(&self) -> f32 {
+        self.inner.borrow().buffer_size_in_bytes.load(Relaxed) as f32 / self.max_buffer_size_in_bytes.max(1) as f32
+    }
+
+    pub fn plane_formats(&self) -> Vec<crate::Format> {
+        self.inner.borrow().plane_textures.iter().map(|t| t.format).collect()
+    }
+
+    pub fn resize(&self, device: &wgpu::Device, size: (u32, u32, u32)) {
         let mut inner = self.inner.borrow_mut();
 
-        let load = if inner.cleared_this_frame || inner.clear_color.is_none() {
-            wgpu::LoadOp::Load
-        } else {
-            inner.cleared_this_frame = true;
-            wgpu::LoadOp::Clear(inner.clear_color.as_ref().unwrap().inner)
-        };
+        inner.recording_texture.resize(device, size);
+        for texture in &mut inner.plane_textures { texture.resize(device, size); }
+    }
 
-        let store = wgpu::StoreOp::Store;
-        let ops = wgpu::Operations { load, store };
+    // The recording texture's view followed by the view of each plane.
+    pub fn views(&self) -> Vec<rc::Rc<wgpu::TextureView>> {
+        let inner = self.inner.borrow();
+        let planes = inner.plane_textures.iter().map(|t| t.view());
 
-        drop(inner);
-        let inner = unsafe { self.inner.try_borrow_unguarded().unwrap() };
+        std::iter::once(inner.recording_texture.view()).chain(planes).collect()
+    }
 
-        wgpu::RenderPassColorAttachment { view: &inner.recording_texture.view, resolve_target: None, ops }
+    // The planes are cleared to zero when the recording texture is cleared.
+    pub fn color_attachments<'a>(&self, views: &'a [rc::Rc<wgpu::TextureView>]) -> Vec<wgpu::RenderPassColorAttachment<'a>> {
+        let mut inner = self.inner.borrow_mut();
+        let clear = !inner.cleared_this_frame && inner.clear_color.is_some();
+
+        let attachments = views.iter().enumerate().map(|(i, view)| {
+            let load = match (clear, i) {
+                (false, _) => wgpu::LoadOp::Load,
+                (true, 0) => wgpu::LoadOp::Clear(inner.clear_color.as_ref().unwrap().inner),
+                (true, _) => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            };
+
+            let store = wgpu::StoreOp::Store;
+            let ops = wgpu::Operations { load, store };
+
+            wgpu::RenderPassColorAttachment { view, resolve_target: None, ops }
+        }).collect();
+
+        inner.cleared_this_frame |= clear;
+        attachments
     }
 
     pub fn finish_frame(&self) {
         self.inner.borrow_mut().cleared_this_frame = false;
     }
 
+    // Frames are recorded at half resolution rather than dropped when they would
+    // exceed the buffer size. Full resolution resumes once the buffers are less
+    // than half full. This only applies to recordings without planes.
+    pub fn set_adaptive_quality(&self, enabled: bool) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.adaptive_quality = enabled;
+        inner.downscaled &= enabled;
+    }
+
+    // Only records the (x, y, width, height) region of the target from the next
+    // frame onwards. It is clipped to the viewport. None records the whole viewport.
+    pub fn set_region(&self, region: Option<(u32, u32, u32, u32)>) {
+        self.inner.borrow_mut().region = region;
+    }
+
+    // Frames are XORed with a keyframe every keyframe_interval frames. None turns it off.
+    pub fn set_delta_encoding(&self, device: &wgpu::Device, keyframe_interval: Option<usize>) {
+        self.inner.borrow_mut().delta_encoder = keyframe_interval.map(|i| crate::DeltaEncoder::new(device, i));
+    }
+
+    // None copies each frame as soon as it has rendered. Frames that are waiting
+    // to be copied are still copied at the previous budget's rate.
+    pub fn set_readback_budget(&self, bytes_per_second: Option<f64>) {
+        let mut inner = self.inner.borrow_mut();
+
+        match (&mut inner.readback_scheduler, bytes_per_second) {
+            (Some(scheduler), _) => scheduler.bytes_per_second = bytes_per_second.unwrap_or(f64::INFINITY),
+            (None, Some(b)) => inner.readback_scheduler = Some(crate::ReadbackScheduler::new(b)),
+            (None, None) => {},
+        }
+    }
+
+    // Only copies every nth frame. The frames in between are added as if they'd
+    // been skipped so that frame numbers and elapsed times carry on.
+    pub fn set_capture_interval(&self, n: usize) {
+        self.inner.borrow_mut().capture_interval = n.max(1);
+    }
+
     pub fn create_buffer_if_within_memory_limit(&self, device: &wgpu::Device, viewport: Option<&crate::Viewport>) {
         let mut inner = self.inner.borrow_mut();
 
-        let width = viewport.map(|v| v.width.floor() as usize).unwrap_or(inner.recording_texture.size.0 as usize);
-        let height = viewport.map(|v| v.height.floor() as usize).unwrap_or(inner.recording_texture.size.1 as usize);
+        if inner.frame_number % inner.capture_interval != 0 {
+            drop(inner);
+            return self.skip_frame();
+        }
+
+        let (texture_width, texture_height, _) = inner.recording_texture.size();
         let format = inner.recording_texture.format;
 
-        let unpadded_bytes_per_row = width * format.bytes_per_texel() as usize;
+        let (mut width, mut height) = match (inner.area, viewport) {
+            (RecordingArea::Viewport, Some(v)) => (v.width.floor() as usize, v.height.floor() as usize),
+            (RecordingArea::Logical(width, height), _) => (width.max(1) as usize, height.max(1) as usize),
+            _ => (texture_width as usize, texture_height as usize),
+        };
+
+        // The region is ignored at a logical resolution because the area is scaled.
+        let (margin_x, margin_y) = margins(inner.area, viewport);
+        let region = inner.region.filter(|_| !matches!(inner.area, RecordingArea::Logical(..)));
+        let region = region.map(|r| clip_region(r, (margin_x, margin_y), (width as u32, height as u32)));
 
-        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
-        let row_padding = (alignment - unpadded_bytes_per_row % alignment) % alignment;
+        if let Some((_, _, w, h)) = region {
+            width = w as usize;
+            height = h as usize;
+        }
 
-        let padded_bytes_per_row = unpadded_bytes_per_row + row_padding;
-        let frame_size_in_bytes = padded_bytes_per_row * height;
+        if inner.adaptive_quality && inner.plane_textures.is_empty() {
+            let full_size_in_bytes = bytes_per_row(width, format).1 * height;
+            let buffer_size_in_bytes = inner.buffer_size_in_bytes.load(Relaxed);
+
+            if buffer_size_in_bytes + full_size_in_bytes > self.max_buffer_size_in_bytes {
+                inner.downscaled = true;
+            } else if buffer_size_in_bytes < self.max_buffer_size_in_bytes / 2 {
+                inner.downscaled = false;
+            }
+        }
+
+        let scale_factor = if inner.downscaled { 0.5 } else { 1. };
+
+        if inner.downscaled {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            inner.downscaler.get_or_insert_with(|| crate::Downscaler::new(device));
+        }
+
+        let (unpadded_bytes_per_row, padded_bytes_per_row) = bytes_per_row(width, format);
+        let plane_rows = inner.plane_textures.iter().map(|t| bytes_per_row(width, t.format)).collect::<Vec<_>>();
+
+        let frame_size_in_bytes = (padded_bytes_per_row + plane_rows.iter().map(|(_, p)| p).sum::<usize>()) * height;
 
         let prev_size = inner.buffer_size_in_bytes.fetch_add(frame_size_in_bytes, Relaxed);
         let drop_frame = prev_size > self.max_buffer_size_in_bytes;
 
-        let buffer = if drop_frame {
+        if drop_frame {
             inner.buffer_size_in_bytes.fetch_sub(frame_size_in_bytes, Relaxed);
-            None
-        } else {
+        }
+
+        let create_buffer = |padded_bytes_per_row: usize| if drop_frame { None } else {
             let usage =  wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ;
-            let descriptor = wgpu::BufferDescriptor { label: None, size: frame_size_in_bytes as u64, usage, mapped_at_creation: false };
+            let descriptor = wgpu::BufferDescriptor { label: None, size: (padded_bytes_per_row * height) as u64, usage, mapped_at_creation: false };
 
-            Some(device.create_buffer(&descriptor))
+            Some(crate::ImageData::Buffer(device.create_buffer(&descriptor)))
         };
 
+        let image_data = create_buffer(padded_bytes_per_row);
+
+        let planes = inner.plane_textures.iter().zip(&plane_rows).map(|(texture, (unpadded_bytes_per_row, padded_bytes_per_row))| {
+            crate::Plane { format: texture.format, image_data: create_buffer(*padded_bytes_per_row), unpadded_bytes_per_row: *unpadded_bytes_per_row, padded_bytes_per_row: *padded_bytes_per_row }
+        }).collect();
+
         // The frame number is incremented regardless of whether the frame is dropped.
         inner.frame_number += 1;
 
+        // Downscaled frames are stored as they are because they're a different size.
+        let texture_size = inner.recording_texture.size();
+        let (frame_number, downscaled) = (inner.frame_number, inner.downscaled);
+        let frame_region = region.unwrap_or((margin_x, margin_y, width as u32, height as u32));
+
+        let delta_encoding = match &mut inner.delta_encoder {
+            Some(encoder) if !drop_frame && !downscaled => encoder.next_encoding(frame_number, texture_size, frame_region),
+            _ => crate::DeltaEncoding::None,
+        };
+
         let status = if drop_frame { crate::FrameStatus::Dropped } else { crate::FrameStatus::Captured };
-        let image_data = buffer.map(|b| crate::ImageData::Buffer(b));
+        let buffer_size_in_bytes = Arc::clone(&inner.buffer_size_in_bytes);
+        let elapsed_time = inner.started_at.get_or_insert_with(time::Instant::now).elapsed().as_secs_f64();
+
+        inner.video_frames.push_back(crate::VideoFrame {
+            status, image_data, format, width, height, scale_factor, region, unpadded_bytes_per_row, padded_bytes_per_row, frame_number, delta_encoding, elapsed_time, frame_size_in_bytes, buffer_size_in_bytes, planes
+        });
+    }
+
+    // Adds a frame without image data while the window is hidden so that frame
+    // numbers and elapsed times carry on as if it had been rendered. Consumers
+    // treat it like a dropped frame, e.g. FfmpegPipe repeats the previous frame.
+    pub fn skip_frame(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.frame_number += 1;
+
+        let (width, height, _) = inner.recording_texture.size();
+        let format = inner.recording_texture.format;
+        let (unpadded_bytes_per_row, padded_bytes_per_row) = bytes_per_row(width as usize, format);
+
+        let planes = inner.plane_textures.iter().map(|texture| {
+            let (unpadded_bytes_per_row, padded_bytes_per_row) = bytes_per_row(width as usize, texture.format);
+            crate::Plane { format: texture.format, image_data: None, unpadded_bytes_per_row, padded_bytes_per_row }
+        }).collect();
+
         let frame_number = inner.frame_number;
         let buffer_size_in_bytes = Arc::clone(&inner.buffer_size_in_bytes);
+        let elapsed_time = inner.started_at.get_or_insert_with(time::Instant::now).elapsed().as_secs_f64();
 
         inner.video_frames.push_back(crate::VideoFrame {
-            status, image_data, format, width, height, unpadded_bytes_per_row, padded_bytes_per_row, frame_number, frame_size_in_bytes, buffer_size_in_bytes
+            status: crate::FrameStatus::Skipped, image_data: None, format, width: width as usize, height: height as usize, scale_factor: 1., region: None, unpadded_bytes_per_row, padded_bytes_per_row, frame_number, delta_encoding: crate::DeltaEncoding::None, elapsed_time, frame_size_in_bytes: 0, buffer_size_in_bytes, planes
         });
     }
 
-    pub fn copy_texture_to_buffer_if_present(&self, encoder: &mut wgpu::CommandEncoder, viewport: Option<&crate::Viewport>) {
-        let inner = self.inner.borrow_mut();
+    pub fn copy_texture_to_buffer_if_present(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, viewport: Option<&crate::Viewport>) {
+        let mut inner = self.inner.borrow_mut();
+        let inner = &mut *inner;
 
         let video_frame = inner.video_frames.back().unwrap();
         let image_data = match &video_frame.image_data { Some(d) => d, _ => return };
+        span!("recording_copy", frame = video_frame.frame_number);
+
+        let (mut margin_x, mut margin_y) = margins(inner.area, viewport);
 
-        let margin_x = viewport.map(|v| v.margin_x.ceil() as u32).unwrap_or(0);
-        let margin_y = viewport.map(|v| v.margin_y.ceil() as u32).unwrap_or(0);
+        // Logical recordings are resampled first and then copied like a full target.
+        let source = match inner.area {
+            RecordingArea::Logical(width, height) => {
+                let resampler = inner.resampler.get_or_insert_with(|| crate::Resampler::new(device));
+                let (texture_width, texture_height, _) = inner.recording_texture.size();
 
-        let image_copy = inner.recording_texture.image_copy_texture((margin_x, margin_y, 0));
+                let extent = (texture_width - 2 * margin_x, texture_height - 2 * margin_y);
+                resampler.resample(device, encoder, &inner.recording_texture, (margin_x, margin_y), extent, (width, height));
 
-        let buffer_copy = wgpu::ImageCopyBuffer {
-            buffer: image_data.buffer(),
-            layout: inner.recording_texture.image_data_layout(video_frame.padded_bytes_per_row as u32, video_frame.height as u32),
+                (margin_x, margin_y) = (0, 0);
+                &resampler.texture
+            },
+            _ => &inner.recording_texture,
         };
 
-        let mut extent = inner.recording_texture.extent();
-        extent.width -= 2 * margin_x;
-        extent.height -= 2 * margin_y;
+        let origin = video_frame.region.map(|(x, y, _, _)| (x, y)).unwrap_or((margin_x, margin_y));
+
+        // Each copy is (texture, origin, extent, buffer, padded_bytes_per_row).
+        let mut copies = vec![];
+
+        if video_frame.scale_factor < 1. {
+            let downscaler = inner.downscaler.as_mut().unwrap();
+            downscaler.downscale(device, encoder, source);
+
+            let extent = wgpu::Extent3d { width: video_frame.width as u32, height: video_frame.height as u32, depth_or_array_layers: 1 };
+            copies.push((&downscaler.texture, (origin.0 / 2, origin.1 / 2), extent, image_data, video_frame.padded_bytes_per_row));
+        } else {
+            let main_texture = match (video_frame.delta_encoding, &mut inner.delta_encoder) {
+                (crate::DeltaEncoding::Keyframe, Some(delta_encoder)) => { delta_encoder.set_keyframe(device, encoder, source); source },
+                (crate::DeltaEncoding::Delta { .. }, Some(delta_encoder)) => { delta_encoder.encode(device, encoder, source); &delta_encoder.delta_texture },
+                _ => source,
+            };
+
+            let main = (main_texture, image_data, video_frame.padded_bytes_per_row);
+            let planes = inner.plane_textures.iter().zip(&video_frame.planes).map(|(t, p)| (t, p.image_data.as_ref().unwrap(), p.padded_bytes_per_row));
+
+            for (texture, image_data, padded_bytes_per_row) in std::iter::once(main).chain(planes) {
+                let mut extent = texture.extent();
+                extent.width -= 2 * margin_x;
+                extent.height -= 2 * margin_y;
+
+                if let Some((_, _, width, height)) = video_frame.region {
+                    extent.width = width;
+                    extent.height = height;
+                }
+
+                copies.push((texture, origin, extent, image_data, padded_bytes_per_row));
+            }
+        }
+
+        // With a readback budget, snapshot the frame and copy it over the next frames.
+        if let Some(scheduler) = inner.readback_scheduler.as_mut().filter(|s| s.bytes_per_second.is_finite()) {
+            let snapshots = copies.iter().map(|(texture, origin, extent, _, padded_bytes_per_row)| {
+                (scheduler.snapshot(device, encoder, texture, *origin, *extent), *padded_bytes_per_row)
+            }).collect();
+
+            return scheduler.push(video_frame.frame_number, snapshots);
+        }
+
+        for (texture, origin, extent, image_data, padded_bytes_per_row) in copies {
+            let wgpu_texture = texture.texture();
+            let image_copy = crate::Texture::image_copy_texture(&wgpu_texture, (origin.0, origin.1, 0));
+
+            let buffer_copy = wgpu::ImageCopyBuffer {
+                buffer: image_data.buffer(),
+                layout: texture.image_data_layout(padded_bytes_per_row as u32, video_frame.height as u32),
+            };
 
-        encoder.copy_texture_to_buffer(image_copy, buffer_copy, extent);
+            encoder.copy_texture_to_buffer(image_copy, buffer_copy, extent);
+        }
+    }
+
+    // Returns true if any rows were copied and the encoder should be submitted.
+    pub fn copy_scheduled_readbacks(&self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let inner = &mut *inner;
+
+        let scheduler = match &mut inner.readback_scheduler { Some(s) => s, _ => return false };
+        let video_frames = &inner.video_frames;
+
+        scheduler.copy_rows(encoder, |frame_number| {
+            let video_frame = video_frames.iter().find(|f| f.frame_number == frame_number).unwrap();
+            let planes = video_frame.planes.iter().filter_map(|p| p.image_data.as_ref());
+
+            video_frame.image_data.iter().chain(planes).map(|d| d.buffer()).collect()
+        })
     }
 
     pub fn initiate_buffer_mapping(&mut self) {
@@ -134,7 +394,13 @@ impl VideoRecorder {
             if inner.frame_states.get(i).is_some() { continue; }
             let video_frame = &inner.video_frames[i];
 
-            if let Some(image_data) = &video_frame.image_data {
+            // Frames are mapped in order so stop at the first one that's still being copied.
+            if inner.readback_scheduler.as_ref().map_or(false, |s| s.is_pending(video_frame.frame_number)) { break; }
+
+            let image_data = video_frame.image_data.iter().chain(video_frame.planes.iter().filter_map(|p| p.image_data.as_ref()));
+
+            // Dropped frames don't have any buffers so they have no states.
+            let frame_states = image_data.map(|image_data| {
                 let frame_state = Arc::new(AtomicUsize::new(1)); // 1=mapping
                 let frame_state_ = Arc::clone(&frame_state);
 
@@ -142,10 +408,10 @@ impl VideoRecorder {
                     frame_state_.store(if result.is_ok() { 2 } else { 3 }, Relaxed); // 2=mapped, 3=failed-to-map
                 });
 
-                inner.frame_states.push_back(frame_state);
-            } else {
-                inner.frame_states.push_back(Arc::new(AtomicUsize::new(0))); // 0=dropped
-            }
+                frame_state
+            }).collect();
+
+            inner.frame_states.push_back(frame_states);
         }
     }
 
@@ -154,7 +420,7 @@ impl VideoRecorder {
 
         loop {
             if inner.video_frames.is_empty() { break; }
-            let frame_state = inner.frame_states[0].load(Relaxed);
+            let frame_state = combined_state(&inner.frame_states[0]);
 
             match frame_state {
                 // If the frame was dropped or mapped, call the process function and keep going.
@@ -162,6 +428,8 @@ impl VideoRecorder {
                 0 | 2 => {
                     let video_frame = inner.video_frames.pop_front().unwrap();
                     inner.frame_states.pop_front().unwrap();
+
+                    span!("process_video_frame", frame = video_frame.frame_number);
                     (self.process_function)(video_frame);
                 }
 
@@ -176,9 +444,24 @@ impl VideoRecorder {
     }
 }
 
-fn create_recording_texture(device: &wgpu::Device, size: (u32, u32, u32)) -> crate::Texture {
+// The state of the frame is the state of its least progressed buffer.
+fn combined_state(frame_states: &[Arc<FrameState>]) -> usize {
+    let states = frame_states.iter().map(|s| s.load(Relaxed)).collect::<Vec<_>>();
+
+    if states.contains(&3) { 3 } else if states.contains(&1) { 1 } else if states.is_empty() { 0 } else { 2 }
+}
+
+fn bytes_per_row(width: usize, format: crate::Format) -> (usize, usize) {
+    let unpadded_bytes_per_row = width * format.bytes_per_texel() as usize;
+
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+    let row_padding = (alignment - unpadded_bytes_per_row % alignment) % alignment;
+
+    (unpadded_bytes_per_row, unpadded_bytes_per_row + row_padding)
+}
+
+fn create_recording_texture(device: &wgpu::Device, size: (u32, u32, u32), format: crate::Format) -> crate::Texture {
     let filter_mode = crate::FilterMode::Nearest; // Not used
-    let format = crate::Format::RgbaU8;
     let msaa_samples = 1;
     let renderable = true;
     let copyable = true;
@@ -186,3 +469,22 @@ fn create_recording_texture(device: &wgpu::Device, size: (u32, u32, u32)) -> cra
 
     crate::Texture::new(device, size, filter_mode, format, msaa_samples, renderable, copyable, with_sampler)
 }
+
+// The margins aren't recorded unless the area is the full target.
+fn margins(area: RecordingArea, viewport: Option<&crate::Viewport>) -> (u32, u32) {
+    match (area, viewport) {
+        (RecordingArea::FullTarget, _) | (_, None) => (0, 0),
+        (_, Some(v)) => (v.margin_x.ceil() as u32, v.margin_y.ceil() as u32),
+    }
+}
+
+// Clips the region to the area inside the viewport's margins. It is at least one
+// pixel so that frames can still be recorded if it's outside the viewport.
+fn clip_region((x, y, width, height): (u32, u32, u32, u32), (margin_x, margin_y): (u32, u32), (max_width, max_height): (u32, u32)) -> (u32, u32, u32, u32) {
+    let (right, bottom) = (margin_x + max_width.max(1), margin_y + max_height.max(1));
+
+    let x = x.clamp(margin_x, right - 1);
+    let y = y.clamp(margin_y, bottom - 1);
+
+    (x, y, width.min(right - x).max(1), height.min(bottom - y).max(1))
+}
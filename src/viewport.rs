@@ -4,6 +4,7 @@ pub struct Viewport {
     pub height: f32,
     pub margin_x: f32,
     pub margin_y: f32,
+    pub aspect: Option<(f32, f32)>,
 }
 
 impl Viewport {
@@ -24,6 +25,39 @@ impl Viewport {
             margin_y = (max_height as f32 - height) / 2.;
         }
 
-        Self { width, height, margin_x, margin_y }
+        Self { width, height, margin_x, margin_y, aspect: Some((aspect_x, aspect_y)) }
+    }
+
+    // Viewports created from an aspect ratio are recalculated when they are
+    // rendered so they stay letterboxed correctly after the window resizes.
+    // Viewports with explicit margins (aspect=None) are left as they are.
+
+    pub fn resized(&self, max_width: f32, max_height: f32) -> Self {
+        match self.aspect {
+            Some((aspect_x, aspect_y)) => Self::new(aspect_x, aspect_y, max_width, max_height),
+            None => self.clone(),
+        }
+    }
+
+    // Fits the viewport inside the parent (e.g. a panel inside a letterboxed
+    // view) so its margins are relative to the parent rather than the window.
+
+    pub fn within(&self, parent: &Self) -> Self {
+        let mut viewport = self.resized(parent.width, parent.height);
+
+        viewport.margin_x += parent.margin_x;
+        viewport.margin_y += parent.margin_y;
+        viewport.aspect = None;
+
+        viewport
+    }
+
+    // Splits the viewport into left and right halves, e.g. one per eye for stereo.
+    pub fn side_by_side(&self) -> [Self; 2] {
+        let width = self.width / 2.;
+        let left = Self { width, height: self.height, margin_x: self.margin_x, margin_y: self.margin_y, aspect: None };
+        let right = Self { margin_x: self.margin_x + width, ..left.clone() };
+
+        [left, right]
     }
 }
@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, Utc};
+
+// Burns the frame number, the time it was recorded and/or a logo into frames
+// before they're encoded so that exported videos are self-describing. Call
+// apply on each frame, e.g. in the Decompressor's in_order_function. Text uses
+// a small built-in pixel font that is enlarged by scale.
+
+pub struct Watermark {
+    pub show_frame_number: bool,
+    pub show_timestamp: bool,
+    pub frame_rate: f32,
+    pub corner: Corner,
+    pub scale: usize,
+    pub color: [u8; 4],
+    pub logo: Option<(Vec<u8>, (usize, usize))>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Watermark {
+    pub fn new(show_frame_number: bool, show_timestamp: bool, frame_rate: f32, corner: Corner, scale: usize) -> Self {
+        Self { show_frame_number, show_timestamp, frame_rate, corner, scale: scale.max(1), color: [255, 255, 255, 255], logo: None }
+    }
+
+    // The logo is RgbaU8 with no row padding and is alpha blended onto the frame.
+    pub fn set_logo(&mut self, rgba_bytes: &[u8], size: (usize, usize)) {
+        assert_eq!(rgba_bytes.len(), size.0 * size.1 * 4, "The logo's bytes don't match its size.");
+        self.logo = Some((rgba_bytes.to_vec(), size));
+    }
+
+    // The session timestamp is when the recording started. The time of each frame
+    // is worked out from its frame number and the frame rate. The frame's image
+    // data is replaced with bytes if it is still a buffer. Frames without image
+    // data (dropped or missing) or that aren't RgbaU8/BgraU8 are left alone.
+
+    pub fn apply(&self, video_frame: &mut crate::VideoFrame, session_timestamp: Option<&DateTime<Utc>>) {
+        let image_data = match &video_frame.image_data { Some(d) => d, _ => return };
+        let bgra = match video_frame.format { crate::Format::RgbaU8 => false, crate::Format::BgraU8 => true, _ => return };
+        span!("watermark", frame = video_frame.frame_number);
+
+        let mut bytes = match image_data { crate::ImageData::Bytes(b) => b.clone(), _ => vec![] };
+        if bytes.is_empty() { image_data.bytes_fn(|b| bytes.extend_from_slice(b)); }
+
+        let text = self.text(video_frame.frame_number, session_timestamp);
+        let mut canvas = Canvas { bytes: &mut bytes, width: video_frame.width, height: video_frame.height, bytes_per_row: video_frame.padded_bytes_per_row, bgra };
+
+        let text_size = (text.len() * GLYPH_WIDTH * self.scale + self.scale, GLYPH_HEIGHT * self.scale + 2 * self.scale);
+        let logo_size = self.logo.as_ref().map(|(_, s)| *s).unwrap_or((0, 0));
+
+        let gap = if text.is_empty() || self.logo.is_none() { 0 } else { self.scale * 2 };
+        let text_height = if text.is_empty() { 0 } else { text_size.1 };
+
+        let block_size = (text_size.0.max(logo_size.0), logo_size.1 + gap + text_height);
+        let (x, y) = self.block_position(block_size, (canvas.width, canvas.height));
+
+        let align = |width: usize| match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => x,
+            Corner::TopRight | Corner::BottomRight => x + block_size.0 - width,
+        };
+
+        if let Some((logo, size)) = &self.logo {
+            canvas.blend_image(logo, *size, (align(size.0), y));
+        }
+
+        if !text.is_empty() {
+            let (text_x, text_y) = (align(text_size.0), y + logo_size.1 + gap);
+
+            canvas.fill_rect((text_x, text_y), text_size, [0, 0, 0, 160]);
+            canvas.draw_text(&text, (text_x + self.scale, text_y + self.scale), self.scale, self.color);
+        }
+
+        video_frame.image_data = Some(crate::ImageData::Bytes(bytes));
+    }
+
+    fn text(&self, frame_number: usize, session_timestamp: Option<&DateTime<Utc>>) -> String {
+        let mut parts = vec![];
+
+        if let (true, Some(started_at)) = (self.show_timestamp, session_timestamp) {
+            let elapsed = (frame_number.saturating_sub(1) as f64 / self.frame_rate as f64 * 1000.) as i64;
+            let time = *started_at + Duration::milliseconds(elapsed);
+
+            parts.push(time.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+        }
+
+        if self.show_frame_number {
+            parts.push(format!("#{}", frame_number));
+        }
+
+        parts.join(" ")
+    }
+
+    fn block_position(&self, block_size: (usize, usize), frame_size: (usize, usize)) -> (usize, usize) {
+        let margin = self.scale * 2;
+
+        let left = margin;
+        let top = margin;
+        let right = frame_size.0.saturating_sub(block_size.0 + margin);
+        let bottom = frame_size.1.saturating_sub(block_size.1 + margin);
+
+        match self.corner {
+            Corner::TopLeft => (left, top),
+            Corner::TopRight => (right, top),
+            Corner::BottomLeft => (left, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+    }
+}
+
+struct Canvas<'a> {
+    bytes: &'a mut [u8],
+    width: usize,
+    height: usize,
+    bytes_per_row: usize,
+    bgra: bool,
+}
+
+impl<'a> Canvas<'a> {
+    fn blend_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        if x >= self.width || y >= self.height { return; }
+
+        let color = if self.bgra { [color[2], color[1], color[0], color[3]] } else { color };
+
+        let offset = y * self.bytes_per_row + x * 4;
+        let pixel = &mut self.bytes[offset..offset + 4];
+        let alpha = color[3] as u32;
+
+        for i in 0..3 {
+            pixel[i] = ((color[i] as u32 * alpha + pixel[i] as u32 * (255 - alpha)) / 255) as u8;
+        }
+    }
+
+    fn fill_rect(&mut self, position: (usize, usize), size: (usize, usize), color: [u8; 4]) {
+        for y in position.1..position.1 + size.1 {
+            for x in position.0..position.0 + size.0 {
+                self.blend_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn blend_image(&mut self, rgba_bytes: &[u8], size: (usize, usize), position: (usize, usize)) {
+        for (i, color) in rgba_bytes.chunks_exact(4).enumerate() {
+            self.blend_pixel(position.0 + i % size.0, position.1 + i / size.0, color.try_into().unwrap());
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, position: (usize, usize), scale: usize, color: [u8; 4]) {
+        for (i, character) in text.chars().enumerate() {
+            let rows = glyph(character);
+            let glyph_x = position.0 + i * GLYPH_WIDTH * scale;
+
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) == 0 { continue; }
+
+                    let x = glyph_x + column * scale;
+                    let y = position.1 + row * scale;
+
+                    self.fill_rect((x, y), (scale, scale), color);
+                }
+            }
+        }
+    }
+}
+
+// Glyphs are 3x5 pixels with one pixel of spacing. Each row's bits are left to right.
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 5;
+
+fn glyph(character: char) -> [u8; 5] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0; 5],
+    }
+}
@@ -0,0 +1,44 @@
+use renderer::{Harness, Renderer};
+
+const A_POSITION: usize = 0;
+const A_TEX_COORD: usize = 1;
+
+const I_OFFSET: (usize, usize) = (0, 0);
+const T_TEXTURE: (usize, usize) = (0, 1);
+
+// Runs the quads example's pipeline for a few frames. Skipped if there's no GPU.
+#[test]
+fn quads_render_without_validation_errors() {
+    let report = Harness::run((64, 64), 3, |renderer| {
+        let vert = include_bytes!("../examples/quads/hello.vert.spirv");
+        let frag = include_bytes!("../examples/quads/hello.frag.spirv");
+
+        let a_position = renderer.attribute(A_POSITION, 2);
+        let a_tex_coord = renderer.attribute(A_TEX_COORD, 2);
+        let i_offset = renderer.instanced();
+        let t_texture = renderer.texture(2, 2, 1, Renderer::linear_filtering(), Renderer::rgba_u8(), false, false, true);
+
+        let program = renderer.program(vert, frag, vec![a_position, a_tex_coord], vec![i_offset], vec![], vec![
+            (t_texture, Renderer::visible_to_fragment_shader()),
+        ]);
+
+        let pipeline = renderer.pipeline(program, Renderer::pre_multiplied_blend(), Renderer::triangle_strip_primitive(), 1, vec![Renderer::screen_target()]);
+        let clear_color = Renderer::clear_color(0., 0., 0., 1.);
+
+        renderer.set_attribute(&pipeline, A_POSITION, &[-0.1, -0.1, -0.1, 0.1, 0.1, -0.1, 0.1, 0.1]);
+        renderer.set_attribute(&pipeline, A_TEX_COORD, &[0., 1., 0., 0., 1., 1., 1., 0.]);
+        renderer.set_texture(&pipeline, T_TEXTURE, &[&[255_u8; 16]]);
+
+        move |renderer, frame_number| {
+            let x = frame_number as f32 * 0.1;
+
+            renderer.set_instanced(&pipeline, I_OFFSET, &[x, 0., -x, 0.]);
+            renderer.render(&pipeline, Some(clear_color), None, (2, 4));
+        }
+    });
+
+    match report {
+        Some(report) => report.assert_ok(None, Some(3)),
+        None => eprintln!("Skipping because there's no GPU adapter."),
+    }
+}